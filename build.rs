@@ -0,0 +1,16 @@
+// Record the git commit being built, for run manifests. Builds outside a git checkout just go
+// without.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output();
+    if let Ok(output) = output {
+        if output.status.success() {
+            let hash = String::from_utf8_lossy(&output.stdout);
+            println!("cargo:rustc-env=GENE_CODE_GIT_HASH={}", hash.trim());
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//
+// Evolve stack programs against each ready-made test suite with its own config, and print the best
+// program found for each. Programs that solve their suite are then shrunk as far as possible.
+//
+//     cargo run --release --example suites [NAME]
+//
+// where NAME is one of sort-two, isqrt, gcd, sign, multiplexer6, or even-parity3
+//
+
+extern crate gene_code;
+extern crate rand;
+
+use gene_code::gene::Pool;
+use gene_code::minimize::minimize;
+use gene_code::prog_gene::ProgramGene;
+use gene_code::suite::TestSuite;
+use rand::{SeedableRng, StdRng};
+
+fn main() {
+    let only = std::env::args().nth(1);
+    for suite in TestSuite::all() {
+        if only.as_ref().is_some_and(|name| name != suite.name) {
+            continue;
+        }
+        let rng = &mut StdRng::from_seed(&[1]);
+        let config = suite.config;
+        let mut pool = Pool::new(config.pool_size, |g: &ProgramGene| suite.score(g), rng);
+        for _ in 0 .. config.generations {
+            pool.evolve(rng);
+        }
+        let (best, fit) = pool.get_best();
+        println!("{}\t{}\t{}", suite.name, fit, best);
+        if suite.solves(best) {
+            let shortest = minimize(best, |g| suite.solves(g), config.pool_size, config.generations, rng);
+            println!("{}\tshortest\t{}", suite.name, shortest);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//
+// What a program does, as opposed to how it's written: its outputs on a fixed set of probe inputs.
+// Programs that differ in their code but agree on every probe count as the same behavior, which is
+// what novelty search, behavioral dedup, and MAP-Elites descriptors want to compare.
+//
+
+use super::prog_gene::Runnable;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// The inputs (a, b) every program is probed with. They cover zero, small and larger values, and
+// both signs, so the common arithmetic differences show up.
+pub const PROBES: [(i32, i32); 16] = [
+    (0, 0), (0, 1), (1, 0), (1, 1),
+    (2, 3), (3, 2), (-1, 2), (2, -1),
+    (5, 5), (-4, -7), (7, -4), (10, 3),
+    (3, 10), (-10, 0), (0, -10), (100, 7),
+];
+
+// A program's output on each of the probes, in the order of `PROBES`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Behavior(pub Vec<i32>);
+
+impl Behavior {
+    // Probe a program of any representation
+    pub fn of<P: Runnable + ?Sized>(program: &P) -> Behavior {
+        Behavior(PROBES.iter().map(|&(a, b)| program.run(a, b)).collect())
+    }
+
+    // A compact hash of the outputs, for deduplicating or bucketing behaviors cheaply. Equal
+    // behaviors always hash the same, within one build.
+    pub fn hash64(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.0.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // The number of probes on which two behaviors differ, from 0 to `PROBES.len()`
+    pub fn distance(&self, other: &Behavior) -> usize {
+        self.0.iter().zip(&other.0).filter(|&(x, y)| x != y).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::ProgramGene;
+    use lang::Command;
+    use tree_gene::{Node, TreeGene};
+
+    #[test]
+    fn behavior() {
+        let add: ProgramGene = "+".parse().unwrap();
+        let also_add: ProgramGene = "swap + 0 +".parse().unwrap();
+        let sub: ProgramGene = "-".parse().unwrap();
+        assert_eq!(add.behavior(), also_add.behavior());
+        assert_eq!(add.behavior().hash64(), also_add.behavior().hash64());
+        assert_ne!(add.behavior().hash64(), sub.behavior().hash64());
+        assert_eq!(add.behavior().0[4], 5);
+
+        // a + b and a - b only agree where b is 0
+        let zeros = PROBES.iter().filter(|p| p.1 == 0).count();
+        assert_eq!(add.behavior().distance(&sub.behavior()), PROBES.len() - zeros);
+
+        // Other representations can be compared too
+        let tree = TreeGene(Node::Op(Command::Add, Box::new(Node::A), Box::new(Node::B)));
+        assert_eq!(Behavior::of(&tree), add.behavior());
+    }
+}
@@ -0,0 +1,64 @@
+//
+// Cooperative cancellation, so code embedding the crate can stop a long evolution from another
+// thread and keep the best gene found so far
+//
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// A shared flag asking an evolution to stop. Clones share the same flag, so one clone can be handed
+// to the evolving thread and another kept to cancel it. Cancelling can't be undone.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    // Ask everything holding this token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Pool;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::{SeedableRng, StdRng};
+    use std::thread;
+
+    #[test]
+    fn cancel_evolution() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+
+        // A cancelled generation leaves the pool full and keeps the best gene so far
+        let rng = &mut StdRng::from_seed(&[123]);
+        let mut pool = Pool::new(20, |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g), rng);
+        let best = pool.get_best().1;
+        assert!(!pool.evolve_until(rng, &token));
+        assert_eq!(pool.len(), 20);
+        assert_eq!(pool.get_best().1, best);
+        assert!(!pool.evolve_queued_until(rng, 4, &token));
+        assert_eq!(pool.get_best().1, best);
+
+        // Another thread can stop a run that would otherwise never end. The loop ending is the test.
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            thread::spawn(move || token.cancel())
+        };
+        while pool.evolve_until(rng, &token) {}
+        canceller.join().unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+//
+// Checkpoints of a running evolution, so a long run leaves its population behind
+//
+
+use super::gene::DynPool;
+use super::objective::Objective;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Write the pool as a checkpoint: a header line with the generation, then one line per gene with
+// its fitness and the gene itself, separated by a tab. The best gene comes first.
+pub fn write<W: Write + ?Sized>(pool: &dyn DynPool, generation: usize, w: &mut W) -> io::Result<()> {
+    let mut genes = pool.genes_dyn();
+    genes.sort_by_key(|g| Reverse(Objective(g.1)));
+    write_genes(&genes, generation, w)
+}
+
+// Write genes with their fitness in the checkpoint format, in the order given
+pub fn write_genes<T: fmt::Display, W: Write + ?Sized>(genes: &[(T, f32)], generation: usize, w: &mut W) -> io::Result<()> {
+    writeln!(w, "# generation {}", generation)?;
+    for (gene, fit) in genes {
+        writeln!(w, "{}\t{}", fit, gene)?;
+    }
+    Ok(())
+}
+
+// Save a checkpoint to the given path, compressed if it ends in .zst. See `write_file`.
+pub fn save<P: AsRef<Path>>(pool: &dyn DynPool, generation: usize, path: P) -> io::Result<()> {
+    write_file(path.as_ref(), |w| write(pool, generation, w))
+}
+
+// Saves the checkpoints of a run as deltas. Each one lists only the genes that are new since a full
+// base checkpoint, saved next to it, and refers to the lines of the base for the rest. Most of a
+// pool survives from one checkpoint to the next, so deltas are much smaller than full checkpoints.
+// The base is saved again whenever less than half the pool is in it. `load` reads deltas the same
+// as full checkpoints.
+#[derive(Debug, Default)]
+pub struct DeltaCheckpoints {
+    // The base saved last, and the line of each of its genes by their text
+    base: Option<(PathBuf, HashMap<String, usize>)>,
+}
+
+impl DeltaCheckpoints {
+    pub fn new() -> DeltaCheckpoints {
+        DeltaCheckpoints::default()
+    }
+
+    // Save a delta checkpoint to the given path, and a new base next to it if it's time for one.
+    // The new base has a name of its own, so the last delta stays readable until the new one
+    // replaces it, and the old base is removed after.
+    pub fn save<P: AsRef<Path>>(&mut self, pool: &dyn DynPool, generation: usize, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut genes = pool.genes_dyn();
+        genes.sort_by_key(|g| Reverse(Objective(g.1)));
+        let genes: Vec<(String, f32)> = genes.into_iter().map(|g| (g.0.to_string(), g.1)).collect();
+        let in_base = |index: &HashMap<String, usize>| genes.iter().filter(|g| index.contains_key(&g.0)).count();
+        let stale = self.base.as_ref().is_none_or(|b| in_base(&b.1) * 2 < genes.len());
+        let mut old_base = None;
+        if stale {
+            let base = base_path_for(path, generation);
+            write_file(&base, |w| write_genes(&genes, generation, w))?;
+            let index = genes.iter().enumerate().rev().map(|(i, g)| (g.0.clone(), i)).collect();
+            old_base = self.base.replace((base, index)).map(|b| b.0);
+        }
+        let (base, index) = self.base.as_ref().unwrap();
+        let name = base.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        write_file(path, |w| {
+            writeln!(w, "# generation {}", generation)?;
+            writeln!(w, "# base {}", name)?;
+            for (gene, fit) in &genes {
+                match index.get(gene) {
+                    Some(i) => writeln!(w, "{}\t@{}", fit, i)?,
+                    None => writeln!(w, "{}\t{}", fit, gene)?,
+                }
+            }
+            Ok(())
+        })?;
+        match old_base {
+            Some(ref old) if old != base => fs::remove_file(old),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Where the base saved at the given generation for delta checkpoints at `path` goes, e.g.
+// checkpoint.base40.txt for checkpoint.txt
+pub fn base_path_for<P: AsRef<Path>>(path: P, generation: usize) -> PathBuf {
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => path.with_extension(format!("base{}.{}", generation, ext)),
+        None => path.with_extension(format!("base{}", generation)),
+    }
+}
+
+// Whether files at this path are compressed with zstd when written
+pub fn is_compressed<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension().is_some_and(|e| e == "zst")
+}
+
+// The first bytes of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// Write a file next to the given path and then move it into place, so an interrupted save never
+// leaves a truncated file behind. Paths ending in .zst are compressed with zstd, which needs the
+// `zstd` feature.
+fn write_file<F>(path: &Path, write: F) -> io::Result<()>
+    where F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        if is_compressed(path) {
+            write_compressed(&mut file, write)?;
+        } else {
+            write(&mut file)?;
+        }
+        file.flush()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+#[cfg(feature = "zstd")]
+fn write_compressed<W: Write, F>(out: W, write: F) -> io::Result<()>
+    where F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+    let mut encoder = zstd::Encoder::new(out, 0)?;
+    write(&mut encoder)?;
+    encoder.finish().map(|_| ())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn write_compressed<W: Write, F>(_out: W, _write: F) -> io::Result<()>
+    where F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+    Err(io::Error::other("compressed checkpoints need the zstd feature"))
+}
+
+// Open a file for reading, decompressing it if it starts like a zstd file, whatever its name
+fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut file = io::BufReader::new(fs::File::open(path)?);
+    if file.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+        decompress(file)
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(file: io::BufReader<fs::File>) -> io::Result<Box<dyn BufRead>> {
+    Ok(Box::new(io::BufReader::new(zstd::Decoder::with_buffer(file)?)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_file: io::BufReader<fs::File>) -> io::Result<Box<dyn BufRead>> {
+    Err(io::Error::other("this checkpoint is compressed, which needs the zstd feature"))
+}
+
+// A line of a checkpoint: a gene, or in a delta checkpoint, the line of its base with the same gene
+enum Line<T> {
+    Gene(T),
+    Base(usize),
+}
+
+// The lines of a checkpoint, each with its fitness
+type Lines<T> = Vec<(Line<T>, f32)>;
+
+// Read the lines of a checkpoint, giving its generation, the name of its base if it's a delta, and
+// each gene or reference to the base with its fitness
+fn read_lines<T: FromStr, B: BufRead>(r: B) -> io::Result<(usize, Option<String>, Lines<T>)> {
+    let bad = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad checkpoint line: {}", line));
+    let mut lines = r.lines().peekable();
+    let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+    let generation = header.trim_start_matches("# generation ").parse().map_err(|_| bad(&header))?;
+    let base = match lines.peek() {
+        Some(Ok(line)) if line.starts_with("# base ") => Some(line["# base ".len() ..].to_string()),
+        _ => None,
+    };
+    if base.is_some() {
+        lines.next();
+    }
+    let mut entries = Vec::new();
+    for line in lines {
+        let line = line?;
+        let mut fields = line.splitn(2, '\t');
+        let fit = fields.next().and_then(|f| f.parse().ok()).ok_or_else(|| bad(&line))?;
+        let entry = match fields.next() {
+            Some(r) if base.is_some() && r.starts_with('@') => Line::Base(r[1 ..].parse().map_err(|_| bad(&line))?),
+            Some(g) => Line::Gene(g.parse().map_err(|_| bad(&line))?),
+            None => return Err(bad(&line)),
+        };
+        entries.push((entry, fit));
+    }
+    Ok((generation, base, entries))
+}
+
+// Read a full checkpoint written by `write`, giving its generation and its genes with their
+// recorded fitness. Delta checkpoints need their base, so `load` them instead.
+pub fn read<T: FromStr, B: BufRead>(r: B) -> io::Result<(usize, Vec<(T, f32)>)> {
+    let (generation, base, entries) = read_lines(r)?;
+    if let Some(base) = base {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("a delta checkpoint can only be loaded from a file next to its base {}", base)));
+    }
+    let genes = entries.into_iter().filter_map(|(line, fit)| match line {
+        Line::Gene(gene) => Some((gene, fit)),
+        Line::Base(_) => None,
+    }).collect();
+    Ok((generation, genes))
+}
+
+// Load a checkpoint saved by `save` or `DeltaCheckpoints::save`, compressed or not
+pub fn load<T: FromStr + Clone, P: AsRef<Path>>(path: P) -> io::Result<(usize, Vec<(T, f32)>)> {
+    let path = path.as_ref();
+    let (generation, base, entries) = read_lines(open(path)?)?;
+    let base: Vec<(T, f32)> = match base {
+        Some(name) => read(open(&path.with_file_name(name))?)?.1,
+        None => Vec::new(),
+    };
+    let genes = entries.into_iter().map(|(line, fit)| match line {
+        Line::Gene(gene) => Ok((gene, fit)),
+        Line::Base(i) => base.get(i).map(|b| (b.0.clone(), fit)).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("no line {} in the base checkpoint", i))
+        }),
+    }).collect::<io::Result<_>>()?;
+    Ok((generation, genes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::SeedableRng;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn write_checkpoint() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let pool = gene::new_dyn_pool(10, Box::new(|g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g)), rng);
+        let mut out = Vec::new();
+        write(&*pool, 7, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        // A header, then every gene with the best first
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "# generation 7");
+        let (best, fit) = pool.best_dyn();
+        assert_eq!(lines[1], format!("{}\t{}", fit, best));
+
+        // Stack programs can be read back
+        let (generation, genes) = read::<ProgramGene, _>(out.as_bytes()).unwrap();
+        assert_eq!(generation, 7);
+        assert_eq!(genes.len(), 10);
+        assert_eq!(genes[0].0.to_string(), best.to_string());
+        assert_eq!(genes[0].1, fit);
+        assert!(read::<ProgramGene, _>("# generation 7\n0.5\tsqrt\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn delta_checkpoints() {
+        let dir = env::temp_dir().join(format!("gene_code_delta_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("checkpoint.txt");
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let mut pool = gene::new_dyn_pool(20, Box::new(|g: &ProgramGene| prog_gene::fitness(|a, b| a * b + 1, g)), rng);
+        let mut deltas = DeltaCheckpoints::new();
+
+        // The first save is all references to a new base
+        deltas.save(&*pool, 0, &path).unwrap();
+        let first_base = base_path_for(&path, 0);
+        assert_eq!(first_base, dir.join("checkpoint.base0.txt"));
+        let delta = fs::read_to_string(&path).unwrap();
+        assert!(delta.starts_with("# generation 0\n# base checkpoint.base0.txt\n"));
+        assert!(delta.lines().skip(2).all(|line| line.contains("\t@")));
+
+        // Every save loads the same as a full checkpoint, until the pool has moved on from the base
+        // and a new one replaces it
+        let mut generation = 0;
+        while first_base.exists() {
+            generation += 1;
+            assert!(generation < 100, "never rebased");
+            pool.evolve_dyn(rng);
+            deltas.save(&*pool, generation, &path).unwrap();
+            let mut full = Vec::new();
+            write(&*pool, generation, &mut full).unwrap();
+            let (loaded_at, genes) = load::<ProgramGene, _>(&path).unwrap();
+            assert_eq!((loaded_at, genes), read::<ProgramGene, _>(&full[..]).unwrap());
+        }
+        assert!(base_path_for(&path, generation).exists());
+
+        // A delta can't be read without its base
+        assert!(read::<ProgramGene, _>(fs::File::open(&path).map(io::BufReader::new).unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_checkpoints() {
+        let path = env::temp_dir().join(format!("gene_code_checkpoint_{}.zst", process::id()));
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let pool = gene::new_dyn_pool(10, Box::new(|g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g)), rng);
+        save(&*pool, 3, &path).unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(&ZSTD_MAGIC));
+        let mut full = Vec::new();
+        write(&*pool, 3, &mut full).unwrap();
+        assert_eq!(load::<ProgramGene, _>(&path).unwrap(), read::<ProgramGene, _>(&full[..]).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//
+// Stack programs that keep a compiled form alongside them. Compiling flattens a program into just
+// the progs that run within `MAX_STEPS`, so running it is a straight pass with no command stack to
+// queue. Which progs run after an `if` or `times` depends on the inputs, so programs that branch or
+// loop within their steps aren't compiled, and run as they are. The compiled form is made the first
+// time the program runs and shared between clones, so survivors re-evaluated in later generations
+// don't compile again.
+//
+
+use super::gene::Gene;
+use super::lang::{Command, Prog, Stack, Underflow};
+use super::memory::GeneSize;
+use super::prog_gene::{ProgramConfig, ProgramGene, Runnable, MAX_STEPS};
+
+use rand::Rng;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
+
+// The progs of a program that actually run, in order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Compiled(pub Arc<[Prog]>);
+
+impl Compiled {
+    // Compile a program, or None if it branches or loops within its steps
+    pub fn new(program: &ProgramGene) -> Option<Compiled> {
+        let runs = &program.0[.. program.0.len().min(MAX_STEPS)];
+        if runs.iter().any(|&p| p == Prog::C(Command::If) || p == Prog::C(Command::Times)) {
+            return None;
+        }
+        Some(Compiled(runs.iter().cloned().collect()))
+    }
+
+    // Run on the inputs (a, b) and pop `count` outputs, top of the stack first. This gives the
+    // same outputs as `ProgramGene::run_outputs`.
+    pub fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        let mut s = Stack::new();
+        s.push(a);
+        s.push(b);
+        for &p in self.0.iter() {
+            match p {
+                Prog::D(d) => s.push(d),
+                Prog::C(c) => s.run(c),
+            }
+        }
+        (0 .. count).map(|_| s.pop()).collect()
+    }
+}
+
+// A stack program with its compiled form cached. Equality and hashing only look at the program.
+#[derive(Clone, Debug)]
+pub struct CompiledGene {
+    pub program: ProgramGene,
+    compiled: OnceLock<Option<Compiled>>,
+}
+
+impl CompiledGene {
+    pub fn new(program: ProgramGene) -> CompiledGene {
+        CompiledGene { program, compiled: OnceLock::new() }
+    }
+
+    // The compiled form, compiling it now if this is the first time it's needed, or None if the
+    // program can't be compiled
+    pub fn compiled(&self) -> Option<&Compiled> {
+        self.compiled.get_or_init(|| Compiled::new(&self.program)).as_ref()
+    }
+
+    // Whether the program has been compiled yet, or found not to compile
+    pub fn is_compiled(&self) -> bool {
+        self.compiled.get().is_some()
+    }
+}
+
+impl PartialEq for CompiledGene {
+    fn eq(&self, other: &CompiledGene) -> bool {
+        self.program == other.program
+    }
+}
+
+impl Eq for CompiledGene {}
+
+impl Hash for CompiledGene {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.program.hash(state);
+    }
+}
+
+impl Gene for CompiledGene {
+    type Config = ProgramConfig;
+
+    fn generate<R: Rng>(cfg: &ProgramConfig, rng: &mut R) -> Self {
+        CompiledGene::new(ProgramGene::generate(cfg, rng))
+    }
+
+    fn mutate<R: Rng>(&self, cfg: &ProgramConfig, rng: &mut R) -> Self {
+        CompiledGene::new(self.program.mutate(cfg, rng))
+    }
+
+    fn cross<R: Rng>(&self, cfg: &ProgramConfig, other: &Self, rng: &mut R) -> Self {
+        CompiledGene::new(self.program.cross(cfg, &other.program, rng))
+    }
+
+    fn size(&self) -> usize {
+        self.program.size()
+    }
+
+    // Free the compiled form as soon as the pool is done with the gene, even if clones of it are
+    // still around elsewhere
+    fn on_discarded(&mut self) {
+        self.compiled.take();
+    }
+}
+
+// The compiled form is counted in full, though clones share it
+impl GeneSize for CompiledGene {
+    fn heap_bytes(&self) -> usize {
+        let compiled = self.compiled.get().and_then(Option::as_ref)
+            .map_or(0, |c| c.0.len() * ::std::mem::size_of::<Prog>());
+        self.program.heap_bytes() + compiled
+    }
+}
+
+impl Runnable for CompiledGene {
+    fn run(&self, a: i32, b: i32) -> i32 {
+        self.run_outputs(a, b, 1)[0]
+    }
+
+    fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        match self.compiled() {
+            Some(compiled) => compiled.run_outputs(a, b, count),
+            None => self.program.run_outputs(a, b, count),
+        }
+    }
+
+    // The compiled form only pops 0 from an empty stack, so other policies run the program itself
+    fn run_checked(&self, a: i32, b: i32, count: usize, underflow: Underflow) -> Option<Vec<i32>> {
+        match underflow {
+            Underflow::Zero => Some(self.run_outputs(a, b, count)),
+            _ => self.program.run_checked(a, b, count, underflow),
+        }
+    }
+
+    // The compiled form only runs `MAX_STEPS` steps, so other budgets run the program itself
+    fn run_limited(&self, a: i32, b: i32, count: usize, underflow: Underflow, steps: usize) -> Option<Vec<i32>> {
+        if steps == MAX_STEPS {
+            self.run_checked(a, b, count, underflow)
+        } else {
+            self.program.run_limited(a, b, count, underflow, steps)
+        }
+    }
+}
+
+impl fmt::Display for CompiledGene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.program.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Pool;
+    use prog_gene;
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn compiled_gene() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig { conditionals: true, loops: true, ..ProgramConfig::default() };
+        for _ in 0 .. 200 {
+            // Long programs too, so some run out of steps
+            let other = ProgramGene::generate(cfg, rng);
+            let program = ProgramGene::generate(cfg, rng).cross(cfg, &other, rng).mutate(cfg, rng);
+            let gene = CompiledGene::new(program.clone());
+            assert!(!gene.is_compiled());
+            for &(a, b) in &[(0, 0), (3, -7), (10, 2)] {
+                assert_eq!(gene.run_outputs(a, b, 3), program.run_outputs(a, b, 3));
+            }
+            assert!(gene.is_compiled());
+
+            // Clones share the compiled form, and new genes start without one
+            if let Some(compiled) = gene.compiled() {
+                assert!(Arc::ptr_eq(&gene.clone().compiled().unwrap().0, &compiled.0));
+            }
+            assert!(!gene.mutate(cfg, rng).is_compiled());
+        }
+
+        // Branches and loops run as the interpreter runs them
+        for &(program, output) in &[("0 1 if 7", 3), ("3 1 times 1 +", 2), ("+ 2 *", 10)] {
+            let gene = CompiledGene::new(program.parse().unwrap());
+            assert_eq!(gene.run(2, 3), output, "{}", program);
+            assert_eq!(gene.compiled().is_some(), program == "+ 2 *");
+        }
+
+        // Discarded genes drop their compiled form
+        let fitness = |g: &CompiledGene| prog_gene::score_cases(|a, b| a + b, g.size(), |a, b| g.run(a, b));
+        let mut pool = Pool::new(20, fitness, rng);
+        assert!(pool.iter().all(|g| g.0.is_compiled()));
+        let mut discarded = pool.iter().next().unwrap().0.clone();
+        discarded.on_discarded();
+        assert!(!discarded.is_compiled());
+        pool.evolve(rng);
+        assert_eq!(pool.len(), 20);
+    }
+}
@@ -0,0 +1,129 @@
+//
+// Genes made of other genes. `composite_gene!` declares a struct whose fields are each a gene, along
+// with a config holding each field's config, and implements `Gene` for it: a mutation changes one
+// field chosen at random, and crossover crosses each field with the same field of the other
+// parent. For example
+//
+//     composite_gene! {
+//         #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+//         pub struct Strategy {
+//             pub opening: ProgramGene,
+//             pub closing: TreeGene,
+//         }
+//         #[derive(Clone, Default)]
+//         pub config StrategyConfig;
+//     }
+//
+// makes `Strategy` and `StrategyConfig { opening: ProgramConfig, closing: TreeConfig }`. The fields
+// must be `Clone`. A strategy's size is the sum of its fields' sizes.
+//
+
+#[macro_export]
+macro_rules! composite_gene {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident : $ty:ty),+ $(,)*
+        }
+        $(#[$config_meta:meta])*
+        $config_vis:vis config $config:ident;
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty),+
+        }
+
+        $(#[$config_meta])*
+        $config_vis struct $config {
+            $($field_vis $field: <$ty as $crate::gene::Gene>::Config),+
+        }
+
+        impl $crate::gene::Gene for $name {
+            type Config = $config;
+
+            fn generate<R: ::rand::Rng>(cfg: &$config, rng: &mut R) -> Self {
+                $name { $($field: <$ty as $crate::gene::Gene>::generate(&cfg.$field, rng)),+ }
+            }
+
+            // Mutate one field, each as likely as the others
+            fn mutate<R: ::rand::Rng>(&self, cfg: &$config, rng: &mut R) -> Self {
+                let fields = [$(stringify!($field)),+].len();
+                let chosen = ::rand::Rng::gen_range(rng, 0, fields);
+                let mut index = 0 ..;
+                $name {
+                    $($field: if index.next() == Some(chosen) {
+                        $crate::gene::Gene::mutate(&self.$field, &cfg.$field, rng)
+                    } else {
+                        self.$field.clone()
+                    }),+
+                }
+            }
+
+            // Cross each field with the same field of the other parent
+            fn cross<R: ::rand::Rng>(&self, cfg: &$config, other: &Self, rng: &mut R) -> Self {
+                $name { $($field: $crate::gene::Gene::cross(&self.$field, &cfg.$field, &other.$field, rng)),+ }
+            }
+
+            fn size(&self) -> usize {
+                0 $(+ $crate::gene::Gene::size(&self.$field))+
+            }
+
+            fn on_created(&mut self) {
+                $($crate::gene::Gene::on_created(&mut self.$field);)+
+            }
+
+            fn on_selected(&mut self) {
+                $($crate::gene::Gene::on_selected(&mut self.$field);)+
+            }
+
+            fn on_discarded(&mut self) {
+                $($crate::gene::Gene::on_discarded(&mut self.$field);)+
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use gene::{Gene, Pool};
+    use prog_gene::{self, ProgramGene, Runnable};
+    use tree_gene::TreeGene;
+    use ::rand::{SeedableRng, StdRng};
+
+    composite_gene! {
+        #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+        pub struct Pair {
+            pub stack: ProgramGene,
+            pub tree: TreeGene,
+        }
+        #[derive(Clone, Default)]
+        pub config PairConfig;
+    }
+
+    #[test]
+    fn composite_genes() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let cfg = PairConfig::default();
+        let a = Pair::generate(&cfg, rng);
+        let b = Pair::generate(&cfg, rng);
+        assert_eq!(a.size(), a.stack.size() + a.tree.size());
+
+        // A mutation changes at most one field
+        for _ in 0 .. 50 {
+            let m = a.mutate(&cfg, rng);
+            assert!(m.stack == a.stack || m.tree == a.tree);
+        }
+
+        // Both fields evolve, each against its own half of the target
+        let fitness = |p: &Pair| {
+            prog_gene::score(|a, b| a + b, &p.stack) + prog_gene::score_cases(|a, b| a * b, p.tree.size(), |a, b| p.tree.run(a, b))
+        };
+        let mut pool = Pool::from_genes(vec![a, b], fitness);
+        pool.resize(50, rng);
+        let start = pool.get_best().1;
+        for _ in 0 .. 30 {
+            pool.evolve(rng);
+        }
+        assert!(pool.get_best().1 > start);
+    }
+}
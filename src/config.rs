@@ -0,0 +1,758 @@
+//
+// Everything that configures a run, in one place. A config round-trips through TOML, so the same
+// file can start a run, be saved next to its results, and be compared when a checkpoint is resumed.
+//
+
+use super::checkpoint;
+use super::lang::Underflow;
+use super::plateau::HyperMutation;
+use super::prog_gene::PARSIMONY;
+use super::schedule::Schedule;
+use super::suite::TestSuite;
+use super::team::Aggregate;
+use super::warm_up::WarmUp;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+// The program representations a run can evolve
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Repr {
+    Stack,
+    Tree,
+    Register,
+}
+
+impl Repr {
+    pub fn parse(s: &str) -> Option<Repr> {
+        match s {
+            "stack" => Some(Repr::Stack),
+            "tree" => Some(Repr::Tree),
+            "register" => Some(Repr::Register),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Repr::Stack => "stack",
+            Repr::Tree => "tree",
+            Repr::Register => "register",
+        }
+    }
+}
+
+// How each generation is made from the last
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    // Select, cross, and mutate genes
+    Genetic,
+    // Sample programs from a model learned from the most fit ones. Only for stack programs. See
+    // src/eda.rs.
+    Eda,
+}
+
+// How parents are crossed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Crossover {
+    // Splice the parents at random cut points
+    Cut,
+    // Align the parents and exchange the regions where they differ. Only for stack programs.
+    Aligned,
+    // Splice the parents where their stacks are most alike on the probe inputs. Only for stack
+    // programs.
+    Homologous,
+}
+
+// How survivors are chosen each generation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    // At random, weighted by fitness
+    Proportionate,
+    // The most fit `operators.truncation_fraction` of the pool
+    Truncation,
+    // Tournaments between pairs of genes at `operators.boltzmann_temperature`
+    Boltzmann,
+    // Tournaments of `operators.tournament_size` genes, which the fittest wins
+    Tournament,
+    // At random, weighted by rank
+    Rank,
+    // Stochastic universal sampling: one spin of evenly spaced pointers, weighted by fitness
+    #[serde(rename = "sus")]
+    StochasticUniversal,
+}
+
+// What the genes that don't survive are replaced with each generation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Offspring {
+    // Crosses, mutants, and fresh genes in equal parts
+    #[default]
+    Mixed,
+    // Only mutants of the survivors
+    Mutation,
+    // Only crosses of the survivors
+    Crossover,
+}
+
+// Which gene of the final pool a run reports as its answer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Champion {
+    // The most fit
+    #[default]
+    Fitness,
+    // The best on the `harness.holdout` cases, and then the shortest
+    Validation,
+}
+
+// One ready-made suite of a multitask run, and how much it counts
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskWeight {
+    pub suite: String,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+// Parse a task from "SUITE:WEIGHT", e.g. "sign:2", or just "SUITE" for a weight of 1
+impl FromStr for TaskWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TaskWeight, String> {
+        let mut parts = s.splitn(2, ':');
+        let suite = parts.next().unwrap_or("").to_string();
+        let weight = match parts.next() {
+            Some(w) => w.parse().map_err(|_| format!("bad weight in task: {}", s))?,
+            None => default_weight(),
+        };
+        Ok(TaskWeight { suite, weight })
+    }
+}
+
+// The whole configuration of a run. Missing keys take their defaults, and unknown keys are errors.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    // The RNG seed. A random seed is chosen if this isn't given.
+    pub seed: Option<u64>,
+    pub pool: PoolConfig,
+    pub operators: OperatorConfig,
+    pub harness: HarnessConfig,
+    pub termination: TerminationConfig,
+    pub logging: LoggingConfig,
+}
+
+// What is evolved, and where the first generation comes from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub size: usize,
+    pub repr: Repr,
+    // Let stack programs use the frame commands, pushframe and popframe. See
+    // `lang::Command::PushFrame`.
+    pub frames: bool,
+    // Let stack programs use the comparisons, not, and if, so they can branch. See
+    // `lang::Command::If`.
+    pub conditionals: bool,
+    // Let stack programs use times, so they can loop. See `lang::Command::Times`.
+    pub loops: bool,
+    pub engine: Engine,
+    // Evolve teams of programs combined this way, instead of single programs
+    pub team: Option<Aggregate>,
+    // A checkpoint to continue from
+    pub resume: Option<String>,
+    // Resume even if the checkpoint's manifest doesn't match this run
+    pub force: bool,
+    // Explore with a larger pool and heavier mutation for the first generations
+    pub warm_up: Option<WarmUp>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig {
+            size: 100,
+            repr: Repr::Stack,
+            frames: false,
+            conditionals: false,
+            loops: false,
+            engine: Engine::Genetic,
+            team: None,
+            resume: None,
+            force: false,
+            warm_up: None,
+        }
+    }
+}
+
+// How genes are chosen and refined
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OperatorConfig {
+    // The weight of shortness in selection, over the generations
+    pub parsimony: Schedule,
+    pub selection: Selection,
+    // The fraction of the pool truncation selection keeps, from 0 to 1
+    pub truncation_fraction: f32,
+    // The temperature of Boltzmann tournaments over the generations, at least 0
+    pub boltzmann_temperature: Schedule,
+    // The number of genes in each tournament of tournament selection, at least 1
+    pub tournament_size: usize,
+    // The number of most fit genes carried into each next generation unchanged
+    pub elitism: usize,
+    // Genes that have survived fewer generations than this survive before any but the elites, and
+    // genes that have survived `retire_age` generations never do. See `gene::AgeLimits`.
+    pub protect_age: usize,
+    pub retire_age: Option<usize>,
+    // The fraction of each generation that survives, above 0 and at most 1, whatever the selection.
+    // None keeps a fourth, or `truncation_fraction` for truncation selection.
+    pub survivors: Option<f32>,
+    // What replaces the rest of the pool. See `gene::Proportions`.
+    pub offspring: Offspring,
+    // Breed each gene as a cross with this chance, or else a copy of its parent, and then mutate it
+    // with `mutation_rate`'s chance, from 0 to 1. The two go together. See `gene::Pool::set_rates`.
+    pub crossover_rate: Option<f32>,
+    pub mutation_rate: Option<f32>,
+    pub crossover: Crossover,
+    // Each generation, bias new progs toward those common among the most fit programs, at this
+    // temperature. See `prog_gene::ProgWeights::learn`.
+    pub learn_temperature: Option<f32>,
+    // The chance that a mutation reverses or moves a run of progs instead of editing single progs,
+    // from 0 to 1. See `prog_gene::ProgramConfig::structural`.
+    pub structural_mutation: f32,
+    // Make mutations of stack programs edit the progs that run in the failing cases but not in the
+    // passing ones more often. See `localize`.
+    pub localize: bool,
+    // Give each stack program its own mutation rate and edit weights, inherited and mutated along
+    // with it. See `prog_gene::AdaptiveGene`.
+    pub self_adaptive: bool,
+    // Mutate harder and bring in new random genes while the run is stuck on a plateau
+    pub hyper_mutation: Option<HyperMutation>,
+    // How far the model of the EDA engine moves toward each generation's most fit programs, from 0
+    // to 1
+    pub eda_rate: f32,
+    // Spend this many generations shrinking the best program after the run, if it's correct
+    pub minimize: Option<usize>,
+}
+
+impl Default for OperatorConfig {
+    fn default() -> OperatorConfig {
+        OperatorConfig {
+            parsimony: Schedule::Constant(PARSIMONY),
+            selection: Selection::Proportionate,
+            truncation_fraction: 0.25,
+            boltzmann_temperature: Schedule::Exponential { from: 10.0, to: 0.01, over: 500 },
+            tournament_size: 7,
+            elitism: 0,
+            protect_age: 0,
+            retire_age: None,
+            survivors: None,
+            offspring: Offspring::Mixed,
+            crossover_rate: None,
+            mutation_rate: None,
+            crossover: Crossover::Cut,
+            learn_temperature: None,
+            structural_mutation: 0.0,
+            localize: false,
+            self_adaptive: false,
+            hyper_mutation: None,
+            eda_rate: 0.5,
+            minimize: None,
+        }
+    }
+}
+
+// What programs are tested against
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HarnessConfig {
+    // The name of a ready-made test suite. Without one, programs learn the built-in target function.
+    pub suite: Option<String>,
+    // Give this fitness to programs the fitness function panics on, instead of ending the run
+    pub panic_penalty: Option<f32>,
+    // A file of fitness scores to warm-start from, and to save the run's scores to. It is only used
+    // if it was scored against the same suite and representation.
+    pub fitness_cache: Option<String>,
+    // Hold out this fraction of the suite's cases, between 0 and 1, and score the best program on
+    // them each generation to show overfitting. They aren't used for fitness.
+    pub holdout: Option<f32>,
+    // How the program the run reports is chosen
+    pub champion: Champion,
+    // After the run, compare the median of this many of the best distinct programs with the best
+    // one alone on the `harness.holdout` cases
+    pub ensemble: Option<usize>,
+    // What popping an empty stack gives while stack programs are scored: zero, repeat, or error
+    pub underflow: Underflow,
+    // Score single programs on all of these ready-made suites at once, instead of one. See
+    // `TestSuite::multitask`.
+    pub tasks: Vec<TaskWeight>,
+}
+
+// When the run stops
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminationConfig {
+    // The number of generations to run, not counting any before a resume
+    pub generations: usize,
+    // Stop early once the best fitness reaches this
+    pub target_fitness: Option<f32>,
+}
+
+impl Default for TerminationConfig {
+    fn default() -> TerminationConfig {
+        TerminationConfig { generations: 1000, target_fitness: None }
+    }
+}
+
+// What the run reports and saves along the way
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // Only print a line when the best fitness improves
+    pub watch: bool,
+    // Print nothing about the run but warnings, leaving the results to the `RunReport`
+    pub quiet: bool,
+    // Where to write the run's artifacts
+    pub out_dir: Option<String>,
+    // Where to save checkpoints
+    pub checkpoint: String,
+    // Save a checkpoint every this many generations
+    pub checkpoint_every: Option<usize>,
+    // Save checkpoints as deltas from a base checkpoint saved next to them
+    pub checkpoint_delta: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> LoggingConfig {
+        LoggingConfig { watch: false, quiet: false, out_dir: None, checkpoint: "checkpoint.txt".to_string(), checkpoint_every: None,
+                        checkpoint_delta: false }
+    }
+}
+
+impl RunConfig {
+    // Parse a config from TOML. This only checks the syntax and types; see `validate`.
+    pub fn from_toml(s: &str) -> Result<RunConfig, String> {
+        toml::from_str(s).map_err(|e| format!("bad config: {}", e))
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("configs are always representable as TOML")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<RunConfig, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read config {}: {}", path.display(), e))?;
+        RunConfig::from_toml(&text)
+    }
+
+    // Check that the settings make sense together, listing every problem found
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.pool.size == 0 {
+            problems.push("pool.size must be at least 1".to_string());
+        }
+        let single_stack = self.pool.repr == Repr::Stack && self.pool.team.is_none();
+        if self.pool.resume.is_some() && !single_stack {
+            problems.push("pool.resume only works for stack programs without teams".to_string());
+        }
+        if self.pool.force && self.pool.resume.is_none() {
+            problems.push("pool.force only applies with pool.resume".to_string());
+        }
+        if self.pool.frames && (!single_stack || self.pool.engine == Engine::Eda || self.operators.learn_temperature.is_some()) {
+            problems.push("pool.frames only works for stack programs of the genetic engine without teams or \
+                           operators.learn_temperature".to_string());
+        }
+        for &(name, on) in &[("conditionals", self.pool.conditionals), ("loops", self.pool.loops)] {
+            if on && (!single_stack || self.pool.engine == Engine::Eda || self.operators.learn_temperature.is_some()) {
+                problems.push(format!("pool.{} only works for stack programs of the genetic engine without teams or \
+                                       operators.learn_temperature", name));
+            }
+        }
+        if let Some(warm_up) = self.pool.warm_up {
+            if warm_up.generations == 0 || warm_up.mutations == 0 {
+                problems.push(format!("pool.warm_up needs at least 1 generation and mutation: {}", warm_up));
+            }
+            if warm_up.size < self.pool.size {
+                problems.push(format!("pool.warm_up.size must be at least pool.size: {}", warm_up.size));
+            }
+            if self.pool.engine == Engine::Eda {
+                problems.push("pool.warm_up only works with pool.engine genetic".to_string());
+            }
+        }
+        if self.pool.engine == Engine::Eda {
+            if !single_stack {
+                problems.push("pool.engine eda only works for stack programs without teams".to_string());
+            }
+            if self.pool.resume.is_some() {
+                problems.push("pool.engine eda can't resume from a checkpoint".to_string());
+            }
+            if self.operators.crossover != Crossover::Cut || self.operators.learn_temperature.is_some() {
+                problems.push("pool.engine eda doesn't use operators.crossover or operators.learn_temperature".to_string());
+            }
+            if !(self.operators.eda_rate > 0.0 && self.operators.eda_rate <= 1.0) {
+                problems.push(format!("operators.eda_rate must be above 0 and at most 1: {}", self.operators.eda_rate));
+            }
+            if self.operators.selection != Selection::Proportionate || self.operators.elitism > 0 {
+                problems.push("pool.engine eda doesn't use operators.selection or operators.elitism".to_string());
+            }
+            if self.operators.crossover_rate.is_some() || self.operators.mutation_rate.is_some() {
+                problems.push("pool.engine eda doesn't use operators.crossover_rate or operators.mutation_rate".to_string());
+            }
+            if self.operators.protect_age > 0 || self.operators.retire_age.is_some() {
+                problems.push("pool.engine eda doesn't use operators.protect_age or operators.retire_age".to_string());
+            }
+            if self.operators.survivors.is_some() || self.operators.offspring != Offspring::Mixed {
+                problems.push("pool.engine eda doesn't use operators.survivors or operators.offspring".to_string());
+            }
+        }
+        if !(self.operators.truncation_fraction > 0.0 && self.operators.truncation_fraction <= 1.0) {
+            problems.push(format!("operators.truncation_fraction must be above 0 and at most 1: {}",
+                                  self.operators.truncation_fraction));
+        }
+        if self.operators.tournament_size == 0 {
+            problems.push("operators.tournament_size must be at least 1".to_string());
+        }
+        if let Some(retire) = self.operators.retire_age {
+            if retire == 0 {
+                problems.push("operators.retire_age must be at least 1".to_string());
+            } else if self.operators.protect_age >= retire {
+                problems.push(format!("operators.protect_age must be below operators.retire_age: {} and {}",
+                                      self.operators.protect_age, retire));
+            }
+        }
+        if let Some(survivors) = self.operators.survivors {
+            if !(survivors > 0.0 && survivors <= 1.0) {
+                problems.push(format!("operators.survivors must be above 0 and at most 1: {}", survivors));
+            }
+        }
+        if self.operators.offspring != Offspring::Mixed && self.operators.crossover_rate.is_some() {
+            problems.push("operators.offspring can't be used with operators.crossover_rate and operators.mutation_rate, \
+                           which decide how each gene is bred".to_string());
+        }
+        match (self.operators.crossover_rate, self.operators.mutation_rate) {
+            (Some(crossover), Some(mutation)) => {
+                for &(name, rate) in &[("crossover_rate", crossover), ("mutation_rate", mutation)] {
+                    if !(0.0 ..= 1.0).contains(&rate) {
+                        problems.push(format!("operators.{} must be from 0 to 1: {}", name, rate));
+                    }
+                }
+            }
+            (None, None) => {}
+            _ => problems.push("operators.crossover_rate and operators.mutation_rate go together".to_string()),
+        }
+
+        if !schedule_values(&self.operators.parsimony).iter().all(|x| (0.0 ..= 1.0).contains(x)) {
+            problems.push(format!("operators.parsimony must stay between 0 and 1: {}", self.operators.parsimony));
+        }
+        if !schedule_values(&self.operators.boltzmann_temperature).iter().all(|&x| x >= 0.0) {
+            problems.push(format!("operators.boltzmann_temperature must stay at least 0: {}",
+                                  self.operators.boltzmann_temperature));
+        }
+        if self.operators.crossover != Crossover::Cut && self.pool.repr != Repr::Stack {
+            problems.push("operators.crossover aligned and homologous only work for stack programs".to_string());
+        }
+        if let Some(t) = self.operators.learn_temperature {
+            if !(t > 0.0 && t.is_finite()) {
+                problems.push(format!("operators.learn_temperature must be positive: {}", t));
+            }
+            if !single_stack {
+                problems.push("operators.learn_temperature only works for stack programs without teams".to_string());
+            }
+        }
+        if !(0.0 ..= 1.0).contains(&self.operators.structural_mutation) {
+            problems.push(format!("operators.structural_mutation must be between 0 and 1: {}",
+                                  self.operators.structural_mutation));
+        }
+        if self.operators.structural_mutation > 0.0 && (self.pool.repr != Repr::Stack || self.pool.engine == Engine::Eda) {
+            problems.push("operators.structural_mutation only works for stack programs of the genetic engine".to_string());
+        }
+        if self.operators.structural_mutation > 0.0 && self.pool.frames {
+            problems.push("operators.structural_mutation can't be used with pool.frames, since it doesn't keep frames whole"
+                          .to_string());
+        }
+        if self.operators.localize && (!single_stack || self.pool.engine == Engine::Eda || self.operators.self_adaptive) {
+            problems.push("operators.localize only works for stack programs of the genetic engine without teams or \
+                           operators.self_adaptive".to_string());
+        }
+        if self.operators.self_adaptive
+            && (self.pool.repr != Repr::Stack || self.pool.engine == Engine::Eda || self.operators.crossover != Crossover::Cut)
+        {
+            problems.push("operators.self_adaptive only works for stack programs of the genetic engine with \
+                           operators.crossover cut".to_string());
+        }
+        if let Some(hyper) = self.operators.hyper_mutation {
+            if hyper.window == 0 || hyper.generations == 0 || hyper.mutations == 0 {
+                problems.push(format!("operators.hyper_mutation needs at least 1 generation and mutation: {}", hyper));
+            }
+            if !(hyper.tolerance >= 0.0 && hyper.tolerance.is_finite()) {
+                problems.push(format!("operators.hyper_mutation.tolerance must be at least 0: {}", hyper.tolerance));
+            }
+            if self.pool.engine == Engine::Eda {
+                problems.push("operators.hyper_mutation only works with pool.engine genetic".to_string());
+            }
+            if self.pool.warm_up.is_some() {
+                problems.push("operators.hyper_mutation and pool.warm_up can't both set the mutations".to_string());
+            }
+        }
+        if self.operators.minimize.is_some() && !single_stack {
+            problems.push("operators.minimize only works for stack programs without teams".to_string());
+        }
+
+        if let Some(ref name) = self.harness.suite {
+            if TestSuite::by_name(name).is_none() {
+                let names: Vec<_> = TestSuite::all().iter().map(|s| s.name).collect();
+                problems.push(format!("harness.suite {} is unknown; the suites are {}", name, names.join(", ")));
+            }
+            if self.pool.team.is_some() {
+                problems.push("harness.suite can't be used with teams".to_string());
+            }
+        }
+
+        if !self.harness.tasks.is_empty() {
+            if self.harness.suite.is_some() {
+                problems.push("harness.tasks and harness.suite can't both be set".to_string());
+            }
+            for task in &self.harness.tasks {
+                if TestSuite::by_name(&task.suite).is_none() {
+                    problems.push(format!("harness.tasks suite {} is unknown", task.suite));
+                }
+                if !(task.weight > 0.0 && task.weight.is_finite()) {
+                    problems.push(format!("harness.tasks weight must be positive: {}", task.weight));
+                }
+            }
+            if self.pool.team.is_some() {
+                problems.push("harness.tasks can't be used with teams".to_string());
+            }
+        }
+
+        if let Some(fraction) = self.harness.holdout {
+            if self.harness.suite.is_none() {
+                problems.push("harness.holdout needs a harness.suite".to_string());
+            }
+            if !(fraction > 0.0 && fraction < 1.0) {
+                problems.push("harness.holdout must be between 0 and 1".to_string());
+            }
+        } else {
+            if self.harness.champion == Champion::Validation {
+                problems.push("harness.champion validation needs a harness.holdout".to_string());
+            }
+            if self.harness.ensemble.is_some() {
+                problems.push("harness.ensemble needs a harness.holdout".to_string());
+            }
+        }
+        if self.harness.ensemble == Some(0) {
+            problems.push("harness.ensemble must be at least 1".to_string());
+        }
+
+        if self.harness.underflow != Underflow::Zero && !single_stack {
+            problems.push("harness.underflow only works for stack programs without teams".to_string());
+        }
+
+        if let Some(penalty) = self.harness.panic_penalty {
+            if penalty.is_nan() {
+                problems.push("harness.panic_penalty must be a number".to_string());
+            }
+        }
+
+        if let Some(target) = self.termination.target_fitness {
+            if target.is_nan() {
+                problems.push("termination.target_fitness must be a number".to_string());
+            }
+        }
+
+        if self.harness.fitness_cache.as_ref().is_some_and(|path| path.is_empty()) {
+            problems.push("harness.fitness_cache must not be empty".to_string());
+        }
+        if self.logging.checkpoint.is_empty() {
+            problems.push("logging.checkpoint must not be empty".to_string());
+        }
+        if self.logging.checkpoint_every == Some(0) {
+            problems.push("logging.checkpoint_every must be at least 1".to_string());
+        }
+        if checkpoint::is_compressed(&self.logging.checkpoint) && !cfg!(feature = "zstd") {
+            problems.push("logging.checkpoint ending in .zst needs the zstd feature".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+// The values a schedule starts, ends, or steps at. It stays between the smallest and largest.
+fn schedule_values(schedule: &Schedule) -> Vec<f32> {
+    match *schedule {
+        Schedule::Constant(x) => vec![x],
+        Schedule::Linear { from, to, .. } | Schedule::Exponential { from, to, .. } => vec![from, to],
+        Schedule::Step(ref steps) => steps.iter().map(|s| s.1).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut config = RunConfig::default();
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(RunConfig::from_toml(&config.to_toml()), Ok(config.clone()));
+
+        config.seed = Some(7);
+        config.pool.repr = Repr::Tree;
+        config.pool.engine = Engine::Eda;
+        config.pool.team = Some(Aggregate::Vote);
+        config.operators.parsimony = "linear:0.1:0.01:500".parse().unwrap();
+        config.operators.crossover = Crossover::Aligned;
+        config.termination.target_fitness = Some(0.99);
+        config.logging.out_dir = Some("runs/a".to_string());
+        config.pool.warm_up = Some(WarmUp { generations: 20, size: 1000, mutations: 3 });
+        config.operators.hyper_mutation = Some("30:10:4:20:0.001".parse().unwrap());
+        assert_eq!(RunConfig::from_toml(&config.to_toml()), Ok(config));
+
+        // Anything left out takes its default, and a constant parsimony can be a bare number
+        let config = RunConfig::from_toml("[pool]\nrepr = \"register\"\n[operators]\nparsimony = 0.5\n").unwrap();
+        assert_eq!(config.pool.repr, Repr::Register);
+        assert_eq!(config.pool.size, 100);
+        assert_eq!(config.operators.parsimony, Schedule::Constant(0.5));
+        assert_eq!(config.termination, TerminationConfig::default());
+
+        assert!(RunConfig::from_toml("[pool]\nsise = 10\n").is_err());
+        assert!(RunConfig::from_toml("[pool]\nrepr = \"lisp\"\n").is_err());
+        assert!(RunConfig::from_toml("[operators]\nparsimony = \"exp:0:1:10\"\n").is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let mut config = RunConfig::default();
+        config.pool.size = 0;
+        config.pool.repr = Repr::Tree;
+        config.pool.team = Some(Aggregate::Median);
+        config.pool.force = true;
+        config.operators.parsimony = Schedule::Step(vec![(0, 0.5), (10, 2.0)]);
+        config.operators.crossover = Crossover::Aligned;
+        config.operators.learn_temperature = Some(0.0);
+        config.operators.minimize = Some(10);
+        config.harness.suite = Some("fib".to_string());
+        config.logging.checkpoint_every = Some(0);
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 10);
+        assert!(problems[0].starts_with("pool.size"));
+        assert!(problems.iter().any(|p| p.contains("harness.suite fib is unknown")));
+
+        let mut config = RunConfig::default();
+        config.harness.suite = Some("sort-two".to_string());
+        config.pool.resume = Some("checkpoint.txt".to_string());
+        config.operators.learn_temperature = Some(2.0);
+        config.operators.minimize = Some(10);
+        config.harness.underflow = Underflow::Error;
+        assert_eq!(config.validate(), Ok(()));
+        config.pool.repr = Repr::Tree;
+        assert!(config.validate().unwrap_err().iter().any(|p| p.starts_with("harness.underflow")));
+        config.pool.repr = Repr::Stack;
+        config.logging.checkpoint = "checkpoint.zst".to_string();
+        assert_eq!(config.validate().is_ok(), cfg!(feature = "zstd"));
+
+        let mut config = RunConfig::default();
+        config.harness.tasks = vec!["sort-two".parse().unwrap(), "sign:3".parse().unwrap()];
+        assert_eq!(config.harness.tasks[1], TaskWeight { suite: "sign".to_string(), weight: 3.0 });
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(RunConfig::from_toml(&config.to_toml()), Ok(config.clone()));
+        config.harness.suite = Some("sign".to_string());
+        config.harness.tasks.push("fib:0".parse().unwrap());
+        assert_eq!(config.validate().unwrap_err().len(), 3);
+        assert!("sign:x".parse::<TaskWeight>().is_err());
+
+        let mut config = RunConfig::default();
+        config.operators.hyper_mutation = Some("30:10:4:20".parse().unwrap());
+        assert_eq!(config.validate(), Ok(()));
+        config.operators.hyper_mutation = Some("0:10:4:20:-1".parse().unwrap());
+        config.pool.warm_up = Some(WarmUp { generations: 20, size: 1000, mutations: 3 });
+        assert_eq!(config.validate().unwrap_err().len(), 3);
+
+        let mut config = RunConfig::default();
+        config.pool.engine = Engine::Eda;
+        assert_eq!(config.validate(), Ok(()));
+        config.operators.eda_rate = 0.0;
+        config.operators.crossover = Crossover::Aligned;
+        config.operators.selection = Selection::Truncation;
+        config.operators.truncation_fraction = 1.5;
+        config.operators.boltzmann_temperature = Schedule::Constant(-1.0);
+        config.pool.warm_up = Some(WarmUp { generations: 0, size: 10, mutations: 2 });
+        config.harness.holdout = Some(1.0);
+        assert_eq!(config.validate().unwrap_err().len(), 10);
+        config.harness.holdout = None;
+        config.harness.champion = Champion::Validation;
+        config.harness.ensemble = Some(0);
+        config.operators.structural_mutation = 1.5;
+        assert_eq!(config.validate().unwrap_err().len(), 13);
+        config.operators.self_adaptive = true;
+        assert_eq!(config.validate().unwrap_err().len(), 14);
+
+        let mut config = RunConfig::default();
+        config.operators.selection = Selection::Tournament;
+        assert_eq!(config.validate(), Ok(()));
+        config.operators.tournament_size = 0;
+        assert_eq!(config.validate().unwrap_err(), vec!["operators.tournament_size must be at least 1".to_string()]);
+        config.operators.tournament_size = 7;
+        config.operators.crossover_rate = Some(0.9);
+        assert_eq!(config.validate().unwrap_err(),
+                   vec!["operators.crossover_rate and operators.mutation_rate go together".to_string()]);
+        config.operators.mutation_rate = Some(1.5);
+        assert_eq!(config.validate().unwrap_err(), vec!["operators.mutation_rate must be from 0 to 1: 1.5".to_string()]);
+        config.operators.mutation_rate = Some(0.1);
+        assert_eq!(config.validate(), Ok(()));
+        config.operators.offspring = Offspring::Mutation;
+        assert!(config.validate().unwrap_err()[0].starts_with("operators.offspring can't be used"));
+        config.operators.crossover_rate = None;
+        config.operators.mutation_rate = None;
+        config.operators.survivors = Some(0.0);
+        assert_eq!(config.validate().unwrap_err(), vec!["operators.survivors must be above 0 and at most 1: 0".to_string()]);
+        config.operators.survivors = Some(0.5);
+        assert_eq!(config.validate(), Ok(()));
+        config.operators.protect_age = 3;
+        config.operators.retire_age = Some(3);
+        assert_eq!(config.validate().unwrap_err(),
+                   vec!["operators.protect_age must be below operators.retire_age: 3 and 3".to_string()]);
+        config.operators.retire_age = Some(0);
+        assert_eq!(config.validate().unwrap_err(), vec!["operators.retire_age must be at least 1".to_string()]);
+        config.operators.retire_age = Some(50);
+        assert_eq!(config.validate(), Ok(()));
+
+        let mut config = RunConfig::default();
+        config.operators.self_adaptive = true;
+        config.pool.team = Some(Aggregate::Vote);
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(RunConfig::from_toml(&config.to_toml()), Ok(config.clone()));
+        config.pool.frames = true;
+        assert!(config.validate().unwrap_err()[0].starts_with("pool.frames"));
+        config.pool.team = None;
+        assert_eq!(config.validate(), Ok(()));
+        config.operators.structural_mutation = 0.1;
+        assert!(config.validate().unwrap_err()[0].contains("keep frames whole"));
+        config.pool.frames = false;
+        config.pool.conditionals = true;
+        assert_eq!(config.validate(), Ok(()));
+        config.pool.loops = true;
+        assert_eq!(config.validate(), Ok(()));
+        config.operators.localize = true;
+        assert!(config.validate().unwrap_err()[0].starts_with("operators.localize"));
+        config.operators.self_adaptive = false;
+        assert_eq!(config.validate(), Ok(()));
+        config.pool.engine = Engine::Eda;
+        assert!(config.validate().unwrap_err().iter().any(|p| p.starts_with("pool.conditionals")));
+        assert!(config.validate().unwrap_err().iter().any(|p| p.starts_with("pool.loops")));
+    }
+}
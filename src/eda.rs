@@ -0,0 +1,174 @@
+//
+// An estimation-of-distribution engine for stack programs, in the style of PBIL. Instead of
+// crossing and mutating genes, it keeps a probabilistic model of good programs: a distribution over
+// lengths, and one over progs for each position. Every generation the model moves toward the
+// programs of the most fit quarter, and the rest of the pool is replaced by samples from it.
+//
+
+use super::gene::{self, DynGene, DynPool, Fitness};
+use super::objective::Objective;
+use super::prog_gene::{ProgWeights, ProgramGene, MAX_STEPS};
+
+use rand::Rng;
+use std::cmp::Reverse;
+
+// A model of programs where each position is chosen independently
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionModel {
+    // The weight of each length, from 1 to `MAX_STEPS`. Longer programs could never run to the end.
+    lengths: Vec<f32>,
+    // The weights of the progs at each position
+    positions: Vec<ProgWeights>,
+}
+
+impl PositionModel {
+    // The unbiased model, which samples programs much like `ProgramGene::generate`
+    pub fn new() -> PositionModel {
+        PositionModel { lengths: vec![1.0; MAX_STEPS], positions: vec![ProgWeights::base(); MAX_STEPS] }
+    }
+
+    // Sample a program from the model
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> ProgramGene {
+        let total: f32 = self.lengths.iter().sum();
+        let mut f = rng.gen_range(0.0, total);
+        let mut len = self.lengths.len();
+        for (i, &w) in self.lengths.iter().enumerate() {
+            if f < w {
+                len = i + 1;
+                break;
+            }
+            f -= w;
+        }
+        ProgramGene(self.positions[.. len].iter().map(|w| w.sample(rng)).collect())
+    }
+
+    // Move the model toward the given programs by `rate`, from 0 (ignore them) to 1 (forget the
+    // old model). Does nothing if there are no programs.
+    pub fn update(&mut self, elite: &[&ProgramGene], rate: f32) {
+        if elite.is_empty() {
+            return;
+        }
+        let n = elite.len() as f32;
+        for (i, w) in self.lengths.iter_mut().enumerate() {
+            let share = elite.iter().filter(|g| g.0.len().min(MAX_STEPS) == i + 1).count() as f32 / n;
+            *w = (1.0 - rate) * *w + rate * share * MAX_STEPS as f32;
+        }
+        for (i, w) in self.positions.iter_mut().enumerate() {
+            let learned = ProgWeights::learn_progs(elite.iter().filter_map(|g| g.0.get(i).cloned()), 1.0);
+            *w = w.blend(&learned, rate);
+        }
+    }
+}
+
+impl Default for PositionModel {
+    fn default() -> PositionModel {
+        PositionModel::new()
+    }
+}
+
+// A pool of stack programs evolved by model building rather than by genetic operators
+pub struct EdaPool<F> {
+    // The programs paired with their fitness, in no particular order
+    pub genes: Vec<(ProgramGene, f32)>,
+    pub model: PositionModel,
+    // How far the model moves toward each generation's elite. See `PositionModel::update`.
+    rate: f32,
+    fitness: F,
+}
+
+impl<F: Fitness<ProgramGene>> EdaPool<F> {
+    // Create a pool of the given size, sampled from the unbiased model
+    pub fn new<R: Rng>(size: usize, fitness: F, rate: f32, rng: &mut R) -> Self {
+        let model = PositionModel::new();
+        let genes = (0 .. size).map(|_| {
+            let g = model.sample(rng);
+            let fit = fitness.evaluate(&g);
+            (g, fit)
+        }).collect();
+        EdaPool { genes, model, rate, fitness }
+    }
+
+    // Evolve one generation: keep the most fit quarter, learn from it, and replace the rest with
+    // samples from the updated model
+    pub fn evolve<R: Rng>(&mut self, rng: &mut R) {
+        let len = self.genes.len();
+        self.genes.sort_by_key(|g| Reverse(Objective(g.1)));
+        self.genes.truncate((len / 4).max(1).min(len));
+        {
+            let elite: Vec<&ProgramGene> = self.genes.iter().map(|g| &g.0).collect();
+            self.model.update(&elite, self.rate);
+        }
+        while self.genes.len() < len {
+            let g = self.model.sample(rng);
+            let fit = self.fitness.evaluate(&g);
+            self.genes.push((g, fit));
+        }
+    }
+
+    // Get the current best program and its fitness
+    pub fn get_best(&self) -> (&ProgramGene, f32) {
+        let best = self.genes.iter().max_by_key(|g| Objective(g.1)).expect("the pool is empty");
+        (&best.0, best.1)
+    }
+}
+
+impl<F: Fitness<ProgramGene>> DynPool for EdaPool<F> {
+    fn evolve_dyn(&mut self, mut rng: &mut dyn Rng) {
+        self.evolve(&mut rng);
+    }
+
+    fn best_dyn(&self) -> (&dyn DynGene, f32) {
+        let (gene, fit) = self.get_best();
+        (gene, fit)
+    }
+
+    fn genes_dyn(&self) -> Vec<(&dyn DynGene, f32)> {
+        self.genes.iter().map(|g| (&g.0 as &dyn DynGene, g.1)).collect()
+    }
+
+    fn fingerprint_dyn(&self) -> u64 {
+        gene::fingerprint(&self.genes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::Command::*;
+    use lang::Prog::{C, D};
+    use prog_gene;
+    use prog_gene::Runnable;
+    use gene::Gene;
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn position_model() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let mut model = PositionModel::new();
+        for _ in 0 .. 100 {
+            let len = model.sample(rng).0.len();
+            assert!((1 ..= MAX_STEPS).contains(&len));
+        }
+
+        // Learning fully from one program makes it a common sample, where it was a rare one
+        let add = ProgramGene(vec![D(1), C(Add)]);
+        let same = (0 .. 1000).filter(|_| model.sample(rng) == add).count();
+        assert!(same < 5);
+        model.update(&[&add], 1.0);
+        assert!(model.sample(rng).0.len() == 2);
+        let same = (0 .. 1000).filter(|_| model.sample(rng) == add).count();
+        assert!(same > 200, "{}", same);
+    }
+
+    #[test]
+    fn evolve_eda() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness = |g: &ProgramGene| prog_gene::score_cases(|a, b| a + b, g.size(), |a, b| g.run(a, b));
+        let mut pool = EdaPool::new(50, fitness, 0.5, rng);
+        for _ in 0 .. 20 {
+            pool.evolve(rng);
+        }
+        assert_eq!(pool.genes.len(), 50);
+        assert!(pool.get_best().1 > 0.99);
+    }
+}
@@ -0,0 +1,95 @@
+//
+// The standard measures of how much search it takes to solve a problem, from many independent runs
+// of the same config, so results can be compared with published ones directly. These follow Koza,
+// Genetic Programming (1992), chapter 8. A run that has evolved g generations has evaluated its
+// initial pool and g more, so g + 1 pools in all.
+//
+
+// How a number of independent runs of the same config went
+#[derive(Clone, Debug, PartialEq)]
+pub struct Experiment {
+    // The size of each run's pool
+    pub pool_size: usize,
+    // The most generations each run could evolve
+    pub generations: usize,
+    // For each run, the number of generations it took to solve the problem, if it did
+    pub solved_at: Vec<Option<usize>>,
+}
+
+// Koza's computational effort, and the generation budget it's reached at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Effort {
+    // The fewest programs to evaluate to solve the problem with the asked-for probability
+    pub individuals: usize,
+    // The generations each run gets
+    pub generations: usize,
+    // The number of independent runs of that many generations
+    pub runs: usize,
+}
+
+impl Experiment {
+    // The fraction of runs solved within the whole generation budget
+    pub fn success_rate(&self) -> f32 {
+        self.success_by(self.generations)
+    }
+
+    // The fraction of runs solved within `generations` generations, Koza's P(M, i)
+    pub fn success_by(&self, generations: usize) -> f32 {
+        if self.solved_at.is_empty() {
+            return 0.0;
+        }
+        let solved = self.solved_at.iter().filter(|s| s.is_some_and(|g| g <= generations)).count();
+        solved as f32 / self.solved_at.len() as f32
+    }
+
+    // Koza's computational effort E for probability `z`, e.g. 0.99: over every generation budget,
+    // the fewest programs to evaluate for at least one of the runs to solve the problem with that
+    // probability. None if no run solved it.
+    pub fn effort(&self, z: f32) -> Option<Effort> {
+        (0 ..= self.generations).filter_map(|generations| {
+            let runs = runs_needed(self.success_by(generations), z)?;
+            Some(Effort { individuals: self.pool_size * (generations + 1) * runs, generations, runs })
+        }).min_by_key(|e| e.individuals)
+    }
+}
+
+// The number of independent runs, each solving the problem with probability `p`, that it takes for
+// at least one to solve it with probability `z`: Koza's R(z). None if `p` is 0.
+pub fn runs_needed(p: f32, z: f32) -> Option<usize> {
+    if p <= 0.0 {
+        None
+    } else if p >= 1.0 {
+        Some(1)
+    } else {
+        let runs = (1.0 - z as f64).ln() / (1.0 - p as f64).ln();
+        Some(runs.ceil().max(1.0) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computational_effort() {
+        assert_eq!(runs_needed(0.5, 0.99), Some(7));
+        assert_eq!(runs_needed(1.0, 0.99), Some(1));
+        assert_eq!(runs_needed(0.0, 0.99), None);
+
+        let experiment = Experiment {
+            pool_size: 100,
+            generations: 50,
+            solved_at: vec![Some(5), Some(10), Some(10), None, Some(20), None, None, Some(40), None, None],
+        };
+        assert_eq!(experiment.success_by(4), 0.0);
+        assert_eq!(experiment.success_by(10), 0.3);
+        assert_eq!(experiment.success_rate(), 0.5);
+        // 30% of runs solve it within 10 generations, so 13 runs of 11 pools each are enough, which
+        // beats every other budget
+        assert_eq!(experiment.effort(0.99), Some(Effort { individuals: 14300, generations: 10, runs: 13 }));
+
+        let unsolved = Experiment { pool_size: 100, generations: 50, solved_at: vec![None, None] };
+        assert_eq!(unsolved.effort(0.99), None);
+        assert_eq!(Experiment { solved_at: vec![], ..unsolved }.success_rate(), 0.0);
+    }
+}
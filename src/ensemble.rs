@@ -0,0 +1,104 @@
+//
+// Ensembles of the best programs of a run, answering with the median of their outputs. Programs
+// that each overfit in their own way often disagree where they're wrong and agree where they're
+// right, so the median can do better on unseen cases than any one of them.
+//
+
+use super::objective::Objective;
+use super::prog_gene::Runnable;
+use super::suite::TestSuite;
+use super::team::Aggregate;
+
+use std::cmp::Reverse;
+
+// Programs run together, best first
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ensemble<T>(pub Vec<T>);
+
+impl<T: Clone + Eq> Ensemble<T> {
+    // The `k` most fit distinct genes, from genes paired with their fitness
+    pub fn of_best(genes: &[(T, f32)], k: usize) -> Ensemble<T> {
+        let mut order: Vec<&(T, f32)> = genes.iter().collect();
+        order.sort_by_key(|g| Reverse(Objective(g.1)));
+        let mut members: Vec<T> = Vec::with_capacity(k);
+        for g in order {
+            if members.len() == k {
+                break;
+            }
+            if !members.contains(&g.0) {
+                members.push(g.0.clone());
+            }
+        }
+        Ensemble(members)
+    }
+}
+
+// Each output is the median of the members' outputs. Panics if there are no members.
+impl<T: Runnable> Runnable for Ensemble<T> {
+    fn run(&self, a: i32, b: i32) -> i32 {
+        self.run_outputs(a, b, 1)[0]
+    }
+
+    fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        let outputs: Vec<Vec<i32>> = self.0.iter().map(|m| m.run_outputs(a, b, count)).collect();
+        (0 .. count).map(|i| {
+            let mut column: Vec<i32> = outputs.iter().map(|o| o[i]).collect();
+            Aggregate::Median.combine(&mut column)
+        }).collect()
+    }
+}
+
+// How an ensemble does on a suite's holdout cases compared to its best member alone
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnsembleReport {
+    pub members: usize,
+    // The fraction of expected outputs each gets right. See `TestSuite::holdout_accuracy`.
+    pub single: f32,
+    pub ensemble: f32,
+}
+
+// Compare an ensemble with its best member on the suite's holdout cases. None if the ensemble is
+// empty or the suite has no holdout cases.
+pub fn compare<T: Runnable>(suite: &TestSuite, ensemble: &Ensemble<T>) -> Option<EnsembleReport> {
+    let best = ensemble.0.first()?;
+    Some(EnsembleReport {
+        members: ensemble.0.len(),
+        single: suite.holdout_accuracy(best)?,
+        ensemble: suite.holdout_accuracy(ensemble)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::Command::*;
+    use lang::Prog::{C, D};
+    use prog_gene::ProgramGene;
+
+    #[test]
+    fn median_of_best() {
+        let add = ProgramGene(vec![C(Add)]);
+        let sub = ProgramGene(vec![C(Sub)]);
+        let mult = ProgramGene(vec![C(Mult)]);
+        let genes = vec![
+            (sub.clone(), 0.5),
+            (add.clone(), 0.9),
+            (add.clone(), 0.9),
+            (mult.clone(), 0.4),
+            (ProgramGene(vec![D(0)]), 0.1),
+        ];
+        // Duplicates count once
+        let ensemble = Ensemble::of_best(&genes, 3);
+        assert_eq!(ensemble, Ensemble(vec![add.clone(), sub.clone(), mult.clone()]));
+        assert_eq!(ensemble.run(5, 2), 7);
+        assert_eq!(ensemble.run_outputs(5, 2, 2), vec![7, 0]);
+
+        // The held out cases have b of 1 or 3. When b is 1, the median is a, which is wrong.
+        let suite = TestSuite::builder(|a, b| vec![a + b]).grid(0 .. 4, 0 .. 4).build().with_holdout(0.5);
+        let report = compare(&suite, &ensemble).unwrap();
+        assert_eq!(report.members, 3);
+        assert_eq!(report.single, 1.0);
+        assert!(report.ensemble < report.single);
+        assert_eq!(compare(&suite, &Ensemble::<ProgramGene>(vec![])), None);
+    }
+}
@@ -0,0 +1,128 @@
+//
+// Brute-force search over every short stack program. With a small enough instruction set this
+// finds the true optimum, which makes it a correctness oracle for the genetic algorithm and a way
+// to say how far an evolved program is from the best possible.
+//
+
+use super::gene::Fitness;
+use super::lang::{Command, Prog};
+use super::objective::Objective;
+use super::prog_gene::ProgramGene;
+
+// A small instruction set that still reaches most simple targets: the numbers -2 to 2 and every
+// command but the frame commands
+pub fn small_progs() -> Vec<Prog> {
+    let numbers = (-2 .. 3).map(Prog::D);
+    let commands = Command::BASIC.iter().map(|&c| Prog::C(c));
+    numbers.chain(commands).collect()
+}
+
+// Every program of length 1 to `max_len` over the given progs, shortest first. There are k + k^2 +
+// ... + k^max_len of them for k progs, so keep both small.
+pub struct Programs<'a> {
+    progs: &'a [Prog],
+    max_len: usize,
+    // The index into `progs` of each prog in the next program, or None when there are no more
+    next: Option<Vec<usize>>,
+}
+
+impl<'a> Programs<'a> {
+    pub fn new(progs: &'a [Prog], max_len: usize) -> Programs<'a> {
+        let next = if progs.is_empty() || max_len == 0 { None } else { Some(vec![0]) };
+        Programs { progs, max_len, next }
+    }
+}
+
+impl<'a> Iterator for Programs<'a> {
+    type Item = ProgramGene;
+
+    fn next(&mut self) -> Option<ProgramGene> {
+        let indexes = self.next.take()?;
+        let program = ProgramGene(indexes.iter().map(|&i| self.progs[i]).collect());
+
+        // Count up like an odometer, moving on to the next length once every digit rolls over
+        let mut following = indexes;
+        let mut carried = true;
+        for i in following.iter_mut().rev() {
+            *i += 1;
+            if *i < self.progs.len() {
+                carried = false;
+                break;
+            }
+            *i = 0;
+        }
+        if carried {
+            following.push(0);
+        }
+        if following.len() <= self.max_len {
+            self.next = Some(following);
+        }
+        Some(program)
+    }
+}
+
+// The best program found by brute force
+#[derive(Clone, Debug, PartialEq)]
+pub struct Optimum {
+    pub program: ProgramGene,
+    pub fitness: f32,
+    // The number of programs scored to find it
+    pub searched: usize,
+}
+
+impl Optimum {
+    // How much fitness a program is missing compared to the optimum. An evolved program longer than
+    // the search went can beat the optimum, giving a negative gap.
+    pub fn gap(&self, fitness: f32) -> f32 {
+        self.fitness - fitness
+    }
+}
+
+// Score every program of length 1 to `max_len` over the given progs and return the most fit. Ties
+// go to the program found first, which is also the shortest. Returns None if there are no programs
+// to search.
+pub fn optimum<F: Fitness<ProgramGene>>(progs: &[Prog], max_len: usize, fitness: &F) -> Option<Optimum> {
+    let mut best: Option<Optimum> = None;
+    let mut searched = 0;
+    for program in Programs::new(progs, max_len) {
+        searched += 1;
+        let fit = fitness.evaluate(&program);
+        if best.as_ref().is_none_or(|b| Objective(fit) > Objective(b.fitness)) {
+            best = Some(Optimum { program, fitness: fit, searched: 0 });
+        }
+    }
+    best.map(|b| Optimum { searched, ..b })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene;
+
+    #[test]
+    fn programs() {
+        let progs = [Prog::D(1), Prog::C(Command::Add), Prog::C(Command::Dup)];
+        let all: Vec<String> = Programs::new(&progs, 2).map(|p| p.to_string()).collect();
+        assert_eq!(all.len(), 3 + 9);
+        assert_eq!(&all[.. 4], &["1", "+", "dup", "1 1"]);
+        assert_eq!(all.last().unwrap(), "dup dup");
+        assert_eq!(Programs::new(&progs, 4).count(), 3 + 9 + 27 + 81);
+        assert_eq!(Programs::new(&[], 4).count(), 0);
+        assert_eq!(Programs::new(&progs, 0).count(), 0);
+    }
+
+    #[test]
+    fn find_optimum() {
+        let target = |a: i32, b: i32| a - b * b;
+        let fitness = |g: &ProgramGene| prog_gene::fitness(target, g);
+        let found = optimum(&small_progs(), 3, &fitness).unwrap();
+        assert_eq!(found.program.to_string(), "dup * -");
+        assert_eq!(found.searched, 11 + 121 + 1331);
+        assert!(found.gap(found.fitness) == 0.0);
+
+        // A longer correct program only loses on shortness
+        let longer: ProgramGene = "0 + dup * -".parse().unwrap();
+        let gap = found.gap(fitness(&longer));
+        assert!(gap > 0.0 && gap < 0.001, "{}", gap);
+    }
+}
@@ -0,0 +1,136 @@
+//
+// Simulated environments for evolving programs that make a sequence of decisions. Each timestep the
+// program runs with the environment's sensor readings as its inputs, and its output is taken as the
+// action. The reward collected over an episode is the fitness.
+//
+
+use super::prog_gene::Runnable;
+
+// An environment an agent acts in, one timestep at a time
+pub trait Env {
+    // Start a new episode
+    fn reset(&mut self);
+
+    // The agent's sensor readings, given to the program as its inputs (a, b)
+    fn sensors(&self) -> (i32, i32);
+
+    // Take the action the program chose, returning the reward for this step and whether the
+    // episode is over
+    fn step(&mut self, action: i32) -> (f32, bool);
+}
+
+// Run one episode of at most `max_steps` steps, returning the total reward
+pub fn run_episode<P: Runnable, E: Env>(program: &P, env: &mut E, max_steps: usize) -> f32 {
+    env.reset();
+    let mut total = 0.0;
+    for _ in 0 .. max_steps {
+        let (a, b) = env.sensors();
+        let (reward, done) = env.step(program.run(a, b));
+        total += reward;
+        if done {
+            break;
+        }
+    }
+    total
+}
+
+// A square grid with a goal cell. The agent senses how far the goal is along x and along y, and
+// moves one cell per step: an output of 0 moves right, 1 up, 2 left, and 3 down, taken modulo 4.
+// Each step is rewarded by how much closer it brought the agent, as a fraction of the starting
+// distance, so reaching the goal is worth 1 in total. Reaching it also ends the episode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GridWorld {
+    pub size: i32,
+    pub start: (i32, i32),
+    pub goal: (i32, i32),
+    // Where the agent is now
+    pub pos: (i32, i32),
+}
+
+impl GridWorld {
+    pub fn new(size: i32, start: (i32, i32), goal: (i32, i32)) -> GridWorld {
+        GridWorld { size, start, goal, pos: start }
+    }
+
+    // The distance from the agent to the goal, in steps
+    fn distance(&self) -> i32 {
+        (self.goal.0 - self.pos.0).abs() + (self.goal.1 - self.pos.1).abs()
+    }
+}
+
+impl Env for GridWorld {
+    fn reset(&mut self) {
+        self.pos = self.start;
+    }
+
+    fn sensors(&self) -> (i32, i32) {
+        (self.goal.0 - self.pos.0, self.goal.1 - self.pos.1)
+    }
+
+    fn step(&mut self, action: i32) -> (f32, bool) {
+        let start_distance = (self.goal.0 - self.start.0).abs() + (self.goal.1 - self.start.1).abs();
+        let before = self.distance();
+        let (dx, dy) = match action.rem_euclid(4) {
+            0 => (1, 0),
+            1 => (0, 1),
+            2 => (-1, 0),
+            _ => (0, -1),
+        };
+        // Walls keep the agent on the grid
+        let clamp = |x: i32| x.max(0).min(self.size - 1);
+        self.pos = (clamp(self.pos.0 + dx), clamp(self.pos.1 + dy));
+        let after = self.distance();
+        let reward = if start_distance == 0 { 0.0 } else { (before - after) as f32 / start_distance as f32 };
+        (reward, after == 0)
+    }
+}
+
+// Use as a fitness function: the mean episode reward over grids with the goal in each corner and
+// the agent starting in the middle, never below 0
+pub fn grid_fitness<P: Runnable>(program: &P) -> f32 {
+    let corners = [(0, 0), (0, 6), (6, 0), (6, 6)];
+    let total: f32 = corners.iter()
+        .map(|&goal| run_episode(program, &mut GridWorld::new(7, (3, 3), goal), 20))
+        .sum();
+    (total / corners.len() as f32).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Pool;
+    use lang::Prog::D;
+    use prog_gene::ProgramGene;
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn grid_world() {
+        let mut grid = GridWorld::new(5, (0, 0), (3, 0));
+        assert_eq!(grid.sensors(), (3, 0));
+
+        // Always moving right reaches a goal to the right, collecting a reward of 1
+        let right = ProgramGene(vec![D(0)]);
+        assert_eq!(run_episode(&right, &mut grid, 10), 1.0);
+        assert_eq!(grid.pos, (3, 0));
+
+        // Always moving left runs into the wall and gets nowhere
+        let left = ProgramGene(vec![D(2)]);
+        assert_eq!(run_episode(&left, &mut grid, 10), 0.0);
+        assert_eq!(grid.pos, (0, 0));
+
+        // A constant action gets halfway to two corners, but as far away from the other two
+        assert_eq!(grid_fitness(&right), 0.0);
+    }
+
+    #[test]
+    fn evolve_agent() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let mut pool = Pool::new(50, |g: &ProgramGene| grid_fitness(g), rng);
+        let start = pool.get_best().1;
+        for _ in 0 .. 30 {
+            pool.evolve(rng);
+        }
+        // Evolution finds agents that head toward the goal more often than away from it
+        assert!(pool.get_best().1 > start);
+    }
+}
@@ -0,0 +1,207 @@
+//
+// Prove that two stack programs compute the same thing, instead of only checking that they agree on
+// some test cases. Programs run on symbols for their inputs (see `infix`) and their outputs are put
+// into a normal form: a polynomial in a and b with coefficients that wrap like i32s. Addition,
+// subtraction, and multiplication are exact in that form, since wrapping arithmetic is arithmetic
+// modulo 2^32. A division that can't be worked out stays a quotient, which is only equal to a
+// quotient of equal polynomials, and likewise for comparisons. Programs that branch on their inputs
+// have no polynomial, so they're never proven equal to anything.
+//
+// The proof is sound but not complete: equal polynomials always mean equal programs, but programs
+// that are equal only because of how division truncates aren't recognized as equal.
+//
+
+use super::infix::{self, Expr, Inexpressible};
+use super::lang::{Command, Prog, Underflow};
+
+use std::collections::BTreeMap;
+
+// A factor of a term
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Atom {
+    A,
+    B,
+    // The truncated quotient of two polynomials, which is 0 if the divisor is
+    Quotient(Box<Poly>, Box<Poly>),
+    // Whether one polynomial is less than another, 1 or 0. Greater than is less than the other way
+    // round.
+    Less(Box<Poly>, Box<Poly>),
+    // Whether two polynomials are equal, 1 or 0, with the lesser of them first
+    Equal(Box<Poly>, Box<Poly>),
+}
+
+// A product of atoms, each raised to a power of at least 1
+type Monomial = BTreeMap<Atom, u32>;
+
+// A polynomial in normal form: every term has a nonzero coefficient, and like terms are combined
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Poly {
+    terms: BTreeMap<Monomial, i32>,
+}
+
+impl Poly {
+    pub fn constant(n: i32) -> Poly {
+        let mut poly = Poly::default();
+        poly.add_term(Monomial::new(), n);
+        poly
+    }
+
+    pub fn atom(atom: Atom) -> Poly {
+        let mut poly = Poly::default();
+        poly.add_term(Monomial::from([(atom, 1)]), 1);
+        poly
+    }
+
+    // The polynomial an expression computes
+    pub fn of(expr: &Expr) -> Poly {
+        match *expr {
+            Expr::Num(n) => Poly::constant(n),
+            Expr::A => Poly::atom(Atom::A),
+            Expr::B => Poly::atom(Atom::B),
+            Expr::Op(c, ref x, ref y) => Poly::apply(c, Poly::of(x), Poly::of(y)),
+        }
+    }
+
+    // Apply an arithmetic command or comparison to two polynomials
+    pub fn apply(c: Command, x: Poly, y: Poly) -> Poly {
+        match c {
+            Command::Add => x.plus(&y, 1),
+            Command::Sub => x.plus(&y, -1),
+            Command::Mult => x.times(&y),
+            Command::Div => match (x.constant_value(), y.constant_value()) {
+                (Some(p), Some(q)) => Poly::constant(Command::Div.apply(p, q)),
+                (_, Some(0)) | (Some(0), _) => Poly::default(),
+                (_, Some(1)) => x,
+                (_, Some(-1)) => Poly::default().plus(&x, -1),
+                _ => Poly::atom(Atom::Quotient(Box::new(x), Box::new(y))),
+            },
+            Command::Lt | Command::Gt | Command::Eq => match (x.constant_value(), y.constant_value()) {
+                (Some(p), Some(q)) => Poly::constant(c.apply(p, q)),
+                _ if x == y => Poly::constant((c == Command::Eq) as i32),
+                _ if c == Command::Lt => Poly::atom(Atom::Less(Box::new(x), Box::new(y))),
+                _ if c == Command::Gt => Poly::atom(Atom::Less(Box::new(y), Box::new(x))),
+                _ => Poly::atom(Atom::Equal(Box::new(x.clone().min(y.clone())), Box::new(x.max(y)))),
+            },
+            _ => panic!("{:?} is not an arithmetic command or comparison", c),
+        }
+    }
+
+    // The value of the polynomial if it's a constant
+    pub fn constant_value(&self) -> Option<i32> {
+        match self.terms.len() {
+            0 => Some(0),
+            1 => self.terms.get(&Monomial::new()).cloned(),
+            _ => None,
+        }
+    }
+
+    fn add_term(&mut self, monomial: Monomial, coefficient: i32) {
+        let sum = self.terms.get(&monomial).map_or(coefficient, |c| c.wrapping_add(coefficient));
+        if sum == 0 {
+            self.terms.remove(&monomial);
+        } else {
+            self.terms.insert(monomial, sum);
+        }
+    }
+
+    // This plus `scale` times `other`
+    fn plus(mut self, other: &Poly, scale: i32) -> Poly {
+        for (m, &c) in &other.terms {
+            self.add_term(m.clone(), c.wrapping_mul(scale));
+        }
+        self
+    }
+
+    fn times(&self, other: &Poly) -> Poly {
+        let mut product = Poly::default();
+        for (m1, &c1) in &self.terms {
+            for (m2, &c2) in &other.terms {
+                let mut m = m1.clone();
+                for (atom, &power) in m2 {
+                    *m.entry(atom.clone()).or_insert(0) += power;
+                }
+                product.add_term(m, c1.wrapping_mul(c2));
+            }
+        }
+        product
+    }
+}
+
+// The polynomial a program's output is, with the given policy for popping the empty stack, or why
+// it has none. See `infix::expression`.
+pub fn polynomial(program: &[Prog], underflow: Underflow) -> Result<Poly, Inexpressible> {
+    infix::expression(program, underflow).map(|e| Poly::of(&e))
+}
+
+// Whether two programs provably give the same output on every input. False only means no proof was
+// found. Two programs that both pop the empty stack under `Underflow::Error` are equal, since both
+// are always errors.
+pub fn proves_equal(x: &[Prog], y: &[Prog], underflow: Underflow) -> bool {
+    match (polynomial(x, underflow), polynomial(y, underflow)) {
+        (Err(Inexpressible::Branch), _) | (_, Err(Inexpressible::Branch)) => false,
+        (x, y) => x == y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::ProgramGene;
+
+    fn equal(x: &str, y: &str) -> bool {
+        let (x, y): (ProgramGene, ProgramGene) = (x.parse().unwrap(), y.parse().unwrap());
+        proves_equal(&x.0, &y.0, Underflow::Zero)
+    }
+
+    #[test]
+    fn prove_equivalence() {
+        assert!(equal("+", "swap +"));
+        // a*2b = 2ab
+        assert!(equal("dup + *", "* dup +"));
+        // (a + b)^2 isn't a^2 + b^2
+        assert!(!equal("+ dup *", "dup * swap dup * +"));
+        assert!(equal("dup dup * *", "dup dup * swap *"));
+        // Wrapping makes these the same
+        assert!(equal("65536 * 65536 *", "0 *"));
+        assert!(equal("2147483647 + 1 +", "-2147483648 +"));
+        // Quotients of equal polynomials are equal, but nothing more is known about them
+        assert!(equal("/ dup +", "/ 2 *"));
+        assert!(!equal("/ 2 *", "2 * /"));
+        assert!(equal("0 /", "0"));
+        // Under Error, a program that always underflows is never equal to one that doesn't
+        let (x, y): (ProgramGene, ProgramGene) = ("+ +".parse().unwrap(), "+".parse().unwrap());
+        assert!(!proves_equal(&x.0, &y.0, Underflow::Error));
+        assert!(proves_equal(&x.0, &y.0, Underflow::Zero));
+        // Comparisons the other way round are the same, but nothing more is known about them
+        assert!(equal("<", "swap >"));
+        assert!(equal("=", "swap ="));
+        assert!(!equal("<", "swap <"));
+        assert!(equal("dup <", "0"));
+        // Branching on the inputs is never proven equal, even to itself
+        assert!(!equal("< 1 if dup", "< 1 if dup"));
+        assert!(equal("1 1 if dup", "dup"));
+    }
+
+    // Every rewrite the infix simplifier makes keeps the polynomial the same
+    #[test]
+    fn simplifier_is_sound() {
+        let mut operands = vec![Expr::A, Expr::B];
+        for &n in &[0, 1, -1, 2, -3, i32::MAX, i32::MIN] {
+            operands.push(Expr::Num(n));
+        }
+        let commands: Vec<Command> = Command::ARITHMETIC.iter().chain(&Command::COMPARISON).cloned().collect();
+        for &c in &commands {
+            operands.push(Expr::Op(c, Box::new(Expr::A), Box::new(Expr::B)));
+            operands.push(Expr::Op(c, Box::new(Expr::B), Box::new(Expr::Num(-3))));
+        }
+        for &c in &commands {
+            for x in &operands {
+                for y in &operands {
+                    let raw = Expr::Op(c, Box::new(x.clone()), Box::new(y.clone()));
+                    let simplified = Expr::apply(c, x.clone(), y.clone());
+                    assert_eq!(Poly::of(&simplified), Poly::of(&raw), "{} simplified to {}", raw, simplified);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//
+// Evaluate fitness on several threads at once
+//
+
+use super::cancel::CancellationToken;
+use super::gene::Fitness;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Evaluate the fitness of every gene on `workers` threads, storing it next to the gene. Instead of
+// splitting the genes into one chunk per worker, each worker takes the next unevaluated gene
+// whenever it finishes one. A worker stuck on a slow gene doesn't hold up the rest of its chunk,
+// since other workers keep taking genes until none are left.
+pub fn evaluate<T, F>(genes: &mut [(T, f32)], fitness: &F, workers: usize)
+    where T: Sync,
+          F: Fitness<T> + Sync,
+    {
+    evaluate_until(genes, fitness, workers, &CancellationToken::new());
+}
+
+// Like `evaluate`, but every worker stops taking genes once the token is cancelled. Genes already
+// being evaluated are finished and stored. Returns whether every gene was evaluated.
+pub fn evaluate_until<T, F>(genes: &mut [(T, f32)], fitness: &F, workers: usize, token: &CancellationToken) -> bool
+    where T: Sync,
+          F: Fitness<T> + Sync,
+    {
+    let fits = {
+        let genes: &[(T, f32)] = genes;
+        map(genes.len(), workers, token, |i| fitness.evaluate(&genes[i].0))
+    };
+    let mut complete = true;
+    for (g, fit) in genes.iter_mut().zip(fits) {
+        match fit {
+            Some(fit) => g.1 = fit,
+            None => complete = false,
+        }
+    }
+    complete
+}
+
+// Run `job` on every index from 0 to `count` using `workers` threads pulling from the same queue,
+// as in `evaluate`. Results come back in index order no matter which worker ran them. Indexes not
+// yet taken when the token is cancelled are skipped and give None.
+pub fn map<U, J>(count: usize, workers: usize, token: &CancellationToken, job: J) -> Vec<Option<U>>
+    where U: Send,
+          J: Fn(usize) -> U + Sync,
+    {
+    let mut results: Vec<Option<U>> = (0 .. count).map(|_| None).collect();
+    if workers <= 1 {
+        for (i, r) in results.iter_mut().enumerate() {
+            if token.is_cancelled() {
+                break;
+            }
+            *r = Some(job(i));
+        }
+        return results;
+    }
+
+    let next = AtomicUsize::new(0);
+    let done: Vec<Vec<(usize, U)>> = {
+        let (next, job) = (&next, &job);
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0 .. workers).map(|_| scope.spawn(move || {
+                // The indexes this worker ran, with their results
+                let mut done = Vec::new();
+                loop {
+                    if token.is_cancelled() {
+                        return done;
+                    }
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= count {
+                        return done;
+                    }
+                    done.push((i, job(i)));
+                }
+            })).collect();
+            handles.into_iter().map(|h| h.join().expect("queued job panicked")).collect()
+        })
+    };
+    for (i, r) in done.into_iter().flatten() {
+        results[i] = Some(r);
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::{Gene, Pool};
+    use prog_gene::{self, ProgramGene};
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn evaluate_in_parallel() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a * b, g);
+        let mut genes: Vec<(ProgramGene, f32)> = (0 .. 500).map(|_| (ProgramGene::generate(&Default::default(), rng), -1.0)).collect();
+
+        // Parallel evaluation gives the same fitness as serial evaluation
+        evaluate(&mut genes, &fitness, 4);
+        for g in &genes {
+            assert_eq!(g.1, fitness(&g.0));
+        }
+    }
+
+    #[test]
+    fn evolve_queued() {
+        // Queued evolution produces the same pool as serial evolution
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a * b, g);
+        let mut serial = Pool::new(40, fitness, &mut StdRng::from_seed(&[1]));
+        let mut queued = Pool::new(40, fitness, &mut StdRng::from_seed(&[1]));
+        let (rng_a, rng_b) = (&mut StdRng::from_seed(&[2]), &mut StdRng::from_seed(&[2]));
+        for _ in 0 .. 10 {
+            serial.evolve(rng_a);
+            queued.evolve_queued(rng_b, 3);
+        }
+        assert!(serial.iter().eq(queued.iter()));
+    }
+}
@@ -0,0 +1,102 @@
+//
+// An event bus for runs. Integrations like loggers, dashboards, checkpointers, and plotters
+// subscribe to what happens during a run instead of each being wired into the run loop, and they're
+// told about each event in the order they subscribed.
+//
+
+use super::gene::{DynGene, DynPool};
+use super::plateau::Change;
+
+// Something that happened during a run
+pub enum Event<'a> {
+    // A generation is about to evolve
+    GenerationStarted { generation: usize },
+    // A generation evolved into the pool
+    GenerationFinished { generation: usize, pool: &'a dyn DynPool },
+    // The best gene is fitter than the best of any earlier generation
+    ChampionChanged { generation: usize, champion: &'a dyn DynGene, fitness: f32 },
+    // The run plateaued and hyper-mutation started, or it stopped. See `plateau`.
+    Stagnation { generation: usize, change: Change },
+    // A checkpoint of the pool after `generation` generations was saved
+    CheckpointWritten { generation: usize, path: &'a str },
+}
+
+// Something that's told about events
+pub trait Subscriber {
+    fn notify(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event)> Subscriber for F {
+    fn notify(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+// Another bus subscribes by passing every event on to its own subscribers
+impl<'a, 'b> Subscriber for &'b mut EventBus<'a> {
+    fn notify(&mut self, event: &Event) {
+        self.publish(event)
+    }
+}
+
+// The subscribers of a run
+#[derive(Default)]
+pub struct EventBus<'a> {
+    subscribers: Vec<Box<dyn Subscriber + 'a>>,
+}
+
+impl<'a> EventBus<'a> {
+    pub fn new() -> EventBus<'a> {
+        EventBus::default()
+    }
+
+    pub fn subscribe<S: Subscriber + 'a>(&mut self, subscriber: S) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    // Tell every subscriber about the event
+    pub fn publish(&mut self, event: &Event) {
+        for s in &mut self.subscribers {
+            s.notify(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::RunConfig;
+    use runner;
+    use cancel::CancellationToken;
+
+    #[test]
+    fn publish_events() {
+        let mut seen = Vec::new();
+        let mut champions = 0;
+        {
+            let mut events = EventBus::new();
+            events.subscribe(|e: &Event| match *e {
+                Event::GenerationStarted { generation } => seen.push(format!("started {}", generation)),
+                Event::GenerationFinished { generation, pool } => {
+                    assert_eq!(pool.genes_dyn().len(), 10);
+                    seen.push(format!("finished {}", generation));
+                }
+                Event::CheckpointWritten { generation, .. } => seen.push(format!("checkpoint {}", generation)),
+                _ => {}
+            });
+            events.subscribe(|e: &Event| if let Event::ChampionChanged { .. } = *e { champions += 1 });
+            let path = ::std::env::temp_dir().join("gene-code-events-test.ckpt");
+            let mut config = RunConfig { seed: Some(42), ..RunConfig::default() };
+            config.pool.size = 10;
+            config.termination.generations = 3;
+            config.logging.quiet = true;
+            config.logging.checkpoint = path.to_str().unwrap().to_string();
+            config.logging.checkpoint_every = Some(2);
+            runner::run_with(&config, &CancellationToken::new(), &mut events).unwrap();
+        }
+        assert_eq!(seen, ["started 0", "finished 0", "started 1", "finished 1", "checkpoint 2", "started 2",
+                          "finished 2", "checkpoint 3"]);
+        // The first generation's best is always a new champion
+        assert!((1 ..= 3).contains(&champions));
+    }
+}
@@ -0,0 +1,191 @@
+//
+// A cache of fitness scores that outlives a run, so re-running or resuming with a slightly changed
+// config doesn't score the same genes all over again. Entries are keyed by a hash of the gene (and
+// anything else its score depends on), and the whole cache by a hash of what it was scored against,
+// e.g. the test suite. A cache saved against one suite is never used with another.
+//
+// Hashes come from `DefaultHasher`, which is only stable within one build of the program. A cache
+// from another build just misses, or is rejected for its key.
+//
+
+use super::gene::Fitness;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Hash anything with the cache's hasher, e.g. a gene with the parsimony it was scored with
+pub fn hash_key<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Fitness scores by entry key, for one fitness function
+#[derive(Debug)]
+pub struct FitnessCache {
+    // The hash of what the scores were computed against
+    key: u64,
+    scores: Mutex<HashMap<u64, f32>>,
+    // The number of lookups answered from the cache so far
+    hits: AtomicUsize,
+}
+
+impl FitnessCache {
+    // An empty cache for scores computed against the given key
+    pub fn new(key: u64) -> FitnessCache {
+        FitnessCache { key, scores: Mutex::new(HashMap::new()), hits: AtomicUsize::new(0) }
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    // The number of scores in the cache
+    pub fn len(&self) -> usize {
+        self.scores.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The number of lookups answered from the cache so far
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    // Get the score for an entry, evaluating and remembering it if it isn't cached. The cache
+    // isn't locked while evaluating, so two threads may both evaluate the same new entry.
+    pub fn get_or_evaluate<E: FnOnce() -> f32>(&self, entry: u64, evaluate: E) -> f32 {
+        if let Some(&fit) = self.scores.lock().unwrap().get(&entry) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return fit;
+        }
+        let fit = evaluate();
+        self.scores.lock().unwrap().insert(entry, fit);
+        fit
+    }
+
+    // Write the cache: a header line with its key, then one line per entry with the entry key and
+    // the score, separated by a tab
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "# fitness cache {:016x}", self.key)?;
+        for (entry, fit) in self.scores.lock().unwrap().iter() {
+            writeln!(w, "{:016x}\t{}", entry, fit)?;
+        }
+        Ok(())
+    }
+
+    // Read a cache written by `write_to`
+    pub fn read_from<B: BufRead>(r: B) -> io::Result<FitnessCache> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut lines = r.lines();
+        let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+        let key = header.strip_prefix("# fitness cache ")
+            .and_then(|k| u64::from_str_radix(k, 16).ok())
+            .ok_or_else(|| invalid(format!("bad fitness cache header: {}", header)))?;
+        let mut scores = HashMap::new();
+        for line in lines {
+            let line = line?;
+            let mut fields = line.splitn(2, '\t');
+            let entry = fields.next().and_then(|e| u64::from_str_radix(e, 16).ok());
+            let fit = fields.next().and_then(|f| f.parse().ok());
+            match (entry, fit) {
+                (Some(entry), Some(fit)) => scores.insert(entry, fit),
+                _ => return Err(invalid(format!("bad fitness cache line: {}", line))),
+            };
+        }
+        Ok(FitnessCache { key, scores: Mutex::new(scores), hits: AtomicUsize::new(0) })
+    }
+
+    // Save the cache to the given path, moving it into place once it's written like a checkpoint
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+            self.write_to(&mut file)?;
+            file.flush()?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<FitnessCache> {
+        FitnessCache::read_from(io::BufReader::new(fs::File::open(path)?))
+    }
+
+    // Load the cache at the given path to warm-start scoring against `key`. A missing file gives an
+    // empty cache, and so does a cache saved against a different key, with a warning.
+    pub fn load_or_new<P: AsRef<Path>>(path: P, key: u64) -> io::Result<FitnessCache> {
+        let path = path.as_ref();
+        match FitnessCache::load(path) {
+            Ok(ref cache) if cache.key != key => {
+                eprintln!("Not using the fitness cache {}, since it was scored against something else",
+                          path.display());
+                Ok(FitnessCache::new(key))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(FitnessCache::new(key)),
+            loaded => loaded,
+        }
+    }
+}
+
+// A fitness function whose scores are looked up in a cache by the hash of the gene
+pub struct Cached<F> {
+    pub fitness: F,
+    pub cache: Arc<FitnessCache>,
+}
+
+impl<T: Hash, F: Fitness<T>> Fitness<T> for Cached<F> {
+    fn evaluate(&self, gene: &T) -> f32 {
+        self.cache.get_or_evaluate(hash_key(gene), || self.fitness.evaluate(gene))
+    }
+
+    fn evaluate_detailed(&self, gene: &T) -> Option<Vec<f32>> {
+        self.fitness.evaluate_detailed(gene)
+    }
+
+    fn is_deterministic(&self) -> bool {
+        self.fitness.is_deterministic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::{self, ProgramGene};
+    use std::env;
+
+    #[test]
+    fn warm_start() {
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+        let genes: Vec<ProgramGene> = ["+", "-", "1 +", "+"].iter().map(|s| s.parse().unwrap()).collect();
+        let cached = Cached { fitness, cache: Arc::new(FitnessCache::new(7)) };
+        for g in &genes {
+            assert_eq!(cached.evaluate(g), fitness(g));
+        }
+        assert_eq!((cached.cache.len(), cached.cache.hits()), (3, 1));
+
+        // A saved cache warm-starts the next run against the same key, and only that key
+        let path = env::temp_dir().join(format!("gene_code_fitness_cache_{}.txt", ::std::process::id()));
+        cached.cache.save(&path).unwrap();
+        let warm = Cached { fitness: |_: &ProgramGene| -> f32 { panic!("not cached") },
+                            cache: Arc::new(FitnessCache::load_or_new(&path, 7).unwrap()) };
+        for g in &genes {
+            assert_eq!(warm.evaluate(g), fitness(g));
+        }
+        assert_eq!(warm.cache.hits(), 4);
+        assert!(FitnessCache::load_or_new(&path, 8).unwrap().is_empty());
+        fs::remove_file(&path).unwrap();
+        assert!(FitnessCache::load_or_new(&path, 7).unwrap().is_empty());
+
+        assert!(FitnessCache::read_from(&b"0\t1"[..]).is_err());
+        assert!(FitnessCache::read_from(&b"# fitness cache 7\nxyz\t1"[..]).is_err());
+    }
+}
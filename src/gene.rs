@@ -3,178 +3,1967 @@
 //
 
 extern crate rand;
-use rand::Rng;
+use rand::{Rng, SeedableRng, StdRng};
+use std::any::Any;
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-use std::collections::HashSet;
+use cancel::CancellationToken;
+use eval_queue;
+use objective::Objective;
+use pareto;
+use lexicase;
+use immigration::Immigration;
+use schedule::Schedule;
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "journal")]
+use journal::{Journal, Op};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::{fs, io, path::Path};
 
 // A type that can be used as a gene. Specifically, it must support random generation and mutation.
 pub trait Gene: Hash + Eq {
+    // The parameters of the representation, e.g. the longest program to generate or the
+    // instructions to use. The pool keeps one and passes it to every operator, so gene types don't
+    // need globals for them. Genes with nothing to configure use `()`.
+    type Config;
+
     // Generate a new random gene. This is initially used to fill the pool.
-    fn generate<R: Rng>(rng: &mut R) -> Self;
+    fn generate<R: Rng>(cfg: &Self::Config, rng: &mut R) -> Self;
 
     // Generate a new gene that is a mutation of this gene.
-    fn mutate<R: Rng>(&self, rng: &mut R) -> Self;
+    fn mutate<R: Rng>(&self, cfg: &Self::Config, rng: &mut R) -> Self;
 
     // Cross this gene with another gene to produce a child.
-    fn cross<R: Rng>(&self, other: &Self, rng: &mut R) -> Self;
+    fn cross<R: Rng>(&self, cfg: &Self::Config, other: &Self, rng: &mut R) -> Self;
+
+    // The size of this gene, e.g. the length of a program. This is used for reporting and for
+    // fitness bonuses, so it only needs to be meaningful relative to other genes of the same type.
+    fn size(&self) -> usize {
+        1
+    }
+
+    // Hooks the pool calls as a gene moves through it, so genes can keep their own bookkeeping
+    // (e.g. evaluation counts or cached compiled forms) without the pool knowing about it. They do
+    // nothing by default. Genes shouldn't change their hash or equality in them.
+
+    // Called once for every gene that enters the pool: generated, bred, or given to it
+    fn on_created(&mut self) {}
+
+    // Called when the gene is selected to survive into the next generation
+    fn on_selected(&mut self) {}
+
+    // Called when the pool drops the gene
+    fn on_discarded(&mut self) {}
+}
+
+// A way of scoring genes, where higher is better. Any `Fn(&T) -> f32` closure is a fitness
+// function; implement this trait directly to tell the pool more about how the scores come about.
+pub trait Fitness<T> {
+    // Score a gene
+    fn evaluate(&self, gene: &T) -> f32;
+
+    // Score a gene on each test case separately, for methods that look at the cases one at a time
+    // rather than at the overall score. The default has no cases to report.
+    fn evaluate_detailed(&self, _gene: &T) -> Option<Vec<f32>> {
+        None
+    }
+
+    // Whether scoring the same gene always gives the same result, so a score can be reused instead
+    // of evaluating the gene again. Noisy fitness functions should return false.
+    fn is_deterministic(&self) -> bool {
+        true
+    }
+}
+
+impl<T, F: Fn(&T) -> f32> Fitness<T> for F {
+    fn evaluate(&self, gene: &T) -> f32 {
+        self(gene)
+    }
+}
+
+// When a pool scores the genes it keeps from one generation to the next again. New genes are always
+// scored; this only matters for the survivors, whose old score goes stale if the fitness is noisy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reevaluate {
+    // Keep each survivor's first score for as long as it lives
+    Never,
+    // Score the survivors again every generation
+    EveryGeneration,
+    // Score the survivors again every nth generation. Every(0) is the same as Never.
+    Every(usize),
+    // Only score the survivors again when asked to with `Pool::reevaluate`
+    OnDemand,
+}
+
+impl Reevaluate {
+    // The policy that suits a fitness function: reuse scores unless they can change
+    pub fn default_for<T, F: Fitness<T>>(fitness: &F) -> Reevaluate {
+        if fitness.is_deterministic() { Reevaluate::Never } else { Reevaluate::EveryGeneration }
+    }
+
+    // Whether the survivors should be scored again in the given generation, counting from 1
+    fn is_due(self, generation: usize) -> bool {
+        match self {
+            Reevaluate::Never | Reevaluate::OnDemand => false,
+            Reevaluate::EveryGeneration => true,
+            Reevaluate::Every(n) => n > 0 && generation.is_multiple_of(n),
+        }
+    }
+}
+
+// How a pool chooses the genes that survive into the next generation and parent its new genes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    // Choose a fourth of the pool at random, each gene weighted by its fitness
+    Proportionate,
+    // Keep the given fraction of the pool with the highest fitness, from 0 to 1. Ties go to the
+    // gene that came first.
+    Truncation(f32),
+    // Choose a fourth of the pool by tournaments between two random genes, where the fitter one
+    // wins with a probability that grows with the fitness gap and shrinks with the temperature.
+    // The temperature follows the schedule over the generations: a high temperature is close to
+    // random search, and a temperature of 0 always picks the fitter gene, like hill climbing.
+    Boltzmann(Schedule),
+    // Keep a fourth of the pool by Pareto front and then crowding distance, treating each detailed
+    // score from `Fitness::evaluate_detailed` as a separate objective. See `pareto`. Genes without
+    // detailed scores have their overall fitness as their only objective.
+    Pareto,
+    // Like `Pareto`, but cut the last front by niching around reference directions with the given
+    // number of divisions per objective, as in NSGA-III. Use this with four or more objectives,
+    // where crowding distance stops telling genes apart. See `pareto::select_by_reference`.
+    ReferencePoints(usize),
+    // Choose a fourth of the pool by epsilon-lexicase over the detailed scores from
+    // `Fitness::evaluate_detailed`, one score per test case. See `lexicase`.
+    EpsilonLexicase,
+    // Choose a fourth of the pool by tournaments between the given number of random genes, at
+    // least 1, where the fittest always wins. Bigger tournaments press harder toward the best.
+    Tournament(usize),
+    // Like `Proportionate`, but weighted by rank instead of fitness: the least fit gene has weight
+    // 1, the next 2, and so on. The pressure stays the same however close the fitnesses are.
+    Rank,
+    // Like `Proportionate`, but with one spin of a wheel of evenly spaced pointers, so each gene is
+    // chosen about as often as its fitness says, without the luck of separate draws. A gene a
+    // pointer lands on is only kept once; the places left by genes hit more than once are filled
+    // as `Proportionate` would.
+    StochasticUniversal,
+}
+
+impl Selection {
+    // The number of genes to select from a pool of the given size. Even tiny pools keep at least
+    // one gene.
+    fn survivors(&self, len: usize) -> usize {
+        let n = match *self {
+            Selection::Truncation(fraction) => (len as f32 * fraction).round() as usize,
+            _ => len / 4,
+        };
+        n.max(1).min(len)
+    }
+}
+
+// How each generation of a pool is made up, as fractions of the pool: the survivors, crosses of
+// survivors, mutants of survivors, and fresh genes from the immigration strategy or generated
+// anew. Fresh genes fill whatever the others leave, so they get any rounding. See `PoolBuilder`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Proportions {
+    pub selected: f32,
+    pub crossed: f32,
+    pub mutated: f32,
+    pub fresh: f32,
+}
+
+impl Default for Proportions {
+    // A fourth of each, like a pool without proportions set
+    fn default() -> Proportions {
+        Proportions { selected: 0.25, crossed: 0.25, mutated: 0.25, fresh: 0.25 }
+    }
+}
+
+impl Proportions {
+    // How far the fractions may add up from 1
+    pub const TOLERANCE: f32 = 1e-3;
+
+    // The given fraction of survivors, with the rest of the pool split evenly between crosses,
+    // mutants, and fresh genes as it is by default
+    pub fn with_selected(selected: f32) -> Proportions {
+        let rest = (1.0 - selected) / 3.0;
+        Proportions { selected, crossed: rest, mutated: rest, fresh: rest }
+    }
+
+    // Describe everything wrong with the fractions. Each must be from 0 to 1, some genes must
+    // survive to breed from, and together they must make up the whole pool.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let fractions = [("selected", self.selected), ("crossed", self.crossed), ("mutated", self.mutated),
+                         ("fresh", self.fresh)];
+        for &(name, fraction) in &fractions {
+            if !(0.0 ..= 1.0).contains(&fraction) {
+                problems.push(format!("the {} fraction must be from 0 to 1: {}", name, fraction));
+            }
+        }
+        if self.selected <= 0.0 {
+            problems.push("the selected fraction must be above 0".to_string());
+        }
+        let sum: f32 = fractions.iter().map(|f| f.1).sum();
+        if sum.is_nan() || (sum - 1.0).abs() > Proportions::TOLERANCE {
+            problems.push(format!("the fractions must add up to 1: {}", sum));
+        }
+        problems
+    }
+
+    // The number of genes a fraction of a pool of the given size is
+    fn count(fraction: f32, len: usize) -> usize {
+        (len as f32 * fraction).round() as usize
+    }
+}
+
+// The chances that breeding crosses and mutates each bred gene, as in the canonical genetic
+// algorithm. See `Pool::set_rates`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rates {
+    // The chance that a bred gene is a cross of its parent with a random survivor instead of a copy
+    // of its parent
+    pub crossover: f32,
+    // The chance that a bred gene is then mutated
+    pub mutation: f32,
+}
+
+impl Rates {
+    // Describe everything wrong with the rates. Each is a chance, from 0 to 1.
+    pub fn problems(&self) -> Vec<String> {
+        [("crossover", self.crossover), ("mutation", self.mutation)].iter()
+            .filter(|r| !(0.0 ..= 1.0).contains(&r.1))
+            .map(|r| format!("the {} rate must be from 0 to 1: {}", r.0, r.1))
+            .collect()
+    }
+}
+
+// The weight of each gene for rank selection: 1 for the least fit, up to the number of genes for
+// the most fit. Ties are ranked in the order the genes come.
+fn rank_weights<T>(genes: &[(T, f32)]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0 .. genes.len()).collect();
+    order.sort_by_key(|&i| Objective(genes[i].1));
+    let mut weights = vec![0.0; genes.len()];
+    for (rank, i) in order.into_iter().enumerate() {
+        weights[i] = (rank + 1) as f32;
+    }
+    weights
+}
+
+// The probability that the fitter gene wins a Boltzmann tournament, given how much fitter it is and
+// the temperature. An even match is a coin flip at any temperature.
+fn boltzmann_win(gap: f32, temperature: f32) -> f32 {
+    if gap <= 0.0 {
+        0.5
+    } else if temperature <= 0.0 {
+        1.0
+    } else {
+        1.0 / (1.0 + (-gap / temperature).exp())
+    }
+}
+
+// How long genes may stay in a pool, by their age: the number of generations they've survived
+// into. See `Pool::set_age_limits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AgeLimits {
+    // Genes younger than this survive before any others but the elites, most fit first, as far as
+    // there's room
+    pub protect: usize,
+    // Genes this old never survive, however fit, or None to let genes live forever
+    pub retire: Option<usize>,
+}
+
+// Which genes of the last generation are still unselected, in the order they came. Selecting a
+// gene only marks it, so no gene moves and every index stays put, and a Fenwick tree of the marks
+// finds the nth unselected gene in O(log n). Finding it by that count, instead of by index, makes
+// the same draws select the same genes as taking each selected gene out of the list would.
+struct Unselected {
+    // For each 1-based position p, the number of unselected genes from p - lowbit(p) + 1 to p
+    tree: Vec<usize>,
+    taken: Vec<bool>,
+    left: usize,
+}
+
+impl Unselected {
+    fn new(len: usize) -> Unselected {
+        let mut tree = vec![0; len + 1];
+        for p in 1 ..= len {
+            tree[p] += 1;
+            let parent = p + (p & p.wrapping_neg());
+            if parent <= len {
+                tree[parent] += tree[p];
+            }
+        }
+        Unselected { tree, taken: vec![false; len], left: len }
+    }
+
+    // The number of genes still unselected
+    fn len(&self) -> usize {
+        self.left
+    }
+
+    fn is_empty(&self) -> bool {
+        self.left == 0
+    }
+
+    // The indexes of the unselected genes, in order
+    fn indexes(&self) -> impl Iterator<Item = usize> + '_ {
+        (0 .. self.taken.len()).filter(move |&i| !self.taken[i])
+    }
+
+    // The index of the nth unselected gene, counting from 0
+    fn nth(&self, n: usize) -> usize {
+        let mut p = 0;
+        let mut rest = n + 1;
+        let mut step = (self.tree.len() - 1).checked_next_power_of_two().unwrap_or(0);
+        while step > 0 {
+            if p + step < self.tree.len() && self.tree[p + step] < rest {
+                p += step;
+                rest -= self.tree[p];
+            }
+            step /= 2;
+        }
+        // p is the last position with fewer than n + 1 unselected genes up to it, so the gene is the
+        // next one, which is at index p
+        p
+    }
+
+    fn take(&mut self, i: usize) {
+        self.taken[i] = true;
+        self.left -= 1;
+        let mut p = i + 1;
+        while p < self.tree.len() {
+            self.tree[p] -= 1;
+            p += p & p.wrapping_neg();
+        }
+    }
+}
+
+// Fitness values closer than this count as the same in a pool's fingerprint, so that scores which
+// differ only by rounding, e.g. from summing cases in another order, don't change it
+pub const FINGERPRINT_PRECISION: f32 = 1e-6;
+
+// A fitness as a whole number of FINGERPRINT_PRECISION steps. NaN gets a value of its own.
+fn quantize(fitness: f32) -> i64 {
+    if fitness.is_nan() {
+        i64::MIN
+    } else {
+        (fitness as f64 / FINGERPRINT_PRECISION as f64).round() as i64
+    }
+}
+
+// A hash of a whole generation: every gene with its fitness, rounded to FINGERPRINT_PRECISION,
+// whatever order they're in. Two pools with the same fingerprint hold the same genes scored the
+// same, so tests and replicas can compare pools without comparing every gene. Like journal hashes,
+// it's only stable within one build of the program.
+pub fn fingerprint<T: Hash>(genes: &[(T, f32)]) -> u64 {
+    let mut hashes: Vec<u64> = genes.iter().map(|(gene, fit)| {
+        let mut hasher = DefaultHasher::new();
+        gene.hash(&mut hasher);
+        quantize(*fit).hash(&mut hasher);
+        hasher.finish()
+    }).collect();
+    hashes.sort_unstable();
+    let mut hasher = DefaultHasher::new();
+    hashes.hash(&mut hasher);
+    hasher.finish()
 }
 
 // A pool of genes
-pub struct Pool<T, F> {
+pub struct Pool<T: Gene, F> {
     // The genes in the pool paired with their fitness, in no particular order. Do not assume the
-    // fitness value is up to date
-    pub genes: Vec<(T, f32)>,
+    // fitness value is up to date. See `iter` to look at them from outside.
+    genes: Vec<(T, f32)>,
     // The back-buffer of genes, used when stirring and mutating the pool
     back_genes: Vec<(T, f32)>,
     // The fitness function
     fitness: F,
+    // The parameters the genetic operators are given
+    config: T::Config,
+    // How the survivors of each generation are chosen
+    selection: Selection,
+    // The number of most fit genes that survive each generation whatever the selection
+    elitism: usize,
+    // How each generation is made up, or None for a fourth each, with the selection deciding the
+    // number of survivors
+    proportions: Option<Proportions>,
+    // The chances of crossing and mutating each bred gene, or None to breed crosses and mutants
+    // separately
+    rates: Option<Rates>,
+    // How long genes may stay in the pool, or None for as long as they're selected
+    age_limits: Option<AgeLimits>,
+    // The generation each survivor was first selected in, kept only with age limits
+    births: HashMap<T, usize>,
+    // Where the new genes of each generation come from, or None to generate them
+    immigration: Option<Box<dyn Immigration<T>>>,
+    // The number of mutations in a row that make each mutated gene
+    mutations: usize,
+    // When to score the survivors of each generation again
+    reevaluate: Reevaluate,
+    // The number of generations evolved so far
+    generation: usize,
+    // Every operator applied so far
+    #[cfg(feature = "journal")]
+    pub journal: Journal,
 }
 
 impl<T, F> Pool<T, F>
     where T: Gene + Hash + Eq + Clone,
-          F: Fn(&T) -> f32,
+          F: Fitness<T>,
     {
 
-    // Create and fill a pool of the given size.
-    pub fn new<R: Rng>(size: usize, fitness: F, rng: &mut R) -> Self {
+    // Make a pool of the given genes, e.g. from a checkpoint, evaluating each one. The journal
+    // doesn't know where these genes came from, so it can't replay their descendants. The pool
+    // starts with the default config; see `set_config`.
+    pub fn from_genes(genes: Vec<T>, fitness: F) -> Self
+        where T::Config: Default,
+        {
+        let genes: Vec<(T, f32)> = genes.into_iter().map(|mut g| {
+            g.on_created();
+            let fit = fitness.evaluate(&g);
+            (g, fit)
+        }).collect();
+        Pool {
+            back_genes: Vec::with_capacity(genes.len()),
+            genes,
+            selection: Selection::Proportionate,
+            elitism: 0,
+            proportions: None,
+            rates: None,
+            age_limits: None,
+            births: HashMap::new(),
+            immigration: None,
+            mutations: 1,
+            reevaluate: Reevaluate::default_for(&fitness),
+            generation: 0,
+            fitness,
+            config: T::Config::default(),
+            #[cfg(feature = "journal")]
+            journal: Journal::new(),
+        }
+    }
+
+    // Create and fill a pool of the given size, with the default config.
+    pub fn new<R: Rng>(size: usize, fitness: F, rng: &mut R) -> Self
+        where T::Config: Default,
+        {
+        Pool::with_config(size, T::Config::default(), fitness, rng)
+    }
+
+    // Create a pool of the given size that starts with the given genes, e.g. a previous run's winner
+    // or hand-written programs, and fill the rest with random genes, with the default config. If
+    // there are more seeds than fit, only the first `size` are used.
+    pub fn with_seeds<R: Rng>(size: usize, seeds: Vec<T>, fitness: F, rng: &mut R) -> Self
+        where T::Config: Default,
+        {
+        let mut pool = Pool::with_config(0, T::Config::default(), fitness, rng);
+        for mut gene in seeds.into_iter().take(size) {
+            gene.on_created();
+            let fit = pool.fitness.evaluate(&gene);
+            pool.genes.push((gene, fit));
+        }
+        while pool.genes.len() < size {
+            let gene = pool.generate_gene(rng);
+            let fit = pool.fitness.evaluate(&gene);
+            pool.genes.push((gene, fit));
+        }
+        pool
+    }
+
+    // Create and fill a pool of the given size, generating and breeding genes with the given config
+    pub fn with_config<R: Rng>(size: usize, config: T::Config, fitness: F, rng: &mut R) -> Self {
         let mut pool = Pool {
             genes: Vec::with_capacity(size),
             back_genes: Vec::with_capacity(size),
-            fitness: fitness,
+            config,
+            selection: Selection::Proportionate,
+            elitism: 0,
+            proportions: None,
+            rates: None,
+            age_limits: None,
+            births: HashMap::new(),
+            immigration: None,
+            mutations: 1,
+            reevaluate: Reevaluate::default_for(&fitness),
+            generation: 0,
+            fitness,
+            #[cfg(feature = "journal")]
+            journal: Journal::new(),
         };
         while pool.genes.len() < size {
-            let gene = Gene::generate(rng);
-            let fit = (pool.fitness)(&gene);
+            let gene = pool.generate_gene(rng);
+            let fit = pool.fitness.evaluate(&gene);
             pool.genes.push((gene, fit));
         }
         pool
     }
 
-    // Evolve one generation using the given fitness function. All genes currently in the pool are
-    // evaluated for fitness, then the most fit half is kept and the least fit half is replaced
-    // with mutations of the more fit half.
+    // Evolve one generation using the given fitness function, in three phases. First the survivors
+    // are chosen: genes past the age limits retire, the elite are kept, and the selection picks
+    // the rest, as many as it or the proportions keep. Then offspring fill the pool back up:
+    // crosses and mutants of the survivors, as many of each as there are survivors unless the
+    // proportions say otherwise, or genes bred at the rates in their place, and then fresh genes
+    // from the immigration strategy or generated like the first generation. Last, the new genes
+    // are evaluated, along with the survivors when the reevaluation policy is due.
     pub fn evolve<R: Rng>(&mut self, rng: &mut R) {
+        self.evolve_until(rng, &CancellationToken::new());
+    }
+
+    // Like `evolve`, but give up as soon as the token is cancelled. The token is checked before
+    // breeding and again before each new gene is evaluated. Returns whether the generation
+    // finished; if it didn't, the new genes left unevaluated have a fitness of 0, and the genes
+    // kept from the last generation (including the best) keep a valid score, old or new.
+    pub fn evolve_until<R: Rng>(&mut self, rng: &mut R, token: &CancellationToken) -> bool {
+        self.evolve_phases_until(&mut Shared(rng), token)
+    }
+
+    // Like `evolve`, but each phase of breeding draws from its own RNG, seeded with (seed,
+    // generation, phase). Changing how one phase uses randomness, e.g. trying a different mutation
+    // operator, then leaves the random draws of the other phases as they were, which makes
+    // comparing operators fairer. This gives different results than `evolve` for the same seed.
+    pub fn evolve_streams(&mut self, seed: u64, generation: usize) {
+        self.evolve_phases_until(&mut Streams::new(seed, generation), &CancellationToken::new());
+    }
+
+    fn evolve_phases_until(&mut self, rngs: &mut dyn Randomness, token: &CancellationToken) -> bool {
+        if token.is_cancelled() {
+            return false;
+        }
+        let first_new = self.breed(rngs);
+        // Evaluate the new genes, and the survivors too if the policy says so
+        let first = self.first_to_evaluate(first_new);
+        let fitness = &self.fitness;
+        for g in &mut self.genes[first ..] {
+            if token.is_cancelled() {
+                return false;
+            }
+            g.1 = fitness.evaluate(&g.0);
+        }
+        true
+    }
+
+    // The config the genetic operators are given
+    pub fn config(&self) -> &T::Config {
+        &self.config
+    }
+
+    // Change the config for the genes made from now on, e.g. to follow what the run has learned
+    pub fn set_config(&mut self, config: T::Config) {
+        self.config = config;
+    }
+
+    // Set how the survivors of each generation are chosen. Pools start with
+    // `Selection::Proportionate`.
+    pub fn set_selection(&mut self, selection: Selection) {
+        self.selection = selection;
+    }
+
+    // Carry the `n` most fit genes into each next generation unchanged, before selection chooses
+    // the other survivors, so the best gene found is never lost to bad luck. They count as
+    // survivors, and are crossed and mutated like the rest. If there are more of them than the
+    // selection would keep, all of them survive anyway. Pools start with 0.
+    pub fn set_elitism(&mut self, n: usize) {
+        self.elitism = n;
+    }
+
+    // Make up each generation with the given proportions of survivors, crosses, mutants, and fresh
+    // genes, instead of a fourth each. The selected fraction replaces the number of survivors the
+    // selection would keep. Pools start without proportions; see `Proportions::problems` for what
+    // makes sense.
+    pub fn set_proportions(&mut self, proportions: Proportions) {
+        self.proportions = Some(proportions);
+    }
+
+    // Breed each gene the way the canonical genetic algorithm does, instead of making separate
+    // crosses and mutants: each one crosses its parent with a random survivor with the crossover
+    // rate's chance, or else copies it, and then mutates it with the mutation rate's chance. The
+    // bred genes take the places of both the crosses and the mutants, and the survivors take turns
+    // being their parent. Pools start without rates.
+    pub fn set_rates(&mut self, rates: Rates) {
+        self.rates = Some(rates);
+    }
+
+    // Limit how long genes stay in the pool, so an early champion can't crowd out everything bred
+    // after it. A gene's age is the number of generations it has survived into, so genes bred
+    // last generation are 0. Ages belong to the program rather than the copy: a gene bred the same
+    // as one already in the pool is as old as it. Pools start without limits.
+    pub fn set_age_limits(&mut self, limits: AgeLimits) {
+        self.age_limits = Some(limits);
+    }
+
+    // The number of crosses and of mutants to breed from `num_selected` survivors in a pool of the
+    // given size. Without proportions, there are as many of each as survivors.
+    fn offspring(&self, len: usize, num_selected: usize) -> (usize, usize) {
+        match self.proportions {
+            Some(p) if num_selected > 0 => (Proportions::count(p.crossed, len), Proportions::count(p.mutated, len)),
+            Some(_) => (0, 0),
+            None => (num_selected, num_selected),
+        }
+    }
+
+    // Make each mutated gene with this many mutations in a row, at least 1, e.g. to explore more
+    // widely early in a run. Pools start with 1.
+    pub fn set_mutations(&mut self, mutations: usize) {
+        self.mutations = mutations.max(1);
+    }
+
+    // Make the new genes that fill the last part of each generation with the given strategy,
+    // instead of generating them like the first generation
+    pub fn set_immigration<I: Immigration<T> + 'static>(&mut self, immigration: I) {
+        self.immigration = Some(Box::new(immigration));
+    }
+
+    // Set when the survivors of each generation are scored again. The default depends on whether
+    // the fitness function is deterministic; see `Reevaluate::default_for`.
+    pub fn set_reevaluate(&mut self, policy: Reevaluate) {
+        self.reevaluate = policy;
+    }
+
+    // Score every gene in the pool again now, whatever the policy
+    pub fn reevaluate(&mut self) {
+        let fitness = &self.fitness;
+        for g in &mut self.genes {
+            g.1 = fitness.evaluate(&g.0);
+        }
+    }
+
+    // The index of the first gene to evaluate in the generation just bred: the first new gene, or
+    // 0 if the survivors are due to be scored again
+    fn first_to_evaluate(&self, first_new: usize) -> usize {
+        if self.reevaluate.is_due(self.generation) { 0 } else { first_new }
+    }
+
+    // Replace the pool with the next generation, without evaluating the new genes. Returns the
+    // index of the first new gene; the genes after it need their fitness evaluated.
+    fn breed(&mut self, rngs: &mut dyn Randomness) -> usize {
         // The pool size to maintain
         let len = self.genes.len();
+        let num_selected = self.select(&mut rngs.rng(Phase::Selection));
+        let (mut crosses, mut mutants) = self.offspring(len, num_selected);
+        if let Some(rates) = self.rates {
+            // Genes bred at the rates take the places of both the crosses and the mutants
+            self.breed_at_rates(crosses + mutants, num_selected, len, rates, rngs);
+            crosses = 0;
+            mutants = 0;
+        }
+
+        // Fill the next part, a fourth unless the proportions say otherwise, with crosses, going
+        // around the survivors as many times as it takes.
+        // Tiny pools may have no room left for them.
+        for i in 0 .. crosses {
+            if self.genes.len() >= len {
+                break;
+            }
+            // Pick a random cross partner
+            let mut rng = rngs.rng(Phase::Crossover);
+            let with_i = (&mut rng).gen_range(0, num_selected);
+            let crossed_gene = self.cross_genes(i % num_selected, with_i, &mut rng);
+            self.genes.push((crossed_gene, 0.0));
+        }
+
+        // Fill the next part with mutations
+        for i in 0 .. mutants {
+            if self.genes.len() >= len {
+                break;
+            }
+            let mutated_gene = self.mutate_gene(i % num_selected, &mut rngs.rng(Phase::Mutation));
+            self.genes.push((mutated_gene, 0.0));
+        }
+
+        // Fill the rest with new genes
+        while self.genes.len() < len {
+            let new_gene = self.immigrate_gene(num_selected, &mut rngs.rng(Phase::Immigration));
+            self.genes.push((new_gene, 0.0));
+        }
+        num_selected
+    }
+
+    // Breed up to `count` genes from the first `num_selected` at the given rates, as long as the pool
+    // has room for them below `len`. See `set_rates`.
+    fn breed_at_rates(&mut self, count: usize, num_selected: usize, len: usize, rates: Rates,
+                      rngs: &mut dyn Randomness) {
+        for i in 0 .. count {
+            if self.genes.len() >= len {
+                break;
+            }
+            let (survivors, config, mutations) = (&self.genes[.. num_selected], &self.config, self.mutations);
+            let gene = cross_or_copy(survivors, i % num_selected, config, rates.crossover,
+                                     &mut rngs.rng(Phase::Crossover), #[cfg(feature = "journal")] &mut self.journal);
+            let mut gene = maybe_mutate(gene, config, mutations, rates.mutation, &mut rngs.rng(Phase::Mutation),
+                                        #[cfg(feature = "journal")] &mut self.journal);
+            gene.on_created();
+            self.genes.push((gene, 0.0));
+        }
+    }
+
+    // Start the next generation by moving the selected genes into it, leaving the rest of the last
+    // generation in the back buffer. Returns the number of genes selected.
+    fn select<R: Rng>(&mut self, rng: &mut R) -> usize {
+        let len = self.genes.len();
+        self.generation += 1;
 
         // Swap into the back buffer so we can assemble a new pool of genes
         ::std::mem::swap(&mut self.genes, &mut self.back_genes);
 
-        // Sum up the total fitness
+        self.genes.clear();
+        let births = ::std::mem::take(&mut self.births);
+        let generation = self.generation;
+        let age = |g: &T| generation - births.get(g).cloned().unwrap_or(generation);
+        let limits = self.age_limits.unwrap_or(AgeLimits { protect: 0, retire: None });
+        if let Some(retire) = limits.retire {
+            // Retire the old genes, unless that would leave none
+            if self.back_genes.iter().any(|g| age(&g.0) < retire) {
+                for g in self.back_genes.iter_mut().filter(|g| age(&g.0) >= retire) {
+                    g.0.on_discarded();
+                }
+                self.back_genes.retain(|g| age(&g.0) < retire);
+            }
+        }
+        let elites = self.elitism.min(self.back_genes.len());
+        let survivors = match self.proportions {
+            Some(p) => Proportions::count(p.selected, len).max(1).min(len),
+            None => self.selection.survivors(len),
+        };
+        let num_to_select = survivors.max(elites);
+        if elites > 0 {
+            // Take out the most fit genes, leaving the rest in the order they were. The sort is
+            // stable, so ties go to the gene that came first.
+            let mut order: Vec<usize> = (0 .. self.back_genes.len()).collect();
+            order.sort_by_key(|&i| Reverse(Objective(self.back_genes[i].1)));
+            let mut back: Vec<Option<(T, f32)>> = self.back_genes.drain(..).map(Some).collect();
+            for &i in &order[.. elites] {
+                let mut elite = back[i].take().unwrap();
+                elite.0.on_selected();
+                self.genes.push(elite);
+            }
+            self.back_genes.extend(back.into_iter().flatten());
+        }
+        if limits.protect > 0 {
+            // Then the young genes, most fit first, in the same way
+            let mut order: Vec<usize> = (0 .. self.back_genes.len())
+                .filter(|&i| age(&self.back_genes[i].0) < limits.protect)
+                .collect();
+            order.sort_by_key(|&i| Reverse(Objective(self.back_genes[i].1)));
+            order.truncate(num_to_select - self.genes.len());
+            let mut back: Vec<Option<(T, f32)>> = self.back_genes.drain(..).map(Some).collect();
+            for &i in &order {
+                let mut young = back[i].take().unwrap();
+                young.0.on_selected();
+                self.genes.push(young);
+            }
+            self.back_genes.extend(back.into_iter().flatten());
+        }
+        if let Selection::Truncation(_) = self.selection {
+            // Keep the most fit genes. The sort is stable, so ties keep their order.
+            self.back_genes.sort_by_key(|g| Reverse(Objective(g.1)));
+            let (start, keep) = (self.genes.len(), (num_to_select - self.genes.len()).min(self.back_genes.len()));
+            self.genes.extend(self.back_genes.drain(.. keep));
+            for g in &mut self.genes[start ..] {
+                g.0.on_selected();
+            }
+        }
+        let detailed = matches!(self.selection,
+                                Selection::Pareto | Selection::ReferencePoints(_) | Selection::EpsilonLexicase);
+        if detailed {
+            // Score the cases of every gene again, since pools only keep the overall fitness
+            let fitness = &self.fitness;
+            let points: Vec<Vec<f32>> = self.back_genes.iter()
+                .map(|g| fitness.evaluate_detailed(&g.0).unwrap_or_else(|| vec![g.1]))
+                .collect();
+            let wanted = num_to_select - self.genes.len();
+            let chosen = match self.selection {
+                Selection::ReferencePoints(divisions) => {
+                    pareto::select_by_reference(&points, wanted, divisions, rng)
+                }
+                Selection::EpsilonLexicase => lexicase::select(&points, wanted, rng),
+                _ => pareto::select(&points, wanted),
+            };
+            let mut back: Vec<Option<(T, f32)>> = self.back_genes.drain(..).map(Some).collect();
+            for i in chosen {
+                let mut selected = back[i].take().unwrap();
+                selected.0.on_selected();
+                self.genes.push(selected);
+            }
+            self.back_genes.extend(back.into_iter().flatten());
+        }
+        // The weight of each gene left, by its fitness or its rank. Tournaments fill the selection
+        // unless they run out of genes, so these are only used when no tournament runs.
+        let weights: Vec<f32> = match self.selection {
+            Selection::Rank => rank_weights(&self.back_genes),
+            _ => self.back_genes.iter().map(|g| Objective(g.1).weight()).collect(),
+        };
+        // Sum up the total weight
         let mut total_fitness = 0.0;
-        for pair in &self.back_genes {
-            total_fitness += pair.1;
+        for &w in &weights {
+            total_fitness += w;
         }
 
-        // Fill the first fourth of the pool by stochastic selection (higher fitness = more likely
-        // to be selected)
-        self.genes.clear();
-        while self.genes.len() < len / 4 && !self.back_genes.is_empty() {
-            // Pick a number within total fitness
-            let mut f = rng.gen_range(0.0, total_fitness);
-            // Select the gene under that fitness offset
-            let mut i = 0;
-            f -= self.back_genes[i].1;
-            while f > 0.0 {
-                i = (i + 1) % self.back_genes.len();
-                f -= self.back_genes[i].1;
-            }
-            // Subtract its fitness from the total
-            total_fitness -= self.back_genes[i].1;
-            // Move the gene from back_genes to genes
-            self.genes.push(self.back_genes.remove(i));
+        // Select the rest by marking them, so the genes left in the back buffer never move
+        let mut back: Vec<Option<(T, f32)>> = self.back_genes.drain(..).map(Some).collect();
+        let mut unselected = Unselected::new(back.len());
+        let fitness_of = |back: &[Option<(T, f32)>], i: usize| Objective(back[i].as_ref().unwrap().1);
+        let select = |back: &mut [Option<(T, f32)>], unselected: &mut Unselected, genes: &mut Vec<(T, f32)>, i: usize| {
+            let mut selected = back[i].take().unwrap();
+            selected.0.on_selected();
+            genes.push(selected);
+            unselected.take(i);
+        };
+
+        let temperature = match self.selection {
+            // Schedules start from generation 0
+            Selection::Boltzmann(ref schedule) => Some(schedule.value_at(self.generation - 1)),
+            _ => None,
+        };
+        if let Some(temperature) = temperature {
+            while self.genes.len() < num_to_select && !unselected.is_empty() {
+                // A gene left on its own has no one to compete with
+                if unselected.len() == 1 {
+                    let last = unselected.nth(0);
+                    select(&mut back, &mut unselected, &mut self.genes, last);
+                    continue;
+                }
+                // Two different competitors: the second is drawn from the genes other than the first
+                let i = rng.gen_range(0, unselected.len());
+                let j = rng.gen_range(0, unselected.len() - 1);
+                let (a, b) = (unselected.nth(i), unselected.nth(if j >= i { j + 1 } else { j }));
+                let (fit_a, fit_b) = (fitness_of(&back, a), fitness_of(&back, b));
+                let (fitter, other) = if fit_a >= fit_b { (a, b) } else { (b, a) };
+                let gap = (fit_a.weight() - fit_b.weight()).abs();
+                let winner = if rng.gen::<f32>() < boltzmann_win(gap, temperature) { fitter } else { other };
+                select(&mut back, &mut unselected, &mut self.genes, winner);
+            }
         }
-        // The number of genes that actually got selected
-        let num_selected = self.genes.len();
 
-        // Fill the next fourth with crosses
-        for i in 0 .. num_selected {
-            // Pick a random cross partner
-            let with_i = rng.gen_range(0, len/4);
-            let crossed_gene = self.genes[i].0.cross(&self.genes[with_i].0, rng);
-            let crossed_fit = (self.fitness)(&crossed_gene);
-            self.genes.push((crossed_gene, crossed_fit));
+        if let Selection::Tournament(size) = self.selection {
+            while self.genes.len() < num_to_select && !unselected.is_empty() {
+                let mut winner = unselected.nth(rng.gen_range(0, unselected.len()));
+                for _ in 1 .. size {
+                    let rival = unselected.nth(rng.gen_range(0, unselected.len()));
+                    if fitness_of(&back, rival) > fitness_of(&back, winner) {
+                        winner = rival;
+                    }
+                }
+                select(&mut back, &mut unselected, &mut self.genes, winner);
+            }
+        }
+
+        if self.selection == Selection::StochasticUniversal && total_fitness > 0.0 {
+            let wanted = num_to_select - self.genes.len();
+            let spacing = total_fitness / wanted as f32;
+            let mut pointer = rng.gen_range(0.0, spacing);
+            let mut hit = Vec::new();
+            let mut end = 0.0;
+            for (i, &w) in weights.iter().enumerate() {
+                end += w;
+                if pointer < end && hit.len() < wanted {
+                    hit.push(i);
+                    while pointer < end {
+                        pointer += spacing;
+                    }
+                }
+            }
+            for i in hit {
+                total_fitness -= weights[i];
+                select(&mut back, &mut unselected, &mut self.genes, i);
+            }
+        }
+
+        // Otherwise fill the first part of the pool by stochastic selection (higher weight = more
+        // likely to be selected)
+        while self.genes.len() < num_to_select && !unselected.is_empty() {
+            let i = if total_fitness > 0.0 {
+                // Pick a number within total weight
+                let mut f = rng.gen_range(0.0, total_fitness);
+                // Select the unselected gene under that offset. Rounding can leave a sliver of f
+                // after the last gene, in which case the last gene is selected.
+                let mut rest = unselected.indexes();
+                let mut i = rest.next().unwrap();
+                f -= weights[i];
+                while f > 0.0 {
+                    match rest.next() {
+                        Some(next) => {
+                            i = next;
+                            f -= weights[i];
+                        }
+                        None => break,
+                    }
+                }
+                i
+            } else {
+                // No gene has any weight left to go by, so select uniformly
+                unselected.nth(rng.gen_range(0, unselected.len()))
+            };
+            // Subtract its weight from the total
+            total_fitness -= weights[i];
+            select(&mut back, &mut unselected, &mut self.genes, i);
         }
+        // The rest of the last generation is done with
+        for g in back.iter_mut().flatten() {
+            g.0.on_discarded();
+        }
+        if self.age_limits.is_some() {
+            self.births = self.genes.iter()
+                .map(|g| (g.0.clone(), births.get(&g.0).cloned().unwrap_or(generation)))
+                .collect();
+        }
+        // The number of genes that actually got selected
+        self.genes.len()
+    }
 
-        // Fill the next fourth with mutations
-        for i in 0 .. num_selected {
-            let mutated_gene = self.genes[i].0.mutate(rng);
-            let mutated_fit = (self.fitness)(&mutated_gene);
-            self.genes.push((mutated_gene, mutated_fit));
+    // Change the size of the pool. Growing fills the pool with new random genes, and shrinking
+    // drops the least fit genes. Later generations keep the new size.
+    pub fn resize<R: Rng>(&mut self, new_size: usize, rng: &mut R) {
+        if new_size < self.genes.len() {
+            // Sort the most fit genes first, then drop the rest
+            self.genes.sort_by_key(|g| Reverse(Objective(g.1)));
+            for g in &mut self.genes[new_size ..] {
+                g.0.on_discarded();
+            }
+            self.genes.truncate(new_size);
+        }
+        while self.genes.len() < new_size {
+            let gene = self.generate_gene(rng);
+            let fit = self.fitness.evaluate(&gene);
+            self.genes.push((gene, fit));
         }
+    }
 
-        // Fill the last fourth by generating new genes
+    // Replace the `count` least fit genes with new random ones, e.g. to bring variety back into a
+    // pool that has stopped improving. The pool keeps its size.
+    pub fn refresh<R: Rng>(&mut self, count: usize, rng: &mut R) {
+        let len = self.genes.len();
+        let keep = len - count.min(len);
+        self.genes.sort_by_key(|g| Reverse(Objective(g.1)));
+        for g in &mut self.genes[keep ..] {
+            g.0.on_discarded();
+        }
+        self.genes.truncate(keep);
         while self.genes.len() < len {
-            let generated_gene = Gene::generate(rng);
-            let generated_fit = (self.fitness)(&generated_gene);
-            self.genes.push((generated_gene, generated_fit));
+            let gene = self.generate_gene(rng);
+            let fit = self.fitness.evaluate(&gene);
+            self.genes.push((gene, fit));
+        }
+    }
+
+    // Get copies of the `n` most fit genes with their fitness, best first
+    pub fn best_n(&self, n: usize) -> Vec<(T, f32)> {
+        let mut sorted: Vec<&(T, f32)> = self.genes.iter().collect();
+        sorted.sort_by_key(|g| Reverse(Objective(g.1)));
+        sorted.into_iter().take(n).cloned().collect()
+    }
+
+    // Replace the least fit genes with the given genes, e.g. migrants from another pool. The pool
+    // keeps its size, so at most that many genes are taken in.
+    pub fn immigrate(&mut self, mut genes: Vec<(T, f32)>) {
+        let len = self.genes.len();
+        genes.truncate(len);
+        self.genes.sort_by_key(|g| Reverse(Objective(g.1)));
+        for g in &mut self.genes[len - genes.len() ..] {
+            g.0.on_discarded();
+        }
+        self.genes.truncate(len - genes.len());
+        for g in &mut genes {
+            g.0.on_created();
         }
+        self.genes.extend(genes);
+    }
+
+    // Generate a new random gene, recording it in the journal if that feature is enabled
+    fn generate_gene<R: Rng>(&mut self, rng: &mut R) -> T {
+        let config = &self.config;
+        #[cfg(feature = "journal")]
+        let mut gene = self.journal.record(Op::Generate, &[], rng, |rng| T::generate(config, rng));
+        #[cfg(not(feature = "journal"))]
+        let mut gene = T::generate(config, rng);
+        gene.on_created();
+        gene
+    }
+
+    // Make a new gene with the immigration strategy from the first `num_selected` genes, or
+    // generate one if there's no strategy
+    fn immigrate_gene<R: Rng>(&mut self, num_selected: usize, rng: &mut R) -> T {
+        let immigration = match self.immigration {
+            Some(ref immigration) => immigration,
+            None => return self.generate_gene(rng),
+        };
+        let (survivors, config, generation) = (&self.genes[.. num_selected], &self.config, self.generation);
+        #[cfg(feature = "journal")]
+        let mut gene = self.journal.record(Op::Immigrate, &[], rng, |rng| {
+            immigration.immigrant(config, survivors, generation, rng)
+        });
+        #[cfg(not(feature = "journal"))]
+        let mut gene = immigration.immigrant(config, survivors, generation, rng);
+        gene.on_created();
+        gene
+    }
+
+    // Mutate the gene at index i, recording it in the journal if that feature is enabled
+    fn mutate_gene<R: Rng>(&mut self, i: usize, rng: &mut R) -> T {
+        let mut gene = mutate_repeatedly(&self.genes[i].0, &self.config, self.mutations, rng,
+                                         #[cfg(feature = "journal")] &mut self.journal);
+        gene.on_created();
+        gene
+    }
+
+    // Cross the genes at indexes i and j, recording it in the journal if that feature is enabled
+    fn cross_genes<R: Rng>(&mut self, i: usize, j: usize, rng: &mut R) -> T {
+        let (a, b, config) = (&self.genes[i].0, &self.genes[j].0, &self.config);
+        #[cfg(feature = "journal")]
+        let mut gene = self.journal.record(Op::Cross, &[a, b], rng, |rng| a.cross(config, b, rng));
+        #[cfg(not(feature = "journal"))]
+        let mut gene = a.cross(config, b, rng);
+        gene.on_created();
+        gene
     }
 
     // Get the current best gene and its fitness. This is only valid after a call to evolve.
     pub fn get_best(&self) -> (&T, f32) {
         let mut best = &self.genes[0];
         for g in &self.genes {
-            if g.1 > best.1 {
+            if Objective(g.1) > Objective(best.1) {
                 best = g;
             }
         }
         (&best.0, best.1)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    extern crate rand;
-    use super::*;
-    use rand::Rng;
+    // Look at every gene in the pool with its fitness and metadata, in no particular order. Do not
+    // assume the fitness value is up to date.
+    pub fn iter(&self) -> Genes<'_, T> {
+        Genes { genes: self.genes.iter().enumerate() }
+    }
 
-    static mut NEXT_ID: i32 = 1;
+    // The number of genes in the pool
+    pub fn len(&self) -> usize {
+        self.genes.len()
+    }
 
-    #[derive(PartialEq, Eq, Hash, Clone, Debug)]
-    struct TestGene {
-        id: i32,
+    pub fn is_empty(&self) -> bool {
+        self.genes.is_empty()
     }
 
-    impl Gene for TestGene {
-        fn generate<R: Rng>(_rng: &mut R) -> Self {
-            unsafe {
-                let id = NEXT_ID;
-                NEXT_ID += 1;
-                TestGene { id: id }
-            }
-        }
+    // A hash of the whole generation. See `fingerprint`.
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint(&self.genes)
+    }
 
-        fn mutate<R: Rng>(&self, _rng: &mut R) -> Self {
-            TestGene { id: -self.id }
-        }
+    // Summarize the current generation. This is only valid after a call to evolve, like `get_best`.
+    pub fn stats(&self) -> GenerationStats<&T> {
+        let fits: Vec<f32> = self.genes.iter().map(|g| g.1).collect();
+        let unique = self.genes.iter().map(|g| &g.0).collect::<HashSet<_>>().len();
+        GenerationStats::of(self.get_best().0, &fits, unique)
+    }
+
+    // Get the gene that scores best by `validate`, e.g. on cases held out from fitness, with its
+    // fitness. Ties go to the shorter gene. This is how to pick a final answer that generalizes,
+    // since the gene with the best fitness may only be best at the cases it was evolved against.
+    pub fn get_best_validated<V: Fn(&T) -> f32>(&self, validate: V) -> (&T, f32) {
+        let best = self.genes.iter()
+            .map(|g| (g, validate(&g.0)))
+            .max_by_key(|&(g, v)| (Objective(v), Reverse(g.0.size())))
+            .unwrap().0;
+        (&best.0, best.1)
+    }
+
+    // Evolve one generation per item, forever. Each item describes the pool after its generation,
+    // so callers can stop with `take_while` or `take`, or show progress as it comes.
+    pub fn evolve_iter<'a, R: Rng>(&'a mut self, rng: &'a mut R) -> EvolveIter<'a, T, F, R> {
+        let best_fit = self.get_best().1;
+        EvolveIter { pool: self, rng, generation: 0, best_fit }
+    }
 
-        fn cross<R: Rng>(&self, other: &Self, _rng: &mut R) -> Self {
-            TestGene { id: self.id * 100 + other.id }
+    // Evolve until any of the termination's conditions is met, and say why it stopped. The limits
+    // on generations and time are checked before each generation, and the target and stagnation
+    // after it. Returns the termination's problems instead, without evolving, unless at least one
+    // condition is set, since the run would never stop.
+    pub fn run_until<R: Rng>(&mut self, termination: Termination, rng: &mut R) -> Result<RunSummary<T>, Vec<String>> {
+        let problems = termination.problems();
+        if !problems.is_empty() {
+            return Err(problems);
         }
+        let start = Instant::now();
+        let (mut generations, mut stall, mut longest_stall, mut improved_at) = (0, 0, 0, None);
+        let reason = {
+            let mut results = self.evolve_iter(rng);
+            loop {
+                if termination.generations.is_some_and(|g| generations >= g) {
+                    break StopReason::Generations;
+                }
+                if termination.timeout.is_some_and(|t| start.elapsed() >= t) {
+                    break StopReason::Timeout;
+                }
+                let result = results.next().unwrap();
+                generations += 1;
+                if result.improved {
+                    stall = 0;
+                    improved_at = Some(result.generation);
+                } else {
+                    stall += 1;
+                    longest_stall = longest_stall.max(stall);
+                }
+                if termination.target_fitness.is_some_and(|t| result.fitness >= t) {
+                    break StopReason::TargetFitness;
+                }
+                if termination.stagnation.is_some_and(|n| stall >= n) {
+                    break StopReason::Stagnation;
+                }
+            }
+        };
+        let (best, fitness) = self.get_best();
+        let mean = self.genes.iter().map(|g| g.1).sum::<f32>() / self.genes.len() as f32;
+        Ok(RunSummary {
+            reason,
+            generations,
+            best: best.clone(),
+            fitness,
+            mean,
+            improved_at,
+            longest_stall,
+            elapsed: start.elapsed(),
+        })
     }
+}
 
-    #[test]
-    fn gen_pool() {
-        // A deterministic RNG for testing
-        use rand::SeedableRng;
-        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+// A summary of one generation of a pool. See `Pool::stats`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationStats<G> {
+    // The most fit gene, whose fitness is `max`
+    pub best: G,
+    // The fitness of the pool, leaving out genes that score NaN. All NaN if every gene does.
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub median: f32,
+    pub std_dev: f32,
+    // The number of distinct genes, a measure of diversity
+    pub unique: usize,
+}
 
-        // Generate the pool by calling generate() n times.
-        let fitness = |g: &TestGene| { g.id as f32 };
-        let mut pool = Pool::new(10, fitness, rng);
-        assert_eq!(pool.genes[0].0.id, 1);
-        assert_eq!(pool.genes[9].0.id, 10);
+impl<G> GenerationStats<G> {
+    fn of(best: G, fits: &[f32], unique: usize) -> GenerationStats<G> {
+        let mut fits: Vec<f32> = fits.iter().cloned().filter(|f| !f.is_nan()).collect();
+        fits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = fits.len();
+        if n == 0 {
+            let nan = f32::NAN;
+            return GenerationStats { best, min: nan, max: nan, mean: nan, median: nan, std_dev: nan, unique };
+        }
+        let mean = fits.iter().sum::<f32>() / n as f32;
+        let median = if n % 2 == 1 { fits[n / 2] } else { (fits[n / 2 - 1] + fits[n / 2]) / 2.0 };
+        let std_dev = (fits.iter().map(|f| (f - mean) * (f - mean)).sum::<f32>() / n as f32).sqrt();
+        GenerationStats { best, min: fits[0], max: fits[n - 1], mean, median, std_dev, unique }
+    }
+}
 
-        // Evolve the pool
-        pool.evolve(rng);
+// When `Pool::run_until` stops: at whichever of the conditions that are set comes first
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Termination {
+    // Stop after this many generations
+    pub generations: Option<usize>,
+    // Stop once the best fitness reaches this
+    pub target_fitness: Option<f32>,
+    // Stop once the best fitness hasn't improved for this many generations in a row
+    pub stagnation: Option<usize>,
+    // Stop once this much time has passed. A generation that has started always finishes.
+    pub timeout: Option<Duration>,
+}
 
-        // Make sure 4 new genes were generated
-        unsafe {
-            assert_eq!(NEXT_ID, 15);
+impl Termination {
+    // Describe everything wrong with the termination
+    pub fn problems(&self) -> Vec<String> {
+        if *self == Termination::default() {
+            vec!["the run needs at least one condition to stop on".to_string()]
+        } else {
+            Vec::new()
         }
+    }
+}
 
-        // Make sure the same genes were selected (because we know the random seed)
+// Why `Pool::run_until` stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Generations,
+    TargetFitness,
+    Stagnation,
+    Timeout,
+}
+
+// How a run of `Pool::run_until` went
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunSummary<T> {
+    pub reason: StopReason,
+    // The number of generations evolved
+    pub generations: usize,
+    // The best gene at the end and its fitness
+    pub best: T,
+    pub fitness: f32,
+    // The mean fitness of the pool at the end
+    pub mean: f32,
+    // The last generation whose best fitness beat every earlier one's, counting from 0, or None if
+    // none did
+    pub improved_at: Option<usize>,
+    // The most generations in a row the best fitness didn't improve
+    pub longest_stall: usize,
+    pub elapsed: Duration,
+}
+
+// Set up a pool step by step: its size, how each generation is made up, elitism, and selection.
+// Anything not set is as `Pool::new` leaves it, except that setting any fraction sets them all, the
+// others to their defaults from `Proportions::default`.
+#[derive(Clone, Debug)]
+pub struct PoolBuilder {
+    size: usize,
+    proportions: Option<Proportions>,
+    rates: Option<Rates>,
+    elitism: usize,
+    selection: Selection,
+}
+
+impl PoolBuilder {
+    // Start building a pool of the given size
+    pub fn new(size: usize) -> PoolBuilder {
+        PoolBuilder { size, proportions: None, rates: None, elitism: 0, selection: Selection::Proportionate }
+    }
+
+    // The fraction of the pool that survives each generation. See `Proportions`.
+    pub fn selected(mut self, fraction: f32) -> PoolBuilder {
+        self.proportions.get_or_insert_with(Proportions::default).selected = fraction;
+        self
+    }
+
+    // The fraction of the pool made by crossing survivors
+    pub fn crossed(mut self, fraction: f32) -> PoolBuilder {
+        self.proportions.get_or_insert_with(Proportions::default).crossed = fraction;
+        self
+    }
+
+    // The fraction of the pool made by mutating survivors
+    pub fn mutated(mut self, fraction: f32) -> PoolBuilder {
+        self.proportions.get_or_insert_with(Proportions::default).mutated = fraction;
+        self
+    }
+
+    // The fraction of the pool made of fresh genes
+    pub fn fresh(mut self, fraction: f32) -> PoolBuilder {
+        self.proportions.get_or_insert_with(Proportions::default).fresh = fraction;
+        self
+    }
+
+    // The chances of crossing and mutating each bred gene. See `Pool::set_rates`.
+    pub fn rates(mut self, crossover: f32, mutation: f32) -> PoolBuilder {
+        self.rates = Some(Rates { crossover, mutation });
+        self
+    }
+
+    // See `Pool::set_elitism`
+    pub fn elitism(mut self, n: usize) -> PoolBuilder {
+        self.elitism = n;
+        self
+    }
+
+    // See `Pool::set_selection`
+    pub fn selection(mut self, selection: Selection) -> PoolBuilder {
+        self.selection = selection;
+        self
+    }
+
+    // Describe everything wrong with the settings, like `RunConfig::validate`
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.size == 0 {
+            problems.push("the pool size must be at least 1".to_string());
+        }
+        if let Some(ref proportions) = self.proportions {
+            problems.extend(proportions.problems());
+        }
+        if let Some(ref rates) = self.rates {
+            problems.extend(rates.problems());
+        }
+        if self.elitism > self.size {
+            problems.push(format!("elitism must be at most the pool size {}: {}", self.size, self.elitism));
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    // Validate the settings, then create and fill the pool with the default config
+    pub fn build<T, F, R>(self, fitness: F, rng: &mut R) -> Result<Pool<T, F>, Vec<String>>
+        where T: Gene + Hash + Eq + Clone,
+              T::Config: Default,
+              F: Fitness<T>,
+              R: Rng,
+        {
+        self.build_with_config(T::Config::default(), fitness, rng)
+    }
+
+    // Validate the settings, then create and fill the pool, generating and breeding genes with the
+    // given config
+    pub fn build_with_config<T, F, R>(self, config: T::Config, fitness: F, rng: &mut R)
+        -> Result<Pool<T, F>, Vec<String>>
+        where T: Gene + Hash + Eq + Clone,
+              F: Fitness<T>,
+              R: Rng,
+        {
+        self.validate()?;
+        let mut pool = Pool::with_config(self.size, config, fitness, rng);
+        pool.proportions = self.proportions;
+        pool.rates = self.rates;
+        pool.set_elitism(self.elitism);
+        pool.set_selection(self.selection);
+        Ok(pool)
+    }
+}
+
+// The state of a pool after one generation of `Pool::evolve_iter`
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationResult<T> {
+    // The generation just evolved, counting from 0
+    pub generation: usize,
+    // The best gene and its fitness
+    pub best: T,
+    pub fitness: f32,
+    // The mean fitness of the pool
+    pub mean: f32,
+    // Whether the best fitness beat every earlier generation's, including the starting pool's
+    pub improved: bool,
+}
+
+// What a pool knows about one of its genes besides the gene and its fitness. Pools may track more
+// about their genes later, so this can only be made by a pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GeneMeta {
+    // Where the gene is in the pool. It stays there until the pool next changes.
+    pub index: usize,
+}
+
+// An iterator over the genes of a pool. See `Pool::iter`.
+pub struct Genes<'a, T: 'a> {
+    genes: ::std::iter::Enumerate<::std::slice::Iter<'a, (T, f32)>>,
+}
+
+impl<'a, T> Iterator for Genes<'a, T> {
+    type Item = (&'a T, f32, GeneMeta);
+
+    fn next(&mut self) -> Option<(&'a T, f32, GeneMeta)> {
+        self.genes.next().map(|(index, g)| (&g.0, g.1, GeneMeta { index }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.genes.size_hint()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Genes<'a, T> {}
+
+// An endless iterator evolving a pool. See `Pool::evolve_iter`.
+pub struct EvolveIter<'a, T: Gene + 'a, F: 'a, R: 'a> {
+    pool: &'a mut Pool<T, F>,
+    rng: &'a mut R,
+    generation: usize,
+    // The best fitness seen so far
+    best_fit: f32,
+}
+
+impl<'a, T, F, R> Iterator for EvolveIter<'a, T, F, R>
+    where T: Gene + Hash + Eq + Clone,
+          F: Fitness<T>,
+          R: Rng,
+    {
+
+    type Item = GenerationResult<T>;
+
+    fn next(&mut self) -> Option<GenerationResult<T>> {
+        self.pool.evolve(self.rng);
+        let (best, fitness) = self.pool.get_best();
+        let mean = self.pool.genes.iter().map(|g| g.1).sum::<f32>() / self.pool.genes.len() as f32;
+        let improved = Objective(fitness) > Objective(self.best_fit);
+        if improved {
+            self.best_fit = fitness;
+        }
+        let result = GenerationResult { generation: self.generation, best: best.clone(), fitness, mean, improved };
+        self.generation += 1;
+        Some(result)
+    }
+}
+
+// A saved pool, as TOML: the generation, then a table for each gene with its fitness
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SavedPool<T> {
+    generation: usize,
+    genes: Vec<SavedGene<T>>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SavedGene<T> {
+    fitness: f32,
+    gene: T,
+}
+
+#[cfg(feature = "serde")]
+impl<T, F> Pool<T, F>
+    where T: Gene + Hash + Eq + Clone + Serialize + for<'de> Deserialize<'de>,
+          F: Fitness<T>,
+    {
+
+    // Save every gene in the pool with its fitness, and the number of generations evolved, so a
+    // long run can be resumed with `load_from`. The config and settings aren't saved.
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let saved = SavedPool {
+            generation: self.generation,
+            genes: self.genes.iter().map(|g| SavedGene { fitness: g.1, gene: &g.0 }).collect(),
+        };
+        let text = toml::to_string(&saved).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    // Load a pool saved by `save_to`. The genes keep their saved fitness rather than being evaluated
+    // again. Like `from_genes`, the pool starts with the default config and settings.
+    pub fn load_from<P: AsRef<Path>>(path: P, fitness: F) -> io::Result<Self>
+        where T::Config: Default,
+        {
+        let text = fs::read_to_string(path)?;
+        let saved: SavedPool<T> = toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut pool = Pool::from_genes(Vec::new(), fitness);
+        pool.generation = saved.generation;
+        pool.genes = saved.genes.into_iter().map(|mut g| {
+            g.gene.on_created();
+            (g.gene, g.fitness)
+        }).collect();
+        Ok(pool)
+    }
+}
+
+impl<T, F> Pool<T, F>
+    where T: Gene + Hash + Eq + Clone + Sync,
+          F: Fitness<T> + Sync,
+    {
+
+    // Like `evolve`, but the new genes are evaluated by `workers` threads pulling from a shared
+    // queue. See `eval_queue::evaluate`.
+    pub fn evolve_queued<R: Rng>(&mut self, rng: &mut R, workers: usize) {
+        self.evolve_queued_until(rng, workers, &CancellationToken::new());
+    }
+
+    // Like `evolve_queued`, but give up as soon as the token is cancelled. See `evolve_until`.
+    pub fn evolve_queued_until<R: Rng>(&mut self, rng: &mut R, workers: usize, token: &CancellationToken) -> bool {
+        if token.is_cancelled() {
+            return false;
+        }
+        let first_new = self.breed(&mut Shared(rng));
+        let first = self.first_to_evaluate(first_new);
+        eval_queue::evaluate_until(&mut self.genes[first ..], &self.fitness, workers, token)
+    }
+
+    // Evolve one generation reproducibly on `workers` threads. Selection draws from an RNG seeded
+    // with (seed, generation), and each new gene at index i draws from its own RNG seeded with
+    // (seed, generation, i), so the result is the same for any number of workers. The new genes
+    // are both bred and evaluated on the workers. This gives different results than `evolve` for
+    // the same seed.
+    pub fn evolve_deterministic(&mut self, seed: u64, generation: usize, workers: usize)
+        where T: Send,
+              T::Config: Sync,
+        {
+        let len = self.genes.len();
+        let num_selected = self.select(&mut StdRng::from_seed(&[seed as usize, generation]));
+        let (crosses, mutants) = self.offspring(len, num_selected);
+        if self.first_to_evaluate(num_selected) == 0 {
+            eval_queue::evaluate_until(&mut self.genes[..], &self.fitness, workers, &CancellationToken::new());
+        }
+        let children = {
+            let (parents, fitness) = (&self.genes[..], &self.fitness);
+            let breeding = Breeding {
+                config: &self.config,
+                immigration: self.immigration.as_deref(),
+                generation: self.generation,
+                mutations: self.mutations,
+                crosses,
+                mutants,
+                rates: self.rates,
+            };
+            eval_queue::map(len - num_selected, workers, &CancellationToken::new(), |k| {
+                let rng = &mut gene_rng(seed, generation, num_selected + k);
+                #[cfg(feature = "journal")]
+                let mut journal = Journal::new();
+                let mut gene = make_child(parents, &breeding, k, rng, #[cfg(feature = "journal")] &mut journal);
+                gene.on_created();
+                let fit = fitness.evaluate(&gene);
+                #[cfg(feature = "journal")]
+                return (gene, fit, journal.entries);
+                #[cfg(not(feature = "journal"))]
+                return (gene, fit);
+            })
+        };
+        // Nothing cancels the queue, so every child is there
+        for child in children.into_iter().map(Option::unwrap) {
+            #[cfg(feature = "journal")]
+            let child = {
+                let (gene, fit, entries) = child;
+                self.journal.entries.extend(entries);
+                (gene, fit)
+            };
+            self.genes.push(child);
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<T, F> Pool<T, F>
+    where T: Gene + Hash + Eq + Clone + Send + Sync,
+          F: Fitness<T> + Sync,
+    {
+
+    // Like `evolve`, but the genes are evaluated in parallel on rayon's thread pool. Breeding
+    // draws from `rng` the same way, so with a deterministic fitness function this gives the same
+    // result as `evolve` for the same seed, only sooner when evaluation is what takes the time.
+    pub fn evolve_par<R: Rng>(&mut self, rng: &mut R) {
+        let first_new = self.breed(&mut Shared(rng));
+        let first = self.first_to_evaluate(first_new);
+        let fitness = &self.fitness;
+        self.genes[first ..].par_iter_mut().for_each(|g| g.1 = fitness.evaluate(&g.0));
+    }
+}
+
+// The RNG for the gene at `index` in the given generation of `Pool::evolve_deterministic`
+pub fn gene_rng(seed: u64, generation: usize, index: usize) -> StdRng {
+    StdRng::from_seed(&[seed as usize, generation, index])
+}
+
+// The phases of breeding a generation, each of which can draw from its own RNG. See
+// `Pool::evolve_streams`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Selection,
+    Crossover,
+    Mutation,
+    Immigration,
+}
+
+// The RNG for a phase of the given generation of `Pool::evolve_streams`. The zero keeps these apart
+// from the RNGs of `gene_rng`.
+pub fn phase_rng(seed: u64, generation: usize, phase: Phase) -> StdRng {
+    StdRng::from_seed(&[seed as usize, generation, 0, phase as usize + 1])
+}
+
+// Where each phase of breeding draws its random numbers from
+trait Randomness {
+    fn rng(&mut self, phase: Phase) -> &mut dyn Rng;
+}
+
+// Every phase draws from the same RNG in turn
+struct Shared<'a, R: 'a>(&'a mut R);
+
+impl<'a, R: Rng> Randomness for Shared<'a, R> {
+    fn rng(&mut self, _phase: Phase) -> &mut dyn Rng {
+        self.0
+    }
+}
+
+// Every phase draws from its own RNG from `phase_rng`
+struct Streams([StdRng; 4]);
+
+impl Streams {
+    fn new(seed: u64, generation: usize) -> Streams {
+        let rng = |phase| phase_rng(seed, generation, phase);
+        Streams([rng(Phase::Selection), rng(Phase::Crossover), rng(Phase::Mutation), rng(Phase::Immigration)])
+    }
+}
+
+impl Randomness for Streams {
+    fn rng(&mut self, phase: Phase) -> &mut dyn Rng {
+        &mut self.0[phase as usize]
+    }
+}
+
+// What a pool breeds its new genes with, for `make_child`
+struct Breeding<'a, T: Gene + 'a> {
+    config: &'a T::Config,
+    immigration: Option<&'a dyn Immigration<T>>,
+    // The generation being bred, counting from 1
+    generation: usize,
+    mutations: usize,
+    // The number of crosses and of mutants to breed. See `Pool::offspring`.
+    crosses: usize,
+    mutants: usize,
+    rates: Option<Rates>,
+}
+
+// Cross the parent at index `i` with a random one with the given chance, or else copy it
+fn cross_or_copy<T, R>(parents: &[(T, f32)], i: usize, config: &T::Config, rate: f32, rng: &mut R,
+                       #[cfg(feature = "journal")] journal: &mut Journal) -> T
+    where T: Gene + Hash + Clone,
+          R: Rng,
+    {
+    if rng.gen::<f32>() >= rate {
+        return parents[i].0.clone();
+    }
+    let with_i = rng.gen_range(0, parents.len());
+    let (a, b) = (&parents[i].0, &parents[with_i].0);
+    #[cfg(feature = "journal")]
+    return journal.record(Op::Cross, &[a, b], rng, |rng| a.cross(config, b, rng));
+    #[cfg(not(feature = "journal"))]
+    return a.cross(config, b, rng);
+}
+
+// Mutate a gene the given number of times in a row with the given chance, or else leave it be
+fn maybe_mutate<T, R>(gene: T, config: &T::Config, mutations: usize, rate: f32, rng: &mut R,
+                      #[cfg(feature = "journal")] journal: &mut Journal) -> T
+    where T: Gene + Hash,
+          R: Rng,
+    {
+    if rng.gen::<f32>() >= rate {
+        return gene;
+    }
+    mutate_repeatedly(&gene, config, mutations, rng, #[cfg(feature = "journal")] journal)
+}
+
+// Mutate a gene the given number of times in a row, at least once. Each mutation is its own journal
+// entry, so the journal can still replay them.
+fn mutate_repeatedly<T, R>(parent: &T, config: &T::Config, mutations: usize, rng: &mut R,
+                           #[cfg(feature = "journal")] journal: &mut Journal) -> T
+    where T: Gene + Hash,
+          R: Rng,
+    {
+    #[cfg(feature = "journal")]
+    let mut gene = journal.record(Op::Mutate, &[parent], rng, |rng| parent.mutate(config, rng));
+    #[cfg(not(feature = "journal"))]
+    let mut gene = parent.mutate(config, rng);
+    for _ in 1 .. mutations {
+        #[cfg(feature = "journal")]
+        let next = journal.record(Op::Mutate, &[&gene], rng, |rng| gene.mutate(config, rng));
+        #[cfg(not(feature = "journal"))]
+        let next = gene.mutate(config, rng);
+        gene = next;
+    }
+    gene
+}
+
+// Make the kth new gene after the selected `parents`, laid out the same way as `Pool::evolve`:
+// crosses of the parents in turn, then mutations of the parents in turn, then new genes from the
+// immigration strategy, or generated ones if there's none
+fn make_child<T, R>(parents: &[(T, f32)], breeding: &Breeding<T>, k: usize, rng: &mut R,
+                    #[cfg(feature = "journal")] journal: &mut Journal) -> T
+    where T: Gene + Hash + Clone,
+          R: Rng,
+    {
+    let Breeding { config, immigration, generation, mutations, crosses, mutants, rates } = *breeding;
+    let n = parents.len();
+    if let (Some(rates), true) = (rates, k < crosses + mutants) {
+        let gene = cross_or_copy(parents, k % n, config, rates.crossover, rng,
+                                 #[cfg(feature = "journal")] journal);
+        return maybe_mutate(gene, config, mutations, rates.mutation, rng, #[cfg(feature = "journal")] journal);
+    }
+    if k < crosses {
+        // Pick a random cross partner
+        let with_k = rng.gen_range(0, n);
+        let (a, b) = (&parents[k % n].0, &parents[with_k].0);
+        #[cfg(feature = "journal")]
+        return journal.record(Op::Cross, &[a, b], rng, |rng| a.cross(config, b, rng));
+        #[cfg(not(feature = "journal"))]
+        return a.cross(config, b, rng);
+    }
+    if k < crosses + mutants {
+        return mutate_repeatedly(&parents[(k - crosses) % n].0, config, mutations, rng, #[cfg(feature = "journal")] journal);
+    }
+    if let Some(immigration) = immigration {
+        #[cfg(feature = "journal")]
+        return journal.record(Op::Immigrate, &[], rng, |rng| immigration.immigrant(config, parents, generation, rng));
+        #[cfg(not(feature = "journal"))]
+        return immigration.immigrant(config, parents, generation, rng);
+    }
+    #[cfg(feature = "journal")]
+    return journal.record(Op::Generate, &[], rng, |rng| T::generate(config, rng));
+    #[cfg(not(feature = "journal"))]
+    return T::generate(config, rng);
+}
+
+// An object-safe view of a gene. Genes are erased into this so callers can hold them without
+// knowing their concrete type, e.g. when the representation is chosen at runtime.
+pub trait DynGene: fmt::Display {
+    // Clone this gene into a new erased handle
+    fn clone_box(&self) -> Box<dyn DynGene>;
+
+    // Access the concrete gene, so callers that do know its type can downcast it
+    fn as_any(&self) -> &dyn Any;
+
+    // The size of the gene. See `Gene::size`.
+    fn size_dyn(&self) -> usize;
+}
+
+impl<T> DynGene for T
+    where T: Gene + Clone + fmt::Display + Any,
+    {
+
+    fn clone_box(&self) -> Box<dyn DynGene> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn size_dyn(&self) -> usize {
+        self.size()
+    }
+}
+
+impl Clone for Box<dyn DynGene> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+// An object-safe view of a pool. This erases both the gene type and the fitness function, so the
+// binary can drive any pool through a `Box<dyn DynPool>`.
+pub trait DynPool {
+    // Evolve one generation. See `Pool::evolve`.
+    fn evolve_dyn(&mut self, rng: &mut dyn Rng);
+
+    // Get the current best gene and its fitness. See `Pool::get_best`.
+    fn best_dyn(&self) -> (&dyn DynGene, f32);
+
+    // Get every gene in the pool paired with its fitness, in no particular order
+    fn genes_dyn(&self) -> Vec<(&dyn DynGene, f32)>;
+
+    // A hash of the whole generation. See `fingerprint`.
+    fn fingerprint_dyn(&self) -> u64;
+
+    // Summarize the current generation. See `Pool::stats`. By default genes are distinct if they
+    // display differently.
+    fn stats_dyn(&self) -> GenerationStats<&dyn DynGene> {
+        let genes = self.genes_dyn();
+        let fits: Vec<f32> = genes.iter().map(|g| g.1).collect();
+        let unique = genes.iter().map(|g| g.0.to_string()).collect::<HashSet<_>>().len();
+        GenerationStats::of(self.best_dyn().0, &fits, unique)
+    }
+
+    // The config the genetic operators are given, for callers that know the gene type to downcast
+    // and change it. Pools that don't breed with `Gene` operators have none.
+    fn config_dyn(&mut self) -> Option<&mut dyn Any> {
+        None
+    }
+
+    // Set how survivors are chosen. See `Pool::set_selection`. Pools that choose survivors their
+    // own way ignore this.
+    fn set_selection_dyn(&mut self, _selection: Selection) {}
+
+    // Set how many of the most fit genes always survive. See `Pool::set_elitism`. Pools that choose
+    // survivors their own way ignore this.
+    fn set_elitism_dyn(&mut self, _n: usize) {}
+
+    // Breed genes at the given crossover and mutation rates. See `Pool::set_rates`. Pools that
+    // don't cross and mutate genes ignore this.
+    fn set_rates_dyn(&mut self, _rates: Rates) {}
+
+    // Limit how long genes stay in the pool. See `Pool::set_age_limits`. Pools that choose
+    // survivors their own way ignore this.
+    fn set_age_limits_dyn(&mut self, _limits: AgeLimits) {}
+
+    // Make up each generation with the given proportions. See `Pool::set_proportions`. Pools that
+    // make up generations their own way ignore this.
+    fn set_proportions_dyn(&mut self, _proportions: Proportions) {}
+
+    // Change the size of the pool. See `Pool::resize`. Pools that can't change size ignore this.
+    fn resize_dyn(&mut self, _new_size: usize, _rng: &mut dyn Rng) {}
+
+    // Set the number of mutations in a row that make each mutated gene. See `Pool::set_mutations`.
+    // Pools that don't mutate genes ignore this.
+    fn set_mutations_dyn(&mut self, _mutations: usize) {}
+
+    // Replace the least fit genes with new random ones. See `Pool::refresh`. Pools that can't
+    // generate genes ignore this.
+    fn refresh_dyn(&mut self, _count: usize, _rng: &mut dyn Rng) {}
+}
+
+impl<T, F> DynPool for Pool<T, F>
+    where T: Gene + Hash + Eq + Clone + fmt::Display + Any,
+          T::Config: Any,
+          F: Fitness<T>,
+    {
+
+    fn evolve_dyn(&mut self, mut rng: &mut dyn Rng) {
+        self.evolve(&mut rng);
+    }
+
+    fn best_dyn(&self) -> (&dyn DynGene, f32) {
+        let (gene, fit) = self.get_best();
+        (gene, fit)
+    }
+
+    fn genes_dyn(&self) -> Vec<(&dyn DynGene, f32)> {
+        self.genes.iter().map(|g| (&g.0 as &dyn DynGene, g.1)).collect()
+    }
+
+    fn fingerprint_dyn(&self) -> u64 {
+        self.fingerprint()
+    }
+
+    fn stats_dyn(&self) -> GenerationStats<&dyn DynGene> {
+        let GenerationStats { best, min, max, mean, median, std_dev, unique } = self.stats();
+        GenerationStats { best, min, max, mean, median, std_dev, unique }
+    }
+
+    fn config_dyn(&mut self) -> Option<&mut dyn Any> {
+        Some(&mut self.config)
+    }
+
+    fn set_selection_dyn(&mut self, selection: Selection) {
+        self.set_selection(selection);
+    }
+
+    fn set_elitism_dyn(&mut self, n: usize) {
+        self.set_elitism(n);
+    }
+
+    fn set_rates_dyn(&mut self, rates: Rates) {
+        self.set_rates(rates);
+    }
+
+    fn set_age_limits_dyn(&mut self, limits: AgeLimits) {
+        self.set_age_limits(limits);
+    }
+
+    fn set_proportions_dyn(&mut self, proportions: Proportions) {
+        self.set_proportions(proportions);
+    }
+
+    fn resize_dyn(&mut self, new_size: usize, mut rng: &mut dyn Rng) {
+        self.resize(new_size, &mut rng);
+    }
+
+    fn set_mutations_dyn(&mut self, mutations: usize) {
+        self.set_mutations(mutations);
+    }
+
+    fn refresh_dyn(&mut self, count: usize, mut rng: &mut dyn Rng) {
+        self.refresh(count, &mut rng);
+    }
+}
+
+// A boxed fitness function, so pools of different gene types can be built from the same kind of
+// value
+pub type BoxedFitness<T> = Box<dyn Fn(&T) -> f32>;
+
+// Create and fill a pool of the given size, then erase its type.
+pub fn new_dyn_pool<T>(size: usize, fitness: BoxedFitness<T>, mut rng: &mut dyn Rng) -> Box<dyn DynPool>
+    where T: Gene + Hash + Eq + Clone + fmt::Display + Any,
+          T::Config: Default,
+    {
+    Box::new(Pool::new(size, fitness, &mut rng))
+}
+
+// Make and fill a pool of the given size, generating genes with the given config. See
+// `Pool::with_config`.
+pub fn new_dyn_pool_with<T>(size: usize, config: T::Config, fitness: BoxedFitness<T>, mut rng: &mut dyn Rng)
+    -> Box<dyn DynPool>
+    where T: Gene + Hash + Eq + Clone + fmt::Display + Any,
+          T::Config: Default,
+    {
+    Box::new(Pool::with_config(size, config, fitness, &mut rng))
+}
+
+// Make a pool of the given genes. See `Pool::from_genes`.
+pub fn dyn_pool_from<T>(genes: Vec<T>, fitness: BoxedFitness<T>) -> Box<dyn DynPool>
+    where T: Gene + Hash + Eq + Clone + fmt::Display + Any,
+          T::Config: Default,
+    {
+    Box::new(Pool::from_genes(genes, fitness))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+    use super::*;
+    use rand::Rng;
+
+    use std::cell::Cell;
+
+    // Each test runs on its own thread, so this gives every test its own id sequence
+    thread_local!(static NEXT_ID: Cell<i32> = const { Cell::new(1) });
+
+    #[derive(PartialEq, Eq, Hash, Clone, Debug)]
+    struct TestGene {
+        id: i32,
+    }
+
+    impl Gene for TestGene {
+        type Config = ();
+
+        fn generate<R: Rng>(_cfg: &(), _rng: &mut R) -> Self {
+            let id = NEXT_ID.with(|n| n.replace(n.get() + 1));
+            TestGene { id }
+        }
+
+        fn mutate<R: Rng>(&self, _cfg: &(), _rng: &mut R) -> Self {
+            TestGene { id: -self.id }
+        }
+
+        fn cross<R: Rng>(&self, _cfg: &(), other: &Self, _rng: &mut R) -> Self {
+            TestGene { id: self.id.wrapping_mul(100).wrapping_add(other.id) }
+        }
+    }
+
+    // Counts of the lifecycle hooks called on HookGenes: created, selected, discarded
+    thread_local!(static HOOKS: Cell<(usize, usize, usize)> = const { Cell::new((0, 0, 0)) });
+
+    #[derive(PartialEq, Eq, Hash, Clone, Debug)]
+    struct HookGene(i32);
+
+    impl Gene for HookGene {
+        type Config = ();
+
+        fn generate<R: Rng>(_cfg: &(), rng: &mut R) -> Self {
+            HookGene(rng.gen_range(0, 100))
+        }
+
+        fn mutate<R: Rng>(&self, _cfg: &(), rng: &mut R) -> Self {
+            HookGene(self.0 + rng.gen_range(-5, 6))
+        }
+
+        fn cross<R: Rng>(&self, _cfg: &(), other: &Self, _rng: &mut R) -> Self {
+            HookGene((self.0 + other.0) / 2)
+        }
+
+        fn on_created(&mut self) {
+            HOOKS.with(|h| { let (c, s, d) = h.get(); h.set((c + 1, s, d)) });
+        }
+
+        fn on_selected(&mut self) {
+            HOOKS.with(|h| { let (c, s, d) = h.get(); h.set((c, s + 1, d)) });
+        }
+
+        fn on_discarded(&mut self) {
+            HOOKS.with(|h| { let (c, s, d) = h.get(); h.set((c, s, d + 1)) });
+        }
+    }
+
+    #[test]
+    fn gen_pool() {
+        // A deterministic RNG for testing
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Generate the pool by calling generate() n times.
+        let fitness = |g: &TestGene| { g.id as f32 };
+        let mut pool = Pool::new(10, fitness, rng);
+        assert_eq!(pool.genes[0].0.id, 1);
+        assert_eq!(pool.genes[9].0.id, 10);
+
+        // Evolve the pool
+        pool.evolve(rng);
+
+        // Make sure 4 new genes were generated
+        assert_eq!(NEXT_ID.with(Cell::get), 15);
+
+        // Make sure the same genes were selected (because we know the random seed)
         assert_eq!(pool.genes[0].0.id, 6);
         assert_eq!(pool.genes[1].0.id, 9);
         assert_eq!(pool.genes[2].0.id, 606);
@@ -193,4 +1982,777 @@ mod tests {
         assert_eq!(pool.get_best().0.id, 906);
         assert_eq!(pool.get_best().1, 906.0);
     }
+    #[test]
+    fn degenerate_pools() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Pools too small to split into fourths keep their size
+        for size in 1 .. 8 {
+            let mut pool = Pool::new(size, |g: &NamedGene| g.0 as f32, rng);
+            for _ in 0 .. 10 {
+                pool.evolve(rng);
+                assert_eq!(pool.genes.len(), size);
+            }
+        }
+
+        // A generation where every gene has zero fitness falls back to uniform selection
+        let mut pool = Pool::new(20, |_: &NamedGene| 0.0, rng);
+        for _ in 0 .. 10 {
+            pool.evolve(rng);
+            assert_eq!(pool.genes.len(), 20);
+        }
+    }
+
+    #[test]
+    fn resize_pool() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let mut pool = Pool::new(10, |g: &TestGene| g.id as f32, rng);
+
+        // Growing adds new genes
+        pool.resize(20, rng);
+        assert_eq!(pool.genes.len(), 20);
+        assert_eq!(NEXT_ID.with(Cell::get), 21);
+
+        // Shrinking keeps the most fit genes
+        pool.resize(5, rng);
+        let mut ids: Vec<i32> = pool.genes.iter().map(|g| g.0.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![16, 17, 18, 19, 20]);
+
+        // Evolution continues at the new size
+        pool.evolve(rng);
+        assert_eq!(pool.genes.len(), 5);
+        assert_eq!(pool.len(), 5);
+        let indexes: Vec<usize> = pool.iter().map(|g| g.2.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2, 3, 4]);
+        assert!(pool.iter().all(|g| pool.genes[g.2.index] == (g.0.clone(), g.1)));
+    }
+
+    #[test]
+    fn phase_streams() {
+        use prog_gene::{self, ProgramConfig, ProgramGene};
+        use rand::SeedableRng;
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+        let make = || Pool::new(40, fitness, &mut rand::StdRng::from_seed(&[123]));
+        let (mut a, mut b) = (make(), make());
+        // Immigrants from a different strategy draw differently from the default
+        b.set_immigration(|cfg: &ProgramConfig, _: &[(ProgramGene, f32)], _: usize, mut rng: &mut dyn Rng| {
+            let cfg = ProgramConfig { max_len: 30, ..cfg.clone() };
+            ProgramGene::generate(&cfg, &mut rng)
+        });
+        for generation in 0 .. 3 {
+            a.evolve_streams(7, generation);
+            b.evolve_streams(7, generation);
+            // The same survivors, crosses, and mutations, and only the immigrants differ
+            assert_eq!(a.genes[.. 30], b.genes[.. 30]);
+            assert!(a.genes[30 ..] != b.genes[30 ..]);
+            // Survivors of the next generation are chosen from the same fitnesses
+            b.genes[30 ..].clone_from_slice(&a.genes[30 ..]);
+        }
+        assert!(phase_rng(7, 0, Phase::Mutation).gen::<u64>() != phase_rng(7, 0, Phase::Crossover).gen::<u64>());
+    }
+
+    #[test]
+    fn best_validated() {
+        use prog_gene::ProgramGene;
+        let genes: Vec<ProgramGene> = ["+", "swap -", "- dup +"].iter().map(|s| s.parse().unwrap()).collect();
+        // Longer is more fit, but only "swap -" validates
+        let pool = Pool::from_genes(genes, |g: &ProgramGene| g.size() as f32);
+        assert_eq!(pool.get_best().0.to_string(), "- dup +");
+        let (best, fit) = pool.get_best_validated(|g| if g.size() == 2 { 1.0 } else { 0.0 });
+        assert_eq!((best.to_string().as_str(), fit), ("swap -", 2.0));
+        // Ties go to the shortest
+        assert_eq!(pool.get_best_validated(|_| 1.0).0.to_string(), "+");
+    }
+
+    #[test]
+    fn pool_fingerprint() {
+        use prog_gene::{self, ProgramGene};
+        use rand::SeedableRng;
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+        let mut a = Pool::new(20, fitness, &mut StdRng::from_seed(&[123]));
+        let b = Pool::new(20, fitness, &mut StdRng::from_seed(&[123]));
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        // Order doesn't matter
+        let genes: Vec<ProgramGene> = a.iter().map(|g| g.0.clone()).collect();
+        let reversed = Pool::from_genes(genes.into_iter().rev().collect(), fitness);
+        assert_eq!(reversed.fingerprint(), a.fingerprint());
+        // Rounding differences in fitness don't either, but real differences do
+        let gene = || vec![TestGene { id: 1 }];
+        let plain = Pool::from_genes(gene(), |g: &TestGene| g.id as f32);
+        let nudged = Pool::from_genes(gene(), |g: &TestGene| g.id as f32 + 2e-7);
+        let moved = Pool::from_genes(gene(), |g: &TestGene| g.id as f32 + 0.5);
+        assert_eq!(nudged.fingerprint(), plain.fingerprint());
+        assert!(moved.fingerprint() != plain.fingerprint());
+        assert!(quantize(f32::NAN) != quantize(0.0));
+
+        a.evolve(&mut StdRng::from_seed(&[5]));
+        assert!(a.fingerprint() != b.fingerprint());
+    }
+
+    #[test]
+    fn migrate_genes() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let fitness = |g: &TestGene| g.id as f32;
+        let from = Pool::new(5, fitness, rng);
+        let mut to = Pool::new(5, fitness, rng);
+
+        // The best genes leave, best first, and replace the worst genes of the other pool
+        let migrants = from.best_n(2);
+        assert_eq!(migrants.iter().map(|g| g.0.id).collect::<Vec<_>>(), vec![5, 4]);
+        to.immigrate(migrants);
+        let mut ids: Vec<i32> = to.genes.iter().map(|g| g.0.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![4, 5, 8, 9, 10]);
+
+        // The pool never grows from immigration
+        to.immigrate(from.best_n(10));
+        assert_eq!(to.genes.len(), 5);
+    }
+
+    #[test]
+    fn seed_pool() {
+        use rand::SeedableRng;
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness = |g: &NamedGene| g.0 as f32;
+        let pool = Pool::with_seeds(5, vec![NamedGene(500), NamedGene(400)], fitness, rng);
+        assert_eq!(pool.len(), 5);
+        assert_eq!(&pool.genes[.. 2], &[(NamedGene(500), 500.0), (NamedGene(400), 400.0)]);
+        assert!(pool.genes[2 ..].iter().all(|g| g.0 .0 < 100));
+        // Seeds past the size are left out
+        let pool = Pool::with_seeds(1, vec![NamedGene(500), NamedGene(400)], fitness, rng);
+        assert_eq!(pool.genes, vec![(NamedGene(500), 500.0)]);
+    }
+
+    #[test]
+    fn pool_config() {
+        use prog_gene::{ProgramConfig, ProgramGene};
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // The operators get the pool's config, and changes to it apply to the genes made after
+        let short = ProgramConfig { max_len: 2, ..ProgramConfig::default() };
+        let mut pool = Pool::with_config(20, short, |g: &ProgramGene| g.0.len() as f32, rng);
+        assert!(pool.genes.iter().all(|g| g.0 .0.len() <= 2));
+        assert_eq!(pool.config().max_len, 2);
+        pool.set_config(ProgramConfig { max_len: 1, ..ProgramConfig::default() });
+        pool.resize(40, rng);
+        assert!(pool.genes[20 ..].iter().all(|g| g.0 .0.len() == 1));
+    }
+
+    #[test]
+    fn lifecycle_hooks() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let mut pool = Pool::new(8, |g: &HookGene| g.0 as f32, rng);
+        assert_eq!(HOOKS.with(Cell::get), (8, 0, 0));
+
+        // A quarter survive, and the rest are replaced
+        pool.evolve(rng);
+        assert_eq!(HOOKS.with(Cell::get), (14, 2, 6));
+        // One worker, so the hooks run on this thread and its counts see them
+        pool.evolve_deterministic(1, 1, 1);
+        assert_eq!(HOOKS.with(Cell::get), (20, 4, 12));
+
+        // Every gene that leaves the pool is discarded, and every one that enters is created
+        pool.resize(4, rng);
+        pool.immigrate(vec![(HookGene(1), 1.0)]);
+        let (created, _, discarded) = HOOKS.with(Cell::get);
+        assert_eq!(created - discarded, pool.genes.len());
+    }
+
+    #[test]
+    fn evolve_iter() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let mut pool = Pool::new(10, |g: &TestGene| g.id as f32, rng);
+        let mut best_fit = pool.get_best().1;
+
+        // Callers pick their own stopping point
+        let results: Vec<_> = pool.evolve_iter(rng).take_while(|r| r.generation < 5).collect();
+        assert_eq!(results.len(), 5);
+        for (i, r) in results.iter().enumerate() {
+            assert_eq!(r.generation, i);
+            assert_eq!(r.fitness, r.best.id as f32);
+            assert!(r.mean <= r.fitness);
+            assert_eq!(r.improved, r.fitness > best_fit);
+            best_fit = best_fit.max(r.fitness);
+        }
+        // `take_while` evolved one more generation to see that it was past the end
+        assert_eq!(pool.evolve_iter(rng).next().unwrap().generation, 0);
+    }
+
+    #[test]
+    fn evolve_deterministic() {
+        use rand::SeedableRng;
+        // TestGene ids are per thread, so use a gene that only depends on its RNG
+        let fitness = |g: &NamedGene| g.0 as f32;
+        let new_pool = || Pool::new(12, fitness, &mut rand::Isaac64Rng::from_seed(&[123]));
+
+        // Any number of workers gives the same populations
+        let mut serial = new_pool();
+        let mut parallel = new_pool();
+        for generation in 0 .. 4 {
+            serial.evolve_deterministic(7, generation, 1);
+            parallel.evolve_deterministic(7, generation, 4);
+            assert_eq!(serial.genes, parallel.genes);
+        }
+        for g in &serial.genes {
+            assert_eq!(g.1, fitness(&g.0));
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn evolve_par() {
+        use prog_gene::{self, ProgramGene};
+        use rand::SeedableRng;
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+        let mut serial = Pool::new(50, fitness, &mut StdRng::from_seed(&[123]));
+        let mut parallel = Pool::new(50, fitness, &mut StdRng::from_seed(&[123]));
+        let (rng_a, rng_b) = (&mut StdRng::from_seed(&[5]), &mut StdRng::from_seed(&[5]));
+        // The same generations as evolving serially
+        for _ in 0 .. 5 {
+            serial.evolve(rng_a);
+            parallel.evolve_par(rng_b);
+            assert_eq!(serial.genes, parallel.genes);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_and_load() {
+        use prog_gene::{self, ProgramGene};
+        use rand::SeedableRng;
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a * b, g);
+        let rng = &mut StdRng::from_seed(&[123]);
+        let mut pool = Pool::new(20, fitness, rng);
+        for _ in 0 .. 3 {
+            pool.evolve(rng);
+        }
+        pool.genes[0] = ("3 dup pushframe times -7 if".parse().unwrap(), f32::NEG_INFINITY);
+
+        let path = ::std::env::temp_dir().join(format!("gene_code_pool_{}.toml", ::std::process::id()));
+        pool.save_to(&path).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("gene = [3, \"dup\", \"pushframe\", \"times\", -7, \"if\"]"));
+        // Every gene comes back with the fitness it had, even one the fitness function wouldn't give
+        let loaded = Pool::load_from(&path, fitness).unwrap();
+        assert_eq!(loaded.genes, pool.genes);
+        assert_eq!(loaded.generation, 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fitness_trait() {
+        use rand::SeedableRng;
+
+        // Scores genes by their distance from a target, on two differently weighted cases
+        struct Target(i32);
+
+        impl Fitness<NamedGene> for Target {
+            fn evaluate(&self, gene: &NamedGene) -> f32 {
+                self.evaluate_detailed(gene).unwrap().iter().sum()
+            }
+
+            fn evaluate_detailed(&self, gene: &NamedGene) -> Option<Vec<f32>> {
+                let case = |weight: i32| -(((gene.0 - self.0) * weight).abs() as f32);
+                Some(vec![case(1), case(2)])
+            }
+        }
+
+        let target = Target(50);
+        assert_eq!(target.evaluate_detailed(&NamedGene(47)), Some(vec![-3.0, -6.0]));
+        assert!(target.is_deterministic());
+
+        // Closures keep working, with no detailed scores
+        let closure = |g: &NamedGene| g.0 as f32;
+        assert_eq!(closure.evaluate(&NamedGene(3)), 3.0);
+        assert_eq!(closure.evaluate_detailed(&NamedGene(3)), None);
+
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let mut pool = Pool::new(10, target, rng);
+        pool.evolve(rng);
+        let (best, fit) = pool.get_best();
+        assert_eq!(fit, -3.0 * (best.0 - 50).abs() as f32);
+
+        // Lexicase selects on the cases, and closes in on the target like any other selection
+        let mut pool = Pool::new(20, Target(50), rng);
+        pool.set_selection(Selection::EpsilonLexicase);
+        let start = pool.get_best().1;
+        for _ in 0 .. 10 {
+            pool.evolve(rng);
+        }
+        assert_eq!(pool.genes.len(), 20);
+        assert!(pool.get_best().1 >= start);
+    }
+
+    #[test]
+    fn reevaluate() {
+        use rand::SeedableRng;
+        use std::cell::Cell;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // A noisy fitness whose scores drift up by one with every evaluation
+        struct Drift(Cell<f32>);
+
+        impl Fitness<NamedGene> for Drift {
+            fn evaluate(&self, _gene: &NamedGene) -> f32 {
+                self.0.set(self.0.get() + 1.0);
+                self.0.get()
+            }
+
+            fn is_deterministic(&self) -> bool {
+                false
+            }
+        }
+
+        // Noisy fitness scores the survivors every generation by default
+        let mut pool = Pool::new(8, Drift(Cell::new(0.0)), rng);
+        assert_eq!(pool.reevaluate, Reevaluate::EveryGeneration);
+        pool.evolve(rng);
+        assert!(pool.genes.iter().all(|g| g.1 > 8.0));
+
+        // Only every other generation, counting from the first
+        pool.set_reevaluate(Reevaluate::Every(2));
+        pool.evolve(rng);
+        assert!(pool.genes.iter().all(|g| g.1 > 16.0));
+        pool.evolve(rng);
+        assert!(pool.genes[.. 2].iter().all(|g| g.1 <= 24.0));
+
+        // Only on demand
+        pool.set_reevaluate(Reevaluate::OnDemand);
+        pool.evolve(rng);
+        assert!(pool.genes[.. 2].iter().all(|g| g.1 <= 30.0));
+        pool.reevaluate();
+        assert!(pool.genes.iter().all(|g| g.1 > 36.0));
+
+        // Deterministic fitness never scores them again
+        let pool = Pool::new(8, |g: &NamedGene| g.0 as f32, rng);
+        assert_eq!(pool.reevaluate, Reevaluate::Never);
+        assert!(!Reevaluate::Every(0).is_due(3));
+    }
+
+    #[test]
+    fn truncation_selection() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // The most fit half survives, every time
+        let mut pool = Pool::new(20, |g: &NamedGene| g.0 as f32, rng);
+        pool.set_selection(Selection::Truncation(0.5));
+        for _ in 0 .. 5 {
+            let mut best: Vec<i32> = pool.genes.iter().map(|g| g.0 .0).collect();
+            best.sort_by_key(|&g| Reverse(g));
+            pool.evolve(rng);
+            let survivors: Vec<i32> = pool.genes[.. 10].iter().map(|g| g.0 .0).collect();
+            assert_eq!(survivors, best[.. 10]);
+            assert_eq!(pool.genes.len(), 20);
+        }
+
+        // Fractions round, and always keep at least one gene
+        assert_eq!(Selection::Truncation(0.1).survivors(20), 2);
+        assert_eq!(Selection::Truncation(0.0).survivors(20), 1);
+        assert_eq!(Selection::Truncation(1.0).survivors(20), 20);
+        assert_eq!(Selection::Proportionate.survivors(20), 5);
+    }
+
+    #[test]
+    fn more_selections() {
+        use rand::SeedableRng;
+        assert_eq!(rank_weights(&[((), 0.5), ((), -1.0), ((), 0.5), ((), f32::NAN)]), vec![3.0, 2.0, 4.0, 1.0]);
+
+        // The fitness of the survivors of one generation from the same pool
+        let survivors = |selection: Selection| {
+            let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+            let mut pool = Pool::new(40, |g: &NamedGene| g.0 as f32, rng);
+            pool.set_selection(selection);
+            pool.evolve(rng);
+            assert_eq!(pool.genes.len(), 40);
+            pool.genes[.. 10].iter().map(|g| g.0 .0).sum::<i32>()
+        };
+        // Bigger tournaments press harder, and a tournament of one is a random pick
+        assert!(survivors(Selection::Tournament(7)) > survivors(Selection::Tournament(1)));
+        assert!(survivors(Selection::Rank) > survivors(Selection::Tournament(1)));
+        assert!(survivors(Selection::StochasticUniversal) > survivors(Selection::Tournament(1)));
+        assert_eq!(Selection::Tournament(7).survivors(20), 5);
+    }
+
+    #[test]
+    fn unselected_genes() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Finding the nth unselected gene gives what taking selected genes out of a list would
+        for &len in &[1, 2, 7, 8, 33] {
+            let mut unselected = Unselected::new(len);
+            let mut left: Vec<usize> = (0 .. len).collect();
+            while !left.is_empty() {
+                assert_eq!(unselected.len(), left.len());
+                assert!((0 .. left.len()).all(|n| unselected.nth(n) == left[n]));
+                assert!(unselected.indexes().eq(left.iter().cloned()));
+                let i = left.remove(rng.gen_range(0, left.len()));
+                unselected.take(i);
+            }
+            assert!(unselected.is_empty());
+        }
+    }
+
+    #[test]
+    fn build_pool() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Each part of the next generation is as big as asked, in order: survivors, crosses (big
+        // ids), mutants (negative ids), then fresh genes
+        let builder = PoolBuilder::new(20).selected(0.1).crossed(0.5).mutated(0.25).fresh(0.15);
+        let same = |_: &TestGene| 1.0;
+        let check = |pool: &Pool<TestGene, _>| {
+            let ids: Vec<i32> = pool.genes.iter().map(|g| g.0.id).collect();
+            assert_eq!(ids.len(), 20);
+            assert!(ids[2 .. 12].iter().all(|&id| id > 100), "{:?}", ids);
+            assert!(ids[12 .. 17].iter().all(|&id| id < 0), "{:?}", ids);
+            assert!(ids[17 ..].iter().all(|&id| id > 0 && id < 100), "{:?}", ids);
+        };
+        let mut pool = builder.clone().elitism(1).build(same, rng).unwrap();
+        pool.evolve(rng);
+        check(&pool);
+        let mut pool = builder.build(same, rng).unwrap();
+        pool.evolve_deterministic(7, 1, 3);
+        check(&pool);
+
+        // Without fractions, the builder makes the same pool as `Pool::new`
+        let fitness = |g: &HookGene| g.0 as f32;
+        let mut built = PoolBuilder::new(10).build(fitness, &mut rand::Isaac64Rng::from_seed(&[5])).unwrap();
+        let mut new = Pool::new(10, fitness, &mut rand::Isaac64Rng::from_seed(&[5]));
+        built.evolve(&mut rand::Isaac64Rng::from_seed(&[123]));
+        new.evolve(&mut rand::Isaac64Rng::from_seed(&[123]));
+        assert_eq!(built.fingerprint(), new.fingerprint());
+
+        let problems = PoolBuilder::new(0).selected(0.0).crossed(1.5).elitism(3).validate().unwrap_err();
+        assert_eq!(problems, vec![
+            "the pool size must be at least 1".to_string(),
+            "the crossed fraction must be from 0 to 1: 1.5".to_string(),
+            "the selected fraction must be above 0".to_string(),
+            "the fractions must add up to 1: 2".to_string(),
+            "elitism must be at most the pool size 0: 3".to_string(),
+        ]);
+        assert_eq!(Proportions::default().problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn breed_at_rates() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let same = |_: &TestGene| 1.0;
+
+        // The ids of the genes bred in place of the crosses and mutants of one generation
+        let mut bred = |crossover: f32, mutation: f32, deterministic: bool| {
+            NEXT_ID.with(|n| n.set(1));
+            let mut pool = PoolBuilder::new(20).rates(crossover, mutation).build(same, rng).unwrap();
+            let parents: Vec<i32> = pool.genes.iter().map(|g| g.0.id).collect();
+            if deterministic {
+                pool.evolve_deterministic(7, 1, 3);
+            } else {
+                pool.evolve(rng);
+            }
+            let ids: Vec<i32> = pool.genes[5 .. 15].iter().map(|g| g.0.id).collect();
+            (parents, ids)
+        };
+        for &deterministic in &[false, true] {
+            // Neither: copies of the parents
+            let (parents, ids) = bred(0.0, 0.0, deterministic);
+            assert!(ids.iter().all(|id| parents.contains(id)), "{:?}", ids);
+            // Only crossover: crosses, with big ids
+            assert!(bred(1.0, 0.0, deterministic).1.iter().all(|&id| id > 100));
+            // Only mutation: mutants of copies, with negative ids
+            assert!(bred(0.0, 1.0, deterministic).1.iter().all(|&id| id < 0 && id > -100));
+            // Both: mutants of crosses
+            assert!(bred(1.0, 1.0, deterministic).1.iter().all(|&id| id < -100));
+            // Half the time each, some of every kind
+            let (_, ids) = bred(0.5, 0.5, deterministic);
+            assert!(ids.iter().any(|&id| id > 0) && ids.iter().any(|&id| id < 0), "{:?}", ids);
+        }
+        assert_eq!(PoolBuilder::new(20).rates(1.5, -0.1).validate().unwrap_err(), vec![
+            "the crossover rate must be from 0 to 1: 1.5".to_string(),
+            "the mutation rate must be from 0 to 1: -0.1".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn elitism() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let value = |g: &NamedGene| g.0 as f32;
+        let top = |pool: &Pool<NamedGene, _>, n: usize| pool.best_n(n).into_iter().map(|g| g.0 .0).collect::<Vec<_>>();
+
+        // Random survivors still keep the best genes, best first, and the best never gets worse
+        let mut pool = Pool::new(40, value, rng);
+        pool.set_selection(Selection::Tournament(1));
+        pool.set_elitism(3);
+        for _ in 0 .. 10 {
+            let elites = top(&pool, 3);
+            pool.evolve(rng);
+            assert_eq!(pool.genes[.. 3].iter().map(|g| g.0 .0).collect::<Vec<_>>(), elites);
+            assert_eq!(pool.genes.len(), 40);
+        }
+
+        // More elites than the selection keeps all survive, including with selections that choose
+        // survivors all at once
+        for selection in [Selection::Proportionate, Selection::Truncation(0.1), Selection::Pareto] {
+            let mut pool = Pool::new(40, value, rng);
+            pool.set_selection(selection);
+            pool.set_elitism(12);
+            let elites = top(&pool, 12);
+            pool.evolve(rng);
+            assert_eq!(pool.genes[.. 12].iter().map(|g| g.0 .0).collect::<Vec<_>>(), elites);
+            assert_eq!(pool.genes.len(), 40);
+        }
+    }
+
+    #[test]
+    fn age_limits() {
+        use rand::SeedableRng;
+        use std::collections::HashSet;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+        let values = |genes: &[(NamedGene, f32)]| genes.iter().map(|g| g.0 .0).collect::<HashSet<_>>();
+
+        // Newborns survive first, so none of one generation's survivors survive the next
+        let mut pool = Pool::from_genes((0 .. 40).map(NamedGene).collect(), |g: &NamedGene| g.0 as f32);
+        pool.set_selection(Selection::Truncation(0.25));
+        pool.set_age_limits(AgeLimits { protect: 1, retire: None });
+        pool.evolve(rng);
+        let first = values(&pool.genes[.. 10]);
+        pool.evolve(rng);
+        assert!(values(&pool.genes[.. 10]).is_disjoint(&first));
+
+        // The only fit gene is an elite until it's retired, along with every copy of it
+        let champion = |g: &NamedGene| if g.0 == 1000 { 1.0 } else { 0.0 };
+        let mut pool = Pool::from_genes((0 .. 39).chain(Some(1000)).map(NamedGene).collect(), champion);
+        pool.set_elitism(1);
+        pool.set_age_limits(AgeLimits { protect: 0, retire: Some(3) });
+        for _ in 0 .. 3 {
+            pool.evolve(rng);
+            assert_eq!(pool.genes[0].0, NamedGene(1000));
+        }
+        pool.evolve(rng);
+        assert!(!values(&pool.genes).contains(&1000));
+    }
+
+    #[test]
+    fn boltzmann_selection() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        assert_eq!(boltzmann_win(0.0, 0.0), 0.5);
+        assert_eq!(boltzmann_win(0.1, 0.0), 1.0);
+        assert!((boltzmann_win(0.1, 1000.0) - 0.5).abs() < 0.001);
+        assert!(boltzmann_win(0.1, 0.01) > 0.99);
+        assert!(boltzmann_win(0.1, 0.1) > boltzmann_win(0.1, 0.2));
+
+        // Starting from the same pool, cold tournaments keep fitter survivors than hot ones
+        let survivors = |temperature: f32| {
+            let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+            let mut pool = Pool::new(40, |g: &NamedGene| g.0 as f32, rng);
+            pool.set_selection(Selection::Boltzmann(Schedule::Constant(temperature)));
+            pool.evolve(rng);
+            assert_eq!(pool.genes.len(), 40);
+            pool.genes[.. 10].iter().map(|g| g.0 .0).sum::<i32>()
+        };
+        assert!(survivors(0.0) > survivors(1000.0));
+
+        // A gene never competes with itself, so in a pool of two the fitter one always wins a cold
+        // tournament
+        for _ in 0 .. 20 {
+            let mut pair = Pool::new(2, |g: &NamedGene| g.0 as f32, rng);
+            pair.set_selection(Selection::Boltzmann(Schedule::Constant(0.0)));
+            let fittest = pair.genes.iter().map(|g| g.0 .0).max();
+            pair.evolve(rng);
+            assert_eq!(Some(pair.genes[0].0 .0), fittest);
+        }
+
+        // The temperature follows its schedule from generation 0
+        let mut pool = Pool::new(40, |g: &NamedGene| g.0 as f32, rng);
+        pool.set_selection(Selection::Boltzmann(Schedule::Linear { from: 100.0, to: 0.0, over: 3 }));
+        for _ in 0 .. 5 {
+            pool.evolve(rng);
+        }
+        assert_eq!(pool.genes.len(), 40);
+    }
+
+    #[test]
+    fn pareto_selection() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Two objectives that pull in opposite directions, so every distinct gene is on the front
+        struct Opposed;
+
+        impl Fitness<NamedGene> for Opposed {
+            fn evaluate(&self, gene: &NamedGene) -> f32 {
+                gene.0 as f32
+            }
+
+            fn evaluate_detailed(&self, gene: &NamedGene) -> Option<Vec<f32>> {
+                Some(vec![gene.0 as f32, -gene.0 as f32])
+            }
+        }
+
+        // Crowding and niching both keep the ends of the front, where proportionate selection
+        // favors the top
+        for selection in [Selection::Pareto, Selection::ReferencePoints(4)] {
+            let mut pool = Pool::new(40, Opposed, rng);
+            pool.set_selection(selection);
+            for _ in 0 .. 3 {
+                let lowest = pool.genes.iter().map(|g| g.0 .0).min().unwrap();
+                let highest = pool.genes.iter().map(|g| g.0 .0).max().unwrap();
+                pool.evolve(rng);
+                assert_eq!(pool.genes.len(), 40);
+                let survivors: Vec<i32> = pool.genes[.. 10].iter().map(|g| g.0 .0).collect();
+                assert!(survivors.contains(&lowest) && survivors.contains(&highest), "{:?}", survivors);
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_mutations() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Each mutation adds one, and the mutated genes follow the crosses
+        let mut pool = Pool::new(8, |g: &NamedGene| g.0 as f32, rng);
+        pool.set_mutations(3);
+        pool.evolve(rng);
+        assert_eq!(pool.genes[4].0 .0, pool.genes[0].0 .0 + 3);
+        assert_eq!(pool.genes[5].0 .0, pool.genes[1].0 .0 + 3);
+        pool.set_mutations(0);
+        pool.evolve(rng);
+        assert_eq!(pool.genes[4].0 .0, pool.genes[0].0 .0 + 1);
+    }
+
+    #[test]
+    fn run_until_termination() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Mutation only ever adds one, so the best climbs
+        let mut pool = Pool::new(8, |g: &NamedGene| g.0 as f32, rng);
+        let start = pool.get_best().1;
+        let summary = pool.run_until(Termination { generations: Some(5), ..Termination::default() }, rng).unwrap();
+        assert_eq!(summary.reason, StopReason::Generations);
+        assert_eq!(summary.generations, 5);
+        assert!(summary.improved_at.is_some_and(|g| g < 5));
+        assert!(summary.fitness > start);
+        assert_eq!(summary.fitness, pool.get_best().1);
+        assert!(summary.mean <= summary.fitness);
+
+        // The target is checked after each generation, before the generation limit runs out
+        let goal = summary.fitness + 2.0;
+        let target = Termination { generations: Some(100), target_fitness: Some(goal), ..Termination::default() };
+        let summary = pool.run_until(target, rng).unwrap();
+        assert_eq!(summary.reason, StopReason::TargetFitness);
+        assert!(summary.generations < 100);
+        assert_eq!(summary.fitness, goal);
+
+        // A fitness that never improves stagnates
+        let mut flat = Pool::new(8, |_: &NamedGene| 1.0, rng);
+        let summary = flat.run_until(Termination { stagnation: Some(4), ..Termination::default() }, rng).unwrap();
+        assert_eq!(summary.reason, StopReason::Stagnation);
+        assert_eq!((summary.generations, summary.longest_stall, summary.improved_at), (4, 4, None));
+
+        let summary = flat.run_until(Termination { timeout: Some(Duration::from_secs(0)), ..Termination::default() }, rng).unwrap();
+        assert_eq!((summary.reason, summary.generations), (StopReason::Timeout, 0));
+        // Without a condition the run would never stop, so it doesn't start
+        assert_eq!(flat.run_until(Termination::default(), rng).unwrap_err(), Termination::default().problems());
+        assert!(!Termination::default().problems().is_empty());
+    }
+
+    #[test]
+    fn generation_stats() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        let mut pool = Pool::from_genes(vec![NamedGene(1), NamedGene(4), NamedGene(4), NamedGene(7)],
+                                        |g: &NamedGene| g.0 as f32);
+        let stats = pool.stats();
+        assert_eq!(*stats.best, NamedGene(7));
+        assert_eq!((stats.min, stats.max, stats.mean, stats.median), (1.0, 7.0, 4.0, 4.0));
+        assert!((stats.std_dev - 4.5f32.sqrt()).abs() < 1e-6);
+        assert_eq!(stats.unique, 3);
+        // NaN scores are left out, and an odd count has a middle gene
+        pool.genes[0].1 = f32::NAN;
+        let stats = pool.stats();
+        assert_eq!((stats.min, stats.median, stats.mean), (4.0, 4.0, 5.0));
+
+        // The erased pool agrees
+        pool.evolve(rng);
+        let stats = pool.stats();
+        let erased = pool.stats_dyn();
+        assert_eq!(erased.best.to_string(), stats.best.to_string());
+        assert_eq!((erased.max, erased.mean, erased.unique), (stats.max, stats.mean, stats.unique));
+    }
+
+    #[test]
+    fn nan_fitness() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // Genes scoring NaN are never the best, and never selected while other genes have weight
+        let fitness = |g: &NamedGene| if g.0 % 2 == 0 { f32::NAN } else { g.0 as f32 };
+        let mut pool = Pool::new(12, fitness, rng);
+        pool.genes[0].1 = f32::NAN;
+        assert!(!pool.get_best().1.is_nan());
+        pool.evolve(rng);
+        assert!(!pool.get_best().1.is_nan());
+        assert!(pool.genes[.. 3].iter().all(|g| !g.1.is_nan()));
+        pool.resize(6, rng);
+        assert!(!pool.genes[0].1.is_nan());
+    }
+
+    #[test]
+    fn dyn_pool() {
+        use rand::SeedableRng;
+        let rng = &mut rand::Isaac64Rng::from_seed(&[123]);
+
+        // A pool can be driven without knowing its gene type
+        let fitness: BoxedFitness<NamedGene> = Box::new(|g| g.0 as f32);
+        let mut pool = new_dyn_pool(8, fitness, rng);
+        pool.evolve_dyn(rng);
+        assert_eq!(pool.genes_dyn().len(), 8);
+
+        // The erased best gene can be displayed and downcast back to its concrete type
+        let (best, fit) = pool.best_dyn();
+        let concrete = best.as_any().downcast_ref::<NamedGene>().unwrap();
+        assert_eq!(concrete.0 as f32, fit);
+        assert_eq!(format!("{}", best), format!("gene {}", concrete.0));
+        assert_eq!(format!("{}", best.clone_box()), format!("{}", best));
+    }
+
+    // A gene that doesn't share the global id counter, so it can be used alongside `gen_pool`
+    #[derive(PartialEq, Eq, Hash, Clone, Debug)]
+    struct NamedGene(i32);
+
+    impl Gene for NamedGene {
+        type Config = ();
+
+        fn generate<R: Rng>(_cfg: &(), rng: &mut R) -> Self {
+            NamedGene(rng.gen_range(0, 100))
+        }
+
+        fn mutate<R: Rng>(&self, _cfg: &(), _rng: &mut R) -> Self {
+            NamedGene(self.0 + 1)
+        }
+
+        fn cross<R: Rng>(&self, _cfg: &(), other: &Self, _rng: &mut R) -> Self {
+            NamedGene(self.0.max(other.0))
+        }
+    }
+
+    impl fmt::Display for NamedGene {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "gene {}", self.0)
+        }
+    }
 }
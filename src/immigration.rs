@@ -0,0 +1,81 @@
+//
+// Where the new genes that fill the last part of every generation come from. By default they're
+// generated just like the first generation was, but a pool can be given a different strategy, e.g.
+// mutating its best survivors, or generating longer programs later in the run.
+//
+
+use super::gene::Gene;
+use super::objective::Objective;
+
+use rand::Rng;
+use std::cmp::Reverse;
+
+// A way to make the new genes of each generation, separate from how the pool was first filled
+pub trait Immigration<T: Gene>: Send + Sync {
+    // Make one new gene for the given generation, counting from 1. `survivors` are the genes
+    // selected this generation with their fitness, in no particular order.
+    fn immigrant(&self, config: &T::Config, survivors: &[(T, f32)], generation: usize, rng: &mut dyn Rng) -> T;
+}
+
+// Any closure with the same arguments is an immigration strategy
+impl<T, F> Immigration<T> for F
+    where T: Gene,
+          F: Fn(&T::Config, &[(T, f32)], usize, &mut dyn Rng) -> T + Send + Sync,
+    {
+    fn immigrant(&self, config: &T::Config, survivors: &[(T, f32)], generation: usize, rng: &mut dyn Rng) -> T {
+        self(config, survivors, generation, rng)
+    }
+}
+
+// Mutate one of the most fit survivors, chosen at random among the best `count` of them. This keeps
+// new genes close to what already works, at the cost of less variety than generating them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MutateElite(pub usize);
+
+impl<T: Gene> Immigration<T> for MutateElite {
+    fn immigrant(&self, config: &T::Config, survivors: &[(T, f32)], _generation: usize, mut rng: &mut dyn Rng) -> T {
+        if survivors.is_empty() {
+            return T::generate(config, &mut rng);
+        }
+        let mut order: Vec<usize> = (0 .. survivors.len()).collect();
+        order.sort_by_key(|&i| Reverse(Objective(survivors[i].1)));
+        let elite = self.0.max(1).min(order.len());
+        let i = order[(&mut rng).gen_range(0, elite)];
+        survivors[i].0.mutate(config, &mut rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Pool;
+    use prog_gene::{self, ProgramConfig, ProgramGene};
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn immigrants() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+
+        // Later generations generate longer programs than the first
+        let longer = |cfg: &ProgramConfig, _: &[(ProgramGene, f32)], generation: usize, mut rng: &mut dyn Rng| {
+            let cfg = ProgramConfig { max_len: cfg.max_len + 10 * generation, ..cfg.clone() };
+            ProgramGene::generate(&cfg, &mut rng)
+        };
+        let mut pool = Pool::new(40, fitness, rng);
+        assert!(pool.iter().all(|g| g.0.size() <= 10));
+        pool.set_immigration(longer);
+        pool.evolve(rng);
+        assert_eq!(pool.len(), 40);
+        assert!(pool.iter().skip(30).any(|g| g.0.size() > 10));
+
+        // Elite immigrants are one mutation away from the best survivor
+        let survivors = vec![(ProgramGene(vec![]), 0.0), ("+".parse().unwrap(), 1.0)];
+        let best: ProgramGene = "+".parse().unwrap();
+        for _ in 0 .. 20 {
+            let gene: ProgramGene = MutateElite(1).immigrant(&Default::default(), &survivors, 1, rng);
+            assert!(gene != survivors[0].0);
+            assert!(gene.size() <= best.size() + 1);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//
+// Export stack programs as infix math, e.g. "3 + a - b*b", by running them on symbols for the
+// inputs instead of numbers. Programs run for a bounded number of steps, so each one that only
+// branches and loops on constants comes out as a single expression of a and b, simplified wherever
+// that can't change what it computes. The arithmetic is the language's own: it wraps on overflow, `/` truncates, dividing by
+// zero gives 0, and comparisons give 1 or 0.
+//
+
+use super::lang::{self, Command, Prog, Underflow};
+use super::prog_gene::MAX_STEPS;
+
+use std::fmt;
+
+// An arithmetic expression of the inputs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Num(i32),
+    A,
+    B,
+    // An arithmetic command or comparison applied to two expressions
+    Op(Command, Box<Expr>, Box<Expr>),
+}
+
+// Why a program doesn't come out as an expression
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inexpressible {
+    // It pops the empty stack under `Underflow::Error`, which makes the run an error
+    Underflow,
+    // Whether it skips or repeats progs depends on the inputs, so it has no single expression
+    Branch,
+}
+
+impl Expr {
+    // Apply an arithmetic command or comparison to two expressions, folding constants and leaving
+    // out operations that do nothing
+    pub fn apply(c: Command, a: Expr, b: Expr) -> Expr {
+        use self::Command::*;
+        use self::Expr::Num;
+        match (c, a, b) {
+            (_, Num(x), Num(y)) => Num(c.apply(x, y)),
+            (Add, Num(0), e) | (Add, e, Num(0)) | (Sub, e, Num(0)) | (Mult, Num(1), e) | (Mult, e, Num(1))
+                | (Div, e, Num(1)) => e,
+            (Mult, Num(0), _) | (Mult, _, Num(0)) | (Div, Num(0), _) | (Div, _, Num(0)) => Num(0),
+            (Sub, ref x, ref y) | (Lt, ref x, ref y) | (Gt, ref x, ref y) if x == y => Num(0),
+            (Eq, ref x, ref y) if x == y => Num(1),
+            // Subtract a negative number as its opposite, which i32::MIN doesn't have
+            (Add, e, Num(y)) if y < 0 && y != i32::MIN => Expr::Op(Sub, Box::new(e), Box::new(Num(-y))),
+            (Sub, e, Num(y)) if y < 0 && y != i32::MIN => Expr::Op(Add, Box::new(e), Box::new(Num(-y))),
+            (c, a, b) => Expr::Op(c, Box::new(a), Box::new(b)),
+        }
+    }
+
+    // How tightly the expression binds, for deciding where it needs parentheses
+    fn precedence(&self) -> u8 {
+        match *self {
+            Expr::Op(Command::Lt, ..) | Expr::Op(Command::Gt, ..) | Expr::Op(Command::Eq, ..) => 0,
+            Expr::Op(Command::Add, ..) | Expr::Op(Command::Sub, ..) => 1,
+            Expr::Op(..) => 2,
+            _ => 3,
+        }
+    }
+}
+
+// Display in the usual notation, with only the parentheses that matter. Operators are left
+// associative, except that comparisons of comparisons are always grouped, and `*` and `/` are
+// written without spaces so they stand out from the rest.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::A => write!(f, "a"),
+            Expr::B => write!(f, "b"),
+            Expr::Op(c, ref a, ref b) => {
+                let precedence = self.precedence();
+                if a.precedence() < precedence || (precedence == 0 && a.precedence() == 0) {
+                    write!(f, "({})", a)?;
+                } else {
+                    write!(f, "{}", a)?;
+                }
+                if precedence <= 1 {
+                    write!(f, " {} ", c.symbol())?;
+                } else {
+                    write!(f, "{}", c.symbol())?;
+                }
+                // a - (b + c) and a*(b/c) aren't a - b + c and a*b/c, and a negative number on the
+                // right would run into the operator
+                let grouped = b.precedence() < precedence
+                    || (b.precedence() == precedence && (precedence == 0 || c == Command::Sub || c == Command::Div
+                                                         || matches!(**b, Expr::Op(Command::Div, ..))))
+                    || matches!(**b, Expr::Num(n) if n < 0);
+                if grouped {
+                    write!(f, "({})", b)
+                } else {
+                    write!(f, "{}", b)
+                }
+            }
+        }
+    }
+}
+
+// The state of a program running on symbols
+struct Symbols {
+    data: Vec<Expr>,
+    frames: Vec<Vec<Expr>>,
+    last: Expr,
+    underflow: Underflow,
+    underflowed: bool,
+}
+
+impl Symbols {
+    // Pop the way `lang::Stack::pop` does
+    fn pop(&mut self) -> Expr {
+        match self.data.pop() {
+            Some(e) => {
+                self.last = e.clone();
+                e
+            }
+            None => {
+                self.underflowed |= self.underflow == Underflow::Error;
+                if self.underflow == Underflow::Repeat { self.last.clone() } else { Expr::Num(0) }
+            }
+        }
+    }
+}
+
+// Run a program on the inputs a and b as symbols, the same way `ProgramGene::run_checked_with`
+// runs it on numbers, and give its output as an expression. An `if` or `times` whose operands are
+// constants skips or repeats progs as it would on numbers; any other is a branch.
+pub fn expression(program: &[Prog], underflow: Underflow) -> Result<Expr, Inexpressible> {
+    let mut s = Symbols { data: vec![Expr::A, Expr::B], frames: Vec::new(), last: Expr::Num(0), underflow,
+                          underflowed: false };
+    // The progs yet to run, the next on top, as on `lang::Stack`
+    let mut commands: Vec<Prog> = program.iter().rev().cloned().collect();
+    let mut steps = 0;
+    while steps < MAX_STEPS && !s.underflowed {
+        let p = match commands.pop() {
+            Some(p) => p,
+            None => break,
+        };
+        steps += 1;
+        match p {
+            Prog::D(d) => s.data.push(Expr::Num(d)),
+            Prog::C(c) => match c {
+                Command::Add | Command::Sub | Command::Mult | Command::Div
+                    | Command::Lt | Command::Gt | Command::Eq => {
+                    let b = s.pop();
+                    let a = s.pop();
+                    s.data.push(Expr::apply(c, a, b));
+                }
+                // Not x is exactly x = 0
+                Command::Not => {
+                    let a = s.pop();
+                    s.data.push(Expr::apply(Command::Eq, a, Expr::Num(0)));
+                }
+                Command::Dup => {
+                    let a = s.pop();
+                    s.data.push(a.clone());
+                    s.data.push(a);
+                }
+                Command::Swap => {
+                    let b = s.pop();
+                    let a = s.pop();
+                    s.data.push(b);
+                    s.data.push(a);
+                }
+                Command::PushFrame => {
+                    let a = s.pop();
+                    s.frames.push(::std::mem::replace(&mut s.data, vec![a]));
+                }
+                Command::PopFrame => {
+                    if let Some(outer) = s.frames.pop() {
+                        let a = s.pop();
+                        s.data = outer;
+                        s.data.push(a);
+                    }
+                }
+                Command::If => {
+                    let count = s.pop();
+                    let condition = s.pop();
+                    match (condition, count) {
+                        _ if s.underflowed => {}
+                        (Expr::Num(0), Expr::Num(n)) => lang::skip_next(&mut commands, n as i64),
+                        (Expr::Num(_), Expr::Num(_)) => {}
+                        _ => return Err(Inexpressible::Branch),
+                    }
+                }
+                Command::Times => {
+                    let len = s.pop();
+                    let count = s.pop();
+                    match (count, len) {
+                        _ if s.underflowed => {}
+                        (Expr::Num(n), Expr::Num(k)) => {
+                            lang::repeat_next(&mut commands, n as i64, k as i64, Some(MAX_STEPS - steps))
+                        }
+                        _ => return Err(Inexpressible::Branch),
+                    }
+                }
+            },
+        }
+    }
+    let output = s.pop();
+    if s.underflowed { Err(Inexpressible::Underflow) } else { Ok(output) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::{ProgramGene, Runnable};
+
+    fn infix(program: &str) -> String {
+        let program: ProgramGene = program.parse().unwrap();
+        expression(&program.0, Underflow::Zero).unwrap().to_string()
+    }
+
+    #[test]
+    fn export_infix() {
+        assert_eq!(infix("swap 3 swap + swap dup * -"), "3 + a - b*b");
+        assert_eq!(infix("swap"), "a");
+        assert_eq!(infix("+ 2 *"), "(a + b)*2");
+        assert_eq!(infix("- 5 swap -"), "5 - (a - b)");
+        assert_eq!(infix("/ 7 swap /"), "7/(a/b)");
+        assert_eq!(infix("* -3 *"), "a*b*(-3)");
+        assert_eq!(infix("+ -3 +"), "a + b - 3");
+        // Folding and identities
+        assert_eq!(infix("2 3 * +"), "b + 6");
+        assert_eq!(infix("0 * +"), "a");
+        assert_eq!(infix("dup - +"), "a");
+        assert_eq!(infix("0 / +"), "a");
+        // The empty stack gives 0
+        assert_eq!(infix("+ + +"), "a + b");
+        // Frames keep what's under them
+        assert_eq!(infix("pushframe dup * popframe +"), "a + b*b");
+        // Comparisons bind loosest, and never chain
+        assert_eq!(infix("< 2 *"), "(a < b)*2");
+        assert_eq!(infix("+ 3 <"), "a + b < 3");
+        assert_eq!(infix("= 1 ="), "(a = b) = 1");
+        assert_eq!(infix("dup ="), "1");
+        assert_eq!(infix("< not"), "(a < b) = 0");
+        // Branches on constants are taken, but branches on the inputs have no expression
+        assert_eq!(infix("0 1 if * +"), "a + b");
+        assert_eq!(infix("2 1 if * +"), "a*b");
+        let program: ProgramGene = "< 1 if dup".parse().unwrap();
+        assert_eq!(expression(&program.0, Underflow::Zero), Err(Inexpressible::Branch));
+        // Loops on constants are unrolled, but loops on the inputs have no expression
+        assert_eq!(infix("3 2 times dup *"), "b*b*b*b*b*b*b*b");
+        let program: ProgramGene = "2 times dup *".parse().unwrap();
+        assert_eq!(expression(&program.0, Underflow::Zero), Err(Inexpressible::Branch));
+
+        // Each underflow policy pops the empty stack the way the interpreter does
+        let program: ProgramGene = "+ * dup".parse().unwrap();
+        assert_eq!(expression(&program.0, Underflow::Repeat).unwrap().to_string(), "(a + b)*(a + b)");
+        assert_eq!(expression(&program.0, Underflow::Error), Err(Inexpressible::Underflow));
+
+        // The expression computes what the program does
+        for program in &["3 + dup * -", "swap - 7 / dup *", "pushframe 2 * swap popframe -", "-1 + swap * 4 /",
+                         "dup 4 > swap 1 = +", "not swap 0 not -", "0 2 if 9 + -1 3 if", "4 2 times 1 -", "swap 1000 3 times - 2 *"] {
+            let gene: ProgramGene = program.parse().unwrap();
+            let expr = expression(&gene.0, Underflow::Zero).unwrap();
+            for &(a, b) in &[(0, 0), (3, -4), (-7, 2), (100, 9)] {
+                assert_eq!(eval(&expr, a, b), gene.run(a, b), "{} on ({}, {})", program, a, b);
+            }
+        }
+    }
+
+    fn eval(e: &Expr, a: i32, b: i32) -> i32 {
+        match *e {
+            Expr::Num(n) => n,
+            Expr::A => a,
+            Expr::B => b,
+            Expr::Op(c, ref x, ref y) => c.apply(eval(x, a, b), eval(y, a, b)),
+        }
+    }
+}
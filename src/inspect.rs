@@ -0,0 +1,92 @@
+//
+// Filtering, sorting, and deduping the stack programs of a saved population, e.g. a checkpoint or
+// the population.txt of a run directory
+//
+
+use super::objective::Objective;
+use super::prog_gene::ProgramGene;
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+// The order to list genes in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+    // Most fit first, then shortest first
+    Fitness,
+    // Shortest first, then most fit first
+    Length,
+}
+
+// Which genes of a population to keep, and how to list them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    pub min_fitness: Option<f32>,
+    pub max_length: Option<usize>,
+    // Keep only programs that run these progs in a row somewhere
+    pub contains: Option<ProgramGene>,
+    // Keep only the first of genes with the same program, after sorting
+    pub dedup: bool,
+    // None keeps the order the genes came in
+    pub sort: Option<Sort>,
+}
+
+impl Query {
+    // Whether a gene with this fitness passes the filters
+    pub fn keeps(&self, gene: &ProgramGene, fitness: f32) -> bool {
+        self.min_fitness.is_none_or(|m| fitness >= m)
+            && self.max_length.is_none_or(|m| gene.0.len() <= m)
+            && self.contains.as_ref().is_none_or(|c| contains(&gene.0, &c.0))
+    }
+
+    // The genes that pass the filters, sorted and deduped
+    pub fn apply(&self, genes: Vec<(ProgramGene, f32)>) -> Vec<(ProgramGene, f32)> {
+        let mut genes: Vec<(ProgramGene, f32)> = genes.into_iter().filter(|g| self.keeps(&g.0, g.1)).collect();
+        match self.sort {
+            Some(Sort::Fitness) => genes.sort_by_key(|g| (Reverse(Objective(g.1)), g.0.0.len())),
+            Some(Sort::Length) => genes.sort_by_key(|g| (g.0.0.len(), Reverse(Objective(g.1)))),
+            None => {}
+        }
+        if self.dedup {
+            let mut seen = HashSet::new();
+            genes.retain(|g| seen.insert(g.0.clone()));
+        }
+        genes
+    }
+}
+
+// Whether `part` appears as a run somewhere in `whole`. Everything contains the empty program.
+fn contains<T: PartialEq>(whole: &[T], part: &[T]) -> bool {
+    part.is_empty() || whole.windows(part.len()).any(|w| w == part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_population() {
+        let gene = |s: &str| s.parse::<ProgramGene>().unwrap();
+        let genes = vec![
+            (gene("swap dup *"), 0.5),
+            (gene("+"), 0.9),
+            (gene("dup * 3"), 0.7),
+            (gene("+"), 0.9),
+            (gene("2 2 2 2 2"), 0.1),
+        ];
+
+        // Nothing asked for keeps everything as it was
+        assert_eq!(Query::default().apply(genes.clone()), genes);
+
+        let query = Query { min_fitness: Some(0.5), sort: Some(Sort::Fitness), dedup: true, ..Query::default() };
+        let kept: Vec<String> = query.apply(genes.clone()).iter().map(|g| g.0.to_string()).collect();
+        assert_eq!(kept, vec!["+", "dup * 3", "swap dup *"]);
+
+        let query = Query { contains: Some(gene("dup *")), sort: Some(Sort::Length), ..Query::default() };
+        let kept = query.apply(genes.clone());
+        assert_eq!(kept, vec![(gene("dup * 3"), 0.7), (gene("swap dup *"), 0.5)]);
+
+        let query = Query { max_length: Some(1), ..Query::default() };
+        assert_eq!(query.apply(genes).len(), 2);
+    }
+}
@@ -0,0 +1,200 @@
+//
+// The island model: several pools evolving side by side, occasionally trading their best genes
+//
+
+use super::gene::{Fitness, Gene, Pool};
+use super::objective::Objective;
+
+use rand::{Rng, SeedableRng, StdRng};
+use std::hash::Hash;
+use std::sync::mpsc;
+use std::thread;
+
+// How often and how much the islands trade genes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Migration {
+    // Trade genes every this many generations
+    pub interval: usize,
+    // The number of best genes each island sends out per trade
+    pub migrants: usize,
+    // Which islands each island sends its migrants to
+    pub topology: Topology,
+}
+
+// How the islands are connected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    // Each island sends to the next, and the last to the first
+    Ring,
+    // Each island sends to every other island
+    Full,
+}
+
+impl Topology {
+    // The islands that island i of n sends its migrants to
+    pub fn destinations(self, i: usize, n: usize) -> Vec<usize> {
+        match self {
+            Topology::Ring if n > 1 => vec![(i + 1) % n],
+            Topology::Ring => vec![],
+            Topology::Full => (0 .. n).filter(|&j| j != i).collect(),
+        }
+    }
+}
+
+// Several pools evolving in turn on one thread, trading their best genes every
+// `migration.interval` generations. Unlike `evolve_parallel`, every island sends its migrants
+// before any takes them in, so a run with a fixed seed is reproducible.
+pub struct Archipelago<T: Gene, F> {
+    islands: Vec<Pool<T, F>>,
+    migration: Migration,
+    generation: usize,
+}
+
+impl<T, F> Archipelago<T, F>
+    where T: Gene + Hash + Eq + Clone,
+          F: Fitness<T>,
+    {
+    pub fn new(islands: Vec<Pool<T, F>>, migration: Migration) -> Archipelago<T, F> {
+        Archipelago { islands, migration, generation: 0 }
+    }
+
+    // Evolve every island one generation, in order, then migrate if it's time
+    pub fn evolve<R: Rng>(&mut self, rng: &mut R) {
+        for pool in &mut self.islands {
+            pool.evolve(rng);
+        }
+        self.generation += 1;
+        if self.migration.interval > 0 && self.generation.is_multiple_of(self.migration.interval) {
+            self.migrate();
+        }
+    }
+
+    // Send copies of each island's best genes to its destinations. Migrants are chosen from every
+    // island before any arrive, so they never travel more than one hop per trade.
+    pub fn migrate(&mut self) {
+        let n = self.islands.len();
+        let outgoing: Vec<_> = self.islands.iter().map(|pool| pool.best_n(self.migration.migrants)).collect();
+        let mut incoming: Vec<Vec<(T, f32)>> = (0 .. n).map(|_| Vec::new()).collect();
+        for (i, migrants) in outgoing.into_iter().enumerate() {
+            for j in self.migration.topology.destinations(i, n) {
+                incoming[j].extend(migrants.iter().cloned());
+            }
+        }
+        for (pool, migrants) in self.islands.iter_mut().zip(incoming) {
+            if !migrants.is_empty() {
+                pool.immigrate(migrants);
+            }
+        }
+    }
+
+    // The best gene on any island and its fitness. This is only valid after a call to evolve.
+    pub fn get_best(&self) -> (&T, f32) {
+        self.islands.iter().map(|pool| pool.get_best())
+            .max_by_key(|&(_, fit)| Objective(fit))
+            .expect("an archipelago needs at least one island")
+    }
+
+    // The number of generations evolved so far
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn islands(&self) -> &[Pool<T, F>] {
+        &self.islands
+    }
+
+    pub fn into_islands(self) -> Vec<Pool<T, F>> {
+        self.islands
+    }
+}
+
+// Evolve every pool on its own thread for the given number of generations. Every
+// `migration.interval` generations each island sends copies of its best genes to its destinations
+// in the topology, and takes in whatever migrants have arrived without waiting for them. Island i
+// draws from an RNG seeded with (seed, i).
+//
+// Because migrants arrive whenever the sending thread gets to them, runs with more than one island
+// aren't reproducible even with a fixed seed.
+pub fn evolve_parallel<T, F>(pools: Vec<Pool<T, F>>, generations: usize, migration: Migration, seed: u64)
+    -> Vec<Pool<T, F>>
+    where T: Gene + Hash + Eq + Clone + Send,
+          T::Config: Send,
+          F: Fitness<T> + Send,
+    {
+    let n = pools.len();
+    // One inbox per island
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0 .. n).map(|_| mpsc::channel::<Vec<(T, f32)>>()).unzip();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = pools.into_iter().zip(receivers).enumerate().map(|(i, (mut pool, inbox))| {
+            let destinations: Vec<_> = migration.topology.destinations(i, n).into_iter()
+                .map(|j| senders[j].clone()).collect();
+            scope.spawn(move || {
+                let rng = &mut StdRng::from_seed(&[seed as usize, i]);
+                for generation in 1 ..= generations {
+                    pool.evolve(rng);
+                    if migration.interval > 0 && generation % migration.interval == 0 {
+                        // A destination may already be done, in which case nobody needs migrants
+                        let migrants = pool.best_n(migration.migrants);
+                        for d in &destinations {
+                            let _ = d.send(migrants.clone());
+                        }
+                        while let Ok(migrants) = inbox.try_recv() {
+                            pool.immigrate(migrants);
+                        }
+                    }
+                }
+                pool
+            })
+        }).collect();
+
+        handles.into_iter().map(|h| h.join().expect("island thread panicked")).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::{self, ProgramGene};
+
+    #[test]
+    fn evolve_islands() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+        let pools: Vec<_> = (0 .. 4).map(|_| Pool::new(20, fitness, rng)).collect();
+
+        let pools = evolve_parallel(pools, 30, Migration { interval: 5, migrants: 2, topology: Topology::Ring }, 123);
+        assert_eq!(pools.len(), 4);
+        for pool in &pools {
+            assert_eq!(pool.len(), 20);
+        }
+    }
+
+    #[test]
+    fn archipelago() {
+        assert_eq!(Topology::Ring.destinations(3, 4), vec![0]);
+        assert_eq!(Topology::Ring.destinations(0, 1), Vec::<usize>::new());
+        assert_eq!(Topology::Full.destinations(1, 4), vec![0, 2, 3]);
+
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a * b - a, g);
+        let run = || {
+            let rng = &mut StdRng::from_seed(&[123]);
+            let pools: Vec<_> = (0 .. 3).map(|_| Pool::new(20, fitness, rng)).collect();
+            let mut archipelago = Archipelago::new(pools, Migration { interval: 4, migrants: 2, topology: Topology::Full });
+            for _ in 0 .. 8 {
+                archipelago.evolve(rng);
+            }
+            archipelago
+        };
+        let archipelago = run();
+        assert_eq!(archipelago.generation(), 8);
+        // Right after a trade on a full topology, every island has the best gene
+        let best = archipelago.get_best().1;
+        for pool in archipelago.islands() {
+            assert_eq!(pool.len(), 20);
+            assert_eq!(pool.get_best().1, best);
+        }
+        // With one thread, a seeded run is the same every time
+        assert_eq!(run().get_best().1, best);
+    }
+}
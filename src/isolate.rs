@@ -0,0 +1,92 @@
+//
+// Keep a run going when the fitness function panics. A panic on one pathological gene would
+// otherwise end hours of evolution; isolated, the gene just gets a penalty fitness.
+//
+
+use super::gene::Fitness;
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A fitness function that gives `penalty` to any gene it panics on, and logs the gene to stderr
+pub struct Isolated<F> {
+    pub fitness: F,
+    pub penalty: f32,
+    // The number of panics caught so far
+    panics: AtomicUsize,
+}
+
+impl<F> Isolated<F> {
+    pub fn new(fitness: F, penalty: f32) -> Isolated<F> {
+        Isolated { fitness, penalty, panics: AtomicUsize::new(0) }
+    }
+
+    // The number of genes the fitness function has panicked on so far
+    pub fn panics(&self) -> usize {
+        self.panics.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: fmt::Display, F: Fitness<T>> Fitness<T> for Isolated<F> {
+    fn evaluate(&self, gene: &T) -> f32 {
+        // A panic can't leave the gene or the fitness function half-changed, since both are only
+        // borrowed immutably
+        match panic::catch_unwind(AssertUnwindSafe(|| self.fitness.evaluate(gene))) {
+            Ok(fit) => fit,
+            Err(cause) => {
+                self.panics.fetch_add(1, Ordering::Relaxed);
+                let message = panic_message(&*cause);
+                eprintln!("Fitness panicked on {} ({}), scoring it {}", gene, message, self.penalty);
+                self.penalty
+            }
+        }
+    }
+
+    fn evaluate_detailed(&self, gene: &T) -> Option<Vec<f32>> {
+        panic::catch_unwind(AssertUnwindSafe(|| self.fitness.evaluate_detailed(gene))).unwrap_or(None)
+    }
+
+    fn is_deterministic(&self) -> bool {
+        self.fitness.is_deterministic()
+    }
+}
+
+// The message a panic was given, if it was given one as text
+pub fn panic_message(cause: &(dyn Any + Send)) -> String {
+    cause.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| cause.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Pool;
+    use prog_gene::ProgramGene;
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn isolate_panics() {
+        // Panic on any program containing a 7
+        let touchy = |g: &ProgramGene| {
+            if g.to_string().split(' ').any(|p| p == "7") {
+                panic!("found a 7");
+            }
+            g.0.len() as f32
+        };
+        let isolated = Isolated::new(touchy, -1.0);
+        assert_eq!(isolated.evaluate(&"1 2 +".parse().unwrap()), 3.0);
+        assert_eq!(isolated.evaluate(&"1 7 +".parse().unwrap()), -1.0);
+        assert_eq!(isolated.panics(), 1);
+
+        // A whole run survives
+        let rng = &mut StdRng::from_seed(&[123]);
+        let mut pool = Pool::new(50, Isolated::new(touchy, -1.0), rng);
+        for _ in 0 .. 10 {
+            pool.evolve(rng);
+        }
+        assert!(pool.get_best().1 > 0.0);
+    }
+}
@@ -0,0 +1,280 @@
+//
+// A journal of every genetic operator applied during a run, for debugging operator bugs. Each entry
+// records the operator, the hashes of its parents, the random numbers it drew, and the hash of the
+// gene it produced, so the run can be replayed and checked for determinism.
+//
+// Gene hashes come from `DefaultHasher`, which is only stable within one build of the program.
+//
+
+use super::gene::Gene;
+
+use rand::Rng;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Write};
+
+// Hash a gene the same way the journal does
+pub fn hash_gene<T: Hash>(gene: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    gene.hash(&mut hasher);
+    hasher.finish()
+}
+
+// A genetic operator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Generate,
+    Mutate,
+    Cross,
+    // A new gene from the pool's immigration strategy, which can't be replayed
+    Immigrate,
+}
+
+impl Op {
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Generate => "generate",
+            Op::Mutate => "mutate",
+            Op::Cross => "cross",
+            Op::Immigrate => "immigrate",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Op> {
+        match s {
+            "generate" => Some(Op::Generate),
+            "mutate" => Some(Op::Mutate),
+            "cross" => Some(Op::Cross),
+            "immigrate" => Some(Op::Immigrate),
+            _ => None,
+        }
+    }
+}
+
+// One application of a genetic operator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub op: Op,
+    // The hashes of the parents, in argument order
+    pub parents: Vec<u64>,
+    // Every value drawn from the RNG, in order
+    pub draws: Vec<u64>,
+    // The hash of the resulting gene
+    pub child: u64,
+}
+
+// An RNG that records every value drawn from the RNG it wraps
+pub struct RecordingRng<'a, R: 'a> {
+    inner: &'a mut R,
+    draws: Vec<u64>,
+}
+
+impl<'a, R: Rng> Rng for RecordingRng<'a, R> {
+    fn next_u32(&mut self) -> u32 {
+        let x = self.inner.next_u32();
+        self.draws.push(x as u64);
+        x
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let x = self.inner.next_u64();
+        self.draws.push(x);
+        x
+    }
+}
+
+// An RNG that plays back recorded draws. Once the draws run out it keeps returning 0 and remembers
+// that it was overdrawn.
+struct ReplayRng<'a> {
+    draws: &'a [u64],
+    overdrawn: bool,
+}
+
+impl<'a> ReplayRng<'a> {
+    fn next(&mut self) -> u64 {
+        match self.draws.split_first() {
+            Some((&x, rest)) => {
+                self.draws = rest;
+                x
+            }
+            None => {
+                self.overdrawn = true;
+                0
+            }
+        }
+    }
+}
+
+impl<'a> Rng for ReplayRng<'a> {
+    fn next_u32(&mut self) -> u32 {
+        self.next() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next()
+    }
+}
+
+// Where a replay stopped matching its journal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    // The entry at this index has a parent that no earlier entry produced
+    UnknownParent(usize),
+    // The entry at this index drew a different number of values from the RNG than it recorded
+    DrawCount(usize),
+    // The entry at this index produced a different gene than it recorded
+    Child { entry: usize, expected: u64, actual: u64 },
+    // The entry at this index came from an immigration strategy, which the journal can't run
+    Immigrant(usize),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Divergence::UnknownParent(i) => write!(f, "entry {}: unknown parent", i),
+            Divergence::DrawCount(i) => write!(f, "entry {}: drew a different number of values", i),
+            Divergence::Immigrant(i) => write!(f, "entry {}: can't replay an immigrant", i),
+            Divergence::Child { entry, expected, actual } =>
+                write!(f, "entry {}: expected child {:016x}, got {:016x}", entry, expected, actual),
+        }
+    }
+}
+
+// The operator applications of a run, in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Journal {
+    pub entries: Vec<Entry>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal { entries: Vec::new() }
+    }
+
+    // Apply an operator to the given parents and record it. `apply` must draw all of its random
+    // numbers from the RNG it is given.
+    pub fn record<T, R, O>(&mut self, op: Op, parents: &[&T], rng: &mut R, apply: O) -> T
+        where T: Hash,
+              R: Rng,
+              O: FnOnce(&mut RecordingRng<R>) -> T,
+        {
+        let mut recording = RecordingRng { inner: rng, draws: Vec::new() };
+        let child = apply(&mut recording);
+        self.entries.push(Entry {
+            op,
+            parents: parents.iter().map(|p| hash_gene(*p)).collect(),
+            draws: recording.draws,
+            child: hash_gene(&child),
+        });
+        child
+    }
+
+    // Replay every entry with its recorded draws and the config the pool had, checking that each
+    // operator produces the same gene it did originally. Returns the number of entries checked.
+    pub fn replay<T: Gene + Clone>(&self, config: &T::Config) -> Result<usize, Divergence> {
+        let mut known: HashMap<u64, T> = HashMap::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let mut parents = Vec::with_capacity(entry.parents.len());
+            for hash in &entry.parents {
+                parents.push(known.get(hash).ok_or(Divergence::UnknownParent(i))?);
+            }
+            let rng = &mut ReplayRng { draws: &entry.draws, overdrawn: false };
+            let child = match (entry.op, parents.as_slice()) {
+                (Op::Generate, &[]) => T::generate(config, rng),
+                (Op::Mutate, &[p]) => p.mutate(config, rng),
+                (Op::Cross, &[p, q]) => p.cross(config, q, rng),
+                (Op::Immigrate, _) => return Err(Divergence::Immigrant(i)),
+                _ => return Err(Divergence::UnknownParent(i)),
+            };
+            if rng.overdrawn || !rng.draws.is_empty() {
+                return Err(Divergence::DrawCount(i));
+            }
+            let actual = hash_gene(&child);
+            if actual != entry.child {
+                return Err(Divergence::Child { entry: i, expected: entry.child, actual });
+            }
+            known.insert(actual, child);
+        }
+        Ok(self.entries.len())
+    }
+
+    // Write the journal as one line per entry: the operator, the parent hashes, the child hash, and
+    // the draws, separated by tabs
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let join = |xs: &[u64]| xs.iter().map(|x| format!("{:x}", x)).collect::<Vec<_>>().join(",");
+        for e in &self.entries {
+            writeln!(w, "{}\t{}\t{:x}\t{}", e.op.name(), join(&e.parents), e.child, join(&e.draws))?;
+        }
+        Ok(())
+    }
+
+    // Read a journal written by `write_to`
+    pub fn read_from<B: BufRead>(r: B) -> io::Result<Self> {
+        let bad = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad journal line: {}", line));
+        let split = |s: &str| -> Result<Vec<u64>, ::std::num::ParseIntError> {
+            s.split(',').filter(|x| !x.is_empty()).map(|x| u64::from_str_radix(x, 16)).collect()
+        };
+        let mut journal = Journal::new();
+        for line in r.lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 4 {
+                return Err(bad(&line));
+            }
+            journal.entries.push(Entry {
+                op: Op::parse(fields[0]).ok_or_else(|| bad(&line))?,
+                parents: split(fields[1]).map_err(|_| bad(&line))?,
+                child: u64::from_str_radix(fields[2], 16).map_err(|_| bad(&line))?,
+                draws: split(fields[3]).map_err(|_| bad(&line))?,
+            });
+        }
+        Ok(journal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Pool;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::SeedableRng;
+
+    #[test]
+    fn record_and_replay() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let mut pool = Pool::new(20, |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g), rng);
+        for _ in 0 .. 5 {
+            pool.evolve(rng);
+        }
+        // 20 generated, then each generation crosses 5, mutates 5 and generates 5
+        assert_eq!(pool.journal.entries.len(), 20 + 5 * 15);
+        assert_eq!(pool.journal.replay::<ProgramGene>(&Default::default()), Ok(95));
+
+        // The journal survives a round trip through text
+        let mut text = Vec::new();
+        pool.journal.write_to(&mut text).unwrap();
+        let read = Journal::read_from(&text[..]).unwrap();
+        assert_eq!(read, pool.journal);
+
+        // Tampering is detected
+        let i = read.entries.iter().position(|e| e.op == Op::Mutate).unwrap();
+        let mut tampered = read.clone();
+        tampered.entries[i].draws.pop();
+        assert_eq!(tampered.replay::<ProgramGene>(&Default::default()), Err(Divergence::DrawCount(i)));
+        let mut tampered = read.clone();
+        tampered.entries[i].child ^= 1;
+        match tampered.replay::<ProgramGene>(&Default::default()) {
+            Err(Divergence::Child { entry, .. }) => assert_eq!(entry, i),
+            other => panic!("tampering not detected: {:?}", other),
+        }
+
+        // Immigrants are recorded, but can't be replayed
+        pool.set_immigration(::immigration::MutateElite(3));
+        pool.evolve(rng);
+        let i = pool.journal.entries.iter().position(|e| e.op == Op::Immigrate).unwrap();
+        assert_eq!(i, 95 + 10);
+        assert_eq!(pool.journal.replay::<ProgramGene>(&Default::default()), Err(Divergence::Immigrant(i)));
+    }
+}
@@ -1,9 +1,18 @@
 //
 // The stack-based programming language
 //
+// Values have a canonical truthiness wherever the language treats them as conditions: 0 is false and
+// every other value is true. Comparisons and `not` give 1 for true and 0 for false.
+//
+
+use std::fmt;
+use std::num::{IntErrorKind, ParseIntError};
+use serde::{Deserialize, Serialize};
 
 // A builtin command to run on the stack
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Command {
     Add,
     Sub,
@@ -11,56 +20,561 @@ pub enum Command {
     Div,
     Dup,
     Swap,
+    // Start a new frame: the top value moves to a new, otherwise empty stack, and the rest of the
+    // stack is put aside until the frame ends. Results worked out inside the frame can't disturb
+    // the values underneath.
+    PushFrame,
+    // End the frame: the top value moves back onto the stack that was put aside, and the rest of
+    // the frame is dropped. Does nothing outside of a frame.
+    PopFrame,
+    // Compare the top value with the one under it, pushing 1 if the lower is less than, greater
+    // than, or equal to the top, or 0 if not
+    Lt,
+    Gt,
+    Eq,
+    // Negate the top value's truthiness: 1 if it's false (0), or 0 if it's true
+    Not,
+    // Branch: pop a count, then a condition, and if the condition is false skip that many of the
+    // progs that come next. A count below 0 skips nothing, and one past the end of the program skips the
+    // rest of it. Skipped progs aren't steps, so they use no fuel.
+    If,
+    // Loop: pop a length, then a count, and run the block of that many of the progs that come next
+    // that many times in all. A count of 0 or below skips the block. Each run of each prog is a
+    // step, so loops are bounded by the fuel, and repeats the fuel left couldn't reach are never
+    // queued. See `repeat_next`.
+    Times,
+}
+
+impl Command {
+    // The arithmetic commands, which combine two values into one
+    pub const ARITHMETIC: [Command; 4] = [Command::Add, Command::Sub, Command::Mult, Command::Div];
+
+    // The comparisons, which combine two values into 1 or 0
+    pub const COMPARISON: [Command; 3] = [Command::Lt, Command::Gt, Command::Eq];
+
+    // The commands random programs are made of, unless they may use frames or branch
+    pub const BASIC: [Command; 6] = [Command::Add, Command::Sub, Command::Mult, Command::Div, Command::Dup, Command::Swap];
+
+    // The commands random programs may also use when they may branch
+    pub const CONDITIONAL: [Command; 5] = [Command::Lt, Command::Gt, Command::Eq, Command::Not, Command::If];
+
+    // Every command
+    pub const ALL: [Command; 14] = [Command::Add, Command::Sub, Command::Mult, Command::Div, Command::Dup, Command::Swap,
+                                    Command::PushFrame, Command::PopFrame, Command::Lt, Command::Gt, Command::Eq,
+                                    Command::Not, Command::If, Command::Times];
+
+    // Apply an arithmetic command or comparison to two values. Arithmetic wraps on overflow and
+    // division by zero gives 0, so every program has a defined result. Panics if this is some
+    // other command.
+    pub fn apply(self, a: i32, b: i32) -> i32 {
+        use self::Command::*;
+        match self {
+            Add => a.wrapping_add(b),
+            Sub => a.wrapping_sub(b),
+            Mult => a.wrapping_mul(b),
+            Div => if b != 0 { a.wrapping_div(b) } else { 0 },
+            Lt => (a < b) as i32,
+            Gt => (a > b) as i32,
+            Eq => (a == b) as i32,
+            _ => panic!("{:?} is not an arithmetic command or comparison", self),
+        }
+    }
+
+    // The symbol used to display a command
+    pub fn symbol(self) -> &'static str {
+        use self::Command::*;
+        match self {
+            Add => "+",
+            Sub => "-",
+            Mult => "*",
+            Div => "/",
+            Dup => "dup",
+            Swap => "swap",
+            PushFrame => "pushframe",
+            PopFrame => "popframe",
+            Lt => "<",
+            Gt => ">",
+            Eq => "=",
+            Not => "not",
+            If => "if",
+            Times => "times",
+        }
+    }
+
+    // Find the command displayed with the given symbol
+    pub fn from_symbol(s: &str) -> Option<Command> {
+        Command::ALL.iter().cloned().find(|c| c.symbol() == s)
+    }
+
+    // The number that stands for this command in encoded programs. These never change, whatever
+    // order the commands are declared in. See `OPCODE_VERSION`.
+    pub fn opcode(self) -> u8 {
+        use self::Command::*;
+        match self {
+            Add => 1,
+            Sub => 2,
+            Mult => 3,
+            Div => 4,
+            Dup => 5,
+            Swap => 6,
+            PushFrame => 7,
+            PopFrame => 8,
+            Lt => 9,
+            Gt => 10,
+            Eq => 11,
+            If => 12,
+            Times => 13,
+            Not => 14,
+        }
+    }
+
+    // Find the command with the given opcode
+    pub fn from_opcode(op: u8) -> Option<Command> {
+        Command::ALL.iter().cloned().find(|c| c.opcode() == op)
+    }
 }
 
-// Either a piece of data or a command. Programs are sequences of Progs
+// The version of the opcode numbering, written first in every encoded program. A new command gets
+// the next unused opcode and bumps the version, so programs encoded by older versions still decode
+// the same. Programs from newer versions are refused, since they may use opcodes this one doesn't
+// know.
+pub const OPCODE_VERSION: u8 = 5;
+
+// The opcode of a number, which follows it as 4 little-endian bytes
+const DATA_OPCODE: u8 = 0;
+
+// Encode a program as bytes: the opcode version, then each prog's opcode, with numbers following
+// their opcode
+pub fn encode(program: &[Prog]) -> Vec<u8> {
+    let mut bytes = vec![OPCODE_VERSION];
+    for p in program {
+        match *p {
+            Prog::D(d) => {
+                bytes.push(DATA_OPCODE);
+                bytes.extend_from_slice(&d.to_le_bytes());
+            }
+            Prog::C(c) => bytes.push(c.opcode()),
+        }
+    }
+    bytes
+}
+
+// Decode a program encoded by `encode` in this or any earlier version
+pub fn decode(bytes: &[u8]) -> Result<Vec<Prog>, String> {
+    let (&version, mut rest) = bytes.split_first().ok_or("empty program encoding")?;
+    if version == 0 || version > OPCODE_VERSION {
+        return Err(format!("unsupported opcode version {} (this build reads up to {})", version, OPCODE_VERSION));
+    }
+    let mut program = Vec::new();
+    while let Some((&op, after)) = rest.split_first() {
+        rest = after;
+        if op == DATA_OPCODE {
+            if rest.len() < 4 {
+                return Err("number cut off at the end of the program".to_string());
+            }
+            let (d, after) = rest.split_at(4);
+            program.push(Prog::D(i32::from_le_bytes([d[0], d[1], d[2], d[3]])));
+            rest = after;
+        } else {
+            program.push(Prog::C(Command::from_opcode(op).ok_or_else(|| format!("unknown opcode {}", op))?));
+        }
+    }
+    Ok(program)
+}
+
+// The most progs `Command::Times` queues at once when there's no fuel to bound it
+pub const MAX_QUEUED: usize = 100_000;
+
+// Take the next `count` progs off a command stack, where the next prog is on top, as
+// `Command::If` does. Counts below 0 take nothing.
+pub fn skip_next<P>(commands: &mut Vec<P>, count: i64) {
+    let skipped = count.clamp(0, commands.len() as i64) as usize;
+    let rest = commands.len() - skipped;
+    commands.truncate(rest);
+}
+
+// Make the block of the next `len` progs on a command stack run `count` times in all, as
+// `Command::Times` does. Only as many repeats are queued as `fuel` more steps could reach, or
+// `MAX_QUEUED` progs' worth without fuel, and the progs queued deeper than that are dropped but
+// one, which shows the run wasn't over. A loop that repeats itself then can't pile up more than that
+// however it nests. Only a skip could have reached the dropped progs.
+pub fn repeat_next<P: Clone>(commands: &mut Vec<P>, count: i64, len: i64, fuel: Option<usize>) {
+    let len = len.clamp(0, commands.len() as i64) as usize;
+    if len == 0 {
+        return;
+    }
+    if count <= 0 {
+        skip_next(commands, len as i64);
+        return;
+    }
+    let reach = fuel.unwrap_or(MAX_QUEUED).min(MAX_QUEUED);
+    let repeats = (count as u64 - 1).min(reach.div_ceil(len) as u64) as usize;
+    let block = commands[commands.len() - len ..].to_vec();
+    for _ in 0 .. repeats {
+        commands.extend_from_slice(&block);
+    }
+    if commands.len() > reach + 1 {
+        let unreachable = commands.len() - reach - 1;
+        commands.drain(.. unreachable);
+    }
+}
+
+// A type of value that programs can compute with. Numbers in programs are always i32s, and wider
+// values give programs room to compute things that grow quickly, like factorials, without wrapping.
+pub trait Value: Copy + Default + PartialEq + fmt::Debug {
+    // The value of a number in a program
+    fn from_constant(d: i32) -> Self;
+
+    // Apply an arithmetic command or comparison to two values. See `Command::apply`.
+    fn apply(c: Command, a: Self, b: Self) -> Self;
+
+    // The value as an i64, e.g. for the number of progs `Command::If` skips or `Command::Times`
+    // repeats
+    fn to_i64(self) -> i64;
+
+    // Whether the value is true as a condition, i.e. not 0
+    fn truthy(self) -> bool {
+        self != Self::default()
+    }
+}
+
+impl Value for i32 {
+    fn from_constant(d: i32) -> i32 {
+        d
+    }
+
+    fn apply(c: Command, a: i32, b: i32) -> i32 {
+        c.apply(a, b)
+    }
+
+    fn to_i64(self) -> i64 {
+        self as i64
+    }
+}
+
+// Arithmetic wraps and division by zero gives 0, the same as for i32s
+impl Value for i64 {
+    fn from_constant(d: i32) -> i64 {
+        d as i64
+    }
+
+    fn apply(c: Command, a: i64, b: i64) -> i64 {
+        use self::Command::*;
+        match c {
+            Add => a.wrapping_add(b),
+            Sub => a.wrapping_sub(b),
+            Mult => a.wrapping_mul(b),
+            Div => if b != 0 { a.wrapping_div(b) } else { 0 },
+            Lt => (a < b) as i64,
+            Gt => (a > b) as i64,
+            Eq => (a == b) as i64,
+            _ => panic!("{:?} is not an arithmetic command or comparison", c),
+        }
+    }
+
+    fn to_i64(self) -> i64 {
+        self
+    }
+}
+
+// Either a piece of data or a command. Programs are sequences of Progs. With the serde feature, data
+// is saved as a number and a command as its lowercase name, e.g. [3, "dup", "mult"].
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
 pub enum Prog {
     D(i32),
     C(Command),
 }
 
-// A stack to run programs on, and all other state used by the interpreter
+// Display data as its number and commands as their symbol
+impl fmt::Display for Prog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Prog::D(d) => write!(f, "{}", d),
+            Prog::C(c) => write!(f, "{}", c.symbol()),
+        }
+    }
+}
+
+// Parse a program in the format progs are displayed in, e.g. "1 - -30 dup": numbers and command
+// symbols separated by whitespace. The error names the first token that is neither, with its
+// position counting progs from 0.
+pub fn parse(s: &str) -> Result<Vec<Prog>, String> {
+    s.split_whitespace().enumerate().map(|(position, token)| {
+        if let Some(c) = Command::from_symbol(token) {
+            return Ok(Prog::C(c));
+        }
+        token.parse().map(Prog::D).map_err(|e: ParseIntError| match *e.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow =>
+                format!("number {} at position {} doesn't fit in 32 bits", token, position),
+            _ => format!("unknown token {:?} at position {}", token, position),
+        })
+    }).collect()
+}
+
+// For each prog of a program, the index of the frame command it pairs with, if it's a pushframe or
+// popframe with a partner. A popframe pairs with the nearest unpaired pushframe before it, so pairs
+// nest like brackets. The rest are None.
+pub fn frame_pairs(program: &[Prog]) -> Vec<Option<usize>> {
+    let mut pairs = vec![None; program.len()];
+    let mut open = Vec::new();
+    for (i, p) in program.iter().enumerate() {
+        match *p {
+            Prog::C(Command::PushFrame) => open.push(i),
+            Prog::C(Command::PopFrame) => if let Some(start) = open.pop() {
+                pairs[start] = Some(i);
+                pairs[i] = Some(start);
+            },
+            _ => {}
+        }
+    }
+    pairs
+}
+
+// For each prog of a program, how many blocks it's inside of. A block is the inside of a pair of
+// frame commands (see `frame_pairs`), or the progs a `times` repeats. A `times` right after a
+// number repeats that many progs; any other may repeat anything up to the end of the program, so
+// that's taken as its block.
+pub fn nesting_depths(program: &[Prog]) -> Vec<usize> {
+    // How many blocks start at each prog, less how many end just before it
+    let mut starts = vec![0i64; program.len() + 1];
+    for (open, end) in blocks(program) {
+        starts[open + 1] += 1;
+        starts[end] -= 1;
+    }
+    let mut depth = 0;
+    starts[.. program.len()].iter().map(|&d| {
+        depth += d;
+        depth as usize
+    }).collect()
+}
+
+// Every block of a program, as the position of the command that opens it and the position just
+// past its last prog. See `nesting_depths`.
+fn blocks(program: &[Prog]) -> Vec<(usize, usize)> {
+    let mut blocks: Vec<(usize, usize)> = frame_pairs(program).into_iter().enumerate()
+        .filter_map(|(i, pair)| pair.filter(|&end| end > i).map(|end| (i, end)))
+        .collect();
+    for (i, p) in program.iter().enumerate() {
+        if *p == Prog::C(Command::Times) {
+            let len = match i.checked_sub(1).map(|j| program[j]) {
+                Some(Prog::D(len)) => len.max(0) as usize,
+                _ => program.len(),
+            };
+            blocks.push((i, (i + 1).saturating_add(len).min(program.len())));
+        }
+    }
+    blocks
+}
+
+// The most blocks a prog of a generated or bred program may be inside of, and the sandbox's
+// default. See `nesting_depths`.
+pub const MAX_NESTING: usize = 8;
+
+// Drop the frame commands of a program that don't pair up (see `frame_pairs`), e.g. where a
+// crossover cut a frame in two
+pub fn repair(program: &mut Vec<Prog>) {
+    let pairs = frame_pairs(program);
+    let mut i = 0;
+    program.retain(|p| {
+        let unmatched = pairs[i].is_none() && (*p == Prog::C(Command::PushFrame) || *p == Prog::C(Command::PopFrame));
+        i += 1;
+        !unmatched
+    });
+}
+
+// Unwrap the blocks of a program until no prog is inside more than `max_nesting` of them, starting
+// with the innermost block around the first prog that is. Unwrapping a frame drops both its frame
+// commands, and unwrapping a loop drops its `times`, so the progs it repeated run once.
+pub fn flatten(program: &mut Vec<Prog>, max_nesting: usize) {
+    while let Some(deep) = nesting_depths(program).into_iter().position(|d| d > max_nesting) {
+        let (open, end) = blocks(program).into_iter()
+            .filter(|&(open, end)| open < deep && deep < end)
+            .max_by_key(|b| b.0)
+            .expect("a nested prog is inside a block");
+        if program[open] == Prog::C(Command::PushFrame) {
+            program.remove(end);
+        }
+        program.remove(open);
+    }
+}
+
+// Limits for running programs that can't be trusted, e.g. seed programs from users. See
+// `Stack::run_sandboxed`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sandbox {
+    // The most steps a program may take
+    pub max_steps: usize,
+    // The most data the stack may hold at once, in every frame together
+    pub max_depth: usize,
+    // The most blocks a prog may be inside of. See `nesting_depths`.
+    pub max_nesting: usize,
+    // The largest magnitude of any number in the program
+    pub max_constant: u32,
+    // Commands the program may not use
+    pub forbidden: Vec<Command>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Sandbox {
+        Sandbox { max_steps: 10_000, max_depth: 1000, max_nesting: MAX_NESTING, max_constant: 1_000_000, forbidden: Vec::new() }
+    }
+}
+
+// One way a program broke the limits of a sandbox. Positions count progs from 0, and steps count
+// from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    Forbidden { position: usize, command: Command },
+    Constant { position: usize, value: i32 },
+    Nesting { position: usize, depth: usize },
+    Steps { limit: usize },
+    Depth { step: usize, depth: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::Forbidden { position, command } =>
+                write!(f, "forbidden command {} at position {}", command.symbol(), position),
+            Violation::Constant { position, value } => write!(f, "constant {} at position {} is too large", value, position),
+            Violation::Nesting { position, depth } =>
+                write!(f, "prog at position {} is nested {} blocks deep", position, depth),
+            Violation::Steps { limit } => write!(f, "still running after {} steps", limit),
+            Violation::Depth { step, depth } => write!(f, "stack of {} values at step {} is too deep", depth, step),
+        }
+    }
+}
+
+impl Sandbox {
+    // Find everything wrong with a program that shows without running it: forbidden commands,
+    // constants that are too large, and the first prog nested too deep
+    pub fn check(&self, program: &[Prog]) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = program.iter().enumerate().filter_map(|(position, p)| match *p {
+            Prog::C(command) if self.forbidden.contains(&command) => Some(Violation::Forbidden { position, command }),
+            Prog::D(value) if value.unsigned_abs() > self.max_constant => Some(Violation::Constant { position, value }),
+            _ => None,
+        }).collect();
+        let nested = nesting_depths(program).into_iter().enumerate().find(|d| d.1 > self.max_nesting);
+        if let Some((position, depth)) = nested {
+            violations.push(Violation::Nesting { position, depth });
+        }
+        violations
+    }
+}
+
+// A stack to run programs on, and all other state used by the interpreter. The data are i32s unless
+// another `Value` is given.
 #[derive(Clone, Debug)]
-pub struct Stack {
+pub struct Stack<V = i32> {
     // The data on the stack (no commands)
-    data: Vec<i32>,
-    // The stack of commands yet to be executed
-    commands: Vec<Prog>,
+    data: Vec<V>,
+    // The stack of commands yet to be executed, each with its position in the program it was queued
+    // from
+    commands: Vec<(usize, Prog)>,
+    // The position of the prog run last, if any has run
+    position: Option<usize>,
+    // The data put aside by each frame that hasn't ended, outermost first
+    frames: Vec<Vec<V>>,
+    // How many more steps may run, or None for no limit
+    fuel: Option<usize>,
+    // What popping the empty stack gives
+    underflow: Underflow,
+    // The last value popped, for `Underflow::Repeat`
+    last: V,
+    // Whether the empty stack was popped under `Underflow::Error`
+    underflowed: bool,
+}
+
+// What popping an empty stack gives. Zero makes 0 a free constant, which evolution learns to lean
+// on; the others take it away, to see how much a run depends on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Underflow {
+    // The default value, 0
+    #[default]
+    Zero,
+    // The last value popped, or 0 if nothing has been popped yet
+    Repeat,
+    // 0, and the run stops and counts as an error. See `Stack::underflowed`.
+    Error,
+}
+
+impl<V: Value> Default for Stack<V> {
+    fn default() -> Stack<V> {
+        Stack::empty()
+    }
 }
 
 impl Stack {
-    // Create a new, empty stack
+    // Create a new, empty stack of i32s
     pub fn new() -> Stack {
-        Stack { data: Vec::new(), commands: Vec::new() }
+        Stack::empty()
+    }
+}
+
+impl<V: Value> Stack<V> {
+    // Create a new, empty stack of any type of value, e.g. `Stack::<i64>::empty()`
+    pub fn empty() -> Stack<V> {
+        Stack { data: Vec::new(), commands: Vec::new(), position: None, frames: Vec::new(), fuel: None,
+                underflow: Underflow::Zero, last: V::default(), underflowed: false }
+    }
+
+    // Choose what popping the empty stack gives from now on
+    pub fn set_underflow(&mut self, underflow: Underflow) {
+        self.underflow = underflow;
+    }
+
+    // Whether the empty stack has been popped under `Underflow::Error`. Nothing more runs once it
+    // has.
+    pub fn underflowed(&self) -> bool {
+        self.underflowed
     }
 
     // Push data onto the stack
-    pub fn push(&mut self, d: i32) {
+    pub fn push(&mut self, d: V) {
         self.data.push(d);
     }
 
-    // Pop data off the stack, or get the default value from an empty stack
-    pub fn pop(&mut self) -> i32 {
-        self.data.pop().unwrap_or(0)
+    // The data on the stack, bottom first. Inside a frame, this is only the frame's data.
+    pub fn data(&self) -> &[V] {
+        &self.data
+    }
+
+    // The number of frames that haven't ended
+    pub fn frame_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Pop data off the stack. What an empty stack gives depends on its `Underflow`, 0 by default.
+    pub fn pop(&mut self) -> V {
+        match self.data.pop() {
+            Some(d) => {
+                self.last = d;
+                d
+            }
+            None => match self.underflow {
+                Underflow::Zero => V::default(),
+                Underflow::Repeat => self.last,
+                Underflow::Error => {
+                    self.underflowed = true;
+                    V::default()
+                }
+            },
+        }
     }
 
     // Run a single command
     pub fn run(&mut self, c: Command) {
         use self::Command::*;
         match c {
-            Add | Sub | Mult | Div => {
+            Add | Sub | Mult | Div | Lt | Gt | Eq => {
                 // Pop two
                 let b = self.pop();
                 let a = self.pop();
                 // Push the result
-                self.push(match c {
-                    Add => a + b,
-                    Sub => a - b,
-                    Mult => a * b,
-                    Div => if b != 0 { a / b } else { 0 },
-                    _ => panic!(),
-                });
+                self.push(V::apply(c, a, b));
             }
             Dup => {
                 // Pop one
@@ -77,32 +591,86 @@ impl Stack {
                 self.push(b);
                 self.push(a);
             }
+            PushFrame => {
+                let a = self.pop();
+                let outer = ::std::mem::replace(&mut self.data, vec![a]);
+                self.frames.push(outer);
+            }
+            PopFrame => {
+                if let Some(outer) = self.frames.pop() {
+                    let a = self.pop();
+                    self.data = outer;
+                    self.push(a);
+                }
+            }
+            Not => {
+                let a = self.pop();
+                self.push(V::from_constant(!a.truthy() as i32));
+            }
+            If => {
+                let count = self.pop();
+                let condition = self.pop();
+                if !condition.truthy() {
+                    skip_next(&mut self.commands, count.to_i64());
+                }
+            }
+            Times => {
+                let len = self.pop();
+                let count = self.pop();
+                repeat_next(&mut self.commands, count.to_i64(), len.to_i64(), self.fuel);
+            }
         }
     }
 
     // Queue the given program into the command stack. This doesn't actually run anything.
     pub fn queue_program(&mut self, program: &[Prog]) {
         // Copy the program into the top of the stack
-        for p in program.iter().rev() {
-            self.commands.push(*p);
+        for (i, p) in program.iter().enumerate().rev() {
+            self.commands.push((i, *p));
         }
     }
 
-    // Run the next command on the stack. Does nothing if the stack is empty.
-    pub fn run_next(&mut self) {
-        if let Some(p) = self.commands.pop() {
-            match p {
-                Prog::D(d) => self.push(d),
-                Prog::C(c) => self.run(c),
+    // The position of the prog run last in the program it was queued from, e.g. to trace which
+    // progs a run took through branches and loops. None if nothing has run.
+    pub fn position(&self) -> Option<usize> {
+        self.position
+    }
+
+    // Limit how many more steps may run, however they are run. Each step uses one unit of fuel, and
+    // nothing runs once it's gone. None removes the limit.
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    // The fuel left, or None if there is no limit
+    pub fn fuel_remaining(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    // Run the next command on the stack. Does nothing if the stack is empty, the fuel has run out, or
+    // the stack has underflowed. Returns whether a step was taken.
+    pub fn run_next(&mut self) -> bool {
+        if self.fuel == Some(0) || self.underflowed {
+            return false;
+        }
+        match self.commands.pop() {
+            Some((i, p)) => {
+                self.fuel = self.fuel.map(|f| f - 1);
+                self.position = Some(i);
+                match p {
+                    Prog::D(d) => self.push(V::from_constant(d)),
+                    Prog::C(c) => self.run(c),
+                }
+                true
             }
+            None => false,
         }
     }
 
-    // Run until the command stack is empty. Returns the number of steps taken.
+    // Run until the command stack is empty or the fuel runs out. Returns the number of steps taken.
     pub fn run_all(&mut self) -> usize {
         let mut steps = 0;
-        while !self.commands.is_empty() {
-            self.run_next();
+        while self.run_next() {
             steps += 1;
         }
         steps
@@ -111,18 +679,80 @@ impl Stack {
     // Run at most `max` steps. Returns the number of steps taken.
     pub fn run_until(&mut self, max: usize) -> usize {
         let mut steps = 0;
-        while steps < max && !self.commands.is_empty() {
-            self.run_next();
+        while steps < max && self.run_next() {
             steps += 1;
         }
         steps
     }
+
+    // Queue and run a program within the sandbox's limits. A program that fails `Sandbox::check`
+    // doesn't run at all, and every violation is reported. Otherwise it runs until it finishes or
+    // breaks a limit, which stops it, and that violation is reported. Returns the number of steps
+    // taken. The run is fuelled with the sandbox's steps, so loops only queue what those can reach,
+    // and the fuel is given back afterwards, less the steps taken.
+    pub fn run_sandboxed(&mut self, program: &[Prog], sandbox: &Sandbox) -> Result<usize, Vec<Violation>> {
+        let violations = sandbox.check(program);
+        if !violations.is_empty() {
+            return Err(violations);
+        }
+        let fuel = self.fuel;
+        let limit = fuel.map_or(sandbox.max_steps, |f| f.min(sandbox.max_steps));
+        self.fuel = Some(limit);
+        let result = self.run_within(program, sandbox);
+        let used = limit - self.fuel.unwrap_or(0);
+        self.fuel = fuel.map(|f| f - used);
+        result
+    }
+
+    // Queue and run a program that passed `Sandbox::check`, stopping at the sandbox's limits
+    fn run_within(&mut self, program: &[Prog], sandbox: &Sandbox) -> Result<usize, Vec<Violation>> {
+        self.queue_program(program);
+        let mut steps = 0;
+        loop {
+            if steps == sandbox.max_steps && !self.commands.is_empty() {
+                return Err(vec![Violation::Steps { limit: sandbox.max_steps }]);
+            }
+            if !self.run_next() {
+                return Ok(steps);
+            }
+            steps += 1;
+            // Frames put data aside rather than dropping it, so it all counts
+            let depth = self.data.len() + self.frames.iter().map(Vec::len).sum::<usize>();
+            if depth > sandbox.max_depth {
+                return Err(vec![Violation::Depth { step: steps, depth }]);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn encode_and_decode() {
+        let program = vec![Prog::D(-3), Prog::C(Command::Add), Prog::D(70000), Prog::C(Command::Swap)];
+        let bytes = encode(&program);
+        // The layout is fixed, so encoded programs stay readable
+        assert_eq!(bytes, vec![5, 0, 253, 255, 255, 255, 1, 0, 112, 17, 1, 0, 6]);
+        assert_eq!(decode(&bytes), Ok(program.clone()));
+        // Programs encoded before the frame commands still decode
+        assert_eq!(decode(&[1, 0, 253, 255, 255, 255, 1, 0, 112, 17, 1, 0, 6]), Ok(program));
+        assert_eq!(decode(&[2, 7, 8]), Ok(vec![Prog::C(Command::PushFrame), Prog::C(Command::PopFrame)]));
+        assert_eq!(decode(&[3, 9, 12]), Ok(vec![Prog::C(Command::Lt), Prog::C(Command::If)]));
+        assert_eq!(decode(&[4, 13]), Ok(vec![Prog::C(Command::Times)]));
+        assert_eq!(decode(&[5, 14]), Ok(vec![Prog::C(Command::Not)]));
+        assert_eq!(decode(&[1]), Ok(vec![]));
+        for &c in Command::ALL.iter() {
+            assert_eq!(Command::from_opcode(c.opcode()), Some(c));
+        }
+
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[OPCODE_VERSION + 1, 1]).unwrap_err().contains("version"));
+        assert!(decode(&[1, 200]).unwrap_err().contains("opcode 200"));
+        assert!(decode(&[1, 0, 1, 2]).is_err());
+    }
+
     #[test]
     fn use_stack() {
         let mut s = Stack::new();
@@ -178,6 +808,13 @@ mod tests {
         assert_eq!(s.pop(), 3);
         assert_eq!(s.data.len(), 0);
 
+        // Arithmetic wraps instead of overflowing
+        s.push(i32::MAX);
+        s.push(1);
+        s.run(Command::Add);
+        assert_eq!(s.pop(), i32::MIN);
+        assert_eq!(Command::Div.apply(i32::MIN, -1), i32::MIN);
+
         // We can run whole programs (sequences of commands)
         let prog = [Prog::D(10), Prog::D(2), Prog::C(Command::Div), Prog::C(Command::Dup)];
         s.queue_program(&prog);
@@ -197,4 +834,256 @@ mod tests {
         assert_eq!(s.data.len(), 0);
         assert_eq!(s.commands.len(), 0);
     }
+
+    #[test]
+    fn wide_values() {
+        // i64 stacks run the same programs without wrapping at i32's limits
+        let prog = [Prog::D(i32::MAX), Prog::D(2), Prog::C(Command::Mult), Prog::D(4), Prog::C(Command::Div)];
+        let mut s = Stack::<i64>::empty();
+        s.queue_program(&prog);
+        s.run_all();
+        assert_eq!(s.pop(), i32::MAX as i64 / 2);
+        let mut s = Stack::new();
+        s.queue_program(&prog);
+        s.run_all();
+        // The product wraps to -2
+        assert_eq!(s.pop(), 0);
+        assert_eq!(i64::apply(Command::Div, 5, 0), 0);
+        assert_eq!(i64::apply(Command::Add, i64::MAX, 1), i64::MIN);
+    }
+
+    #[test]
+    fn underflow() {
+        // Subtract from a stack with too little on it, twice
+        let prog = [Prog::D(5), Prog::C(Command::Sub), Prog::C(Command::Sub), Prog::D(7)];
+        let run = |underflow| {
+            let mut s = Stack::new();
+            s.set_underflow(underflow);
+            s.queue_program(&prog);
+            let steps = s.run_all();
+            (steps, s.data().to_vec(), s.underflowed())
+        };
+        // 0 - 5, then 0 - -5
+        assert_eq!(run(Underflow::Zero), (4, vec![5, 7], false));
+        // The 5 popped first comes back for a: 5 - 5, then 0 - 0 with 0 popped last
+        assert_eq!(run(Underflow::Repeat), (4, vec![0, 7], false));
+        // Nothing runs after the first pop of the empty stack
+        assert_eq!(run(Underflow::Error), (2, vec![-5], true));
+    }
+
+    #[test]
+    fn parse_programs() {
+        assert_eq!(parse(" 1 - \n-30   dup"), Ok(vec![Prog::D(1), Prog::C(Command::Sub), Prog::D(-30), Prog::C(Command::Dup)]));
+        assert_eq!(parse(""), Ok(vec![]));
+        // Programs read back the same as they're displayed
+        let program = vec![Prog::D(i32::MIN), Prog::C(Command::PushFrame), Prog::C(Command::Not), Prog::C(Command::Times)];
+        let text: Vec<String> = program.iter().map(|p| p.to_string()).collect();
+        assert_eq!(parse(&text.join(" ")), Ok(program));
+
+        assert_eq!(parse("1 dup frob +"), Err("unknown token \"frob\" at position 2".to_string()));
+        assert_eq!(parse("3000000000"), Err("number 3000000000 at position 0 doesn't fit in 32 bits".to_string()));
+    }
+
+    #[test]
+    fn frames() {
+        let program = |s: &str| -> Vec<Prog> {
+            parse(s).unwrap()
+        };
+        let run = |s: &str| {
+            let mut stack = Stack::new();
+            stack.push(3);
+            stack.push(4);
+            stack.queue_program(&program(s));
+            stack.run_all();
+            (stack.data().to_vec(), stack.frame_depth())
+        };
+        // Square the 4 inside a frame, without touching the 3
+        assert_eq!(run("pushframe dup * popframe"), (vec![3, 16], 0));
+        // Only the top of the frame comes back out
+        assert_eq!(run("pushframe 1 2 popframe"), (vec![3, 2], 0));
+        // Popping past the bottom of a frame doesn't reach the stack underneath
+        assert_eq!(run("pushframe + + popframe"), (vec![3, 4], 0));
+        // Frames nest, and can be left open
+        assert_eq!(run("pushframe 5 pushframe dup"), (vec![5, 5], 2));
+        assert_eq!(run("popframe +"), (vec![7], 0));
+    }
+
+    #[test]
+    fn conditionals() {
+        let run = |s: &str| {
+            let program = parse(s).unwrap();
+            let mut stack = Stack::new();
+            stack.push(3);
+            stack.push(4);
+            stack.queue_program(&program);
+            let steps = stack.run_all();
+            (stack.data().to_vec(), steps)
+        };
+        // Comparisons push 1 or 0
+        assert_eq!(run("<"), (vec![1], 1));
+        assert_eq!(run(">"), (vec![0], 1));
+        assert_eq!(run("dup ="), (vec![3, 1], 2));
+        assert_eq!(i64::apply(Command::Lt, -1, 0), 1);
+        // Not gives 1 only for 0, and if takes any nonzero condition as true
+        assert_eq!(run("not"), (vec![3, 0], 1));
+        assert_eq!(run("0 not"), (vec![3, 4, 1], 2));
+        assert_eq!(run("-5 1 if 7"), (vec![3, 4, 7], 4));
+        assert!(!0i64.truthy() && (-1i64).truthy());
+        // Absolute value: negate only if the top is below 0, otherwise skip the 2 progs that would
+        assert_eq!(run("dup 0 < 2 if -1 *"), (vec![3, 4], 5));
+        assert_eq!(run("-8 + dup 0 < 2 if -1 *"), (vec![3, 4], 9));
+        assert_eq!(run("0 2 if 7 8 9"), (vec![3, 4, 9], 4));
+        assert_eq!(run("1 2 if 7 8 9"), (vec![3, 4, 7, 8, 9], 6));
+        // Counts below 0 skip nothing, and counts past the end skip the rest
+        assert_eq!(run("0 -1 if 7"), (vec![3, 4, 7], 4));
+        assert_eq!(run("0 100 if 7 8"), (vec![3, 4], 3));
+    }
+
+    #[test]
+    fn loops() {
+        let run = |s: &str, fuel: Option<usize>| {
+            let program = parse(s).unwrap();
+            let mut stack = Stack::new();
+            stack.push(2);
+            stack.set_fuel(fuel);
+            stack.queue_program(&program);
+            let steps = stack.run_all();
+            (stack.data().to_vec(), steps)
+        };
+        // Square 2 three times
+        assert_eq!(run("3 2 times dup *", None), (vec![256], 9));
+        // A count of 0 skips the block, and lengths are cut to the program
+        assert_eq!(run("0 2 times dup * 5", None), (vec![2, 5], 4));
+        assert_eq!(run("2 100 times 1 +", None), (vec![4], 7));
+        // Loops nest
+        assert_eq!(run("2 5 times 2 1 times dup +", None), (vec![2, 4, 8], 15));
+        // The fuel bounds a loop however long it is, and only what it could reach gets queued
+        assert_eq!(run("1000000000 2 times 1 +", Some(50)), (vec![25, 1], 50));
+        let mut stack = Stack::new();
+        stack.set_fuel(Some(10));
+        stack.queue_program(&[Prog::D(1000000000), Prog::D(1), Prog::C(Command::Times), Prog::C(Command::Dup)]);
+        stack.run_until(3);
+        assert!(stack.commands.len() <= 8);
+        // Without fuel, run_until bounds it instead
+        let mut stack = Stack::new();
+        stack.queue_program(&[Prog::D(1000000000), Prog::D(2), Prog::C(Command::Times), Prog::D(1), Prog::C(Command::Add)]);
+        assert_eq!(stack.run_until(1000), 1000);
+        assert!(stack.commands.len() <= MAX_QUEUED);
+    }
+
+    #[test]
+    fn pair_frames() {
+        let (push, pop, one) = (Prog::C(Command::PushFrame), Prog::C(Command::PopFrame), Prog::D(1));
+        assert_eq!(frame_pairs(&[push, one, push, pop, pop]), vec![Some(4), None, Some(3), Some(2), Some(0)]);
+        // Stray frame commands pair with nothing
+        assert_eq!(frame_pairs(&[pop, push, one]), vec![None, None, None]);
+        assert_eq!(frame_pairs(&[push, push, pop]), vec![None, Some(2), Some(1)]);
+    }
+
+    #[test]
+    fn fuel() {
+        let mut s = Stack::new();
+        let prog = [Prog::D(10), Prog::D(2), Prog::C(Command::Div), Prog::C(Command::Dup)];
+        assert_eq!(s.fuel_remaining(), None);
+
+        // Running stops when the fuel runs out, however the steps are taken
+        s.set_fuel(Some(3));
+        s.queue_program(&prog);
+        assert_eq!(s.run_until(1), 1);
+        assert_eq!(s.fuel_remaining(), Some(2));
+        assert_eq!(s.run_all(), 2);
+        assert_eq!(s.fuel_remaining(), Some(0));
+        assert!(!s.run_next());
+        assert_eq!(s.commands.len(), 1);
+        assert_eq!(s.pop(), 5);
+
+        // Refuelling lets the rest run
+        s.set_fuel(None);
+        assert_eq!(s.run_all(), 1);
+        assert_eq!(s.fuel_remaining(), None);
+    }
+
+    #[test]
+    fn sandbox() {
+        let sandbox = Sandbox { max_steps: 4, max_depth: 2, max_nesting: 1, max_constant: 100,
+                                forbidden: vec![Command::Div] };
+        let prog = [Prog::D(10), Prog::D(2), Prog::C(Command::Mult), Prog::C(Command::Dup)];
+        let mut s = Stack::new();
+        assert_eq!(s.run_sandboxed(&prog, &sandbox), Ok(4));
+        assert_eq!(s.pop(), 20);
+
+        // Nothing runs if the program has anything forbidden in it
+        let mut s = Stack::new();
+        let prog = [Prog::D(-500), Prog::C(Command::Div), Prog::D(100)];
+        let violations = s.run_sandboxed(&prog, &sandbox).unwrap_err();
+        assert_eq!(violations, vec![
+            Violation::Constant { position: 0, value: -500 },
+            Violation::Forbidden { position: 1, command: Command::Div },
+        ]);
+        assert_eq!(violations[1].to_string(), "forbidden command / at position 1");
+        assert_eq!(s.data.len(), 0);
+
+        // Limits while running stop the program
+        let prog = [Prog::D(1), Prog::C(Command::Dup), Prog::C(Command::Dup)];
+        assert_eq!(s.run_sandboxed(&prog, &sandbox), Err(vec![Violation::Depth { step: 3, depth: 3 }]));
+        let mut s = Stack::new();
+        let prog = [Prog::D(1), Prog::D(1), Prog::C(Command::Add), Prog::C(Command::Dup), Prog::C(Command::Add)];
+        assert_eq!(s.run_sandboxed(&prog, &sandbox), Err(vec![Violation::Steps { limit: 4 }]));
+        assert_eq!(s.commands.len(), 1);
+
+        // Data put aside by frames counts toward the depth
+        let mut s = Stack::new();
+        let prog = parse("1 pushframe 1 pushframe 1 pushframe 1 pushframe 1").unwrap();
+        assert_eq!(s.run_sandboxed(&prog, &Sandbox { max_steps: 100, max_nesting: 4, ..sandbox.clone() }),
+                   Err(vec![Violation::Depth { step: 5, depth: 3 }]));
+
+        // Blocks may only nest so deep
+        let mut s = Stack::new();
+        let prog = parse("2 4 times 2 1 times dup +").unwrap();
+        assert_eq!(s.run_sandboxed(&prog, &sandbox), Err(vec![Violation::Nesting { position: 6, depth: 2 }]));
+
+        // Loops only queue what the sandbox's steps can reach, however they nest, and the fuel is
+        // given back less the steps taken
+        let mut s = Stack::new();
+        s.set_fuel(Some(5000));
+        let prog = parse("1000000 3 times 1000000 3 times").unwrap();
+        let sandbox = Sandbox { max_steps: 3000, ..Sandbox::default() };
+        assert_eq!(s.run_sandboxed(&prog, &sandbox), Err(vec![Violation::Steps { limit: 3000 }]));
+        assert!(s.commands.len() <= 3, "{} progs queued", s.commands.len());
+        assert_eq!(s.fuel_remaining(), Some(2000));
+        let mut s = Stack::new();
+        s.set_fuel(Some(3000));
+        s.queue_program(&prog);
+        let mut most = 0;
+        while s.run_next() {
+            most = most.max(s.commands.len());
+        }
+        assert!(most <= 3001, "{} progs queued", most);
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(nesting_depths(&parse("1 pushframe 2 pushframe 3 popframe popframe 4").unwrap()),
+                   vec![0, 0, 1, 1, 2, 1, 0, 0]);
+        // A constant length bounds a loop's block, and any other length reaches the end
+        assert_eq!(nesting_depths(&parse("2 4 times 2 1 times dup +").unwrap()), vec![0, 0, 0, 1, 1, 1, 2, 0]);
+        assert_eq!(nesting_depths(&parse("3 2 times 100 times").unwrap()), vec![0, 0, 0, 1, 1]);
+        // Stray frame commands open no block
+        assert_eq!(nesting_depths(&parse("popframe pushframe 1").unwrap()), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn repair_and_flatten() {
+        let mut program = parse("popframe 1 pushframe pushframe 2 popframe pushframe").unwrap();
+        repair(&mut program);
+        assert_eq!(program, parse("1 pushframe 2 popframe").unwrap());
+        // The innermost block around the first prog too deep is unwrapped first
+        let mut program = parse("pushframe 3 times pushframe 1 popframe popframe 2").unwrap();
+        flatten(&mut program, 2);
+        assert_eq!(program, parse("pushframe 3 times 1 popframe 2").unwrap());
+        flatten(&mut program, 1);
+        assert_eq!(program, parse("pushframe 3 1 popframe 2").unwrap());
+        flatten(&mut program, 0);
+        assert_eq!(program, parse("3 1 2").unwrap());
+    }
 }
@@ -0,0 +1,101 @@
+//
+// Epsilon-lexicase selection, which picks each survivor by filtering the candidates through the test
+// cases one at a time in a random order, keeping only those close enough to the best on each case.
+// Plain lexicase keeps only exact ties, which almost never happen with real-valued scores, so each
+// case gets a tolerance: the median absolute deviation of the scores on it this generation.
+//
+// Scores are higher-is-better, one per case, and NaN is worse than any other value.
+//
+
+use super::objective::Objective;
+
+use rand::Rng;
+
+// The median of some values, or NaN if there are none
+fn median(mut values: Vec<f32>) -> f32 {
+    if values.is_empty() {
+        return f32::NAN;
+    }
+    values.sort_by_key(|&x| Objective(x));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+// The tolerance for each case: the median absolute deviation of the scores on it, leaving out NaN
+pub fn epsilons(points: &[Vec<f32>]) -> Vec<f32> {
+    let cases = points.first().map_or(0, |p| p.len());
+    (0 .. cases).map(|k| {
+        let scores: Vec<f32> = points.iter().map(|p| p[k]).filter(|x| !x.is_nan()).collect();
+        let mid = median(scores.clone());
+        let mad = median(scores.into_iter().map(|x| (x - mid).abs()).collect());
+        if mad.is_nan() { 0.0 } else { mad }
+    }).collect()
+}
+
+// Choose `n` different points by epsilon-lexicase, with the tolerances from `epsilons`. Returns
+// indexes into `points`, in the order they were chosen.
+pub fn select<R: Rng>(points: &[Vec<f32>], n: usize, rng: &mut R) -> Vec<usize> {
+    let eps = epsilons(points);
+    let mut cases: Vec<usize> = (0 .. eps.len()).collect();
+    let mut left: Vec<usize> = (0 .. points.len()).collect();
+    let mut chosen = Vec::with_capacity(n);
+    while chosen.len() < n && !left.is_empty() {
+        rng.shuffle(&mut cases);
+        let mut candidates = left.clone();
+        for &k in &cases {
+            if candidates.len() == 1 {
+                break;
+            }
+            let best = candidates.iter().map(|&i| Objective(points[i][k])).max().unwrap().0;
+            let threshold = Objective(best - eps[k]);
+            candidates.retain(|&i| Objective(points[i][k]) >= threshold);
+        }
+        // Whatever survives every case is equally good
+        let pick = *rng.choose(&candidates).unwrap();
+        left.retain(|&i| i != pick);
+        chosen.push(pick);
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn epsilon_lexicase() {
+        let rng = &mut StdRng::from_seed(&[123]);
+
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(vec![4.0, 1.0, 2.0, 3.0]), 2.5);
+        let points = vec![vec![1.0, 0.0], vec![2.0, 0.0], vec![4.0, f32::NAN], vec![9.0, 0.0]];
+        assert_eq!(epsilons(&points), vec![1.5, 0.0]);
+
+        // Each specialist is best on its own case, and the generalist is close to best on both.
+        // Exact lexicase would never pick the generalist first, but with a tolerance it can.
+        let points = vec![
+            vec![-0.1, -5.0],
+            vec![-5.0, -0.1],
+            vec![-0.3, -0.3],
+            vec![-6.0, -6.0],
+        ];
+        let mut firsts = [0; 4];
+        for _ in 0 .. 100 {
+            let chosen = select(&points, 3, rng);
+            assert_eq!(chosen.len(), 3);
+            firsts[chosen[0]] += 1;
+        }
+        assert!(firsts[2] > 0);
+        // Nothing is close to the hopeless point on any case
+        assert_eq!(firsts[3], 0);
+
+        let mut all = select(&points, 10, rng);
+        all.sort();
+        assert_eq!(all, vec![0, 1, 2, 3]);
+    }
+}
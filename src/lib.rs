@@ -0,0 +1,67 @@
+//
+// A genetic algorithm that evolves stack-based programs
+//
+
+extern crate rand;
+extern crate serde;
+extern crate toml;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
+pub mod lang;
+pub mod gene;
+#[macro_use]
+pub mod composite;
+pub mod prog_gene;
+pub mod tree_gene;
+pub mod reg_gene;
+pub mod team;
+pub mod schedule;
+pub mod checkpoint;
+pub mod run_dir;
+pub mod island;
+pub mod eval_queue;
+pub mod cancel;
+pub mod events;
+pub mod objective;
+pub mod pareto;
+pub mod lexicase;
+pub mod immigration;
+pub mod warm_up;
+pub mod plateau;
+pub mod weighted;
+pub mod manifest;
+pub mod suite;
+pub mod ensemble;
+pub mod minimize;
+pub mod shrink;
+pub mod eda;
+pub mod isolate;
+pub mod compiled;
+pub mod trace;
+pub mod localize;
+pub mod infix;
+pub mod equivalence;
+pub mod inspect;
+pub mod enumerate;
+pub mod behavior;
+pub mod memory;
+pub mod fitness_cache;
+pub mod config;
+pub mod runner;
+pub mod effort;
+pub mod triage;
+pub mod replica;
+pub mod prelude;
+#[cfg(feature = "envs")]
+pub mod envs;
+#[cfg(feature = "journal")]
+pub mod journal;
+#[cfg(feature = "sqlite")]
+pub mod record;
+
+pub use runner::{run, RunError, RunReport};
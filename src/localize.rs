@@ -0,0 +1,70 @@
+//
+// Spectrum-based fault localization for stack programs: which progs are most likely to be why a
+// program fails some cases. Each case is traced, and a prog that runs in the failing cases but not
+// in the passing ones is suspicious. Suspiciousness is the Ochiai coefficient,
+//
+//     failed(p) / sqrt(failed * (failed(p) + passed(p)))
+//
+// where failed(p) and passed(p) count the failing and passing cases prog p runs in, and failed counts
+// every failing case. It goes from 0, for progs no failing case runs, to 1, for progs every failing
+// case and no passing case runs.
+//
+
+use super::lang::Prog;
+use super::suite::Case;
+use super::trace::Trace;
+
+// The cases `prog_gene::score_cases` scores programs on, with the reference function's outputs
+pub fn target_cases<F: Fn(i32, i32) -> i32>(f: F) -> Vec<Case> {
+    (0 .. 10).flat_map(|a| (0 .. 10).map(move |b| (a, b)))
+        .map(|(a, b)| Case { a, b, outputs: vec![f(a, b)] })
+        .collect()
+}
+
+// How suspicious each prog of the program is, from 0 to 1, running it for at most `steps` steps. All
+// 0 if the program passes every case.
+pub fn suspiciousness(program: &[Prog], cases: &[Case], steps: usize) -> Vec<f32> {
+    let mut failed_in = vec![0; program.len()];
+    let mut passed_in = vec![0; program.len()];
+    let mut failed = 0;
+    for case in cases {
+        let trace = Trace::run_limited(program, case.a, case.b, steps);
+        let passes = trace.outputs(case.outputs.len()) == case.outputs;
+        if !passes {
+            failed += 1;
+        }
+        let mut ran = vec![false; program.len()];
+        for step in &trace.steps {
+            ran[step.position] = true;
+        }
+        for (i, _) in ran.iter().enumerate().filter(|r| *r.1) {
+            if passes { passed_in[i] += 1 } else { failed_in[i] += 1 }
+        }
+    }
+    failed_in.iter().zip(&passed_in).map(|(&f, &p)| {
+        if f == 0 { 0.0 } else { f as f32 / ((failed * (f + p)) as f32).sqrt() }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::{ProgramGene, MAX_STEPS};
+
+    #[test]
+    fn localize_faults() {
+        // Absolute value of a - b, except that the negation is wrong
+        let abs = |a: i32, b: i32| (a - b).abs();
+        let cases = target_cases(abs);
+        assert_eq!(cases.len(), 100);
+        let buggy: ProgramGene = "- dup 0 < 2 if -2 *".parse().unwrap();
+        let suspicion = suspiciousness(&buggy.0, &cases, MAX_STEPS);
+        // Only the cases with a < b fail, and only they run the negation, so it's the most suspicious
+        assert_eq!(suspicion[6], 1.0);
+        assert_eq!(suspicion[7], 1.0);
+        assert!(suspicion[.. 6].iter().all(|&s| s > 0.0 && s < 1.0));
+
+        let fixed: ProgramGene = "- dup 0 < 2 if -1 *".parse().unwrap();
+        assert_eq!(suspiciousness(&fixed.0, &cases, MAX_STEPS), vec![0.0; 8]);
+    }
+}
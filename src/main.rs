@@ -2,35 +2,659 @@
 // Evolve stack-based programs
 //
 
+extern crate ctrlc;
+extern crate gene_code;
 extern crate rand;
 
-mod lang;
-mod gene;
-mod prog_gene;
+use gene_code::cancel::CancellationToken;
+use gene_code::checkpoint;
+use gene_code::effort::Experiment;
+use gene_code::config::{Champion, Crossover, Engine, Offspring, Repr, RunConfig, Selection};
+use gene_code::infix;
+use gene_code::inspect::{Query, Sort};
+use gene_code::lang::Underflow;
+use gene_code::prog_gene::{ProgramGene, Runnable};
+use gene_code::replica;
+use gene_code::run_dir::json_string;
+use gene_code::runner::{self, RunError};
+use gene_code::shrink;
+use gene_code::team::Aggregate;
+use gene_code::trace::Trace;
+use gene_code::triage::{self, Outcome};
+use rand::{Rng, SeedableRng, StdRng};
+use std::fs;
+use std::io;
+use std::process;
+use std::thread;
 
-// Evolve programs to solve addition, then print out the winners.
+// Parse the name of a team aggregation
+fn parse_aggregate(s: &str) -> Option<Aggregate> {
+    match s {
+        "median" => Some(Aggregate::Median),
+        "vote" => Some(Aggregate::Vote),
+        _ => None,
+    }
+}
+
+const USAGE: &str = "Usage: gene_code [--config FILE] [--repr stack|tree|register] [--frames] [--conditionals] [--loops]
+                 [--engine genetic|eda] [--team median|vote] [--warm-up GENS:SIZE:MUTATIONS]
+                 [--parsimony SCHEDULE] [--selection proportionate|truncation|boltzmann|tournament|rank|sus]
+                 [--truncation-fraction F] [--boltzmann-temperature SCHEDULE] [--tournament-size N]
+                 [--elitism N] [--protect-age K] [--retire-age M]
+                 [--survivors F] [--offspring mixed|mutation|crossover]
+                 [--crossover-rate P --mutation-rate P]
+                 [--crossover cut|aligned|homologous] [--learn-temperature T] [--structural-mutation P]
+                 [--localize] [--self-adaptive] [--hyper-mutation WINDOW:GENS:MUTATIONS:IMMIGRANTS]
+                 [--suite NAME | --tasks NAME:WEIGHT,...] [--holdout F] [--champion fitness|validation] [--ensemble K]
+                 [--underflow zero|repeat|error] [--panic-penalty F] [--fitness-cache FILE]
+                 [--watch] [--quiet] [--checkpoint FILE] [--checkpoint-every N] [--checkpoint-delta] [--generations N]
+                 [--target-fitness F] [--seed N] [--out-dir DIR] [--resume CHECKPOINT [--force]] [--minimize N]
+       gene_code eval PROGRAM A B [--trace-html FILE]
+       gene_code infix PROGRAM
+       gene_code inspect POPULATION [--min-fitness F] [--max-length N] [--contains PROGRAM]
+                 [--sort fitness|length] [--dedup] [--out FILE]
+       gene_code shrink PROGRAM A B [--expect N]
+       gene_code experiment RUNS --target-fitness F [options]
+       gene_code triage --target-fitness F [--runs N] [--corpus FILE] [options]
+       gene_code verify [--record FILE | --against FILE] [options]
+
+SCHEDULE is a constant like 0.01, or one of linear:FROM:TO:GENS, exp:FROM:TO:GENS, or
+step:GEN=VALUE,GEN=VALUE,...
+
+Options override the config file given with --config, wherever they appear. --json-errors, with any
+command, reports failures on stderr as a line of JSON.";
+
+// Parse the command-line options into a config, or describe what was wrong with them
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<RunConfig, String> {
+    let args: Vec<String> = args.collect();
+    let mut parsed = match args.iter().position(|a| a == "--config") {
+        Some(i) => RunConfig::load(args.get(i + 1).ok_or("--config needs a value")?)?,
+        None => RunConfig::default(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                // Already loaded
+                args.next();
+            }
+            "--repr" => {
+                let value = args.next().ok_or("--repr needs a value")?;
+                parsed.pool.repr = Repr::parse(&value)
+                    .ok_or_else(|| format!("unknown representation: {}", value))?;
+            }
+            "--engine" => {
+                let value = args.next().ok_or("--engine needs a value")?;
+                parsed.pool.engine = match value.as_str() {
+                    "genetic" => Engine::Genetic,
+                    "eda" => Engine::Eda,
+                    _ => return Err(format!("unknown engine: {}", value)),
+                };
+            }
+            "--frames" => parsed.pool.frames = true,
+            "--conditionals" => parsed.pool.conditionals = true,
+            "--loops" => parsed.pool.loops = true,
+            "--team" => {
+                let value = args.next().ok_or("--team needs a value")?;
+                parsed.pool.team = Some(parse_aggregate(&value)
+                    .ok_or_else(|| format!("unknown team aggregation: {}", value))?);
+            }
+            "--warm-up" => {
+                let value = args.next().ok_or("--warm-up needs a value")?;
+                parsed.pool.warm_up = Some(value.parse()?);
+            }
+            "--parsimony" => {
+                let value = args.next().ok_or("--parsimony needs a value")?;
+                parsed.operators.parsimony = value.parse()?;
+            }
+            "--selection" => {
+                let value = args.next().ok_or("--selection needs a value")?;
+                parsed.operators.selection = match value.as_str() {
+                    "proportionate" => Selection::Proportionate,
+                    "truncation" => Selection::Truncation,
+                    "boltzmann" => Selection::Boltzmann,
+                    "tournament" => Selection::Tournament,
+                    "rank" => Selection::Rank,
+                    "sus" => Selection::StochasticUniversal,
+                    _ => return Err(format!("unknown selection: {}", value)),
+                };
+            }
+            "--truncation-fraction" => {
+                let value = args.next().ok_or("--truncation-fraction needs a value")?;
+                parsed.operators.truncation_fraction = value.parse()
+                    .map_err(|_| format!("--truncation-fraction needs a number: {}", value))?;
+            }
+            "--survivors" => {
+                let value = args.next().ok_or("--survivors needs a value")?;
+                parsed.operators.survivors = Some(value.parse()
+                    .map_err(|_| format!("--survivors needs a number: {}", value))?);
+            }
+            "--offspring" => {
+                let value = args.next().ok_or("--offspring needs a value")?;
+                parsed.operators.offspring = match value.as_str() {
+                    "mixed" => Offspring::Mixed,
+                    "mutation" => Offspring::Mutation,
+                    "crossover" => Offspring::Crossover,
+                    _ => return Err(format!("unknown offspring: {}", value)),
+                };
+            }
+            "--tournament-size" => {
+                let value = args.next().ok_or("--tournament-size needs a value")?;
+                parsed.operators.tournament_size = value.parse()
+                    .map_err(|_| format!("--tournament-size needs a number: {}", value))?;
+            }
+            "--crossover-rate" => {
+                let value = args.next().ok_or("--crossover-rate needs a value")?;
+                parsed.operators.crossover_rate = Some(value.parse()
+                    .map_err(|_| format!("--crossover-rate needs a number: {}", value))?);
+            }
+            "--mutation-rate" => {
+                let value = args.next().ok_or("--mutation-rate needs a value")?;
+                parsed.operators.mutation_rate = Some(value.parse()
+                    .map_err(|_| format!("--mutation-rate needs a number: {}", value))?);
+            }
+            "--elitism" => {
+                let value = args.next().ok_or("--elitism needs a value")?;
+                parsed.operators.elitism = value.parse()
+                    .map_err(|_| format!("--elitism needs a number: {}", value))?;
+            }
+            "--protect-age" => {
+                let value = args.next().ok_or("--protect-age needs a value")?;
+                parsed.operators.protect_age = value.parse()
+                    .map_err(|_| format!("--protect-age needs a number: {}", value))?;
+            }
+            "--retire-age" => {
+                let value = args.next().ok_or("--retire-age needs a value")?;
+                parsed.operators.retire_age = Some(value.parse()
+                    .map_err(|_| format!("--retire-age needs a number: {}", value))?);
+            }
+            "--boltzmann-temperature" => {
+                let value = args.next().ok_or("--boltzmann-temperature needs a value")?;
+                parsed.operators.boltzmann_temperature = value.parse()?;
+            }
+            "--crossover" => {
+                let value = args.next().ok_or("--crossover needs a value")?;
+                parsed.operators.crossover = match value.as_str() {
+                    "cut" => Crossover::Cut,
+                    "aligned" => Crossover::Aligned,
+                    "homologous" => Crossover::Homologous,
+                    _ => return Err(format!("unknown crossover: {}", value)),
+                };
+            }
+            "--structural-mutation" => {
+                let value = args.next().ok_or("--structural-mutation needs a value")?;
+                parsed.operators.structural_mutation = value.parse()
+                    .map_err(|_| format!("--structural-mutation needs a number: {}", value))?;
+            }
+            "--localize" => parsed.operators.localize = true,
+            "--self-adaptive" => parsed.operators.self_adaptive = true,
+            "--hyper-mutation" => {
+                let value = args.next().ok_or("--hyper-mutation needs a value")?;
+                parsed.operators.hyper_mutation = Some(value.parse()?);
+            }
+            "--learn-temperature" => {
+                let value = args.next().ok_or("--learn-temperature needs a value")?;
+                parsed.operators.learn_temperature = Some(value.parse()
+                    .map_err(|_| format!("--learn-temperature needs a number: {}", value))?);
+            }
+            "--suite" => {
+                parsed.harness.suite = Some(args.next().ok_or("--suite needs a value")?);
+            }
+            "--tasks" => {
+                let value = args.next().ok_or("--tasks needs a value")?;
+                parsed.harness.tasks = value.split(',').map(str::parse).collect::<Result<_, _>>()?;
+            }
+            "--holdout" => {
+                let value = args.next().ok_or("--holdout needs a value")?;
+                parsed.harness.holdout = Some(value.parse()
+                    .map_err(|_| format!("--holdout needs a number: {}", value))?);
+            }
+            "--champion" => {
+                let value = args.next().ok_or("--champion needs a value")?;
+                parsed.harness.champion = match value.as_str() {
+                    "fitness" => Champion::Fitness,
+                    "validation" => Champion::Validation,
+                    _ => return Err(format!("unknown champion: {}", value)),
+                };
+            }
+            "--underflow" => {
+                let value = args.next().ok_or("--underflow needs a value")?;
+                parsed.harness.underflow = match value.as_str() {
+                    "zero" => Underflow::Zero,
+                    "repeat" => Underflow::Repeat,
+                    "error" => Underflow::Error,
+                    _ => return Err(format!("unknown underflow: {}", value)),
+                };
+            }
+            "--ensemble" => {
+                let value = args.next().ok_or("--ensemble needs a value")?;
+                parsed.harness.ensemble = Some(value.parse()
+                    .map_err(|_| format!("--ensemble needs a number: {}", value))?);
+            }
+            "--panic-penalty" => {
+                let value = args.next().ok_or("--panic-penalty needs a value")?;
+                parsed.harness.panic_penalty = Some(value.parse()
+                    .map_err(|_| format!("--panic-penalty needs a number: {}", value))?);
+            }
+            "--fitness-cache" => {
+                parsed.harness.fitness_cache = Some(args.next().ok_or("--fitness-cache needs a value")?);
+            }
+            "--watch" => parsed.logging.watch = true,
+            "--checkpoint" => {
+                parsed.logging.checkpoint = args.next().ok_or("--checkpoint needs a value")?;
+            }
+            "--checkpoint-every" => {
+                let value = args.next().ok_or("--checkpoint-every needs a value")?;
+                let n = value.parse().ok().filter(|&n| n > 0)
+                    .ok_or_else(|| format!("--checkpoint-every needs a positive number: {}", value))?;
+                parsed.logging.checkpoint_every = Some(n);
+            }
+            "--checkpoint-delta" => parsed.logging.checkpoint_delta = true,
+            "--generations" => {
+                let value = args.next().ok_or("--generations needs a value")?;
+                parsed.termination.generations = value.parse()
+                    .map_err(|_| format!("--generations needs a number: {}", value))?;
+            }
+            "--quiet" => parsed.logging.quiet = true,
+            "--target-fitness" => {
+                let value = args.next().ok_or("--target-fitness needs a value")?;
+                parsed.termination.target_fitness = Some(value.parse()
+                    .map_err(|_| format!("--target-fitness needs a number: {}", value))?);
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a value")?;
+                parsed.seed = Some(value.parse().map_err(|_| format!("--seed needs a number: {}", value))?);
+            }
+            "--out-dir" => {
+                parsed.logging.out_dir = Some(args.next().ok_or("--out-dir needs a value")?);
+            }
+            "--resume" => {
+                parsed.pool.resume = Some(args.next().ok_or("--resume needs a value")?);
+            }
+            "--force" => parsed.pool.force = true,
+            "--minimize" => {
+                let value = args.next().ok_or("--minimize needs a value")?;
+                parsed.operators.minimize = Some(value.parse()
+                    .map_err(|_| format!("--minimize needs a number: {}", value))?);
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+// Run one stack program, e.g. "swap dup *", on the inputs A and B and print its output. With
+// --trace-html, also draw every step of it to FILE.
+fn eval<I: Iterator<Item = String>>(args: I) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut html = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace-html" => html = Some(args.next().ok_or("--trace-html needs a value")?),
+            _ => positional.push(arg),
+        }
+    }
+    let (program, a, b) = match positional[..] {
+        [ref program, ref a, ref b] => (program, a, b),
+        _ => return Err("eval needs PROGRAM A B".to_string()),
+    };
+    let program: ProgramGene = program.parse()?;
+    let input = |x: &String| x.parse().map_err(|_| format!("eval needs a number: {}", x));
+    let trace = Trace::run(&program.0, input(a)?, input(b)?);
+    println!("{}", trace.output());
+    if let Some(path) = html {
+        fs::write(&path, trace.to_html()).map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+// Print what a stack program computes as infix math of its inputs a and b, e.g. "3 + a - b*b"
+fn print_infix<I: Iterator<Item = String>>(mut args: I) -> Result<(), String> {
+    let program: ProgramGene = args.next().ok_or("infix needs PROGRAM")?.parse()?;
+    // Zero never makes a run an error, so only branching on the inputs leaves no expression
+    let expression = infix::expression(&program.0, Underflow::Zero)
+        .map_err(|_| format!("{} branches on its inputs, so it has no single expression", program))?;
+    println!("{}", expression);
+    Ok(())
+}
+
+// List the stack programs of a saved population, e.g. a checkpoint, that pass the filters. With
+// --out, save them to FILE as a population instead.
+fn inspect<I: Iterator<Item = String>>(args: I) -> Result<(), String> {
+    let mut path = None;
+    let mut query = Query::default();
+    let mut out = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--min-fitness" => {
+                let v = value()?;
+                query.min_fitness = Some(v.parse().map_err(|_| format!("bad --min-fitness: {}", v))?);
+            }
+            "--max-length" => {
+                let v = value()?;
+                query.max_length = Some(v.parse().map_err(|_| format!("bad --max-length: {}", v))?);
+            }
+            "--contains" => query.contains = Some(value()?.parse()?),
+            "--sort" => {
+                query.sort = Some(match value()?.as_str() {
+                    "fitness" => Sort::Fitness,
+                    "length" => Sort::Length,
+                    other => return Err(format!("unknown sort: {}", other)),
+                });
+            }
+            "--dedup" => query.dedup = true,
+            "--out" => out = Some(value()?),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(format!("unknown inspect option: {}", arg)),
+        }
+    }
+    let path = path.ok_or("inspect needs a POPULATION file")?;
+    let (generation, genes) = checkpoint::load::<ProgramGene, _>(&path)
+        .map_err(|e| format!("could not read {}: {}", path, e))?;
+    let total = genes.len();
+    let genes = query.apply(genes);
+    match out {
+        Some(out) => {
+            let mut file = fs::File::create(&out).map_err(|e| format!("could not write {}: {}", out, e))?;
+            checkpoint::write_genes(&genes, generation, &mut file)
+                .map_err(|e| format!("could not write {}: {}", out, e))?;
+            println!("Saved {} of {} genes to {}", genes.len(), total, out);
+        }
+        None => {
+            for (gene, fit) in &genes {
+                println!("{}\t{}", fit, gene);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Shrink a stack program that panics on the inputs A and B to as few tokens as still panic, and
+// print it. With --expect, shrink one that gives something other than N instead.
+fn shrink<I: Iterator<Item = String>>(args: I) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut expect = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--expect" => expect = Some(args.next().ok_or("--expect needs a value")?),
+            _ => positional.push(arg),
+        }
+    }
+    let (program, a, b) = match positional[..] {
+        [ref program, ref a, ref b] => (program, a, b),
+        _ => return Err("shrink needs PROGRAM A B".to_string()),
+    };
+    let program: ProgramGene = program.parse()?;
+    let number = |x: &String| x.parse::<i32>().map_err(|_| format!("shrink needs a number: {}", x));
+    let (a, b) = (number(a)?, number(b)?);
+    let expect = expect.as_ref().map(number).transpose()?;
+    let interesting = |p: &[_]| {
+        let gene = ProgramGene(p.to_vec());
+        match expect {
+            Some(n) => !shrink::panics(|| gene.run(a, b)) && gene.run(a, b) != n,
+            None => shrink::panics(|| gene.run(a, b)),
+        }
+    };
+    if !interesting(&program.0) {
+        return Err(match expect {
+            Some(n) => format!("{} already gives {} on ({}, {})", program, n, a, b),
+            None => format!("{} doesn't panic on ({}, {})", program, a, b),
+        });
+    }
+    let shrunk = ProgramGene(shrink::shrink(&program.0, interesting));
+    if shrunk.0.is_empty() {
+        println!("(the empty program)");
+    } else {
+        println!("{}", shrunk);
+    }
+    Ok(())
+}
+
+// Run the config RUNS times with consecutive seeds, quietly, and report how many runs reached the
+// target fitness, how fast, and the computational effort that makes
+fn experiment<I: Iterator<Item = String>>(mut args: I) -> Result<(), String> {
+    let runs = args.next().ok_or("experiment needs RUNS")?;
+    let runs: usize = runs.parse().map_err(|_| format!("experiment needs a number of runs: {}", runs))?;
+    let config = parse_args(args)?;
+    if config.termination.target_fitness.is_none() {
+        return Err("experiment needs --target-fitness to tell which runs solved the problem".to_string());
+    }
+    let first = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let seeds: Vec<u64> = (0 .. runs as u64).map(|i| first.wrapping_add(i)).collect();
+    let reports = run_batch(&config, &seeds)?;
+    let solved_at = reports.iter().map(|r| r.solved_at).collect();
+    let stalls: Vec<usize> = reports.iter().map(|r| r.longest_stall).collect();
+    let experiment = Experiment { pool_size: config.pool.size, generations: config.termination.generations, solved_at };
+    let solved = experiment.solved_at.iter().filter(|s| s.is_some()).count();
+    println!("Solved {} of {} runs within {} generations ({:.1}%)", solved, runs, experiment.generations,
+             100.0 * experiment.success_rate());
+    match experiment.effort(0.99) {
+        Some(e) => println!("Computational effort: {} individuals ({} runs of {} generations for 99%)",
+                            e.individuals, e.runs, e.generations),
+        None => println!("Computational effort: unknown, since no run solved it"),
+    }
+    if let Some(&most) = stalls.iter().max() {
+        let mean = stalls.iter().sum::<usize>() as f32 / stalls.len() as f32;
+        println!("Longest run of generations without improvement: {:.1} on average, {} at most", mean, most);
+    }
+    Ok(())
+}
+
+// Try the config on a batch of short runs from distinct random seeds, 50 generations each unless
+// --generations says otherwise, and report how often it reached the target fitness. --corpus saves
+// each seed with how its run went. With --seed, the batch of seeds is the same every time.
+fn triage<I: Iterator<Item = String>>(args: I) -> Result<(), String> {
+    let mut runs = 20;
+    let mut corpus = None;
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => {
+                let value = args.next().ok_or("--runs needs a value")?;
+                runs = value.parse().map_err(|_| format!("--runs needs a number: {}", value))?;
+            }
+            "--corpus" => corpus = Some(args.next().ok_or("--corpus needs a value")?),
+            _ => rest.push(arg),
+        }
+    }
+    let short = !rest.iter().any(|a| a == "--generations");
+    let mut config = parse_args(rest.into_iter())?;
+    if short {
+        config.termination.generations = 50;
+    }
+    if config.termination.target_fitness.is_none() {
+        return Err("triage needs --target-fitness to tell which runs hit".to_string());
+    }
+    let seeds = match config.seed {
+        Some(seed) => triage::seeds(runs, &mut StdRng::from_seed(&[seed as usize])),
+        None => triage::seeds(runs, &mut rand::thread_rng()),
+    };
+    let outcomes: Vec<Outcome> = run_batch(&config, &seeds)?.iter()
+        .map(|r| Outcome { seed: r.seed, solved_at: r.solved_at, best_fitness: r.best_fitness })
+        .collect();
+    let hits: Vec<String> = outcomes.iter().filter(|o| o.solved_at.is_some()).map(|o| o.seed.to_string()).collect();
+    println!("Hit the target in {} of {} runs of {} generations ({:.1}%)", hits.len(), runs,
+             config.termination.generations, 100.0 * triage::hit_rate(&outcomes));
+    if !hits.is_empty() {
+        println!("Seeds that hit: {}", hits.join(" "));
+    }
+    if let Some(path) = corpus {
+        let mut file = fs::File::create(&path).map_err(|e| format!("could not write {}: {}", path, e))?;
+        triage::write_corpus(&outcomes, &mut file).map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+// Run the config quietly from one seed twice at once, as a coordinator and a verifier, and report
+// the first generation where their pools differ. --record saves the fingerprints of the run, and
+// --against instead runs once and checks it against fingerprints recorded elsewhere, with their
+// seed. Exits with status 1 if the runs diverge.
+fn verify<I: Iterator<Item = String>>(args: I) -> Result<(), String> {
+    let mut record = None;
+    let mut against = None;
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record = Some(args.next().ok_or("--record needs a value")?),
+            "--against" => against = Some(args.next().ok_or("--against needs a value")?),
+            _ => rest.push(arg),
+        }
+    }
+    if record.is_some() && against.is_some() {
+        return Err("verify takes --record or --against, not both".to_string());
+    }
+    let mut config = parse_args(rest.into_iter())?;
+    config.logging.quiet = true;
+    let recorded = match against {
+        Some(ref path) => {
+            let (seed, fingerprints) = fs::File::open(path)
+                .and_then(|file| replica::read_fingerprints(io::BufReader::new(file)))
+                .map_err(|e| format!("could not read {}: {}", path, e))?;
+            if config.seed.is_some_and(|s| s != seed) {
+                return Err(format!("{} was recorded with seed {}, not {}", path, seed, config.seed.unwrap()));
+            }
+            config.seed = Some(seed);
+            Some(fingerprints)
+        }
+        None => None,
+    };
+    let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    config.seed = Some(seed);
+    let (expected, actual) = match recorded {
+        Some(expected) => (expected, runner::run(&config)?.fingerprints),
+        None => {
+            // The verifier leaves the files to the coordinator
+            let mut verifier = config.clone();
+            verifier.logging.out_dir = None;
+            verifier.logging.checkpoint_every = None;
+            verifier.harness.fitness_cache = None;
+            let (coordinator, verifier) = thread::scope(|s| {
+                let verifier = s.spawn(|| runner::run(&verifier));
+                (runner::run(&config), verifier.join())
+            });
+            let verifier = verifier.map_err(|_| "the verifier panicked".to_string())?;
+            (coordinator?.fingerprints, verifier?.fingerprints)
+        }
+    };
+    if let Some(path) = record {
+        let mut file = fs::File::create(&path).map_err(|e| format!("could not write {}: {}", path, e))?;
+        replica::write_fingerprints(seed, &expected, &mut file).map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+    let show = |f: Option<u64>| f.map_or("stopped".to_string(), |f| format!("{:016x}", f));
+    match replica::first_divergence(&expected, &actual) {
+        None => println!("Replicas agree on all {} generations with seed {}", expected.len(), seed),
+        Some(d) => {
+            println!("Replicas diverge at generation {} with seed {}: expected {}, got {}", d.generation, seed,
+                     show(d.expected), show(d.actual));
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+// Run the config quietly once with each seed, printing how each run went
+fn run_batch(config: &RunConfig, seeds: &[u64]) -> Result<Vec<runner::RunReport>, String> {
+    let mut reports = Vec::with_capacity(seeds.len());
+    for (i, &seed) in seeds.iter().enumerate() {
+        let mut config = RunConfig { seed: Some(seed), ..config.clone() };
+        config.logging.quiet = true;
+        let report = runner::run(&config)?;
+        match report.solved_at {
+            Some(g) => println!("Run {}\tseed {}\tsolved in {} generations", i, seed, g),
+            None => println!("Run {}\tseed {}\tnot solved, best {}", i, seed, report.best_fitness),
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+// Exit statuses, so scripts running the binary can tell how it went. Any other failure, e.g. a
+// file that can't be read or written, or replicas that diverge, exits with status 1.
+
+// Bad options or an invalid config
+const EXIT_USAGE: i32 = 2;
+// The checkpoint to resume from can't be loaded, or doesn't match the run
+const EXIT_CHECKPOINT: i32 = 3;
+// The fitness function panicked without --panic-penalty
+const EXIT_EVALUATION: i32 = 4;
+// The run used up its generations without reaching --target-fitness
+const EXIT_UNSOLVED: i32 = 5;
+
+// Report a failure on stderr, as a line of JSON if asked, and exit with the given status. The JSON
+// gives the kind of failure, the status, and the message.
+fn fail(kind: &str, message: &str, status: i32, json: bool) -> ! {
+    if json {
+        eprintln!("{{\"error\": {}, \"status\": {}, \"message\": {}}}", json_string(kind), status,
+                  json_string(message));
+    } else {
+        eprintln!("{}", message);
+    }
+    process::exit(status);
+}
+
+// Evolve programs as the options describe, then print out the winners.
 pub fn main() {
-    // Make a pool
-    let rng = &mut rand::thread_rng();
-    let mut pool = gene::Pool::new(100, |g| prog_gene::fitness(|a, b| 3 + a - b*b, g), rng);
-    // Print header row
-    println!("Generation\tFitness...");
-    // Evolve for many generations
-    for i in 0 .. 1000 {
-        pool.evolve(rng);
-        //println!("Iter {} best: {}", i, pool.get_best());
-        // Print generation
-        println!("{}", i);
-        // Print the fitness of each gene
-        //for g in &pool.genes {
-        //    print!("\t{}", g.1);
-        //}
-        //println!();
-    }
-    for g in &pool.genes {
-        println!("{}", g.0);
-    }
-    // Print the best gene
-    let best = pool.get_best();
-    println!("Best ({}): {}", best.1, best.0);
+    // --json-errors applies to every subcommand, so take it out before parsing the rest
+    let mut args: Vec<String> = std::env::args().collect();
+    let json_errors = args.iter().any(|a| a == "--json-errors");
+    args.retain(|a| a != "--json-errors");
+    let rest = || args.clone().into_iter().skip(2);
+    let subcommand = match args.get(1).map(String::as_str) {
+        Some("eval") => Some(eval(rest())),
+        Some("infix") => Some(print_infix(rest())),
+        Some("inspect") => Some(inspect(rest())),
+        Some("shrink") => Some(shrink(rest())),
+        Some("experiment") => Some(experiment(rest())),
+        Some("triage") => Some(triage(rest())),
+        Some("verify") => Some(verify(rest())),
+        _ => None,
+    };
+    let usage = |e: String| if json_errors { e } else { format!("{}\n{}", e, USAGE) };
+    if let Some(result) = subcommand {
+        if let Err(e) = result {
+            fail("usage", &usage(e), EXIT_USAGE, json_errors);
+        }
+        return;
+    }
+    let config = match parse_args(args.into_iter().skip(1)) {
+        Ok(config) => config,
+        Err(e) => fail("usage", &usage(e), EXIT_USAGE, json_errors),
+    };
+    if let Err(problems) = config.validate() {
+        fail("config", &format!("Invalid options:\n  {}", problems.join("\n  ")), EXIT_USAGE, json_errors);
+    }
+
+    // Stop at the end of the current generation on Ctrl-C, so we can still report and checkpoint
+    let interrupted = CancellationToken::new();
+    {
+        let interrupted = interrupted.clone();
+        if let Err(e) = ctrlc::set_handler(move || interrupted.cancel()) {
+            eprintln!("Could not install Ctrl-C handler: {}", e);
+        }
+    }
+
+    let report = match runner::run_until(&config, &interrupted) {
+        Ok(report) => report,
+        Err(e) => {
+            let status = match e {
+                RunError::Config(_) => EXIT_USAGE,
+                RunError::Checkpoint(_) => EXIT_CHECKPOINT,
+                RunError::Evaluation(_) => EXIT_EVALUATION,
+                RunError::Io(_) => 1,
+            };
+            fail(e.kind(), &e.to_string(), status, json_errors);
+        }
+    };
+    if let Some(target) = config.termination.target_fitness {
+        if report.solved_at.is_none() && !report.interrupted {
+            fail("unsolved", &format!("No program reached the target fitness {} in {} generations", target,
+                                      report.generations), EXIT_UNSOLVED, json_errors);
+        }
+    }
 }
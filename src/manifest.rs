@@ -0,0 +1,204 @@
+//
+// A manifest describing exactly what produced a run, so its results can be reproduced and its
+// checkpoints aren't resumed under different settings by mistake
+//
+
+use super::lang::Command;
+use super::run_dir::json_string;
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+// Config keys that may change when a run is resumed: how long it runs, what it reports and where it
+// saves, how it resumes, and the seed for the generations still to come
+const RESUMABLE_KEYS: [&str; 13] = ["seed", "generations", "target_fitness", "minimize", "watch", "quiet", "out_dir",
+                                    "checkpoint", "checkpoint_every", "checkpoint_delta", "resume", "force",
+                                    "fitness_cache"];
+
+// Everything needed to reproduce a run
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    // The version of this crate
+    pub version: String,
+    // The git commit this crate was built from, if it was built from a git checkout
+    pub git_hash: Option<String>,
+    pub seed: u64,
+    // The commands available to stack programs
+    pub instruction_set: String,
+    // The full config of the run, as TOML
+    pub config: String,
+}
+
+impl Manifest {
+    // Describe a run of this build with the given config and seed
+    pub fn current(config: &str, seed: u64) -> Manifest {
+        Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: option_env!("GENE_CODE_GIT_HASH").map(str::to_string),
+            seed,
+            instruction_set: instruction_set(),
+            config: config.to_string(),
+        }
+    }
+
+    // List the ways a checkpoint made under this manifest would evolve differently if resumed under
+    // `current`. An empty list means it can be resumed safely. The git hash isn't compared, since
+    // any rebuild would change it.
+    pub fn mismatches(&self, current: &Manifest) -> Vec<String> {
+        let mut found = Vec::new();
+        if self.version != current.version {
+            found.push(format!("version {} != {}", self.version, current.version));
+        }
+        if self.instruction_set != current.instruction_set {
+            found.push(format!("instruction set {} != {}", self.instruction_set, current.instruction_set));
+        }
+        let (mine, theirs) = (search_config(&self.config), search_config(&current.config));
+        for line in &mine {
+            if !theirs.contains(line) {
+                found.push(format!("config {} is not set now", line));
+            }
+        }
+        for line in &theirs {
+            if !mine.contains(line) {
+                found.push(format!("config {} was not set before", line));
+            }
+        }
+        found
+    }
+
+    // Write the manifest as TOML, with the config in its own table
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "version = {}", json_string(&self.version))?;
+        if let Some(ref hash) = self.git_hash {
+            writeln!(w, "git_hash = {}", json_string(hash))?;
+        }
+        writeln!(w, "seed = {}", self.seed)?;
+        writeln!(w, "instruction_set = {}", json_string(&self.instruction_set))?;
+        write!(w, "\n[config]\n{}", self.config)
+    }
+
+    // Read a manifest written by `write_to`
+    pub fn read_from<B: BufRead>(r: B) -> io::Result<Manifest> {
+        let bad = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad manifest line: {}", line));
+        let mut manifest = Manifest {
+            version: String::new(),
+            git_hash: None,
+            seed: 0,
+            instruction_set: String::new(),
+            config: String::new(),
+        };
+        let mut in_config = false;
+        for line in r.lines() {
+            let line = line?;
+            if in_config {
+                manifest.config += &line;
+                manifest.config.push('\n');
+                continue;
+            }
+            if line == "[config]" {
+                in_config = true;
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let mut kv = line.splitn(2, " = ");
+            let (key, value) = (kv.next().unwrap_or(""), kv.next().ok_or_else(|| bad(&line))?);
+            match key {
+                "version" => manifest.version = unquote(value).ok_or_else(|| bad(&line))?,
+                "git_hash" => manifest.git_hash = Some(unquote(value).ok_or_else(|| bad(&line))?),
+                "seed" => manifest.seed = value.parse().map_err(|_| bad(&line))?,
+                "instruction_set" => manifest.instruction_set = unquote(value).ok_or_else(|| bad(&line))?,
+                _ => return Err(bad(&line)),
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Manifest> {
+        Manifest::read_from(io::BufReader::new(fs::File::open(path)?))
+    }
+}
+
+// Where the manifest for the checkpoint at `checkpoint` is saved, e.g. checkpoint.manifest.toml
+// for checkpoint.txt
+pub fn path_for<P: AsRef<Path>>(checkpoint: P) -> PathBuf {
+    checkpoint.as_ref().with_extension("manifest.toml")
+}
+
+// The symbols of the commands random programs are made of, separated by spaces. Whether they may
+// use frames, branch, or loop too is in the config.
+pub fn instruction_set() -> String {
+    Command::BASIC.iter().map(|c| c.symbol()).collect::<Vec<_>>().join(" ")
+}
+
+// The lines of a config that affect how the search goes, leaving out `RESUMABLE_KEYS`
+fn search_config(config: &str) -> Vec<&str> {
+    config.lines()
+        .filter(|line| {
+            let key = line.split('=').next().unwrap_or("").trim();
+            !line.trim().is_empty() && !RESUMABLE_KEYS.contains(&key)
+        })
+        .collect()
+}
+
+// Undo `json_string`
+fn unquote(s: &str) -> Option<String> {
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return None;
+    }
+    let mut out = String::new();
+    let mut chars = s[1 .. s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'u' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                out.push(::std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest() {
+        let config = "repr = \"stack\"\nparsimony = \"0.01\"\ngenerations = 1000\nseed = 5\n";
+        let manifest = Manifest::current(config, 5);
+        assert_eq!(manifest.instruction_set, "+ - * / dup swap");
+
+        // A manifest survives a round trip through text
+        let mut text = Vec::new();
+        manifest.write_to(&mut text).unwrap();
+        assert_eq!(Manifest::read_from(&text[..]).unwrap(), manifest);
+
+        // Resuming for longer or with another seed is fine, but not with other search settings
+        let longer = Manifest::current("repr = \"stack\"\nparsimony = \"0.01\"\ngenerations = 50\nseed = 9\n", 9);
+        assert!(manifest.mismatches(&longer).is_empty());
+        let tree = Manifest::current("repr = \"tree\"\nparsimony = \"0.01\"\n", 5);
+        assert_eq!(manifest.mismatches(&tree).len(), 2);
+        let mut old = manifest.clone();
+        old.version = "0.0.1".to_string();
+        assert_eq!(old.mismatches(&manifest), vec![format!("version 0.0.1 != {}", env!("CARGO_PKG_VERSION"))]);
+
+        assert_eq!(unquote("\"a \\\"b\\\"\\\\\\n\""), Some("a \"b\"\\\n".to_string()));
+        assert_eq!(path_for("run/checkpoint.txt"), Path::new("run/checkpoint.manifest.toml"));
+    }
+}
@@ -0,0 +1,87 @@
+//
+// Approximate memory accounting for pools, so very large pools can be kept under a memory cap.
+// The estimates only count the genes themselves, not the allocator's overhead or spare capacity,
+// so leave some headroom below the real limit.
+//
+
+use super::gene::{Fitness, Gene, GeneMeta, Pool};
+use super::objective::Objective;
+
+use rand::Rng;
+use std::cmp::Reverse;
+use std::hash::Hash;
+use std::mem;
+
+// A gene whose memory use can be estimated
+pub trait GeneSize {
+    // The bytes the gene owns on the heap, e.g. the contents of a program's Vec. The gene's own
+    // inline size is counted separately.
+    fn heap_bytes(&self) -> usize;
+
+    // The estimated total bytes one gene takes up in a pool, with its fitness
+    fn pool_bytes(&self) -> usize
+        where Self: Sized,
+        {
+        mem::size_of::<(Self, f32)>() + self.heap_bytes()
+    }
+}
+
+impl<T, F> Pool<T, F>
+    where T: Gene + Hash + Eq + Clone + GeneSize,
+          F: Fitness<T>,
+    {
+
+    // The estimated bytes used by the genes in the pool
+    pub fn memory_usage(&self) -> usize {
+        self.iter().map(|g| g.0.pool_bytes()).sum()
+    }
+
+    // If the pool is estimated to use more than `cap` bytes, shrink it by dropping the least fit
+    // genes until it fits, and keep it at that size for later generations. The most fit gene is
+    // always kept, even if it alone is over the cap. Returns whether the pool shrank.
+    pub fn limit_memory<R: Rng>(&mut self, cap: usize, rng: &mut R) -> bool {
+        if self.memory_usage() <= cap {
+            return false;
+        }
+        let mut sorted: Vec<(&T, f32, GeneMeta)> = self.iter().collect();
+        sorted.sort_by_key(|g| Reverse(Objective(g.1)));
+        let mut total = 0;
+        let fits = sorted.iter().take_while(|g| {
+            total += g.0.pool_bytes();
+            total <= cap
+        }).count();
+        self.resize(fits.max(1), rng);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn limit_memory() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+        let mut pool = Pool::new(100, fitness, rng);
+        let usage = pool.memory_usage();
+        assert!(usage >= 100 * mem::size_of::<(ProgramGene, f32)>());
+        assert!(!pool.limit_memory(usage, rng));
+
+        // Halving the cap about halves the pool, keeping the best
+        let best = pool.get_best().1;
+        assert!(pool.limit_memory(usage / 2, rng));
+        assert!(pool.memory_usage() <= usage / 2);
+        assert!((30 .. 70).contains(&pool.len()), "{}", pool.len());
+        assert_eq!(pool.get_best().1, best);
+
+        // The pool keeps its new size, and always keeps one gene
+        let len = pool.len();
+        pool.evolve(rng);
+        assert_eq!(pool.len(), len);
+        assert!(pool.limit_memory(0, rng));
+        assert_eq!(pool.len(), 1);
+    }
+}
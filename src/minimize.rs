@@ -0,0 +1,57 @@
+//
+// Shrink a correct program after a run. Evolution stops at the first correct program it finds, which
+// is rarely the shortest; this keeps evolving with correctness as a hard requirement, and only
+// accepts programs that are strictly shorter.
+//
+
+use super::gene::{Gene, Pool};
+
+use rand::Rng;
+use std::hash::Hash;
+
+// Evolve shorter programs equivalent to `program` for the given number of generations, returning
+// the shortest one found. `is_correct` must check a candidate against the full suite; anything it
+// rejects scores 0 and is never returned. Returns `program` itself if nothing shorter turns up.
+pub fn minimize<T, C, R>(program: &T, is_correct: C, pool_size: usize, generations: usize, rng: &mut R) -> T
+    where T: Gene + Hash + Eq + Clone,
+          T::Config: Default,
+          C: Fn(&T) -> bool,
+          R: Rng,
+    {
+    let mut shortest = program.clone();
+    // Correct programs score between 1 and 2, shorter ones higher
+    let fitness = |g: &T| if is_correct(g) { 1.0 + 1.0 / (1 + g.size()) as f32 } else { 0.0 };
+    let mut pool = Pool::from_genes(vec![program.clone(); pool_size], fitness);
+    for _ in 0 .. generations {
+        pool.evolve(rng);
+        // Fitness only rewards correct programs, so any gene scoring at least 1 is correct
+        for g in pool.iter() {
+            if g.1 >= 1.0 && g.0.size() < shortest.size() {
+                shortest = g.0.clone();
+            }
+        }
+    }
+    shortest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::Command::*;
+    use lang::Prog::{C, D};
+    use prog_gene::{self, ProgramGene, Runnable};
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn minimize_program() {
+        // a + b, padded with operations that cancel out
+        let padded = ProgramGene(vec![D(0), C(Add), C(Add), D(1), C(Mult), D(3), C(Sub), D(3), C(Add)]);
+        let correct = |g: &ProgramGene| prog_gene::score_cases_with(|a, b| a + b, 1, |a, b| g.run(a, b), 0.0) == 1.0;
+        assert!(correct(&padded));
+
+        let rng = &mut StdRng::from_seed(&[123]);
+        let shortest = minimize(&padded, correct, 50, 50, rng);
+        assert!(correct(&shortest));
+        assert_eq!(shortest, ProgramGene(vec![C(Add)]));
+    }
+}
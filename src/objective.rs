@@ -0,0 +1,65 @@
+//
+// Fitness values with a total order, so a NaN from a fitness function can't upset selection or the
+// choice of the best gene
+//
+
+use std::cmp::Ordering;
+
+// A fitness value that can be sorted and compared. NaN is worse than every other value, including
+// negative infinity, and equal to itself. (The name `Fitness` is taken by the trait for fitness
+// functions.)
+#[derive(Clone, Copy, Debug)]
+pub struct Objective(pub f32);
+
+impl Objective {
+    // How much this value counts for in fitness-proportionate selection. NaN counts for nothing.
+    pub fn weight(self) -> f32 {
+        if self.0.is_nan() {
+            0.0
+        } else {
+            self.0
+        }
+    }
+}
+
+impl Ord for Objective {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.0.is_nan(), other.0.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => self.0.partial_cmp(&other.0).unwrap(),
+        }
+    }
+}
+
+impl PartialOrd for Objective {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Objective {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Objective {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_with_nan() {
+        let mut values: Vec<Objective> = [1.0, f32::NAN, -1.0, f32::NEG_INFINITY, 0.5].iter().map(|&x| Objective(x)).collect();
+        values.sort();
+        let sorted: Vec<f32> = values.iter().map(|o| o.0).collect();
+        assert!(sorted[0].is_nan());
+        assert_eq!(&sorted[1 ..], &[f32::NEG_INFINITY, -1.0, 0.5, 1.0]);
+        assert_eq!(Objective(f32::NAN), Objective(f32::NAN));
+        assert!(Objective(f32::NAN) < Objective(f32::NEG_INFINITY));
+        assert_eq!(Objective(f32::NAN).weight(), 0.0);
+    }
+}
@@ -0,0 +1,305 @@
+//
+// Survivor selection over several objectives at once, in the style of NSGA-II. Genes are ranked into
+// Pareto fronts, where no gene in a front is beaten on every objective by another in the same front,
+// and the fronts are taken best first. The last front that only partly fits is cut by crowding
+// distance, keeping the genes with the most room around them so the survivors stay spread along the
+// front instead of clustering.
+//
+// With many objectives nearly every gene ends up on the first front, and crowding distance stops
+// telling them apart. `select_by_reference` cuts the last front NSGA-III style instead, spreading the
+// survivors over a fixed set of reference directions.
+//
+// Every objective is higher-is-better, and NaN is worse than any other value, as with `Objective`.
+//
+
+use super::objective::Objective;
+
+use rand::Rng;
+use std::cmp::{Ordering, Reverse};
+
+// Whether `a` is at least as good as `b` on every objective and better on at least one
+pub fn dominates(a: &[f32], b: &[f32]) -> bool {
+    let mut better = false;
+    for (&x, &y) in a.iter().zip(b) {
+        match Objective(x).cmp(&Objective(y)) {
+            Ordering::Less => return false,
+            Ordering::Greater => better = true,
+            Ordering::Equal => {}
+        }
+    }
+    better
+}
+
+// Sort the points into Pareto fronts, best first. Each front lists indexes into `points` in order.
+pub fn fronts(points: &[Vec<f32>]) -> Vec<Vec<usize>> {
+    // For each point, the points it dominates and the number of points dominating it
+    let mut dominated: Vec<Vec<usize>> = vec![Vec::new(); points.len()];
+    let mut dominators = vec![0; points.len()];
+    for i in 0 .. points.len() {
+        for j in i + 1 .. points.len() {
+            if dominates(&points[i], &points[j]) {
+                dominated[i].push(j);
+                dominators[j] += 1;
+            } else if dominates(&points[j], &points[i]) {
+                dominated[j].push(i);
+                dominators[i] += 1;
+            }
+        }
+    }
+
+    // Peel off the points nothing is left dominating, one front at a time
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0 .. points.len()).filter(|&i| dominators[i] == 0).collect();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &i in &front {
+            for &j in &dominated[i] {
+                dominators[j] -= 1;
+                if dominators[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        next.sort();
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+// The crowding distance of each point in a front, in the same order: the sum over the objectives of
+// the gap between its neighbors, relative to the spread of the front. The points at either end of
+// any objective are infinitely far from the crowd, so they're always kept.
+pub fn crowding_distance(points: &[Vec<f32>], front: &[usize]) -> Vec<f32> {
+    let mut distance = vec![0.0; front.len()];
+    let objectives = front.first().map_or(0, |&i| points[i].len());
+    // The value of objective k for point i of the front
+    let value = |i: usize, k: usize| points[front[i]][k];
+    for k in 0 .. objectives {
+        let mut order: Vec<usize> = (0 .. front.len()).collect();
+        order.sort_by_key(|&i| Objective(value(i, k)));
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distance[first] = f32::INFINITY;
+        distance[last] = f32::INFINITY;
+        let spread = value(last, k) - value(first, k);
+        // Nothing to spread along in this objective, or no way to measure it
+        if !(spread.is_finite() && spread > 0.0) {
+            continue;
+        }
+        for w in order.windows(3) {
+            let gap = value(w[2], k) - value(w[0], k);
+            if gap.is_finite() {
+                distance[w[1]] += gap / spread;
+            }
+        }
+    }
+    distance
+}
+
+// Take whole fronts, best first, while they fit in `n`. Returns the points taken and the first
+// front that didn't fit, which is empty if there's no need to cut one.
+fn whole_fronts(points: &[Vec<f32>], n: usize) -> (Vec<usize>, Vec<usize>) {
+    let mut chosen = Vec::with_capacity(n);
+    for front in fronts(points) {
+        if chosen.len() + front.len() > n {
+            return (chosen, front);
+        }
+        chosen.extend(front);
+    }
+    (chosen, Vec::new())
+}
+
+// Choose `n` of the points to survive: whole fronts, best first, then the least crowded points of
+// the first front that doesn't fit. Returns indexes into `points`, in the order they were chosen.
+pub fn select(points: &[Vec<f32>], n: usize) -> Vec<usize> {
+    let (mut chosen, last) = whole_fronts(points, n);
+    let distance = crowding_distance(points, &last);
+    let mut order: Vec<usize> = (0 .. last.len()).collect();
+    // The sort is stable, so ties go to the point that came first
+    order.sort_by_key(|&i| Reverse(Objective(distance[i])));
+    let room = n - chosen.len();
+    chosen.extend(order.iter().take(room).map(|&i| last[i]));
+    chosen
+}
+
+// Evenly spread reference directions for the given number of objectives: every point whose
+// coordinates are multiples of 1 / `divisions` and sum to 1. There are (objectives + divisions - 1)
+// choose divisions of them.
+pub fn reference_points(objectives: usize, divisions: usize) -> Vec<Vec<f32>> {
+    // Every way to share out `left` divisions among the remaining coordinates
+    fn fill(point: &mut Vec<usize>, objectives: usize, left: usize, out: &mut Vec<Vec<usize>>) {
+        if point.len() + 1 == objectives {
+            point.push(left);
+            out.push(point.clone());
+            point.pop();
+            return;
+        }
+        for x in (0 ..= left).rev() {
+            point.push(x);
+            fill(point, objectives, left - x, out);
+            point.pop();
+        }
+    }
+
+    if objectives == 0 || divisions == 0 {
+        return Vec::new();
+    }
+    let mut out = Vec::new();
+    fill(&mut Vec::new(), objectives, divisions, &mut out);
+    out.into_iter()
+        .map(|p| p.into_iter().map(|x| x as f32 / divisions as f32).collect())
+        .collect()
+}
+
+// The distance from a point to the line through the origin along `direction`
+fn perpendicular_distance(point: &[f32], direction: &[f32]) -> f32 {
+    let dot: f32 = point.iter().zip(direction).map(|(p, d)| p * d).sum();
+    let norm: f32 = direction.iter().map(|d| d * d).sum();
+    let t = if norm > 0.0 { dot / norm } else { 0.0 };
+    point.iter().zip(direction).map(|(p, d)| (p - t * d).powi(2)).sum::<f32>().sqrt()
+}
+
+// Like `select`, but cut the last front by reference-point niching as in NSGA-III, using the
+// reference directions from `reference_points` with the given number of divisions. Objectives are
+// first scaled so 0 is the best value among the candidates and 1 the worst. Each candidate belongs
+// to its nearest direction, and survivors from the last front go one at a time to the direction with
+// the fewest survivors so far, so no region of the front is left out.
+pub fn select_by_reference<R: Rng>(points: &[Vec<f32>], n: usize, divisions: usize, rng: &mut R) -> Vec<usize> {
+    let (mut chosen, mut last) = whole_fronts(points, n);
+    if last.is_empty() {
+        return chosen;
+    }
+    let objectives = points[last[0]].len();
+    let refs = reference_points(objectives, divisions);
+    if refs.is_empty() {
+        // No directions to spread over, so fall back on the order of the front
+        let room = n - chosen.len();
+        chosen.extend(last.into_iter().take(room));
+        return chosen;
+    }
+
+    // The best and worst value of each objective among the candidates
+    let candidates: Vec<usize> = chosen.iter().chain(&last).cloned().collect();
+    let best: Vec<f32> = (0 .. objectives)
+        .map(|k| candidates.iter().map(|&i| Objective(points[i][k])).max().unwrap().0)
+        .collect();
+    let worst: Vec<f32> = (0 .. objectives)
+        .map(|k| candidates.iter().map(|&i| Objective(points[i][k])).filter(|x| !x.0.is_nan()).min()
+             .map_or(best[k], |x| x.0))
+        .collect();
+    let scaled = |i: usize| -> Vec<f32> {
+        (0 .. objectives).map(|k| {
+            let range = best[k] - worst[k];
+            let x = points[i][k];
+            if x.is_nan() {
+                1.0
+            } else if range.is_finite() && range > 0.0 {
+                (best[k] - x) / range
+            } else {
+                0.0
+            }
+        }).collect()
+    };
+
+    // The nearest direction to a point, and how far it is
+    let associate = |i: usize| -> (usize, f32) {
+        let p = scaled(i);
+        refs.iter().enumerate()
+            .map(|(r, dir)| (r, perpendicular_distance(&p, dir)))
+            .max_by_key(|&(_, d)| Objective(-d))
+            .unwrap()
+    };
+    let mut niche = vec![0; refs.len()];
+    for &i in &chosen {
+        niche[associate(i).0] += 1;
+    }
+    let mut members: Vec<(usize, usize, f32)> = last.drain(..).map(|i| {
+        let (r, d) = associate(i);
+        (i, r, d)
+    }).collect();
+    let mut open = vec![true; refs.len()];
+
+    while chosen.len() < n {
+        // A direction with the fewest survivors, chosen at random among ties
+        let fewest = (0 .. refs.len()).filter(|&r| open[r]).map(|r| niche[r]).min()
+            .expect("ran out of directions with candidates left");
+        let least: Vec<usize> = (0 .. refs.len()).filter(|&r| open[r] && niche[r] == fewest).collect();
+        let r = *rng.choose(&least).unwrap();
+        let near: Vec<usize> = (0 .. members.len()).filter(|&m| members[m].1 == r).collect();
+        if near.is_empty() {
+            open[r] = false;
+            continue;
+        }
+        // An empty niche takes its closest member, and others a random one
+        let m = if niche[r] == 0 {
+            *near.iter().max_by_key(|&&m| Objective(-members[m].2)).unwrap()
+        } else {
+            *rng.choose(&near).unwrap()
+        };
+        chosen.push(members.remove(m).0);
+        niche[r] += 1;
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fronts_and_crowding() {
+        assert!(dominates(&[1.0, 2.0], &[1.0, 1.0]));
+        assert!(!dominates(&[1.0, 1.0], &[1.0, 1.0]));
+        assert!(!dominates(&[2.0, 0.0], &[0.0, 2.0]));
+        assert!(dominates(&[0.0, 0.0], &[0.0, f32::NAN]));
+
+        // A front of four trading off the two objectives, and one point behind it
+        let points = vec![
+            vec![0.0, 4.0],
+            vec![1.0, 3.0],
+            vec![1.1, 2.9],
+            vec![4.0, 0.0],
+            vec![0.5, 0.5],
+        ];
+        assert_eq!(fronts(&points), vec![vec![0, 1, 2, 3], vec![4]]);
+
+        // The ends are kept, and of the two clustered points the one with more room around it
+        let distance = crowding_distance(&points, &[0, 1, 2, 3]);
+        assert!(distance[0].is_infinite() && distance[3].is_infinite());
+        assert!(distance[2] > distance[1]);
+        assert_eq!(select(&points, 3), vec![0, 3, 2]);
+        assert_eq!(select(&points, 5), vec![0, 1, 2, 3, 4]);
+        assert_eq!(select(&points, 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn reference_niching() {
+        use rand::{SeedableRng, StdRng};
+        let rng = &mut StdRng::from_seed(&[123]);
+
+        assert_eq!(reference_points(2, 2), vec![vec![1.0, 0.0], vec![0.5, 0.5], vec![0.0, 1.0]]);
+        assert_eq!(reference_points(4, 4).len(), 35);
+        assert!(reference_points(4, 4).iter().all(|p| (p.iter().sum::<f32>() - 1.0).abs() < 1e-6));
+        assert!(reference_points(3, 0).is_empty());
+
+        // Four objectives where every point is on the first front: a cluster of points that are each
+        // good at the first objective, and one point good at each of the others
+        let mut points: Vec<Vec<f32>> = (0 .. 10).map(|i| vec![10.0 + i as f32 * 0.01, 0.0, 0.0, -(i as f32) * 0.01])
+            .collect();
+        points.push(vec![0.0, 10.0, 0.0, -1.0]);
+        points.push(vec![0.0, 0.0, 10.0, -1.0]);
+        points.push(vec![0.0, 0.0, 0.0, 10.0]);
+        assert_eq!(fronts(&points).len(), 1);
+
+        // Niching keeps every corner instead of filling up on the cluster
+        for _ in 0 .. 10 {
+            let chosen = select_by_reference(&points, 4, 4, rng);
+            assert_eq!(chosen.len(), 4);
+            for corner in 10 .. 13 {
+                assert!(chosen.contains(&corner), "{:?}", chosen);
+            }
+        }
+        assert_eq!(select_by_reference(&points, 13, 4, rng).len(), 13);
+        assert_eq!(select_by_reference(&points, 2, 0, rng), vec![0, 1]);
+    }
+}
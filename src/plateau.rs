@@ -0,0 +1,186 @@
+//
+// Hyper-mutation for runs that stall. Once neither the best nor the mean fitness has risen for a
+// while, mutate much harder and bring in new random genes every generation, to shake the pool out
+// of wherever it's stuck. Go back to normal as soon as the best fitness improves, or after a few
+// generations if it doesn't.
+//
+
+use super::gene::DynPool;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HyperMutation {
+    // The number of generations without improvement that makes a plateau
+    pub window: usize,
+    // The most generations to hyper-mutate for at a time
+    pub generations: usize,
+    // The number of mutations in a row that make each mutated gene while hyper-mutating
+    pub mutations: usize,
+    // The number of least fit genes to replace with new random ones each generation while
+    // hyper-mutating
+    pub immigrants: usize,
+    // How much the best or mean fitness has to rise to count as improving
+    #[serde(default)]
+    pub tolerance: f32,
+}
+
+// Parse hyper-mutation from "WINDOW:GENERATIONS:MUTATIONS:IMMIGRANTS", e.g. "30:10:4:20", with an
+// optional ":TOLERANCE" after
+impl FromStr for HyperMutation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<HyperMutation, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let (counts, tolerance) = match parts.len() {
+            4 => (&parts[..], 0.0),
+            5 => (&parts[.. 4], parts[4].parse().map_err(|_| format!("bad tolerance in hyper-mutation: {}", s))?),
+            _ => return Err(format!("hyper-mutation needs WINDOW:GENERATIONS:MUTATIONS:IMMIGRANTS: {}", s)),
+        };
+        let counts: Vec<usize> = counts.iter().map(|x| x.parse().ok()).collect::<Option<_>>()
+            .ok_or_else(|| format!("bad number in hyper-mutation: {}", s))?;
+        Ok(HyperMutation { window: counts[0], generations: counts[1], mutations: counts[2], immigrants: counts[3],
+                           tolerance })
+    }
+}
+
+impl fmt::Display for HyperMutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.window, self.generations, self.mutations, self.immigrants)?;
+        if self.tolerance != 0.0 {
+            write!(f, ":{}", self.tolerance)?;
+        }
+        Ok(())
+    }
+}
+
+// What happened to hyper-mutation after a generation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    // The run plateaued, so hyper-mutation started
+    Started,
+    // Hyper-mutation stopped, because the run improved or it ran its course
+    Stopped,
+}
+
+// Watches a run for plateaus and hyper-mutates through them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plateau {
+    hyper: HyperMutation,
+    // The best and mean fitness of recent generations, oldest first
+    history: VecDeque<(f32, f32)>,
+    // While hyper-mutating, the generations left and the best fitness when it started
+    burst: Option<(usize, f32)>,
+}
+
+impl Plateau {
+    pub fn new(hyper: HyperMutation) -> Plateau {
+        Plateau { hyper, history: VecDeque::new(), burst: None }
+    }
+
+    // Whether the next generation will be hyper-mutated
+    pub fn is_hyper_mutating(&self) -> bool {
+        self.burst.is_some()
+    }
+
+    // Look at the pool after each generation and set it up for the next. Starting hyper-mutation
+    // also replaces the least fit genes right away.
+    pub fn update(&mut self, pool: &mut dyn DynPool, rng: &mut dyn Rng) -> Option<Change> {
+        let genes = pool.genes_dyn();
+        let best = genes.iter().map(|g| g.1).fold(f32::NAN, f32::max);
+        let scored: Vec<f32> = genes.iter().map(|g| g.1).filter(|f| !f.is_nan()).collect();
+        let mean = scored.iter().sum::<f32>() / scored.len() as f32;
+        let change = self.observe(best, mean);
+        match change {
+            Some(Change::Started) => pool.set_mutations_dyn(self.hyper.mutations),
+            Some(Change::Stopped) => pool.set_mutations_dyn(1),
+            None => {}
+        }
+        if self.is_hyper_mutating() {
+            pool.refresh_dyn(self.hyper.immigrants, rng);
+        }
+        change
+    }
+
+    // Record the best and mean fitness of a generation, and decide whether to hyper-mutate the next
+    pub fn observe(&mut self, best: f32, mean: f32) -> Option<Change> {
+        let tolerance = self.hyper.tolerance;
+        if let Some((left, best_before)) = self.burst {
+            if best > best_before + tolerance || left <= 1 {
+                // Start looking for the next plateau afresh
+                self.burst = None;
+                self.history.clear();
+                return Some(Change::Stopped);
+            }
+            self.burst = Some((left - 1, best_before));
+            return None;
+        }
+        self.history.push_back((best, mean));
+        if self.history.len() <= self.hyper.window {
+            return None;
+        }
+        let (old_best, old_mean) = self.history.pop_front().unwrap();
+        if best <= old_best + tolerance && mean <= old_mean + tolerance {
+            self.burst = Some((self.hyper.generations, best));
+            Some(Change::Started)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn hyper_mutate_plateaus() {
+        let hyper: HyperMutation = "3:2:4:5".parse().unwrap();
+        assert_eq!(hyper, HyperMutation { window: 3, generations: 2, mutations: 4, immigrants: 5, tolerance: 0.0 });
+        assert_eq!(hyper.to_string(), "3:2:4:5");
+        assert_eq!("3:2:4:5:0.5".parse::<HyperMutation>().unwrap().to_string(), "3:2:4:5:0.5");
+        assert!("3:2:4".parse::<HyperMutation>().is_err());
+        assert!("3:2:x:5".parse::<HyperMutation>().is_err());
+
+        let mut plateau = Plateau::new(hyper);
+        // Improving, then flat for 3 generations
+        for &(best, mean) in &[(0.1, 0.0), (0.2, 0.1), (0.2, 0.1), (0.2, 0.1)] {
+            assert_eq!(plateau.observe(best, mean), None);
+        }
+        assert_eq!(plateau.observe(0.2, 0.1), Some(Change::Started));
+        assert!(plateau.is_hyper_mutating());
+        // It runs its course without improving
+        assert_eq!(plateau.observe(0.2, 0.1), None);
+        assert_eq!(plateau.observe(0.2, 0.1), Some(Change::Stopped));
+        // A rising mean alone is still progress
+        for &(best, mean) in &[(0.2, 0.1), (0.2, 0.11), (0.2, 0.12), (0.2, 0.13), (0.2, 0.14)] {
+            assert_eq!(plateau.observe(best, mean), None);
+        }
+        for _ in 0 .. 3 {
+            plateau.observe(0.2, 0.14);
+        }
+        assert!(plateau.is_hyper_mutating());
+        // Improvement stops it early
+        assert_eq!(plateau.observe(0.3, 0.14), Some(Change::Stopped));
+
+        // On a pool, the least fit genes are replaced while hyper-mutating
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness: gene::BoxedFitness<ProgramGene> = Box::new(|g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g));
+        let mut pool = gene::new_dyn_pool(20, fitness, rng);
+        let mut plateau = Plateau::new("1:5:3:20".parse().unwrap());
+        let before: Vec<String> = pool.genes_dyn().iter().map(|g| g.0.to_string()).collect();
+        assert_eq!(plateau.update(&mut *pool, rng), None);
+        assert_eq!(plateau.update(&mut *pool, rng), Some(Change::Started));
+        let after: Vec<String> = pool.genes_dyn().iter().map(|g| g.0.to_string()).collect();
+        assert_eq!(after.len(), 20);
+        assert_ne!(after, before);
+    }
+}
@@ -0,0 +1,34 @@
+//
+// The items most programs using this crate need, so one `use gene_code::prelude::*;` brings them
+// all in
+//
+
+pub use super::gene::{AgeLimits, BoxedFitness, DynPool, Fitness, Gene, GenerationStats, Pool, PoolBuilder, Proportions, Rates,
+                      Reevaluate, RunSummary, Selection, StopReason, Termination};
+pub use super::immigration::{Immigration, MutateElite};
+pub use super::lang::{Command, Prog, Underflow};
+pub use super::prog_gene::{AdaptiveGene, AlignedGene, HomologousGene, ProgramConfig, ProgramGene, Runnable};
+pub use super::suite::TestSuite;
+pub use super::config::RunConfig;
+pub use super::runner::{run, RunError, RunReport};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn evolve_with_prelude() {
+        let suite = TestSuite::builder(|a, b| vec![a + b]).grid(0 .. 4, 0 .. 4).build();
+        let rng = &mut StdRng::from_seed(&[123]);
+        let mut pool = Pool::new(50, |g: &ProgramGene| suite.score(g), rng);
+        pool.set_selection(Selection::Truncation(0.5));
+        pool.set_immigration(MutateElite(5));
+        for _ in 0 .. 20 {
+            pool.evolve(rng);
+        }
+        let (best, fit) = pool.get_best();
+        assert_eq!(fit, suite.score(best));
+        assert!(RunConfig::default().validate().is_ok());
+    }
+}
@@ -4,137 +4,1093 @@
 
 use super::lang;
 use super::gene;
+use super::behavior::{self, Behavior};
+use super::memory::GeneSize;
+use super::localize;
+use super::suite::Case;
 
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// The number of steps a program may run for, enforced with the stack's fuel
+pub const MAX_STEPS: usize = 10;
+
+// The number of steps a program that may loop runs for by default, enough to sum or multiply a
+// range of small inputs. See `ProgramConfig::default_steps`.
+pub const LOOP_STEPS: usize = 1000;
 
 // A program as a gene. This is a simple wrapper so we can implement the required trait.
+//
+// The genetic operators never produce an empty program: generate makes at least one prog, mutate
+// never deletes the last prog, and cross replaces an empty result with a random prog. Empty
+// programs can still be built by hand, and always get a fitness of 0.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProgramGene(pub Vec<lang::Prog>);
 
-// Generate a random number or command
-fn rand_prog<R: Rng>(rng: &mut R) -> lang::Prog {
+// The parameters of stack programs as genes
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgramConfig {
+    // The longest program to generate. Mutation and crossover can still make longer ones.
+    pub max_len: usize,
+    // How likely each prog is to be generated or mutated in, e.g. as learned from the elite. None
+    // chooses numbers and commands evenly.
+    pub weights: Option<ProgWeights>,
+    // The chance that a mutation reverses or moves a whole run of progs instead of editing single
+    // progs, from 0 to 1. These coarser edits can escape local optima that single edits can't.
+    pub structural: f32,
+    // Whether random progs may be the frame commands, `lang::Command::PushFrame` and `PopFrame`.
+    // Weights leave them out.
+    pub frames: bool,
+    // Whether random progs may be the comparisons, `lang::Command::Not`, and `lang::Command::If`, so
+    // programs can branch. Weights leave them out too.
+    pub conditionals: bool,
+    // Whether random progs may be `lang::Command::Times`, so programs can loop. Weights leave it out
+    // too.
+    pub loops: bool,
+    // The cases to localize faults on, so mutations edit the progs most likely to be why a program
+    // fails more often than the rest. Empty edits every prog alike. See `localize`.
+    pub localize: Vec<Case>,
+    // The most steps programs run for while they're scored and localized. See
+    // `ProgramConfig::default_steps`.
+    pub max_steps: usize,
+    // The most blocks a prog of a generated or bred program may be inside of. Deeper blocks are
+    // unwrapped; see `lang::flatten`.
+    pub max_nesting: usize,
+}
+
+impl Default for ProgramConfig {
+    fn default() -> ProgramConfig {
+        ProgramConfig { max_len: MAX_STEPS, weights: None, structural: 0.0, frames: false, conditionals: false,
+                        loops: false, localize: Vec::new(), max_steps: MAX_STEPS, max_nesting: lang::MAX_NESTING }
+    }
+}
+
+impl ProgramConfig {
+    // The step budget to score programs with: `MAX_STEPS`, or `LOOP_STEPS` if they may loop, since
+    // a loop over its inputs takes far more steps than a straight-line program
+    pub fn default_steps(loops: bool) -> usize {
+        if loops { LOOP_STEPS } else { MAX_STEPS }
+    }
+}
+
+// Generate a random number or command, following the config's weights if any
+fn rand_prog<R: Rng>(cfg: &ProgramConfig, rng: &mut R) -> lang::Prog {
+    if let Some(ref weights) = cfg.weights {
+        return weights.sample(rng);
+    }
     if rng.gen() {
         // 50% chance of number
         lang::Prog::D(rng.gen_range(-10, 11))
     } else {
-        // 50% chance of command
-        let cmd = match rng.gen_range(0, 6) {
-            0 => lang::Command::Add,
-            1 => lang::Command::Sub,
-            2 => lang::Command::Mult,
-            3 => lang::Command::Div,
-            4 => lang::Command::Dup,
-            _ => lang::Command::Swap,
-        };
-        lang::Prog::C(cmd)
+        // 50% chance of command, of the basic ones and then whichever others the config allows
+        let mut commands = lang::Command::BASIC.to_vec();
+        if cfg.frames {
+            commands.extend_from_slice(&[lang::Command::PushFrame, lang::Command::PopFrame]);
+        }
+        if cfg.conditionals {
+            commands.extend_from_slice(&lang::Command::CONDITIONAL);
+        }
+        if cfg.loops {
+            commands.push(lang::Command::Times);
+        }
+        // Drawn as an i32, as it always has been, so seeded runs stay the same
+        lang::Prog::C(commands[rng.gen_range(0, commands.len() as i32) as usize])
+    }
+}
+
+// How likely `rand_prog` is to produce each prog, e.g. as learned from the most fit programs of a
+// generation in the style of an estimation-of-distribution algorithm
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgWeights(Vec<(lang::Prog, f32)>);
+
+impl ProgWeights {
+    // The unbiased weights: half numbers from -10 to 10, half basic commands, each uniformly
+    pub fn base() -> ProgWeights {
+        let numbers = (-10 .. 11).map(|d| (lang::Prog::D(d), 0.5 / 21.0));
+        let commands = lang::Command::BASIC.iter().map(|&c| (lang::Prog::C(c), 0.5 / 6.0));
+        ProgWeights(numbers.chain(commands).collect())
+    }
+
+    // Learn weights from how often each prog appears in the elite programs. The counts start from
+    // the base weights, as if one prog had already been seen and split between every kind, so none
+    // become impossible. A temperature of 1 follows the elite's frequencies,
+    // higher temperatures stay closer to the base weights, and lower ones favor the commonest
+    // progs even more. Progs the base weights can't produce are ignored.
+    pub fn learn<'a, I: IntoIterator<Item = &'a ProgramGene>>(elite: I, temperature: f32) -> ProgWeights {
+        ProgWeights::learn_progs(elite.into_iter().flat_map(|g| g.0.iter().cloned()), temperature)
+    }
+
+    // Like `learn`, but from the given progs rather than whole programs
+    pub fn learn_progs<I: IntoIterator<Item = lang::Prog>>(progs: I, temperature: f32) -> ProgWeights {
+        let base = ProgWeights::base();
+        let mut counts: Vec<f32> = base.0.iter().map(|w| w.1).collect();
+        for p in progs {
+            if let Some(i) = base.0.iter().position(|w| w.0 == p) {
+                counts[i] += 1.0;
+            }
+        }
+        let total: f32 = counts.iter().sum();
+        let k = 1.0 / temperature;
+        ProgWeights(base.0.iter().zip(&counts)
+            .map(|(&(p, b), &c)| (p, b.powf(1.0 - k) * (c / total).powf(k)))
+            .collect())
+    }
+
+    // Move each chance toward the one in `other` by `rate`, from 0 (stay) to 1 (take `other`'s)
+    pub fn blend(&self, other: &ProgWeights, rate: f32) -> ProgWeights {
+        ProgWeights(self.0.iter()
+            .map(|&(p, _)| (p, (1.0 - rate) * self.chance(p) + rate * other.chance(p)))
+            .collect())
+    }
+
+    // The chance of producing the given prog
+    pub fn chance(&self, p: lang::Prog) -> f32 {
+        let total: f32 = self.0.iter().map(|w| w.1).sum();
+        self.0.iter().find(|w| w.0 == p).map_or(0.0, |w| w.1 / total)
+    }
+
+    // Pick a prog according to the weights
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> lang::Prog {
+        let total: f32 = self.0.iter().map(|w| w.1).sum();
+        let mut f = rng.gen_range(0.0, total);
+        for &(p, w) in &self.0 {
+            if f < w {
+                return p;
+            }
+            f -= w;
+        }
+        // Rounding can leave a sliver after the last prog
+        self.0[self.0.len() - 1].0
     }
 }
 
 impl gene::Gene for ProgramGene {
-    fn generate<R: Rng>(rng: &mut R) -> Self {
+    type Config = ProgramConfig;
+
+    fn generate<R: Rng>(cfg: &ProgramConfig, rng: &mut R) -> Self {
         // Generate a random sequence of numbers & commands
-        // Pick a length between 1 and 10
-        let len: usize = rng.gen_range(1, 11);
+        // Pick a length between 1 and the max
+        let len: usize = rng.gen_range(1, cfg.max_len.max(1) + 1);
         // Fill a vec with progs
         let mut prog = Vec::new();
         for _ in 0 .. len {
-            prog.push(rand_prog(rng));
+            prog.push(rand_prog(cfg, rng));
         }
+        lang::flatten(&mut prog, cfg.max_nesting);
         ProgramGene(prog)
     }
 
-    fn mutate<R: Rng>(&self, rng: &mut R) -> Self {
+    fn mutate<R: Rng>(&self, cfg: &ProgramConfig, rng: &mut R) -> Self {
+        // Without structural mutation, this draws nothing extra, so runs stay as they were
+        if cfg.structural > 0.0 && self.0.len() > 1 && rng.gen::<f32>() < cfg.structural {
+            return if rng.gen() { self.reverse_run(rng) } else { self.transpose_run(rng) };
+        }
         // Pick a number of modifications between 1 and len of program
         let mods = rng.gen_range(1, self.0.len().max(2));
-        // Add, delete, or replace a random prog
+        // Add, delete, or replace a random prog. Duplicating only comes up with frames, so runs
+        // without them stay as they were.
+        let kinds = if cfg.frames { 4 } else { 3 };
         let mut result = self.0.clone();
+        let mut sites = if cfg.localize.is_empty() { None } else { Some(localized_sites(&self.0, cfg)) };
         for _ in 0 .. mods {
-            match rng.gen_range(0, 3) {
-                0 => {
-                    // Add
-                    let prog = rand_prog(rng);
-                    let i = rng.gen_range(0, result.len()+1);
-                    result.insert(i, prog);
+            let kind = match rng.gen_range(0, kinds) {
+                0 => EditKind::Add,
+                1 => EditKind::Delete,
+                2 => EditKind::Replace,
+                _ => EditKind::Duplicate,
+            };
+            edit_prog(&mut result, kind, cfg, sites.as_mut(), rng);
+        }
+        lang::flatten(&mut result, cfg.max_nesting);
+        // Only possible if this program was already empty
+        if result.is_empty() {
+            result.push(rand_prog(cfg, rng));
+        }
+        ProgramGene(result)
+    }
+
+    fn cross<R: Rng>(&self, cfg: &ProgramConfig, other: &Self, rng: &mut R) -> Self {
+        // Pick a cut point on each gene. With frames, only between units, so frames stay whole.
+        let (stop_self, start_other) = if cfg.frames {
+            (*rng.choose(&cut_points(&self.0)).unwrap(), *rng.choose(&cut_points(&other.0)).unwrap())
+        } else {
+            (rng.gen_range(0, self.0.len().max(1)), rng.gen_range(0, other.0.len().max(1)))
+        };
+        // Replace after the cut point
+        let result = self.0.iter().take(stop_self)
+            .chain(other.0.iter().skip(start_other))
+            .cloned()
+            .collect();
+        finish_cross(result, cfg, rng)
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+// The places a crossover may cut a program with frames: before any prog that isn't inside a frame
+// (see `lang::frame_pairs`), other than past the end. The start is always one.
+fn cut_points(program: &[lang::Prog]) -> Vec<usize> {
+    let mut open = 0;
+    let mut cuts = vec![0];
+    for (i, pair) in lang::frame_pairs(program).into_iter().enumerate().take(program.len().saturating_sub(1)) {
+        match pair {
+            Some(end) if end > i => open += 1,
+            Some(_) => open -= 1,
+            None => {}
+        }
+        if open == 0 {
+            cuts.push(i + 1);
+        }
+    }
+    cuts
+}
+
+// Make a crossed program into a gene. With frames, the frame commands the cut left without a
+// partner are dropped, and blocks nested too deep are unwrapped. A program left empty, e.g. because
+// the other parent was, gets a random prog, other than a lone frame command.
+fn finish_cross<R: Rng>(mut program: Vec<lang::Prog>, cfg: &ProgramConfig, rng: &mut R) -> ProgramGene {
+    if cfg.frames {
+        lang::repair(&mut program);
+    }
+    lang::flatten(&mut program, cfg.max_nesting);
+    while program.is_empty() {
+        program.push(rand_prog(cfg, rng));
+        if cfg.frames {
+            lang::repair(&mut program);
+        }
+    }
+    ProgramGene(program)
+}
+
+// The edits a mutation is made of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EditKind {
+    Add,
+    Delete,
+    Replace,
+    Duplicate,
+}
+
+// Every prog's weight as a site for localized edits, on top of its suspiciousness, so edits can
+// still land anywhere
+const SITE_FLOOR: f32 = 0.1;
+
+// The weight of each prog as a site for localized edits. Every failing case runs most of a program,
+// so suspiciousness is rescaled to rank the progs against each other: from SITE_FLOOR for the least
+// suspicious to 1 + SITE_FLOOR for the most. A program that fails no case, or whose progs are all
+// as suspicious, gets even weights.
+fn localized_sites(program: &[lang::Prog], cfg: &ProgramConfig) -> Vec<f32> {
+    let suspicion = localize::suspiciousness(program, &cfg.localize, cfg.max_steps);
+    let least = suspicion.iter().cloned().fold(f32::INFINITY, f32::min);
+    let most = suspicion.iter().cloned().fold(0.0, f32::max);
+    suspicion.iter().map(|&s| if most > least { SITE_FLOOR + (s - least) / (most - least) } else { SITE_FLOOR }).collect()
+}
+
+// Make one edit at a random place in the program. Frames that pair up (see `lang::frame_pairs`)
+// are kept whole: deleting or duplicating either frame command takes the whole frame with it, the
+// frame commands aren't replaced, and a frame command is only ever added as an empty frame. Frames
+// longer than the config's max_len aren't copied. Edits to programs without frames are single progs.
+//
+// With `sites`, a weight for each prog, the place is chosen in proportion to the weights instead of
+// uniformly, and the weights are kept lined up with the edited program. New progs go between two
+// progs with the greater of their weights.
+fn edit_prog<R: Rng>(result: &mut Vec<lang::Prog>, kind: EditKind, cfg: &ProgramConfig, sites: Option<&mut Vec<f32>>,
+                     rng: &mut R) {
+    match kind {
+        EditKind::Add => {
+            let prog = rand_prog(cfg, rng);
+            let gaps = sites.as_ref().map(|w| (0 ..= w.len()).map(|i| {
+                let before = if i > 0 { w[i - 1] } else { SITE_FLOOR };
+                before.max(w.get(i).cloned().unwrap_or(SITE_FLOOR))
+            }).collect::<Vec<f32>>());
+            let i = pick_site(gaps.as_deref(), result.len() + 1, rng);
+            let added = if is_frame_command(prog) {
+                let frame = [lang::Prog::C(lang::Command::PushFrame), lang::Prog::C(lang::Command::PopFrame)];
+                result.splice(i .. i, frame.iter().cloned());
+                2
+            } else {
+                result.insert(i, prog);
+                1
+            };
+            if let (Some(w), Some(gaps)) = (sites, gaps) {
+                w.splice(i .. i, ::std::iter::repeat_n(gaps[i], added));
+            }
+        }
+        EditKind::Delete => {
+            // Unless this is the last prog
+            if result.len() > 1 {
+                let i = pick_site(sites.as_deref().map(Vec::as_slice), result.len(), rng);
+                let (start, end) = unit_at(result, i);
+                if end - start < result.len() {
+                    result.drain(start .. end);
+                    if let Some(w) = sites {
+                        w.drain(start .. end);
+                    }
                 }
-                1 => {
-                    // Delete
-                    if result.len() > 0 {
-                        let i = rng.gen_range(0, result.len());
-                        result.remove(i);
+            }
+        }
+        EditKind::Replace => {
+            if !result.is_empty() {
+                let mut prog = rand_prog(cfg, rng);
+                let i = pick_site(sites.as_deref().map(Vec::as_slice), result.len(), rng);
+                if unit_at(result, i) == (i, i + 1) {
+                    while is_frame_command(prog) {
+                        prog = rand_prog(cfg, rng);
                     }
+                    result[i] = prog;
                 }
-                _ => {
-                    // Replace
-                    if result.len() > 0 {
-                        let prog = rand_prog(rng);
-                        let i = rng.gen_range(0, result.len());
-                        result[i] = prog;
+            }
+        }
+        EditKind::Duplicate => {
+            if !result.is_empty() {
+                let i = pick_site(sites.as_deref().map(Vec::as_slice), result.len(), rng);
+                let (start, end) = unit_at(result, i);
+                // Copying frames of any length could double the program with every edit
+                if end - start <= cfg.max_len {
+                    let copy: Vec<lang::Prog> = result[start .. end].to_vec();
+                    result.splice(end .. end, copy);
+                    if let Some(w) = sites {
+                        let copy: Vec<f32> = w[start .. end].to_vec();
+                        w.splice(end .. end, copy);
                     }
                 }
             }
         }
-        ProgramGene(result)
     }
+}
 
-    fn cross<R: Rng>(&self, other: &Self, rng: &mut R) -> Self {
-        // Pick a cut point on this gene
-        let stop_self = rng.gen_range(0, self.0.len().max(1));
-        // Pick a cut point on the other gene
-        let start_other = rng.gen_range(0, other.0.len().max(1));
-        // Replace after the cut point
-        ProgramGene(self.0.iter().take(stop_self)
-            .chain(other.0.iter().skip(start_other))
-            .map(Clone::clone)
-            .collect())
+// Pick one of `n` places, in proportion to the weights if there are any, or else uniformly
+fn pick_site<R: Rng>(weights: Option<&[f32]>, n: usize, rng: &mut R) -> usize {
+    let weights = match weights {
+        Some(w) => w,
+        None => return rng.gen_range(0, n),
+    };
+    let mut f = rng.gen_range(0.0, weights.iter().sum::<f32>());
+    for (i, &w) in weights.iter().enumerate() {
+        if f < w {
+            return i;
+        }
+        f -= w;
+    }
+    // Rounding can leave a sliver after the last place
+    n - 1
+}
+
+fn is_frame_command(p: lang::Prog) -> bool {
+    p == lang::Prog::C(lang::Command::PushFrame) || p == lang::Prog::C(lang::Command::PopFrame)
+}
+
+// The range of the unit of the program that prog `i` belongs to when it's edited: the whole frame
+// if it's a frame command with a partner, or else just the prog
+fn unit_at(program: &[lang::Prog], i: usize) -> (usize, usize) {
+    if !is_frame_command(program[i]) {
+        return (i, i + 1);
+    }
+    match lang::frame_pairs(program)[i] {
+        Some(j) => (i.min(j), i.max(j) + 1),
+        None => (i, i + 1),
     }
 }
 
 // Implement Display to produce a concise, human-readable view of a program.
 impl fmt::Display for ProgramGene {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use lang::Prog::{C, D};
-        use lang::Command::*;
-
         let mut add_space = false;
         for prog in &self.0 {
             if add_space {
                 write!(f, " ")?;
             }
             add_space = true;
-            match prog {
-                &D(d) => write!(f, "{}", d)?,
-                &C(Add) => write!(f, "+")?,
-                &C(Sub) => write!(f, "-")?,
-                &C(Mult) => write!(f, "*")?,
-                &C(Div) => write!(f, "/")?,
-                &C(Dup) => write!(f, "dup")?,
-                &C(Swap) => write!(f, "swap")?,
+            write!(f, "{}", prog)?;
+        }
+        Ok(())
+    }
+}
+
+impl ProgramGene {
+    // Reverse a random run of at least two progs. Programs shorter than that stay the same.
+    pub fn reverse_run<R: Rng>(&self, rng: &mut R) -> Self {
+        let mut result = self.0.clone();
+        let len = result.len();
+        if len >= 2 {
+            let run = rng.gen_range(2, len + 1);
+            let start = rng.gen_range(0, len - run + 1);
+            result[start .. start + run].reverse();
+        }
+        ProgramGene(result)
+    }
+
+    // Move a random run of progs, shorter than the whole program, somewhere else in it. Programs
+    // shorter than two progs stay the same.
+    pub fn transpose_run<R: Rng>(&self, rng: &mut R) -> Self {
+        let mut result = self.0.clone();
+        let len = result.len();
+        if len >= 2 {
+            let run = rng.gen_range(1, len);
+            let start = rng.gen_range(0, len - run + 1);
+            let moved: Vec<lang::Prog> = result.drain(start .. start + run).collect();
+            // Anywhere in what's left but where it was
+            let mut to = rng.gen_range(0, result.len());
+            if to >= start {
+                to += 1;
+            }
+            result.splice(to .. to, moved);
+        }
+        ProgramGene(result)
+    }
+}
+
+// A program displayed with the index of each prog, e.g. "0:1 1:- 2:-30 3:dup", for lining up what
+// the genetic operators did with where they did it
+pub struct Annotated<'a>(pub &'a ProgramGene);
+
+impl ProgramGene {
+    pub fn annotated(&self) -> Annotated<'_> {
+        Annotated(self)
+    }
+
+    // The program's outputs on the probe inputs. See `behavior::Behavior`.
+    pub fn behavior(&self) -> Behavior {
+        Behavior::of(self)
+    }
+}
+
+impl<'a> fmt::Display for Annotated<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, prog) in self.0 .0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
             }
+            write!(f, "{}:{}", i, prog)?;
         }
         Ok(())
     }
 }
 
+// One step in turning one program into another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edit {
+    Keep(lang::Prog),
+    Remove(lang::Prog),
+    Insert(lang::Prog),
+    // The first prog was replaced by the second
+    Replace(lang::Prog, lang::Prog),
+}
+
+// The shortest list of edits turning program `a` into program `b`, keeping their longest common
+// subsequence. A removal directly followed by an insertion is a replacement.
+pub fn diff_edits(a: &ProgramGene, b: &ProgramGene) -> Vec<Edit> {
+    let (a, b) = (&a.0, &b.0);
+    // common[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut common = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0 .. a.len()).rev() {
+        for j in (0 .. b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            edits.push(Edit::Keep(a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            edits.push(Edit::Remove(a[i]));
+            i += 1;
+        } else {
+            // Pair the insertion with a removal just before it
+            match edits.last().cloned() {
+                Some(Edit::Remove(old)) => *edits.last_mut().unwrap() = Edit::Replace(old, b[j]),
+                _ => edits.push(Edit::Insert(b[j])),
+            }
+            j += 1;
+        }
+    }
+    edits
+}
+
+// Show how program `a` became program `b`, in the style of a word diff: kept progs as usual,
+// "[-x-]" for a removed prog, "{+x+}" for an inserted prog, and "[x->y]" for a replaced prog
+pub fn diff(a: &ProgramGene, b: &ProgramGene) -> String {
+    diff_edits(a, b).iter().map(|e| match *e {
+        Edit::Keep(p) => p.to_string(),
+        Edit::Remove(p) => format!("[-{}-]", p),
+        Edit::Insert(p) => format!("{{+{}+}}", p),
+        Edit::Replace(p, q) => format!("[{}->{}]", p, q),
+    }).collect::<Vec<_>>().join(" ")
+}
+
+impl ProgramGene {
+    // Cross with another program by aligning the two on their longest common subsequence. The
+    // shared progs are kept in place, and each region where the parents differ comes from one
+    // parent or the other at random. Unlike a random cut, this keeps structure both parents share.
+    // Parents with nothing in common fall back to the usual cut-point crossover.
+    pub fn cross_aligned<R: Rng>(&self, cfg: &ProgramConfig, other: &Self, rng: &mut R) -> Self {
+        let edits = diff_edits(self, other);
+        if !edits.iter().any(|e| matches!(*e, Edit::Keep(_))) {
+            return gene::Gene::cross(self, cfg, other, rng);
+        }
+        let mut result = Vec::new();
+        // Which parent the current region comes from. Each run of differing edits is one region.
+        let mut from_self = None;
+        for e in edits {
+            if let Edit::Keep(p) = e {
+                result.push(p);
+                from_self = None;
+                continue;
+            }
+            let mine = *from_self.get_or_insert_with(|| rng.gen());
+            match (e, mine) {
+                (Edit::Remove(p), true) | (Edit::Insert(p), false) => result.push(p),
+                (Edit::Replace(p, _), true) | (Edit::Replace(_, p), false) => result.push(p),
+                _ => {}
+            }
+        }
+        finish_cross(result, cfg, rng)
+    }
+
+    // Cross with another program at cut points where the two are in the most similar states: the
+    // stacks after this program's prefix and before the other's suffix, on the probe inputs, are
+    // as close as they can be. The suffix then picks up from a state like the one it was evolved
+    // for, so it's more likely to mean the same thing after the new prefix. Ties are broken at
+    // random.
+    pub fn cross_homologous<R: Rng>(&self, cfg: &ProgramConfig, other: &Self, rng: &mut R) -> Self {
+        if self.0.is_empty() || other.0.is_empty() {
+            return gene::Gene::cross(self, cfg, other, rng);
+        }
+        let (mine, theirs) = (self.prefix_states(), other.prefix_states());
+        let mut best = usize::MAX;
+        let mut cuts = Vec::new();
+        // Like the usual crossover, keep a prefix of this program and a non-empty suffix of the other
+        for (i, a) in mine.iter().enumerate().take(self.0.len()) {
+            for (j, b) in theirs.iter().enumerate().take(other.0.len()) {
+                let d = a.iter().zip(b).map(|(x, y)| stack_distance(x, y)).sum();
+                if d < best {
+                    best = d;
+                    cuts.clear();
+                }
+                if d == best {
+                    cuts.push((i, j));
+                }
+            }
+        }
+        let (i, j) = *rng.choose(&cuts).unwrap();
+        finish_cross(self.0[.. i].iter().chain(&other.0[j ..]).cloned().collect(), cfg, rng)
+    }
+
+    // The stack on each probe input after every prefix of the program, from the empty prefix to
+    // the whole program, as it runs in `run`
+    fn prefix_states(&self) -> Vec<Vec<Vec<i32>>> {
+        let mut states = vec![Vec::with_capacity(behavior::PROBES.len()); self.0.len() + 1];
+        for &(a, b) in behavior::PROBES.iter() {
+            let mut s = lang::Stack::new();
+            s.push(a);
+            s.push(b);
+            s.queue_program(&self.0);
+            s.set_fuel(Some(MAX_STEPS));
+            for state in &mut states {
+                state.push(s.data().to_vec());
+                s.run_next();
+            }
+        }
+        states
+    }
+}
+
+// How different two stacks are: the number of places they differ, lined up from the top, plus the
+// difference in their depths
+fn stack_distance(a: &[i32], b: &[i32]) -> usize {
+    let differing = a.iter().rev().zip(b.iter().rev()).filter(|&(x, y)| x != y).count();
+    differing + (a.len() as isize - b.len() as isize).unsigned_abs()
+}
+
+// A stack program that crosses by alignment instead of at random cut points. See
+// `ProgramGene::cross_aligned`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AlignedGene(pub ProgramGene);
+
+impl gene::Gene for AlignedGene {
+    type Config = ProgramConfig;
+
+    fn generate<R: Rng>(cfg: &ProgramConfig, rng: &mut R) -> Self {
+        AlignedGene(ProgramGene::generate(cfg, rng))
+    }
+
+    fn mutate<R: Rng>(&self, cfg: &ProgramConfig, rng: &mut R) -> Self {
+        AlignedGene(self.0.mutate(cfg, rng))
+    }
+
+    fn cross<R: Rng>(&self, cfg: &ProgramConfig, other: &Self, rng: &mut R) -> Self {
+        AlignedGene(self.0.cross_aligned(cfg, &other.0, rng))
+    }
+
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+}
+
+impl fmt::Display for AlignedGene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// A stack program that crosses where the parents' stacks are most alike. See
+// `ProgramGene::cross_homologous`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HomologousGene(pub ProgramGene);
+
+impl gene::Gene for HomologousGene {
+    type Config = ProgramConfig;
+
+    fn generate<R: Rng>(cfg: &ProgramConfig, rng: &mut R) -> Self {
+        HomologousGene(ProgramGene::generate(cfg, rng))
+    }
+
+    fn mutate<R: Rng>(&self, cfg: &ProgramConfig, rng: &mut R) -> Self {
+        HomologousGene(self.0.mutate(cfg, rng))
+    }
+
+    fn cross<R: Rng>(&self, cfg: &ProgramConfig, other: &Self, rng: &mut R) -> Self {
+        HomologousGene(self.0.cross_homologous(cfg, &other.0, rng))
+    }
+
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+}
+
+impl fmt::Display for HomologousGene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// The least a strategy's mutation rate and edit weights can evolve to, so no edit is lost for good
+const MIN_RATE: f32 = 0.01;
+const MIN_WEIGHT: f32 = 0.05;
+// How far each mutation moves a strategy: the learning rate of log-normal self-adaptation
+const STRATEGY_STEP: f32 = 0.3;
+
+// The variation settings a self-adaptive program carries with it. See `AdaptiveGene`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Strategy {
+    // The fraction of the program's progs a mutation edits, from MIN_RATE to 1. A mutation always
+    // makes at least one edit.
+    pub rate: f32,
+    // How likely each edit is to add, delete, or replace a prog. They add up to 1.
+    pub weights: [f32; 3],
+}
+
+impl Default for Strategy {
+    fn default() -> Strategy {
+        Strategy { rate: 0.2, weights: [1.0 / 3.0; 3] }
+    }
+}
+
+impl Strategy {
+    // Mutate the strategy as evolution strategies do, scaling each setting by a log-normal factor,
+    // so it's as likely to halve as to double
+    pub fn mutate<R: Rng>(&self, rng: &mut R) -> Strategy {
+        let mut factor = || (STRATEGY_STEP * rng.gen::<StandardNormal>().0 as f32).exp();
+        let rate = (self.rate * factor()).clamp(MIN_RATE, 1.0);
+        let mut weights = self.weights;
+        for w in &mut weights {
+            *w *= factor();
+        }
+        Strategy { rate, weights }.normalized()
+    }
+
+    // The average of two strategies, as in the intermediate recombination of evolution strategies
+    pub fn blend(&self, other: &Strategy) -> Strategy {
+        let mut weights = self.weights;
+        for (w, o) in weights.iter_mut().zip(&other.weights) {
+            *w = (*w + o) / 2.0;
+        }
+        Strategy { rate: (self.rate + other.rate) / 2.0, weights }.normalized()
+    }
+
+    // Scale the weights to add up to 1, keeping each near MIN_WEIGHT at least
+    fn normalized(mut self) -> Strategy {
+        for _ in 0 .. 2 {
+            let total: f32 = self.weights.iter().sum();
+            for w in &mut self.weights {
+                *w = (*w / total).max(MIN_WEIGHT);
+            }
+        }
+        let total: f32 = self.weights.iter().sum();
+        for w in &mut self.weights {
+            *w /= total;
+        }
+        self
+    }
+
+    // Pick an edit according to the weights
+    fn choose<R: Rng>(&self, rng: &mut R) -> EditKind {
+        let f = rng.gen_range(0.0, 1.0);
+        if f < self.weights[0] {
+            EditKind::Add
+        } else if f < self.weights[0] + self.weights[1] {
+            EditKind::Delete
+        } else {
+            EditKind::Replace
+        }
+    }
+}
+
+// The settings are never NaN
+impl Eq for Strategy {}
+
+impl Hash for Strategy {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rate.to_bits().hash(state);
+        for w in &self.weights {
+            w.to_bits().hash(state);
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rate {:.3}, add {:.3}, delete {:.3}, replace {:.3}",
+               self.rate, self.weights[0], self.weights[1], self.weights[2])
+    }
+}
+
+// A stack program that carries its own mutation settings, inherited and mutated along with it, so
+// each lineage tunes how it varies: self-adaptation, as in evolution strategies. A mutation first
+// mutates the strategy and then edits the program with the new one, so strategies that make fit
+// children spread with them. Crossover cuts the programs as usual and averages the strategies.
+//
+// It displays as just the program, so checkpoints only keep the program, and genes loaded from
+// them start from the default strategy.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AdaptiveGene {
+    pub program: ProgramGene,
+    pub strategy: Strategy,
+}
+
+impl AdaptiveGene {
+    pub fn new(program: ProgramGene) -> AdaptiveGene {
+        AdaptiveGene { program, strategy: Strategy::default() }
+    }
+}
+
+impl gene::Gene for AdaptiveGene {
+    type Config = ProgramConfig;
+
+    // Start from a range of rates, for selection to choose between
+    fn generate<R: Rng>(cfg: &ProgramConfig, rng: &mut R) -> Self {
+        let program = ProgramGene::generate(cfg, rng);
+        let strategy = Strategy { rate: rng.gen_range(MIN_RATE, 0.5), ..Strategy::default() };
+        AdaptiveGene { program, strategy }
+    }
+
+    fn mutate<R: Rng>(&self, cfg: &ProgramConfig, rng: &mut R) -> Self {
+        let strategy = self.strategy.mutate(rng);
+        let len = self.program.0.len();
+        if cfg.structural > 0.0 && len > 1 && rng.gen::<f32>() < cfg.structural {
+            let program = if rng.gen() { self.program.reverse_run(rng) } else { self.program.transpose_run(rng) };
+            return AdaptiveGene { program, strategy };
+        }
+        let edits = ((strategy.rate * len as f32).round() as usize).max(1);
+        let mut result = self.program.0.clone();
+        for _ in 0 .. edits {
+            let kind = strategy.choose(rng);
+            edit_prog(&mut result, kind, cfg, None, rng);
+        }
+        // Only possible if this program was already empty
+        if result.is_empty() {
+            result.push(rand_prog(cfg, rng));
+        }
+        AdaptiveGene { program: ProgramGene(result), strategy }
+    }
+
+    fn cross<R: Rng>(&self, cfg: &ProgramConfig, other: &Self, rng: &mut R) -> Self {
+        AdaptiveGene {
+            program: self.program.cross(cfg, &other.program, rng),
+            strategy: self.strategy.blend(&other.strategy),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.program.size()
+    }
+}
+
+impl fmt::Display for AdaptiveGene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.program.fmt(f)
+    }
+}
+
+impl ProgramGene {
+    // Encode the program with stable opcodes. See `lang::encode`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        lang::encode(&self.0)
+    }
+
+    // Decode a program encoded by `to_bytes`, in this or an earlier version
+    pub fn from_bytes(bytes: &[u8]) -> Result<ProgramGene, String> {
+        lang::decode(bytes).map(ProgramGene)
+    }
+}
+
+// Parse a program in the form it is displayed. See `lang::parse`.
+impl FromStr for ProgramGene {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        lang::parse(s).map(ProgramGene)
+    }
+}
+
+// A candidate program of any representation that can be run on the inputs (a, b)
+pub trait Runnable {
+    // Compute the program's output for the given inputs
+    fn run(&self, a: i32, b: i32) -> i32;
+
+    // Compute `count` outputs for the given inputs. Representations with a single output give it
+    // first, followed by zeros.
+    fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        let mut outputs = vec![self.run(a, b)];
+        outputs.resize(count.max(1), 0);
+        outputs.truncate(count);
+        outputs
+    }
+
+    // Compute `count` outputs with the given policy for popping an empty stack, or None if the
+    // policy made the run an error. Representations without a stack ignore the policy.
+    fn run_checked(&self, a: i32, b: i32, count: usize, _underflow: lang::Underflow) -> Option<Vec<i32>> {
+        Some(self.run_outputs(a, b, count))
+    }
+
+    // Like `run_checked`, but stack programs run for at most `steps` steps instead of `MAX_STEPS`.
+    // Other representations ignore the budget.
+    fn run_limited(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow, _steps: usize)
+        -> Option<Vec<i32>>
+        {
+        self.run_checked(a, b, count, underflow)
+    }
+}
+
+impl ProgramGene {
+    // Run the program on values of any type, e.g. i64s for results too large for an i32. See
+    // `Runnable::run`.
+    pub fn run_with<V: lang::Value>(&self, a: V, b: V) -> V {
+        self.run_outputs_with(a, b, 1)[0]
+    }
+
+    // Run the program on values of any type. See `Runnable::run_outputs`.
+    pub fn run_outputs_with<V: lang::Value>(&self, a: V, b: V, count: usize) -> Vec<V> {
+        // Zero never makes a run an error
+        self.run_checked_with(a, b, count, lang::Underflow::Zero).unwrap()
+    }
+
+    // Run the program on values of any type with the given underflow policy. See
+    // `Runnable::run_checked`.
+    pub fn run_checked_with<V: lang::Value>(&self, a: V, b: V, count: usize, underflow: lang::Underflow)
+        -> Option<Vec<V>>
+        {
+        self.run_limited_with(a, b, count, underflow, MAX_STEPS)
+    }
+
+    // Run the program on values of any type with the given underflow policy and step budget. See
+    // `Runnable::run_limited`.
+    pub fn run_limited_with<V: lang::Value>(&self, a: V, b: V, count: usize, underflow: lang::Underflow,
+                                            steps: usize)
+        -> Option<Vec<V>>
+        {
+        // Create a stack
+        let mut s = lang::Stack::empty();
+        s.set_underflow(underflow);
+        // Add the inputs
+        s.push(a);
+        s.push(b);
+        // Run the program
+        s.queue_program(&self.0);
+        s.set_fuel(Some(steps));
+        s.run_all();
+        // Popping too few outputs is an error too
+        let outputs = (0 .. count).map(|_| s.pop()).collect();
+        if s.underflowed() { None } else { Some(outputs) }
+    }
+}
+
+impl Runnable for ProgramGene {
+    fn run(&self, a: i32, b: i32) -> i32 {
+        self.run_with(a, b)
+    }
+
+    // The outputs are popped off the stack, so the top of the stack is the first output
+    fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        self.run_outputs_with(a, b, count)
+    }
+
+    fn run_checked(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow) -> Option<Vec<i32>> {
+        self.run_checked_with(a, b, count, underflow)
+    }
+
+    fn run_limited(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow, steps: usize)
+        -> Option<Vec<i32>>
+        {
+        self.run_limited_with(a, b, count, underflow, steps)
+    }
+}
+
+impl GeneSize for ProgramGene {
+    fn heap_bytes(&self) -> usize {
+        self.0.capacity() * ::std::mem::size_of::<lang::Prog>()
+    }
+}
+
+impl GeneSize for AlignedGene {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
+}
+
+impl Runnable for AlignedGene {
+    fn run(&self, a: i32, b: i32) -> i32 {
+        self.0.run(a, b)
+    }
+
+    fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        self.0.run_outputs(a, b, count)
+    }
+
+    fn run_checked(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow) -> Option<Vec<i32>> {
+        self.0.run_checked(a, b, count, underflow)
+    }
+
+    fn run_limited(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow, steps: usize)
+        -> Option<Vec<i32>>
+        {
+        self.0.run_limited(a, b, count, underflow, steps)
+    }
+}
+
+impl GeneSize for AdaptiveGene {
+    fn heap_bytes(&self) -> usize {
+        self.program.heap_bytes()
+    }
+}
+
+impl Runnable for AdaptiveGene {
+    fn run(&self, a: i32, b: i32) -> i32 {
+        self.program.run(a, b)
+    }
+
+    fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        self.program.run_outputs(a, b, count)
+    }
+
+    fn run_checked(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow) -> Option<Vec<i32>> {
+        self.program.run_checked(a, b, count, underflow)
+    }
+
+    fn run_limited(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow, steps: usize)
+        -> Option<Vec<i32>>
+        {
+        self.program.run_limited(a, b, count, underflow, steps)
+    }
+}
+
+impl GeneSize for HomologousGene {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes()
+    }
+}
+
+impl Runnable for HomologousGene {
+    fn run(&self, a: i32, b: i32) -> i32 {
+        self.0.run(a, b)
+    }
+
+    fn run_outputs(&self, a: i32, b: i32, count: usize) -> Vec<i32> {
+        self.0.run_outputs(a, b, count)
+    }
+
+    fn run_checked(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow) -> Option<Vec<i32>> {
+        self.0.run_checked(a, b, count, underflow)
+    }
+
+    fn run_limited(&self, a: i32, b: i32, count: usize, underflow: lang::Underflow, steps: usize)
+        -> Option<Vec<i32>>
+        {
+        self.0.run_limited(a, b, count, underflow, steps)
+    }
+}
+
 // Use to create a fitness function that runs the program and compares output to the given reference
 // function. Also gives a slight bonus to shorter programs.
 pub fn fitness<F: Fn(i32, i32) -> i32>(f: F, g: &ProgramGene) -> f32 {
+    score(f, g)
+}
+
+// Score any runnable program against the reference function, using the gene's size for the
+// shortness bonus. See `score_cases`.
+pub fn score<F: Fn(i32, i32) -> i32, P: Runnable + gene::Gene>(f: F, p: &P) -> f32 {
+    score_cases(f, p.size(), |a, b| p.run(a, b))
+}
+
+// How much of the fitness comes from shortness rather than correctness, by default
+pub const PARSIMONY: f32 = 0.01;
+
+// Score any runnable representation against the reference function `f`. `run` computes the
+// candidate's output for inputs (a, b), and `len` is the candidate's size, used for the shortness
+// bonus. This is shared by all representations so their fitness values are comparable.
+pub fn score_cases<F, P>(f: F, len: usize, run: P) -> f32
+    where F: Fn(i32, i32) -> i32,
+          P: Fn(i32, i32) -> i32,
+    {
+    score_cases_with(f, len, run, PARSIMONY)
+}
+
+// Like `score_cases`, but with the given weight for shortness (between 0 and 1) instead of the
+// default `PARSIMONY`. The inputs and outputs can be any `Value`, e.g. i64 for targets that grow too
+// quickly for i32.
+pub fn score_cases_with<V, F, P>(f: F, len: usize, run: P, parsimony: f32) -> f32
+    where V: lang::Value,
+          F: Fn(V, V) -> V,
+          P: Fn(V, V) -> V,
+    {
+    score_cases_checked(f, len, |a, b| Some(run(a, b)), parsimony)
+}
+
+// Like `score_cases_with`, but `run` gives None for a run that's an error, e.g. under
+// `lang::Underflow::Error`. Those cases count as wrong.
+pub fn score_cases_checked<V, F, P>(f: F, len: usize, run: P, parsimony: f32) -> f32
+    where V: lang::Value,
+          F: Fn(V, V) -> V,
+          P: Fn(V, V) -> Option<V>,
+    {
+    // An empty program does nothing, so it only matches the reference by luck
+    if len == 0 {
+        return 0.0;
+    }
     let mut total = 0;
     let mut successful = 0;
     // Iterate through the test cases
     for a in 0 .. 10 {
         for b in 0 .. 10 {
-            // Create a stack
-            let mut s = lang::Stack::new();
-            // Add the inputs
-            s.push(a);
-            s.push(b);
-            // Run the program
-            s.queue_program(&g.0);
-            s.run_until(10);
+            let (a, b) = (V::from_constant(a), V::from_constant(b));
             // Compare the output
-            let result = s.pop();
-            if result == f(a, b) {
+            if run(a, b) == Some(f(a, b)) {
                 successful += 1;
             }
             total += 1;
@@ -142,8 +1098,8 @@ pub fn fitness<F: Fn(i32, i32) -> i32>(f: F, g: &ProgramGene) -> f32 {
     }
     // Fitness is successful / total test cases
     let correctness = successful as f32 / total as f32;
-    let shortness = 1.0 - (g.0.len() as f32 / 100.0);
-    0.99 * correctness + 0.01 * shortness
+    let shortness = 1.0 - (len as f32 / 100.0);
+    (1.0 - parsimony) * correctness + parsimony * shortness
 }
 
 #[cfg(test)]
@@ -151,19 +1107,102 @@ mod tests {
     use super::*;
     use gene::Gene;
     use ::rand::SeedableRng;
+    use suite::TestSuite;
 
     #[test]
     fn generate_and_mutate() {
         let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig::default();
         // Generate some random genes
         let mut genes: Vec<ProgramGene> = Vec::new();
         for _ in 0 .. 1000 {
-            genes.push(gene::Gene::generate(rng));
+            genes.push(gene::Gene::generate(cfg, rng));
         }
 
         // Mutate them
         for g in genes {
-            g.mutate(rng).mutate(rng).mutate(rng);
+            g.mutate(cfg, rng).mutate(cfg, rng).mutate(cfg, rng);
+        }
+
+        // Generation stays within the configured length
+        let short = &ProgramConfig { max_len: 3, ..ProgramConfig::default() };
+        assert!((0 .. 100).all(|_| ProgramGene::generate(short, rng).0.len() <= 3));
+    }
+
+    #[test]
+    fn never_empty() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig::default();
+        let empty = ProgramGene(vec![]);
+        let single = ProgramGene(vec![lang::Prog::D(1)]);
+        for _ in 0 .. 1000 {
+            // Mutating a single prog never deletes it, and mutating an empty program adds one
+            assert!(!single.mutate(cfg, rng).0.is_empty());
+            assert!(!empty.mutate(cfg, rng).0.is_empty());
+            // Crossing with an empty program still produces a prog
+            assert!(!single.cross(cfg, &empty, rng).0.is_empty());
+            assert!(!empty.cross(cfg, &empty, rng).0.is_empty());
+            assert!(!ProgramGene::generate(cfg, rng).0.is_empty());
+        }
+
+        // An empty program gets no fitness, even when 0 is sometimes the right answer
+        assert_eq!(fitness(|a, _| a, &empty), 0.0);
+    }
+
+    #[test]
+    fn localized_mutation() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        // Absolute value of a - b, except that the negation is wrong
+        let buggy: ProgramGene = "- dup 0 < 2 if -2 *".parse().unwrap();
+        let cfg = &ProgramConfig { conditionals: true, ..ProgramConfig::default() };
+        let localized = &ProgramConfig { localize: localize::target_cases(|a, b| (a - b).abs()), ..cfg.clone() };
+        // Replacements land on the negation, a quarter of the program, far more often when localized
+        let negation_edits = |cfg: &ProgramConfig, rng: &mut ::rand::StdRng| (0 .. 400).filter(|_| {
+            let mut result = buggy.0.clone();
+            let mut sites = if cfg.localize.is_empty() { None } else { Some(localized_sites(&buggy.0, cfg)) };
+            edit_prog(&mut result, EditKind::Replace, cfg, sites.as_mut(), rng);
+            result[.. 6] == buggy.0[.. 6] && result != buggy.0
+        }).count();
+        assert!(negation_edits(localized, rng) > 2 * negation_edits(cfg, rng));
+
+        // The weights stay lined up with the program through every kind of edit
+        for &kind in &[EditKind::Add, EditKind::Delete, EditKind::Replace, EditKind::Duplicate] {
+            let mut result = buggy.0.clone();
+            let mut sites = vec![1.0; result.len()];
+            for _ in 0 .. 20 {
+                edit_prog(&mut result, kind, cfg, Some(&mut sites), rng);
+                assert_eq!(sites.len(), result.len());
+            }
+        }
+        assert!(buggy.mutate(localized, rng) != buggy);
+    }
+
+    #[test]
+    fn structural_mutation() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let gene: ProgramGene = "1 2 3 4 5 6".parse().unwrap();
+        let sorted = |g: ProgramGene| {
+            let mut progs: Vec<String> = g.0.iter().map(|p| p.to_string()).collect();
+            progs.sort();
+            progs.join(" ")
+        };
+        for _ in 0 .. 100 {
+            // Both keep every prog, only moved around
+            let reversed = gene.reverse_run(rng);
+            let transposed = gene.transpose_run(rng);
+            assert_ne!(reversed, gene);
+            assert_ne!(transposed, gene);
+            assert_eq!(sorted(reversed), "1 2 3 4 5 6");
+            assert_eq!(sorted(transposed), "1 2 3 4 5 6");
+        }
+        let single = ProgramGene(vec![lang::Prog::D(1)]);
+        assert_eq!(single.reverse_run(rng), single);
+        assert_eq!(single.transpose_run(rng), single);
+
+        // With every mutation structural, mutants are always rearrangements
+        let cfg = &ProgramConfig { structural: 1.0, ..ProgramConfig::default() };
+        for _ in 0 .. 100 {
+            assert_eq!(sorted(gene.mutate(cfg, rng)), "1 2 3 4 5 6");
         }
     }
 
@@ -181,6 +1220,12 @@ mod tests {
         // Test program that always returns -1
         let bad_prog = ProgramGene(vec![lang::Prog::D(-1)]);
         assert!((fitness(|a,b| a + b, &bad_prog) - 0.0099).abs() < eps);
+
+        // a to the 16th overflows an i32 for most inputs, but not an i64
+        let pow16: ProgramGene = "swap dup * dup * dup * dup *".parse().unwrap();
+        assert_eq!(pow16.run_with(9i64, 0), 9i64.pow(16));
+        assert!(pow16.run(9, 0) as i64 != 9i64.pow(16));
+        assert_eq!(score_cases_with(|a: i64, _| a.pow(16), pow16.size(), |a, b| pow16.run_with(a, b), 0.0), 1.0);
     }
 
     #[test]
@@ -191,5 +1236,266 @@ mod tests {
         // Display a concise representation of a program gene
         let prog = ProgramGene(vec![D(1), C(Sub), D(-30), C(Dup)]);
         assert_eq!(format!("{}", prog), "1 - -30 dup");
+
+        assert_eq!(prog.annotated().to_string(), "0:1 1:- 2:-30 3:dup");
+
+        // and parse it back
+        assert_eq!("1 - -30 dup".parse(), Ok(prog));
+        assert!("1 sqrt".parse::<ProgramGene>().is_err());
+    }
+
+    #[test]
+    fn diff_programs() {
+        let parse = |s: &str| s.parse::<ProgramGene>().unwrap();
+        assert_eq!(diff(&parse("1 - dup"), &parse("1 - dup")), "1 - dup");
+        assert_eq!(diff(&parse("1 - dup"), &parse("1 dup")), "1 [---] dup");
+        assert_eq!(diff(&parse("1 dup"), &parse("1 + dup")), "1 {+++} dup");
+        assert_eq!(diff(&parse("1 - dup"), &parse("1 * dup")), "1 [-->*] dup");
+        assert_eq!(diff(&parse("1 - dup"), &parse("2 - dup swap")), "[1->2] - dup {+swap+}");
+        assert_eq!(diff(&parse(""), &parse("1")), "{+1+}");
+    }
+
+    #[test]
+    fn aligned_cross() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig::default();
+        let a: ProgramGene = "1 2 + 3 * 4".parse().unwrap();
+        let b: ProgramGene = "1 5 + 3 - 4".parse().unwrap();
+        for _ in 0 .. 100 {
+            // The shared progs stay in place, and the rest comes from one parent or the other
+            let child = a.cross_aligned(cfg, &b, rng).0;
+            assert_eq!(child.len(), 6);
+            assert_eq!((child[0], child[2], child[3], child[5]), (a.0[0], a.0[2], a.0[3], a.0[5]));
+            assert!(child[1] == a.0[1] || child[1] == b.0[1]);
+            assert!(child[4] == a.0[4] || child[4] == b.0[4]);
+        }
+        assert_eq!(a.cross_aligned(cfg, &a, rng), a);
+
+        // Children of two variants of a correct program are correct more often than with random
+        // cut points
+        let correct: ProgramGene = "dup * swap 2 * - 3 + 0 +".parse().unwrap();
+        let viable = |cross: &dyn Fn(&ProgramGene, &ProgramGene, &mut ::rand::StdRng) -> ProgramGene,
+                      rng: &mut ::rand::StdRng| {
+            (0 .. 500).filter(|_| {
+                let (x, y) = (correct.mutate(cfg, rng), correct.mutate(cfg, rng));
+                let child = cross(&x, &y, rng);
+                score_cases_with(|a, b| correct.run(a, b), 1, |a, b| child.run(a, b), 0.0) == 1.0
+            }).count()
+        };
+        let aligned = viable(&|x, y, rng| x.cross_aligned(cfg, y, rng), rng);
+        let cut = viable(&|x, y, rng| x.cross(cfg, y, rng), rng);
+        assert!(aligned > cut);
+        let homologous = viable(&|x, y, rng| x.cross_homologous(cfg, y, rng), rng);
+        assert!(homologous > cut, "{} {}", homologous, cut);
+    }
+
+    #[test]
+    fn homologous_cross() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig::default();
+        // Every prefix leaves a different stack, so a program only matches itself at the same cut
+        let a: ProgramGene = "1 2 + 3 * 4".parse().unwrap();
+        for _ in 0 .. 20 {
+            assert_eq!(a.cross_homologous(cfg, &a, rng), a);
+        }
+        // The stacks match at the start, after "1 2 +" and "3", and after "1 2 + 3" and "3 dup"
+        let b: ProgramGene = "3 dup *".parse().unwrap();
+        let children: Vec<String> = (0 .. 30).map(|_| a.cross_homologous(cfg, &b, rng).to_string()).collect();
+        assert!(children.iter().all(|c| ["3 dup *", "1 2 + dup *", "1 2 + 3 *"].contains(&c.as_str())));
+        assert!(children.iter().any(|c| c == "1 2 + dup *"));
+        assert_eq!(stack_distance(&[1, 2, 3], &[5, 3]), 2);
+        assert!(!a.cross_homologous(cfg, &ProgramGene(vec![]), rng).0.is_empty());
+    }
+
+    #[test]
+    fn mutate_frames_whole() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig { frames: true, ..ProgramConfig::default() };
+        let program: ProgramGene = "1 pushframe 2 pushframe dup popframe * popframe +".parse().unwrap();
+        // The number of frame commands, and whether they all pair up
+        let frames = |p: &[lang::Prog]| p.iter().filter(|&&q| is_frame_command(q)).count();
+        let paired = |p: &[lang::Prog]| lang::frame_pairs(p).iter().zip(p).all(|(m, &q)| m.is_some() || !is_frame_command(q));
+
+        // Deleting either end of the inner frame deletes the frame, and the outer frame takes the
+        // inner one with it
+        let mut inner = program.0.clone();
+        assert_eq!(unit_at(&inner, 5), (3, 6));
+        inner.drain(3 .. 6);
+        assert_eq!(ProgramGene(inner).to_string(), "1 pushframe 2 * popframe +");
+        assert_eq!(unit_at(&program.0, 1), (1, 8));
+        assert_eq!(unit_at(&program.0, 2), (2, 3));
+
+        for &kind in &[EditKind::Add, EditKind::Delete, EditKind::Replace, EditKind::Duplicate] {
+            let mut grew = false;
+            for _ in 0 .. 200 {
+                let mut result = program.0.clone();
+                edit_prog(&mut result, kind, cfg, None, rng);
+                assert!(paired(&result), "{:?} broke a frame: {}", kind, ProgramGene(result.clone()));
+                assert_eq!(frames(&result) % 2, 0);
+                grew |= frames(&result) > frames(&program.0);
+                if kind == EditKind::Delete {
+                    assert!(frames(&result) > 0 || ProgramGene(result.clone()).to_string() == "1 +");
+                }
+            }
+            // New and copied frames come whole
+            if kind == EditKind::Add || kind == EditKind::Duplicate {
+                assert!(grew);
+            }
+        }
+
+        // Whole mutations keep them too
+        for _ in 0 .. 200 {
+            let child = program.mutate(cfg, rng);
+            assert!(paired(&child.0), "{}", child);
+        }
+        // Stray frame commands are edited like any other prog
+        let stray: ProgramGene = "popframe 1 pushframe".parse().unwrap();
+        assert_eq!(unit_at(&stray.0, 0), (0, 1));
+        assert_eq!(unit_at(&stray.0, 2), (2, 3));
+    }
+
+    #[test]
+    fn cross_frames_whole() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig { frames: true, ..ProgramConfig::default() };
+        let program: ProgramGene = "1 pushframe 2 pushframe dup popframe * popframe +".parse().unwrap();
+        let other: ProgramGene = "3 pushframe 4 + popframe dup".parse().unwrap();
+        let frames = |p: &[lang::Prog]| p.iter().filter(|&&q| is_frame_command(q)).count();
+        let paired = |p: &[lang::Prog]| lang::frame_pairs(p).iter().zip(p).all(|(m, &q)| m.is_some() || !is_frame_command(q));
+        assert_eq!(cut_points(&program.0), vec![0, 1, 8]);
+        assert_eq!(cut_points(&other.0), vec![0, 1, 5]);
+        // Stray frame commands don't hold a cut back
+        assert_eq!(cut_points(&"popframe 1 pushframe 2".parse::<ProgramGene>().unwrap().0), vec![0, 1, 2, 3]);
+
+        let mut kept = false;
+        for _ in 0 .. 200 {
+            for child in &[program.cross(cfg, &other, rng), other.cross(cfg, &program, rng)] {
+                assert!(paired(&child.0), "{}", child);
+                kept |= frames(&child.0) == 6;
+            }
+        }
+        // Frames cross over whole, rather than being dropped
+        assert!(kept);
+    }
+
+    #[test]
+    fn bred_nesting_limited() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig { max_len: 20, frames: true, loops: true, max_nesting: 2, ..ProgramConfig::default() };
+        let deepest = |p: &ProgramGene| lang::nesting_depths(&p.0).into_iter().max().unwrap_or(0);
+        let paired = |p: &ProgramGene| lang::frame_pairs(&p.0).iter().zip(&p.0).all(|(m, &q)| m.is_some() || !is_frame_command(q));
+        let deep: ProgramGene = "pushframe 1 times pushframe 2 times dup popframe popframe".parse().unwrap();
+        assert_eq!(deepest(&deep), 3);
+        for _ in 0 .. 200 {
+            let g = ProgramGene::generate(cfg, rng);
+            assert!(deepest(&g) <= 2, "{}", g);
+            let mutant = deep.mutate(cfg, rng);
+            assert!(deepest(&mutant) <= 2, "{}", mutant);
+            // Every crossover leaves its frames paired
+            for child in &[g.cross(cfg, &deep, rng), deep.cross_aligned(cfg, &g, rng), deep.cross_homologous(cfg, &g, rng)] {
+                assert!(deepest(child) <= 2, "{}", child);
+                assert!(paired(child), "{}", child);
+            }
+        }
+    }
+
+    #[test]
+    fn adapt_strategies() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &ProgramConfig::default();
+        let start = Strategy::default();
+        for _ in 0 .. 200 {
+            let s = start.mutate(rng);
+            assert!(s.rate >= MIN_RATE && s.rate <= 1.0);
+            assert!((s.weights.iter().sum::<f32>() - 1.0).abs() < 0.0001);
+            assert!(s.weights.iter().all(|&w| w > MIN_WEIGHT / 2.0));
+        }
+        let a = Strategy { rate: 0.1, weights: [0.6, 0.2, 0.2] };
+        let b = Strategy { rate: 0.3, weights: [0.2, 0.2, 0.6] };
+        let both = a.blend(&b);
+        assert!((both.rate - 0.2).abs() < 0.0001);
+        assert!((both.weights[0] - 0.4).abs() < 0.0001);
+
+        // Children inherit their parent's strategy, changed a little
+        let parent = AdaptiveGene { program: "1 2 + 3 * 4 5 - 6 7".parse().unwrap(), strategy: a };
+        let child = parent.mutate(cfg, rng);
+        assert_ne!(child.strategy, a);
+        assert!(child.strategy.rate < 0.5);
+        // A strategy that only adds makes programs grow
+        let grower = AdaptiveGene { strategy: Strategy { rate: 0.5, weights: [1.0, 0.0, 0.0] }.normalized(), ..parent.clone() };
+        let longer = (0 .. 50).filter(|_| grower.mutate(cfg, rng).size() > parent.size()).count();
+        assert!(longer > 40, "{}", longer);
+        assert_eq!(parent.cross(cfg, &grower, rng).strategy, a.blend(&grower.strategy));
+
+        // Selection tunes the strategies while the programs evolve
+        let fitness = |g: &AdaptiveGene| fitness(|a, b| 3 + a - b * b, &g.program);
+        let mut pool = gene::Pool::new(100, fitness, rng);
+        let initial = pool.get_best().1;
+        for _ in 0 .. 50 {
+            pool.evolve(rng);
+        }
+        assert!(pool.get_best().1 > initial);
+        assert_eq!(pool.get_best().0.to_string(), pool.get_best().0.program.to_string());
+    }
+
+    #[test]
+    fn learn_weights() {
+        let base = ProgWeights::base();
+        assert!((base.chance(lang::Prog::D(3)) - 0.5 / 21.0).abs() < 0.00001);
+        assert!((base.chance(lang::Prog::C(lang::Command::Dup)) - 0.5 / 6.0).abs() < 0.00001);
+        assert_eq!(base.chance(lang::Prog::D(11)), 0.0);
+
+        // Progs common among the elite become more likely, more so at lower temperatures
+        let elite: Vec<ProgramGene> = vec!["1 +".parse().unwrap(), "1 1 + dup".parse().unwrap()];
+        let one = lang::Prog::D(1);
+        let follow = ProgWeights::learn(&elite, 1.0);
+        let sharp = ProgWeights::learn(&elite, 0.5);
+        assert!(follow.chance(one) > base.chance(one));
+        assert!(sharp.chance(one) > follow.chance(one));
+        assert!(follow.chance(lang::Prog::D(7)) < base.chance(lang::Prog::D(7)));
+        assert!(follow.chance(lang::Prog::D(7)) > 0.0);
+        let warm = ProgWeights::learn(&elite, 1000.0);
+        assert!((warm.chance(one) - base.chance(one)).abs() < 0.001);
+
+        // Weights in the config show up in generated programs
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let biased = ProgramConfig { weights: Some(ProgWeights::learn(&elite, 0.2)), ..ProgramConfig::default() };
+        let ones = (0 .. 100).map(|_| rand_prog(&biased, rng)).filter(|&p| p == one).count();
+        assert!(ones > 50);
+        let ones = (0 .. 100).map(|_| rand_prog(&ProgramConfig::default(), rng)).filter(|&p| p == one).count();
+        assert!(ones < 10);
+
+        // The frame commands only come up when the config allows them
+        let is_frame = |p: &lang::Prog| matches!(*p, lang::Prog::C(lang::Command::PushFrame) | lang::Prog::C(lang::Command::PopFrame));
+        assert!(!(0 .. 200).any(|_| is_frame(&rand_prog(&ProgramConfig::default(), rng))));
+        let framed = ProgramConfig { frames: true, ..ProgramConfig::default() };
+        assert!((0 .. 200).any(|_| is_frame(&rand_prog(&framed, rng))));
+        // And so do the conditionals
+        let is_if = |p: &lang::Prog| *p == lang::Prog::C(lang::Command::If);
+        assert!(!(0 .. 200).any(|_| is_if(&rand_prog(&framed, rng))));
+        let branching = ProgramConfig { conditionals: true, ..framed };
+        assert!((0 .. 200).any(|_| is_if(&rand_prog(&branching, rng))));
+        assert!((0 .. 200).any(|_| is_frame(&rand_prog(&branching, rng))));
+        // And so do loops
+        let is_times = |p: &lang::Prog| *p == lang::Prog::C(lang::Command::Times);
+        assert!(!(0 .. 200).any(|_| is_times(&rand_prog(&branching, rng))));
+        let looping = ProgramConfig { loops: true, ..ProgramConfig::default() };
+        assert!((0 .. 200).any(|_| is_times(&rand_prog(&looping, rng))));
+    }
+
+    #[test]
+    fn step_budget() {
+        // Adding 1 to b five times takes 13 steps, more than programs without loops get
+        let program: ProgramGene = "5 2 times 1 +".parse().unwrap();
+        let steps = ProgramConfig::default_steps(true);
+        assert_eq!(steps, LOOP_STEPS);
+        let zero = lang::Underflow::Zero;
+        assert_eq!(program.run_limited(0, 10, 1, zero, steps), Some(vec![15]));
+        assert_eq!(program.run_limited(0, 10, 1, zero, MAX_STEPS), program.run_checked(0, 10, 1, zero));
+        assert_ne!(program.run(0, 10), 15);
+        // Suites score with the budget they're given
+        let suite = TestSuite::builder(|_, b| vec![b + 5]).grid(0 .. 3, 0 .. 3).build();
+        assert!(!suite.solves(&program));
+        assert!(suite.with_steps(steps).solves(&program));
     }
 }
@@ -0,0 +1,129 @@
+//
+// Stream every evaluated gene of a run into a SQLite database, for analysing large experiments
+// with SQL afterwards. Each gene is stored with its program text, fitness, generation, and the
+// operator and parents that produced it, which come from the pool's journal.
+//
+// Genes and their parents are identified by `journal::hash_gene`, so the parents of a gene can be
+// found by joining on the hash. Like the journal's hashes, these are only stable within one build.
+//
+
+use super::gene::{Fitness, Gene, Pool};
+use super::journal::hash_gene;
+
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS genes (
+        id INTEGER PRIMARY KEY,
+        generation INTEGER NOT NULL,
+        hash INTEGER NOT NULL,
+        program TEXT NOT NULL,
+        fitness REAL,
+        operator TEXT NOT NULL,
+        parent1 INTEGER,
+        parent2 INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS genes_hash ON genes (hash);
+    CREATE INDEX IF NOT EXISTS genes_generation ON genes (generation);
+";
+
+// Records the genes a pool evaluates, a generation at a time
+pub struct Recorder {
+    db: Connection,
+    // The number of journal entries already recorded
+    recorded: usize,
+}
+
+impl Recorder {
+    // Record into the database at the given path, creating it and its table if needed. Rows from
+    // earlier runs in the same database are kept.
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Recorder> {
+        Recorder::new(Connection::open(path)?)
+    }
+
+    // Record into an existing connection, e.g. an in-memory database
+    pub fn new(db: Connection) -> rusqlite::Result<Recorder> {
+        db.execute_batch(SCHEMA)?;
+        Ok(Recorder { db, recorded: 0 })
+    }
+
+    // The database being recorded into, e.g. to query it
+    pub fn db(&self) -> &Connection {
+        &self.db
+    }
+
+    // Record every gene the pool has made since the last call, as part of the given generation.
+    // Call this once after creating the pool and again after each generation. Returns the number
+    // of genes recorded. Genes that aren't in the pool any more, e.g. from a cancelled generation,
+    // are skipped, as are genes the journal didn't see made, like those of `Pool::from_genes`.
+    pub fn record_generation<T, F>(&mut self, generation: usize, pool: &Pool<T, F>) -> rusqlite::Result<usize>
+        where T: Gene + Hash + Eq + Clone + Display,
+              F: Fitness<T>,
+        {
+        let genes: HashMap<u64, (&T, f32)> = pool.iter().map(|g| (hash_gene(g.0), (g.0, g.1))).collect();
+        let entries = &pool.journal.entries[self.recorded.min(pool.journal.entries.len()) ..];
+        let tx = self.db.transaction()?;
+        let mut count = 0;
+        {
+            let mut insert = tx.prepare_cached("INSERT INTO genes
+                (generation, hash, program, fitness, operator, parent1, parent2)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            for entry in entries {
+                let (gene, fit) = match genes.get(&entry.child) {
+                    Some(&g) => g,
+                    None => continue,
+                };
+                let parent = |i: usize| entry.parents.get(i).map(|&h| h as i64);
+                insert.execute(params![
+                    generation as i64, entry.child as i64, gene.to_string(), fit as f64,
+                    entry.op.name(), parent(0), parent(1),
+                ])?;
+                count += 1;
+            }
+        }
+        tx.commit()?;
+        self.recorded = pool.journal.entries.len();
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn record_run() {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness = |g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g);
+        let mut pool = Pool::new(20, fitness, rng);
+        let mut recorder = Recorder::new(Connection::open_in_memory().unwrap()).unwrap();
+        assert_eq!(recorder.record_generation(0, &pool).unwrap(), 20);
+        for generation in 1 ..= 3 {
+            pool.evolve(rng);
+            // Every gene but the five selected is new
+            assert_eq!(recorder.record_generation(generation, &pool).unwrap(), 15);
+        }
+
+        let db = recorder.db();
+        let count = |sql: &str| db.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM genes"), 20 + 3 * 15);
+        assert_eq!(count("SELECT COUNT(*) FROM genes WHERE generation = 0 AND operator != 'generate'"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM genes WHERE operator = 'cross' AND parent2 IS NULL"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM genes WHERE operator = 'mutate' AND parent2 IS NOT NULL"), 0);
+        // Every parent was recorded before its child
+        assert_eq!(count("SELECT COUNT(*) FROM genes c WHERE c.parent1 IS NOT NULL AND NOT EXISTS
+            (SELECT 1 FROM genes p WHERE p.hash = c.parent1 AND p.generation < c.generation)"), 0);
+
+        // The best gene is there with its fitness
+        let (best, fit) = pool.get_best();
+        let stored: f64 = db.query_row("SELECT fitness FROM genes WHERE program = ?1", [best.to_string()],
+                                       |row| row.get(0)).unwrap();
+        assert_eq!(stored as f32, fit);
+    }
+}
@@ -0,0 +1,208 @@
+//
+// Register machine programs as genes, an alternative to the stack-based program representation
+//
+
+use super::lang::Command;
+use super::gene;
+use super::prog_gene;
+use super::memory::GeneSize;
+
+use std::fmt;
+use rand::Rng;
+
+// The number of registers. The inputs a and b start in r0 and r1, the rest start at 0, and the
+// result is read from r0.
+pub const NUM_REGS: usize = 4;
+
+// The parameters of register programs as genes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterConfig {
+    // The longest program to generate. Mutation and crossover can still make longer ones.
+    pub max_len: usize,
+}
+
+impl Default for RegisterConfig {
+    fn default() -> RegisterConfig {
+        RegisterConfig { max_len: 10 }
+    }
+}
+
+// The right-hand operand of an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Src {
+    Reg(usize),
+    Const(i32),
+}
+
+// A single instruction: `r[dst] = r[lhs] op rhs`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Instr {
+    pub op: Command,
+    pub dst: usize,
+    pub lhs: usize,
+    pub rhs: Src,
+}
+
+// Generate a random instruction
+fn rand_instr<R: Rng>(rng: &mut R) -> Instr {
+    let rhs = if rng.gen() {
+        Src::Reg(rng.gen_range(0, NUM_REGS))
+    } else {
+        Src::Const(rng.gen_range(-10, 11))
+    };
+    Instr {
+        op: *rng.choose(&Command::ARITHMETIC).unwrap(),
+        dst: rng.gen_range(0, NUM_REGS),
+        lhs: rng.gen_range(0, NUM_REGS),
+        rhs,
+    }
+}
+
+// A straight-line register machine program as a gene. Like `ProgramGene`, the genetic operators
+// never produce an empty program.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RegisterGene(pub Vec<Instr>);
+
+impl GeneSize for RegisterGene {
+    fn heap_bytes(&self) -> usize {
+        self.0.capacity() * ::std::mem::size_of::<Instr>()
+    }
+}
+
+impl prog_gene::Runnable for RegisterGene {
+    // Run the program on the given inputs and return the result
+    fn run(&self, a: i32, b: i32) -> i32 {
+        let mut regs = [0; NUM_REGS];
+        regs[0] = a;
+        regs[1] = b;
+        for instr in &self.0 {
+            let rhs = match instr.rhs {
+                Src::Reg(r) => regs[r],
+                Src::Const(c) => c,
+            };
+            regs[instr.dst] = instr.op.apply(regs[instr.lhs], rhs);
+        }
+        regs[0]
+    }
+}
+
+impl gene::Gene for RegisterGene {
+    type Config = RegisterConfig;
+
+    fn generate<R: Rng>(cfg: &RegisterConfig, rng: &mut R) -> Self {
+        // Pick a length between 1 and the max
+        let len: usize = rng.gen_range(1, cfg.max_len.max(1) + 1);
+        RegisterGene((0 .. len).map(|_| rand_instr(rng)).collect())
+    }
+
+    fn mutate<R: Rng>(&self, _cfg: &RegisterConfig, rng: &mut R) -> Self {
+        // Add, delete, or replace a random instruction
+        let mut result = self.0.clone();
+        match rng.gen_range(0, 3) {
+            0 => {
+                let i = rng.gen_range(0, result.len() + 1);
+                result.insert(i, rand_instr(rng));
+            }
+            1 if result.len() > 1 => {
+                let i = rng.gen_range(0, result.len());
+                result.remove(i);
+            }
+            _ if !result.is_empty() => {
+                let i = rng.gen_range(0, result.len());
+                result[i] = rand_instr(rng);
+            }
+            _ => result.push(rand_instr(rng)),
+        }
+        RegisterGene(result)
+    }
+
+    fn cross<R: Rng>(&self, _cfg: &RegisterConfig, other: &Self, rng: &mut R) -> Self {
+        // Same one-point crossover as the stack programs
+        let stop_self = rng.gen_range(0, self.0.len().max(1));
+        let start_other = rng.gen_range(0, other.0.len().max(1));
+        let mut result: Vec<Instr> = self.0.iter().take(stop_self)
+            .chain(other.0.iter().skip(start_other))
+            .cloned()
+            .collect();
+        // Only possible if the other program was empty
+        if result.is_empty() {
+            result.push(rand_instr(rng));
+        }
+        RegisterGene(result)
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+// Display one instruction per statement, e.g. "r0 = r1 * 3; r2 = r0 - r0"
+impl fmt::Display for RegisterGene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, instr) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "r{} = r{} {} ", instr.dst, instr.lhs, instr.op.symbol())?;
+            match instr.rhs {
+                Src::Reg(r) => write!(f, "r{}", r)?,
+                Src::Const(c) => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Use to create a fitness function that runs the program and compares output to the given
+// reference function. See `prog_gene::fitness`.
+pub fn fitness<F: Fn(i32, i32) -> i32>(f: F, g: &RegisterGene) -> f32 {
+    prog_gene::score(f, g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Gene;
+    use prog_gene::Runnable;
+    use ::rand::SeedableRng;
+
+    #[test]
+    fn run_and_display() {
+        // r0 = a + b
+        let prog = RegisterGene(vec![Instr { op: Command::Add, dst: 0, lhs: 0, rhs: Src::Reg(1) }]);
+        assert_eq!(prog.run(3, 4), 7);
+        assert!((fitness(|a, b| a + b, &prog) - 0.9999).abs() < 0.000001);
+
+        // r2 = b * b; r0 = r0 - r2; r0 = r0 + 3
+        let prog = RegisterGene(vec![
+            Instr { op: Command::Mult, dst: 2, lhs: 1, rhs: Src::Reg(1) },
+            Instr { op: Command::Sub, dst: 0, lhs: 0, rhs: Src::Reg(2) },
+            Instr { op: Command::Add, dst: 0, lhs: 0, rhs: Src::Const(3) },
+        ]);
+        assert_eq!(prog.run(5, 2), 4);
+        assert_eq!(format!("{}", prog), "r2 = r1 * r1; r0 = r0 - r2; r0 = r0 + 3");
+
+        // The empty program returns a
+        assert_eq!(RegisterGene(vec![]).run(5, 2), 5);
+    }
+
+    #[test]
+    fn generate_mutate_and_cross() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &RegisterConfig::default();
+        let genes: Vec<RegisterGene> = (0 .. 1000).map(|_| RegisterGene::generate(cfg, rng)).collect();
+        for pair in genes.windows(2) {
+            let other = pair[1].mutate(cfg, rng);
+            pair[0].mutate(cfg, rng).cross(cfg, &other, rng).run(1, 2);
+        }
+
+        // The operators never produce an empty program
+        let empty = RegisterGene(vec![]);
+        let single = RegisterGene(vec![rand_instr(rng)]);
+        for _ in 0 .. 1000 {
+            assert!(!single.mutate(cfg, rng).0.is_empty());
+            assert!(!empty.mutate(cfg, rng).0.is_empty());
+            assert!(!single.cross(cfg, &empty, rng).0.is_empty());
+        }
+    }
+}
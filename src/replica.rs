@@ -0,0 +1,76 @@
+//
+// Check that a run is deterministic by replicating it. A coordinator and a verifier each run the
+// same config with the same seed, and their pools' fingerprints are compared generation by
+// generation; the first that differs is where some nondeterminism crept in. The coordinator's
+// fingerprints can be saved as text and checked on another machine, though fingerprints come from
+// `DefaultHasher`, so both machines need the same build of the program.
+//
+
+use std::io::{self, BufRead, Write};
+
+// The first generation at which two replicas of a run differ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    // The generation, counting from 0
+    pub generation: usize,
+    // Each replica's fingerprint after that generation, or None if it stopped before it
+    pub expected: Option<u64>,
+    pub actual: Option<u64>,
+}
+
+// Compare the fingerprints of two replicas of a run. A replica that stops early, e.g. by reaching
+// the target fitness, diverges at the first generation it didn't evolve.
+pub fn first_divergence(expected: &[u64], actual: &[u64]) -> Option<Divergence> {
+    (0 .. expected.len().max(actual.len()))
+        .map(|generation| Divergence {
+            generation,
+            expected: expected.get(generation).cloned(),
+            actual: actual.get(generation).cloned(),
+        })
+        .find(|d| d.expected != d.actual)
+}
+
+// Write a run's seed and fingerprints, the seed on a header line and then one fingerprint per
+// generation in hex
+pub fn write_fingerprints<W: Write>(seed: u64, fingerprints: &[u64], w: &mut W) -> io::Result<()> {
+    writeln!(w, "# seed {}", seed)?;
+    for f in fingerprints {
+        writeln!(w, "{:016x}", f)?;
+    }
+    Ok(())
+}
+
+// Read the seed and fingerprints written by `write_fingerprints`
+pub fn read_fingerprints<B: BufRead>(r: B) -> io::Result<(u64, Vec<u64>)> {
+    let bad = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad fingerprint line: {}", line));
+    let mut lines = r.lines();
+    let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+    let seed = header.trim_start_matches("# seed ").parse().map_err(|_| bad(&header))?;
+    let mut fingerprints = Vec::new();
+    for line in lines {
+        let line = line?;
+        fingerprints.push(u64::from_str_radix(&line, 16).map_err(|_| bad(&line))?);
+    }
+    Ok((seed, fingerprints))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicate_runs() {
+        assert_eq!(first_divergence(&[1, 2, 3], &[1, 2, 3]), None);
+        assert_eq!(first_divergence(&[1, 2, 3], &[1, 5, 3]),
+                   Some(Divergence { generation: 1, expected: Some(2), actual: Some(5) }));
+        assert_eq!(first_divergence(&[1, 2, 3], &[1, 2]),
+                   Some(Divergence { generation: 2, expected: Some(3), actual: None }));
+
+        let mut text = Vec::new();
+        write_fingerprints(42, &[7, u64::MAX], &mut text).unwrap();
+        assert_eq!(String::from_utf8(text.clone()).unwrap(), "# seed 42\n0000000000000007\nffffffffffffffff\n");
+        assert_eq!(read_fingerprints(&text[..]).unwrap(), (42, vec![7, u64::MAX]));
+        assert!(read_fingerprints(&b"# seed 42\nxyz\n"[..]).is_err());
+        assert!(read_fingerprints(&b""[..]).is_err());
+    }
+}
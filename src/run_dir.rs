@@ -0,0 +1,159 @@
+//
+// A directory of artifacts describing one run: the config and seed it used, its manifest, the
+// fitness of every generation, every change of best program, the final population, the best program,
+// and a summary
+//
+
+use super::checkpoint;
+use super::gene::DynPool;
+use super::manifest::Manifest;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// What happened in a finished run
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub seed: u64,
+    pub generations: usize,
+    pub interrupted: bool,
+    pub seconds: f32,
+}
+
+// An open run directory
+pub struct RunDir {
+    path: PathBuf,
+    // The per-generation log, generations.csv
+    csv: io::BufWriter<fs::File>,
+    // The log of best programs, champions.jsonl, written a line at a time so it survives a crash
+    champions: fs::File,
+    // The best program when it was last logged
+    champion: Option<String>,
+}
+
+impl RunDir {
+    // Create the run directory, along with config.toml and seed.txt (from the manifest),
+    // manifest.toml, and the header of generations.csv. champions.jsonl is only ever appended to.
+    pub fn create<P: AsRef<Path>>(path: P, manifest: &Manifest) -> io::Result<RunDir> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+        fs::write(path.join("config.toml"), &manifest.config)?;
+        fs::write(path.join("seed.txt"), format!("{}\n", manifest.seed))?;
+        manifest.save(path.join("manifest.toml"))?;
+        let mut csv = io::BufWriter::new(fs::File::create(path.join("generations.csv"))?);
+        writeln!(csv, "generation,best,mean,worst,best_length,best_validation")?;
+        let champions = fs::OpenOptions::new().create(true).append(true).open(path.join("champions.jsonl"))?;
+        Ok(RunDir { path, csv, champions, champion: None })
+    }
+
+    // The directory's path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Append a generation's fitness statistics to generations.csv, with the best gene's score on the
+    // holdout cases if there are any, and the best gene to champions.jsonl if it's a new one
+    pub fn log_generation(&mut self, generation: usize, pool: &dyn DynPool, validation: Option<f32>)
+                          -> io::Result<()>
+        {
+        let genes = pool.genes_dyn();
+        let fits = genes.iter().map(|g| g.1);
+        let worst = fits.clone().fold(f32::INFINITY, f32::min);
+        let mean = fits.sum::<f32>() / genes.len() as f32;
+        let (best, best_fit) = pool.best_dyn();
+        let program = best.to_string();
+        if self.champion.as_ref() != Some(&program) {
+            let line = format!("{{\"generation\": {}, \"fitness\": {}, \"length\": {}, \"program\": {}}}\n",
+                               generation, best_fit, best.size_dyn(), json_string(&program));
+            self.champions.write_all(line.as_bytes())?;
+            self.champion = Some(program);
+        }
+        let validation = validation.map_or(String::new(), |v| v.to_string());
+        writeln!(self.csv, "{},{},{},{},{},{}", generation, best_fit, mean, worst, best.size_dyn(), validation)
+    }
+
+    // Write the final population (in checkpoint format), best.txt, and summary.json
+    pub fn finish(mut self, pool: &dyn DynPool, summary: &Summary) -> io::Result<()> {
+        self.csv.flush()?;
+        let mut population = io::BufWriter::new(fs::File::create(self.path.join("population.txt"))?);
+        checkpoint::write(pool, summary.generations, &mut population)?;
+        population.flush()?;
+
+        let (best, fit) = pool.best_dyn();
+        fs::write(self.path.join("best.txt"), format!("{}\n", best))?;
+        let json = format!(
+            "{{\n  \"seed\": {},\n  \"generations\": {},\n  \"interrupted\": {},\n  \"seconds\": {},\n  \
+             \"best_fitness\": {},\n  \"best_length\": {},\n  \"best_program\": {}\n}}\n",
+            summary.seed, summary.generations, summary.interrupted, summary.seconds,
+            fit, best.size_dyn(), json_string(&best.to_string()));
+        fs::write(self.path.join("summary.json"), json)
+    }
+}
+
+// Quote a string for JSON
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::SeedableRng;
+    use std::env;
+
+    #[test]
+    fn write_run_dir() {
+        let dir = env::temp_dir().join(format!("gene_code_run_dir_{}", ::std::process::id()));
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let mut pool = gene::new_dyn_pool(10, Box::new(|g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g)), rng);
+
+        let manifest = Manifest::current("repr = \"stack\"\n", 123);
+        let mut run = RunDir::create(&dir, &manifest).unwrap();
+        for i in 0 .. 3 {
+            pool.evolve_dyn(rng);
+            run.log_generation(i, &*pool, if i == 2 { Some(0.5) } else { None }).unwrap();
+        }
+        let summary = Summary { seed: 123, generations: 3, interrupted: false, seconds: 0.5 };
+        run.finish(&*pool, &summary).unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("config.toml"), "repr = \"stack\"\n");
+        assert_eq!(read("seed.txt"), "123\n");
+        assert_eq!(Manifest::load(dir.join("manifest.toml")).unwrap(), manifest);
+        let csv = read("generations.csv");
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().nth(1).unwrap().ends_with(','));
+        assert!(csv.lines().nth(3).unwrap().ends_with(",0.5"));
+        assert_eq!(read("population.txt").lines().count(), 11);
+        assert_eq!(read("best.txt"), format!("{}\n", pool.best_dyn().0));
+        assert!(read("summary.json").contains("\"generations\": 3,"));
+        // The first generation always has a new champion, and the last logged one is the best
+        let champions = read("champions.jsonl");
+        assert!(champions.starts_with("{\"generation\": 0, "));
+        assert!(champions.lines().count() <= 3);
+        let last = format!("\"program\": {}}}", json_string(read("best.txt").trim()));
+        assert!(champions.lines().last().unwrap().ends_with(&last));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn quote_json() {
+        assert_eq!(json_string("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\n\"");
+    }
+}
@@ -0,0 +1,843 @@
+//
+// The top-level driver: evolve programs as a `RunConfig` describes, printing progress and saving
+// checkpoints and run artifacts along the way
+//
+
+use super::cancel::CancellationToken;
+use super::checkpoint::{self, DeltaCheckpoints};
+use super::eda::EdaPool;
+use super::events::{Event, EventBus};
+use super::fitness_cache::{self, FitnessCache};
+use super::infix;
+use super::isolate::{self, Isolated};
+use super::localize;
+use super::lang::Underflow;
+use super::ensemble::{self, Ensemble, EnsembleReport};
+use super::config::{self, Champion, Crossover, Engine, Offspring, OperatorConfig, Repr, RunConfig};
+use super::gene::{self, AgeLimits, DynGene, DynPool, Fitness, Gene, Proportions, Rates};
+use super::manifest::{self, Manifest};
+use super::minimize;
+use super::objective::Objective;
+use super::plateau::{Change, Plateau};
+use super::prog_gene::{self, AdaptiveGene, AlignedGene, HomologousGene, ProgWeights, ProgramConfig, ProgramGene,
+                       Runnable};
+use super::reg_gene::RegisterGene;
+use super::run_dir::{RunDir, Summary};
+use super::suite::TestSuite;
+use super::team::Team;
+use super::tree_gene::TreeGene;
+
+use rand::{self, Rng, SeedableRng, StdRng};
+use std::any::Any;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::fmt;
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+// The fraction of the pool whose progs are learned from with `operators.learn_temperature`
+const ELITE_FRACTION: f32 = 0.1;
+
+// Print a line of progress, unless the run is quiet
+macro_rules! say {
+    ($quiet:expr, $($arg:tt)*) => {
+        if !$quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
+// What a finished run found
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunReport {
+    // The seed that was used, whether or not the config gave one
+    pub seed: u64,
+    // The number of generations evolved, counting any before a resume
+    pub generations: usize,
+    pub interrupted: bool,
+    pub seconds: f32,
+    pub best_program: String,
+    pub best_fitness: f32,
+    pub best_length: usize,
+    // The best program's score on the holdout cases, if some were held out
+    pub best_validation: Option<f32>,
+    // The number of generations it took for the best fitness to reach `termination.target_fitness`,
+    // if it did
+    pub solved_at: Option<usize>,
+    // The most generations in a row the best fitness went without improving
+    pub longest_stall: usize,
+    // The fingerprint of the pool after each generation this run evolved, to check a replica of the
+    // run against. See `gene::fingerprint`.
+    pub fingerprints: Vec<u64>,
+    // How an ensemble of the best programs did on the holdout cases, if that was asked for
+    pub ensemble: Option<EnsembleReport>,
+    // The fraction of expected outputs the best program got right on each suite of a multitask
+    // run, in the order of `harness.tasks`
+    pub task_accuracy: Vec<(String, f32)>,
+    // The shortest correct program found by minimizing the best one, if that was asked for and the
+    // best program was correct
+    pub shortest: Option<String>,
+    // The number of scores taken from the fitness cache instead of evaluated, if there was a cache
+    pub cache_hits: usize,
+}
+
+// Why a run couldn't be done
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunError {
+    // The config has these problems
+    Config(Vec<String>),
+    // The checkpoint to resume from can't be loaded, or doesn't match this run
+    Checkpoint(String),
+    // The run panicked with this message, which is almost always the fitness function panicking
+    // without `harness.panic_penalty` set to catch it
+    Evaluation(String),
+    // A file the run needs can't be read or made, e.g. the fitness cache or the run directory
+    Io(String),
+}
+
+impl RunError {
+    // A short name for the kind of error, for scripts to tell them apart
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            RunError::Config(_) => "config",
+            RunError::Checkpoint(_) => "checkpoint",
+            RunError::Evaluation(_) => "evaluation",
+            RunError::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::Config(ref problems) => write!(f, "invalid config:\n  {}", problems.join("\n  ")),
+            RunError::Checkpoint(ref e) | RunError::Io(ref e) => write!(f, "{}", e),
+            RunError::Evaluation(ref message) => write!(f, "the run panicked: {}", message),
+        }
+    }
+}
+
+impl From<RunError> for String {
+    fn from(e: RunError) -> String {
+        e.to_string()
+    }
+}
+
+// Run to completion. See `run_until`.
+pub fn run(config: &RunConfig) -> Result<RunReport, RunError> {
+    run_until(config, &CancellationToken::new())
+}
+
+// Validate the config and evolve programs as it describes, stopping at the end of a generation once
+// `token` is cancelled. A panic in the fitness function ends the run with an error instead of
+// unwinding, unless `harness.panic_penalty` scores the gene and carries on.
+pub fn run_until(config: &RunConfig, token: &CancellationToken) -> Result<RunReport, RunError> {
+    run_with(config, token, &mut EventBus::new())
+}
+
+// Run as `run_until` does, publishing what happens to `events` after the run's own progress
+// printing and logging have seen it
+pub fn run_with(config: &RunConfig, token: &CancellationToken, events: &mut EventBus)
+    -> Result<RunReport, RunError>
+    {
+    config.validate().map_err(RunError::Config)?;
+    // Nothing outside the run sees what a panic leaves half-done
+    panic::catch_unwind(AssertUnwindSafe(|| evolve(config, token, events)))
+        .unwrap_or_else(|cause| Err(RunError::Evaluation(isolate::panic_message(&*cause))))
+}
+
+// Evolve programs as a valid config describes. See `run_with`.
+fn evolve(config: &RunConfig, token: &CancellationToken, subscribers: &mut EventBus) -> Result<RunReport, RunError> {
+    // Make a pool, or pick up where a checkpoint left off
+    let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let rng = &mut StdRng::from_seed(&[seed as usize]);
+    let manifest = Manifest::current(&RunConfig { seed: Some(seed), ..config.clone() }.to_toml(), seed);
+    let tasks = config.harness.tasks.iter()
+        .filter_map(|t| TestSuite::by_name(&t.suite).map(|suite| (suite, t.weight)))
+        .collect::<Vec<_>>();
+    let multitask = if tasks.is_empty() { None } else { Some(TestSuite::multitask(tasks)) };
+    let harness = config.harness.suite.as_ref().and_then(|name| TestSuite::by_name(name)).or(multitask)
+        .map(|suite| match config.harness.holdout {
+            Some(fraction) => suite.with_holdout(fraction),
+            None => suite,
+        })
+        .map(|suite| suite.with_underflow(config.harness.underflow))
+        .map(|suite| suite.with_steps(ProgramConfig::default_steps(config.pool.loops)));
+    let parsimony = Rc::new(Cell::new(config.operators.parsimony.value_at(0)));
+    let cache = match config.harness.fitness_cache {
+        Some(ref path) => Some(Arc::new(FitnessCache::load_or_new(path, cache_key(config, &harness))
+                                        .map_err(|e| RunError::Io(format!("could not load fitness cache {}: {}", path, e)))?)),
+        None => None,
+    };
+    let (mut pool, first) = match config.pool.resume {
+        Some(ref path) => resume_pool(config, path, &manifest, &harness, &parsimony, &cache)?,
+        None => (make_pool(config, &harness, &parsimony, &cache, rng), 0),
+    };
+    pool.set_selection_dyn(match config.operators.selection {
+        config::Selection::Proportionate => gene::Selection::Proportionate,
+        config::Selection::Truncation => gene::Selection::Truncation(config.operators.truncation_fraction),
+        config::Selection::Boltzmann => gene::Selection::Boltzmann(config.operators.boltzmann_temperature.clone()),
+        config::Selection::Tournament => gene::Selection::Tournament(config.operators.tournament_size),
+        config::Selection::Rank => gene::Selection::Rank,
+        config::Selection::StochasticUniversal => gene::Selection::StochasticUniversal,
+    });
+    pool.set_elitism_dyn(config.operators.elitism);
+    if config.operators.protect_age > 0 || config.operators.retire_age.is_some() {
+        pool.set_age_limits_dyn(AgeLimits { protect: config.operators.protect_age, retire: config.operators.retire_age });
+    }
+    if let (Some(crossover), Some(mutation)) = (config.operators.crossover_rate, config.operators.mutation_rate) {
+        pool.set_rates_dyn(Rates { crossover, mutation });
+    }
+    if let Some(proportions) = proportions(&config.operators) {
+        pool.set_proportions_dyn(proportions);
+    }
+    if let Some(program_config) = pool.config_dyn().and_then(|c| c.downcast_mut::<ProgramConfig>()) {
+        program_config.structural = config.operators.structural_mutation;
+        program_config.frames = config.pool.frames;
+        program_config.conditionals = config.pool.conditionals;
+        program_config.loops = config.pool.loops;
+        program_config.max_steps = ProgramConfig::default_steps(config.pool.loops);
+        if config.operators.localize {
+            program_config.localize = match harness {
+                Some(ref suite) => suite.cases.clone(),
+                None => localize::target_cases(target),
+            };
+        }
+    }
+    let logging = &config.logging;
+    let quiet = logging.quiet;
+    // Print header row
+    if logging.watch {
+        say!(quiet, "Generation\tFitness\tLength\tSeconds\tProgram");
+    } else {
+        say!(quiet, "Generation\tBest\tMean\tMedian\tStdDev\tUnique");
+    }
+    let mut run = match logging.out_dir {
+        Some(ref dir) => Some(RunDir::create(dir, &manifest)
+                              .map_err(|e| RunError::Io(format!("could not create run directory {}: {}", dir, e)))?),
+        None => None,
+    };
+    let mut deltas = if logging.checkpoint_delta { Some(DeltaCheckpoints::new()) } else { None };
+    let start = Instant::now();
+    let watch = logging.watch;
+    let mut events = EventBus::new();
+    // Print progress: every new champion when watching, or else a summary of every generation
+    events.subscribe(move |e: &Event| match *e {
+        Event::Stagnation { generation, change: Change::Started } if watch => {
+            say!(quiet, "{}	Plateau, hyper-mutating", generation)
+        }
+        Event::Stagnation { generation, change: Change::Stopped } if watch => {
+            say!(quiet, "{}	Back to normal mutation", generation)
+        }
+        Event::ChampionChanged { generation, champion, fitness } if watch => {
+            let secs = start.elapsed().as_secs_f32();
+            say!(quiet, "{}	{}	{}	{:.2}	{}", generation, fitness, champion.size_dyn(), secs, champion);
+        }
+        Event::GenerationFinished { generation, pool } if !watch => {
+            let stats = pool.stats_dyn();
+            say!(quiet, "{}\t{}\t{:.2}\t{}\t{:.2}\t{}", generation, stats.max, stats.mean, stats.median,
+                 stats.std_dev, stats.unique);
+        }
+        _ => {}
+    });
+    if let Some(ref mut run) = run {
+        let (harness, parsimony) = (&harness, &parsimony);
+        events.subscribe(move |e: &Event| if let Event::GenerationFinished { generation, pool } = *e {
+            let validation = validate(pool.best_dyn().0, harness, parsimony.get());
+            if let Err(e) = run.log_generation(generation, pool, validation) {
+                eprintln!("Could not log generation to {}: {}", run.path().display(), e);
+            }
+        });
+    }
+    events.subscribe(subscribers);
+    let mut generations = first;
+    let mut solved_at = None;
+    // The best fitness so far, and the generations since it last improved
+    let (mut record, mut stall, mut longest_stall) = (f32::NEG_INFINITY, 0, 0);
+    let mut fingerprints = Vec::new();
+    let mut plateau = config.operators.hyper_mutation.map(Plateau::new);
+    // Evolve for many generations
+    for i in first .. first + config.termination.generations {
+        if token.is_cancelled() {
+            break;
+        }
+        events.publish(&Event::GenerationStarted { generation: i });
+        parsimony.set(config.operators.parsimony.value_at(i));
+        if let Some(warm_up) = config.pool.warm_up {
+            warm_up.prepare(&mut *pool, i, config.pool.size, rng);
+        }
+        pool.evolve_dyn(rng);
+        generations = i + 1;
+        if let Some(temperature) = config.operators.learn_temperature {
+            let weights = learn_weights(&*pool, temperature);
+            if let Some(program_config) = pool.config_dyn().and_then(|c| c.downcast_mut::<ProgramConfig>()) {
+                program_config.weights = Some(weights);
+            }
+        }
+        if let Some(ref mut plateau) = plateau {
+            if let Some(change) = plateau.update(&mut *pool, rng) {
+                events.publish(&Event::Stagnation { generation: i, change });
+            }
+        }
+        fingerprints.push(pool.fingerprint_dyn());
+        let (best, current) = pool.best_dyn();
+        if current > record {
+            record = current;
+            stall = 0;
+            events.publish(&Event::ChampionChanged { generation: i, champion: best, fitness: current });
+        } else {
+            stall += 1;
+            longest_stall = longest_stall.max(stall);
+        }
+        events.publish(&Event::GenerationFinished { generation: i, pool: &*pool });
+        if let Some(every) = logging.checkpoint_every {
+            if generations % every == 0 && save_checkpoint(&*pool, generations, &logging.checkpoint, &manifest, &mut deltas) {
+                events.publish(&Event::CheckpointWritten { generation: generations, path: &logging.checkpoint });
+            }
+        }
+        if let Some(target) = config.termination.target_fitness {
+            if pool.best_dyn().1 >= target {
+                solved_at = Some(generations);
+                break;
+            }
+        }
+    }
+
+    // Always leave a final checkpoint when interrupted or when checkpointing was asked for
+    let interrupted = token.is_cancelled();
+    if (interrupted || logging.checkpoint_every.is_some())
+        && save_checkpoint(&*pool, generations, &logging.checkpoint, &manifest, &mut deltas) {
+        events.publish(&Event::CheckpointWritten { generation: generations, path: &logging.checkpoint });
+    }
+    drop(events);
+    let seconds = start.elapsed().as_secs_f32();
+    if let (Some(cache), Some(path)) = (&cache, &config.harness.fitness_cache) {
+        if let Err(e) = cache.save(path) {
+            eprintln!("Could not save fitness cache to {}: {}", path, e);
+        }
+    }
+    if let Some(run) = run {
+        let path = run.path().to_path_buf();
+        let summary = Summary { seed, generations, interrupted, seconds };
+        if let Err(e) = run.finish(&*pool, &summary) {
+            eprintln!("Could not finish run directory {}: {}", path.display(), e);
+        }
+    }
+    if interrupted {
+        say!(quiet, "Interrupted after {} generations", generations);
+    } else if !logging.watch {
+        for g in pool.genes_dyn() {
+            say!(quiet, "{}", g.0);
+        }
+    }
+    // Print the best gene, or the one that generalizes best if asked
+    let (best, best_fitness) = match config.harness.champion {
+        Champion::Fitness => pool.best_dyn(),
+        Champion::Validation => {
+            let validated = |g: &(&dyn DynGene, f32)| validate(g.0, &harness, parsimony.get());
+            pool.genes_dyn().into_iter()
+                .max_by_key(|g| (Objective(validated(g).unwrap_or(f32::NAN)), Reverse(g.0.size_dyn())))
+                .unwrap()
+        }
+    };
+    let best_validation = validate(best, &harness, parsimony.get());
+    say!(quiet, "Best ({}): {}", best_fitness, best);
+    if let Some(g) = best.as_any().downcast_ref::<AdaptiveGene>() {
+        say!(quiet, "Strategy of the best: {}", g.strategy);
+    }
+    if let Some(expression) = stack_program(best).and_then(|g| infix::expression(&g.0, config.harness.underflow).ok()) {
+        say!(quiet, "As math: {}", expression);
+    }
+    let ensemble = match (config.harness.ensemble, &harness) {
+        (Some(k), Some(suite)) => compare_ensemble(&*pool, suite, k),
+        _ => None,
+    };
+    if let Some(report) = ensemble {
+        say!(quiet, "Holdout accuracy: ensemble of {} {}, best alone {}", report.members, report.ensemble, report.single);
+    }
+    let task_accuracy = match (runnable(best), &harness) {
+        (Some(best), Some(suite)) => suite.breakdown(best).into_iter().map(|t| (t.0.to_string(), t.1)).collect(),
+        _ => Vec::new(),
+    };
+    if !task_accuracy.is_empty() {
+        let tasks: Vec<String> = task_accuracy.iter().map(|t| format!("{} {}", t.0, t.1)).collect();
+        say!(quiet, "Accuracy per task: {}", tasks.join(", "));
+    }
+    let shortest = match config.operators.minimize {
+        Some(generations) => {
+            let steps = ProgramConfig::default_steps(config.pool.loops);
+            minimize_best(best, &harness, config.harness.underflow, steps, generations, quiet, rng)
+        }
+        None => None,
+    };
+    Ok(RunReport {
+        seed,
+        generations,
+        interrupted,
+        seconds,
+        best_program: best.to_string(),
+        best_fitness,
+        best_length: best.size_dyn(),
+        best_validation,
+        solved_at,
+        longest_stall,
+        fingerprints,
+        ensemble,
+        task_accuracy,
+        shortest: shortest.map(|g| g.to_string()),
+        cache_hits: cache.map_or(0, |c| c.hits()),
+    })
+}
+
+// The function programs learn when no test suite is given
+fn target(a: i32, b: i32) -> i32 {
+    3 + a - b*b
+}
+
+// Make a pool of the configured representation. The fitness functions read the shortness weight
+// from `parsimony`, so the driver can change it between generations.
+fn make_pool(config: &RunConfig, harness: &Option<TestSuite>, parsimony: &Rc<Cell<f32>>,
+             cache: &Option<Arc<FitnessCache>>, rng: &mut StdRng) -> Box<dyn DynPool>
+    {
+    if config.pool.engine == Engine::Eda {
+        let fitness = program_fitness::<ProgramGene>(config, harness, parsimony.clone(), cache);
+        return Box::new(EdaPool::new(config.pool.size, fitness, config.operators.eda_rate, rng));
+    }
+    match config.pool.repr {
+        Repr::Stack if config.operators.self_adaptive => {
+            program_pool::<AdaptiveGene>(config, harness, parsimony.clone(), cache, rng)
+        }
+        Repr::Stack if config.operators.crossover == Crossover::Aligned => {
+            program_pool::<AlignedGene>(config, harness, parsimony.clone(), cache, rng)
+        }
+        Repr::Stack if config.operators.crossover == Crossover::Homologous => {
+            program_pool::<HomologousGene>(config, harness, parsimony.clone(), cache, rng)
+        }
+        Repr::Stack => program_pool::<ProgramGene>(config, harness, parsimony.clone(), cache, rng),
+        Repr::Tree => program_pool::<TreeGene>(config, harness, parsimony.clone(), cache, rng),
+        Repr::Register => program_pool::<RegisterGene>(config, harness, parsimony.clone(), cache, rng),
+    }
+}
+
+// Make a pool of programs, or of teams of programs if an aggregation is given. Teams always learn
+// the target function.
+fn program_pool<T>(config: &RunConfig, harness: &Option<TestSuite>, parsimony: Rc<Cell<f32>>,
+                   cache: &Option<Arc<FitnessCache>>, rng: &mut StdRng) -> Box<dyn DynPool>
+    where T: Gene + Runnable + Clone + fmt::Display + Any,
+          T::Config: Default + Any,
+    {
+    // Pools that warm up start out at the exploring size
+    let size = config.pool.warm_up.map_or(config.pool.size, |w| w.size);
+    match config.pool.team {
+        None => {
+            let fitness = program_fitness::<T>(config, harness, parsimony, cache);
+            gene::new_dyn_pool_with(size, gene_config::<T::Config>(config), fitness, rng)
+        }
+        Some(agg) => {
+            let score = parsimony.clone();
+            let fitness: gene::BoxedFitness<Team<T>> = Box::new(move |t: &Team<T>| {
+                prog_gene::score_cases_with(target, t.size(), |a, b| t.run(agg, a, b), score.get())
+            });
+            gene::new_dyn_pool(size, with_cache(cache, parsimony, isolate(config, fitness)), rng)
+        }
+    }
+}
+
+// How each generation is made up, or None to leave the pool's default of a fourth each of
+// survivors, crosses, mutants, and fresh genes
+fn proportions(operators: &OperatorConfig) -> Option<Proportions> {
+    if operators.survivors.is_none() && operators.offspring == Offspring::Mixed {
+        return None;
+    }
+    let selected = match (operators.survivors, operators.selection) {
+        (Some(survivors), _) => survivors,
+        (None, config::Selection::Truncation) => operators.truncation_fraction,
+        (None, _) => Proportions::default().selected,
+    };
+    let rest = 1.0 - selected;
+    Some(match operators.offspring {
+        Offspring::Mixed => Proportions::with_selected(selected),
+        Offspring::Mutation => Proportions { selected, crossed: 0.0, mutated: rest, fresh: 0.0 },
+        Offspring::Crossover => Proportions { selected, crossed: rest, mutated: 0.0, fresh: 0.0 },
+    })
+}
+
+// The config of a representation, with the run's settings for stack programs. Frames,
+// conditionals, and loops have to be set from the start, since they change the programs the first
+// generation is made of.
+fn gene_config<C: Default + Any>(config: &RunConfig) -> C {
+    let mut gene_config = C::default();
+    if let Some(program_config) = (&mut gene_config as &mut dyn Any).downcast_mut::<ProgramConfig>() {
+        program_config.structural = config.operators.structural_mutation;
+        program_config.frames = config.pool.frames;
+        program_config.conditionals = config.pool.conditionals;
+        program_config.loops = config.pool.loops;
+        program_config.max_steps = ProgramConfig::default_steps(config.pool.loops);
+    }
+    gene_config
+}
+
+// The fitness of a single program, against the suite if there is one
+fn program_fitness<T>(config: &RunConfig, harness: &Option<TestSuite>, parsimony: Rc<Cell<f32>>,
+                      cache: &Option<Arc<FitnessCache>>) -> gene::BoxedFitness<T>
+    where T: Gene + Runnable + fmt::Display + 'static,
+    {
+    let score = parsimony.clone();
+    let fitness: gene::BoxedFitness<T> = match *harness {
+        Some(ref suite) => {
+            let suite = suite.clone();
+            Box::new(move |g: &T| suite.score_with(g, score.get()))
+        }
+        None => {
+            let (underflow, steps) = (config.harness.underflow, ProgramConfig::default_steps(config.pool.loops));
+            Box::new(move |g: &T| {
+                let run = |a, b| g.run_limited(a, b, 1, underflow, steps).map(|o| o[0]);
+                prog_gene::score_cases_checked(target, g.size(), run, score.get())
+            })
+        }
+    };
+    with_cache(cache, parsimony, isolate(config, fitness))
+}
+
+// Look scores up in the fitness cache, if there is one. A gene's score also depends on the
+// parsimony, which can change between generations, so that's part of each entry's key.
+fn with_cache<T: Hash + 'static>(cache: &Option<Arc<FitnessCache>>, parsimony: Rc<Cell<f32>>,
+                                 fitness: gene::BoxedFitness<T>) -> gene::BoxedFitness<T>
+    {
+    match *cache {
+        Some(ref cache) => {
+            let cache = cache.clone();
+            Box::new(move |g: &T| {
+                let entry = fitness_cache::hash_key(&(g, parsimony.get().to_bits()));
+                cache.get_or_evaluate(entry, || fitness(g))
+            })
+        }
+        None => fitness,
+    }
+}
+
+// The key of the fitness cache: everything besides the gene and parsimony that scores depend on
+fn cache_key(config: &RunConfig, harness: &Option<TestSuite>) -> u64 {
+    let scored_against = match *harness {
+        Some(ref suite) if suite.tasks.is_empty() => format!("{} {:?}", suite.name, suite.cases),
+        Some(ref suite) => format!("{} {:?} {:?}", suite.name, suite.cases, suite.tasks),
+        None => "3 + a - b*b".to_string(),
+    };
+    // Caches from before there was a choice of underflow policy popped 0
+    let scored_against = match config.harness.underflow {
+        Underflow::Zero => scored_against,
+        underflow => format!("{} underflow {:?}", scored_against, underflow),
+    };
+    // Likewise for the step budget, which only changes with loops
+    let steps = ProgramConfig::default_steps(config.pool.loops);
+    let scored_against = if steps == prog_gene::MAX_STEPS { scored_against } else {
+        format!("{} steps {}", scored_against, steps)
+    };
+    let penalty = config.harness.panic_penalty.map(f32::to_bits);
+    fitness_cache::hash_key(&(scored_against, config.pool.repr.name(), format!("{:?}", config.pool.team), penalty))
+}
+
+// Catch panics in the fitness function if `harness.panic_penalty` is set
+fn isolate<T: fmt::Display + 'static>(config: &RunConfig, fitness: gene::BoxedFitness<T>) -> gene::BoxedFitness<T> {
+    match config.harness.panic_penalty {
+        Some(penalty) => {
+            let isolated = Isolated::new(fitness, penalty);
+            Box::new(move |g: &T| isolated.evaluate(g))
+        }
+        None => fitness,
+    }
+}
+
+// Whether a program is right on every case
+fn is_correct<T: Gene + Runnable>(harness: &Option<TestSuite>, underflow: Underflow, steps: usize, g: &T) -> bool {
+    match *harness {
+        Some(ref suite) => suite.solves(g),
+        None => {
+            let run = |a, b| g.run_limited(a, b, 1, underflow, steps).map(|o| o[0]);
+            prog_gene::score_cases_checked(target, 1, run, 0.0) == 1.0
+        }
+    }
+}
+
+// A program's score on the suite's holdout cases, if it has any
+fn validate(gene: &dyn DynGene, harness: &Option<TestSuite>, parsimony: f32) -> Option<f32> {
+    let suite = harness.as_ref()?;
+    let best = gene.as_any();
+    if let Some(g) = best.downcast_ref::<ProgramGene>() {
+        suite.validate_with(g, parsimony)
+    } else if let Some(g) = best.downcast_ref::<AlignedGene>() {
+        suite.validate_with(g, parsimony)
+    } else if let Some(g) = best.downcast_ref::<HomologousGene>() {
+        suite.validate_with(g, parsimony)
+    } else if let Some(g) = best.downcast_ref::<AdaptiveGene>() {
+        suite.validate_with(g, parsimony)
+    } else if let Some(g) = best.downcast_ref::<TreeGene>() {
+        suite.validate_with(g, parsimony)
+    } else if let Some(g) = best.downcast_ref::<RegisterGene>() {
+        suite.validate_with(g, parsimony)
+    } else {
+        None
+    }
+}
+
+// A gene as something that runs, if it's one of the representations that does
+fn runnable(gene: &dyn DynGene) -> Option<&dyn Runnable> {
+    let gene = gene.as_any();
+    gene.downcast_ref::<ProgramGene>().map(|g| g as &dyn Runnable)
+        .or_else(|| gene.downcast_ref::<AlignedGene>().map(|g| g as &dyn Runnable))
+        .or_else(|| gene.downcast_ref::<HomologousGene>().map(|g| g as &dyn Runnable))
+        .or_else(|| gene.downcast_ref::<AdaptiveGene>().map(|g| g as &dyn Runnable))
+        .or_else(|| gene.downcast_ref::<TreeGene>().map(|g| g as &dyn Runnable))
+        .or_else(|| gene.downcast_ref::<RegisterGene>().map(|g| g as &dyn Runnable))
+}
+
+// The stack program a gene is or wraps, if it's one
+fn stack_program(gene: &dyn DynGene) -> Option<&ProgramGene> {
+    let gene = gene.as_any();
+    gene.downcast_ref::<ProgramGene>()
+        .or_else(|| gene.downcast_ref::<AlignedGene>().map(|g| &g.0))
+        .or_else(|| gene.downcast_ref::<HomologousGene>().map(|g| &g.0))
+        .or_else(|| gene.downcast_ref::<AdaptiveGene>().map(|g| &g.program))
+}
+
+// Compare an ensemble of the `k` most fit programs with the best one on the suite's holdout cases
+fn compare_ensemble(pool: &dyn DynPool, suite: &TestSuite, k: usize) -> Option<EnsembleReport> {
+    compare_ensemble_of::<ProgramGene>(pool, suite, k)
+        .or_else(|| compare_ensemble_of::<AlignedGene>(pool, suite, k))
+        .or_else(|| compare_ensemble_of::<HomologousGene>(pool, suite, k))
+        .or_else(|| compare_ensemble_of::<AdaptiveGene>(pool, suite, k))
+        .or_else(|| compare_ensemble_of::<TreeGene>(pool, suite, k))
+        .or_else(|| compare_ensemble_of::<RegisterGene>(pool, suite, k))
+}
+
+// `compare_ensemble` if the pool's genes are `T`s
+fn compare_ensemble_of<T>(pool: &dyn DynPool, suite: &TestSuite, k: usize) -> Option<EnsembleReport>
+    where T: Runnable + Clone + Eq + 'static,
+    {
+    let genes: Vec<(T, f32)> = pool.genes_dyn().into_iter()
+        .map(|g| g.0.as_any().downcast_ref::<T>().map(|t| (t.clone(), g.1)))
+        .collect::<Option<_>>()?;
+    ensemble::compare(suite, &Ensemble::of_best(&genes, k))
+}
+
+// Learn prog weights from the most fit stack programs in the pool
+fn learn_weights(pool: &dyn DynPool, temperature: f32) -> ProgWeights {
+    let mut genes = pool.genes_dyn();
+    genes.sort_by_key(|g| Reverse(Objective(g.1)));
+    let elite = ((genes.len() as f32 * ELITE_FRACTION) as usize).max(1);
+    let programs: Vec<&ProgramGene> = genes.iter().take(elite).filter_map(|g| stack_program(g.0)).collect();
+    ProgWeights::learn(programs, temperature)
+}
+
+// Load the pool saved in a checkpoint, along with the generation it was saved at. This refuses
+// checkpoints whose manifest doesn't match the current run, unless `pool.force` is set.
+fn resume_pool(config: &RunConfig, path: &str, current: &Manifest, harness: &Option<TestSuite>,
+               parsimony: &Rc<Cell<f32>>, cache: &Option<Arc<FitnessCache>>)
+    -> Result<(Box<dyn DynPool>, usize), RunError>
+    {
+    let manifest_path = manifest::path_for(path);
+    let mismatches = match Manifest::load(&manifest_path) {
+        Ok(saved) => saved.mismatches(current),
+        Err(e) => vec![format!("could not read manifest {}: {}", manifest_path.display(), e)],
+    };
+    if !mismatches.is_empty() && !config.pool.force {
+        return Err(RunError::Checkpoint(format!(
+            "checkpoint {} doesn't match this run (set pool.force to resume anyway):\n  {}",
+            path, mismatches.join("\n  "))));
+    }
+    let (generation, genes) = checkpoint::load::<ProgramGene, _>(path)
+        .map_err(|e| RunError::Checkpoint(format!("could not load checkpoint {}: {}", path, e)))?;
+    if genes.is_empty() {
+        return Err(RunError::Checkpoint(format!("checkpoint {} has no genes", path)));
+    }
+    let pool = match config.operators.crossover {
+        Crossover::Cut if config.operators.self_adaptive => {
+            let genes = genes.into_iter().map(|g| AdaptiveGene::new(g.0)).collect();
+            gene::dyn_pool_from(genes, program_fitness::<AdaptiveGene>(config, harness, parsimony.clone(), cache))
+        }
+        Crossover::Cut => {
+            let genes = genes.into_iter().map(|g| g.0).collect();
+            gene::dyn_pool_from(genes, program_fitness::<ProgramGene>(config, harness, parsimony.clone(), cache))
+        }
+        Crossover::Aligned => {
+            let genes = genes.into_iter().map(|g| AlignedGene(g.0)).collect();
+            gene::dyn_pool_from(genes, program_fitness::<AlignedGene>(config, harness, parsimony.clone(), cache))
+        }
+        Crossover::Homologous => {
+            let genes = genes.into_iter().map(|g| HomologousGene(g.0)).collect();
+            gene::dyn_pool_from(genes, program_fitness::<HomologousGene>(config, harness, parsimony.clone(), cache))
+        }
+    };
+    Ok((pool, generation))
+}
+
+// Save a checkpoint and its manifest, as a delta if `deltas` is given, warning instead of stopping
+// the run if it fails. Returns whether it was saved.
+fn save_checkpoint(pool: &dyn DynPool, generation: usize, path: &str, manifest: &Manifest,
+                   deltas: &mut Option<DeltaCheckpoints>) -> bool {
+    let saved = match *deltas {
+        Some(ref mut deltas) => deltas.save(pool, generation, path),
+        None => checkpoint::save(pool, generation, path),
+    };
+    let saved = saved.and_then(|()| manifest.save(manifest::path_for(path)));
+    if let Err(ref e) = saved {
+        eprintln!("Could not save checkpoint to {}: {}", path, e);
+    }
+    saved.is_ok()
+}
+
+// Search for a shorter program equivalent to the best one, and print it. Validation only allows
+// minimizing stack programs, and only correct ones are minimized.
+fn minimize_best(best: &dyn DynGene, harness: &Option<TestSuite>, underflow: Underflow, steps: usize,
+                 generations: usize, quiet: bool, rng: &mut StdRng)
+    -> Option<ProgramGene>
+    {
+    let best = stack_program(best)?;
+    let correct = |g: &ProgramGene| is_correct(harness, underflow, steps, g);
+    if !correct(best) {
+        say!(quiet, "The best program isn't correct, so it wasn't minimized");
+        return None;
+    }
+    let shortest = minimize::minimize(best, correct, 100, generations, rng);
+    say!(quiet, "Shortest ({} of {}): {}", shortest.size(), best.size(), shortest);
+    Some(shortest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn survivor_fraction() {
+        let mut operators = OperatorConfig::default();
+        assert_eq!(proportions(&operators), None);
+        operators.survivors = Some(0.4);
+        assert_eq!(proportions(&operators), Some(Proportions { selected: 0.4, crossed: 0.2, mutated: 0.2, fresh: 0.2 }));
+        operators.survivors = Some(0.5);
+        operators.offspring = Offspring::Mutation;
+        assert_eq!(proportions(&operators), Some(Proportions { selected: 0.5, crossed: 0.0, mutated: 0.5, fresh: 0.0 }));
+        // Without a fraction, survivors are what the selection would keep
+        operators.survivors = None;
+        operators.offspring = Offspring::Crossover;
+        operators.selection = config::Selection::Truncation;
+        operators.truncation_fraction = 0.1;
+        assert_eq!(proportions(&operators).unwrap().selected, 0.1);
+
+        // Half survivors and half mutants
+        let mut config = RunConfig { seed: Some(42), ..RunConfig::default() };
+        config.harness.suite = Some("sign".to_string());
+        config.termination.generations = 3;
+        config.logging.quiet = true;
+        config.operators.survivors = Some(0.5);
+        config.operators.offspring = Offspring::Mutation;
+        assert_eq!(run(&config).unwrap().generations, 3);
+    }
+
+    #[test]
+    fn localized_mutation() {
+        // Against the built-in target and against a suite
+        let mut config = RunConfig { seed: Some(42), ..RunConfig::default() };
+        config.termination.generations = 3;
+        config.logging.quiet = true;
+        config.operators.localize = true;
+        assert_eq!(run(&config).unwrap().generations, 3);
+        config.harness.suite = Some("sort-two".to_string());
+        assert_eq!(run(&config).unwrap().generations, 3);
+    }
+
+    #[test]
+    fn run_config() {
+        let mut config = RunConfig { seed: Some(42), ..RunConfig::default() };
+        config.harness.suite = Some("sign".to_string());
+        config.termination.generations = 5;
+        let report = run(&config).unwrap();
+        assert_eq!(report.seed, 42);
+        assert_eq!(report.generations, 5);
+        assert!(!report.interrupted);
+        assert_eq!(report.shortest, None);
+        assert_eq!(report.best_validation, None);
+        assert_eq!(report.solved_at, None);
+        assert!(report.longest_stall < 5);
+        assert_eq!(report.fingerprints.len(), 5);
+        // The same seed gives the same run
+        let again = run(&config).unwrap();
+        assert_eq!(again.best_program, report.best_program);
+        assert_eq!(again.fingerprints, report.fingerprints);
+
+        // Learning prog weights changes the run, and nothing learned carries over to the next run
+        config.operators.learn_temperature = Some(0.5);
+        assert_ne!(run(&config).unwrap().best_program, report.best_program);
+        config.operators.learn_temperature = None;
+        assert_eq!(run(&config).unwrap().best_program, report.best_program);
+
+        // Reaching the target fitness ends the run early
+        config.termination.target_fitness = Some(0.0);
+        config.logging.quiet = true;
+        let report = run(&config).unwrap();
+        assert_eq!(report.generations, 1);
+        assert_eq!(report.solved_at, Some(1));
+
+        // Cancelling stops the run before the first generation, and leaves a checkpoint
+        let checkpoint = env::temp_dir().join(format!("gene_code_runner_{}.txt", ::std::process::id()));
+        config.logging.checkpoint = checkpoint.to_string_lossy().into_owned();
+        let token = CancellationToken::new();
+        token.cancel();
+        let report = run_until(&config, &token).unwrap();
+        assert!(report.interrupted);
+        assert_eq!(report.generations, 0);
+        assert!(checkpoint.exists());
+        fs::remove_file(&checkpoint).unwrap();
+        fs::remove_file(manifest::path_for(&checkpoint)).unwrap();
+
+        // A second run with the fitness cache of the first scores nothing it already scored
+        let cache = env::temp_dir().join(format!("gene_code_runner_cache_{}.txt", ::std::process::id()));
+        config.harness.fitness_cache = Some(cache.to_string_lossy().into_owned());
+        config.termination.target_fitness = None;
+        let cold = run(&config).unwrap();
+        let warm = run(&config).unwrap();
+        assert_eq!(warm.best_program, cold.best_program);
+        assert!(warm.cache_hits >= cold.cache_hits + config.pool.size, "{} {}", cold.cache_hits, warm.cache_hits);
+        fs::remove_file(&cache).unwrap();
+        config.harness.fitness_cache = None;
+
+        // Holding out cases gives the best program a validation score
+        config.harness.holdout = Some(0.25);
+        let report = run(&config).unwrap();
+        assert!(report.best_validation.is_some_and(|v| (0.0 ..= 1.0).contains(&v)));
+        // Choosing the answer by validation score can only do as well or better there
+        config.harness.champion = Champion::Validation;
+        let validated = run(&config).unwrap();
+        assert!(validated.best_validation >= report.best_validation);
+        assert!(validated.best_fitness <= report.best_fitness);
+        config.harness.champion = Champion::Fitness;
+        config.harness.ensemble = Some(5);
+        let ensemble = run(&config).unwrap().ensemble.unwrap();
+        assert_eq!(ensemble.members, 5);
+        assert!((0.0 ..= 1.0).contains(&ensemble.ensemble));
+        config.harness.ensemble = None;
+        config.harness.holdout = None;
+        assert_eq!(report.task_accuracy, vec![]);
+
+        // A multitask run reports how its best program does on each suite
+        config.harness.suite = None;
+        config.harness.tasks = vec!["sign".parse().unwrap(), "sort-two:2".parse().unwrap()];
+        let report = run(&config).unwrap();
+        let names: Vec<&str> = report.task_accuracy.iter().map(|t| t.0.as_str()).collect();
+        assert_eq!(names, vec!["sign", "sort-two"]);
+        assert!(report.task_accuracy.iter().all(|t| (0.0 ..= 1.0).contains(&t.1)));
+        config.harness.tasks = vec![];
+
+        // Errors say what kind they are
+        config.pool.resume = Some(env::temp_dir().join("gene_code_no_such_checkpoint.txt").to_string_lossy().into_owned());
+        assert_eq!(run(&config).unwrap_err().kind(), "checkpoint");
+        config.pool.resume = None;
+        config.pool.size = 0;
+        let e = run(&config).unwrap_err();
+        assert_eq!(e.kind(), "config");
+        assert!(e.to_string().starts_with("invalid config:\n  pool.size"));
+    }
+}
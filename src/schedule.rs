@@ -0,0 +1,196 @@
+//
+// Schedules for parameters that change over the generations of a run
+//
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+// A parameter value as a function of the generation number
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+    // The same value every generation
+    Constant(f32),
+    // Move linearly from `from` to `to` over `over` generations, then hold `to`
+    Linear { from: f32, to: f32, over: usize },
+    // Move geometrically from `from` to `to` over `over` generations, then hold `to`. Both values
+    // must be positive.
+    Exponential { from: f32, to: f32, over: usize },
+    // Take each value from its starting generation onward. The steps are sorted by generation,
+    // and the first value also applies before its generation.
+    Step(Vec<(usize, f32)>),
+}
+
+impl Schedule {
+    // Get the parameter value for the given generation
+    pub fn value_at(&self, generation: usize) -> f32 {
+        match *self {
+            Schedule::Constant(x) => x,
+            Schedule::Linear { from, to, over } => {
+                let t = progress(generation, over);
+                from + (to - from) * t
+            }
+            Schedule::Exponential { from, to, over } => {
+                let t = progress(generation, over);
+                from * (to / from).powf(t)
+            }
+            Schedule::Step(ref steps) => {
+                let mut value = steps[0].1;
+                for &(start, x) in steps {
+                    if generation >= start {
+                        value = x;
+                    }
+                }
+                value
+            }
+        }
+    }
+}
+
+// How far through `over` generations we are, from 0 to 1
+fn progress(generation: usize, over: usize) -> f32 {
+    if over == 0 {
+        1.0
+    } else {
+        (generation as f32 / over as f32).min(1.0)
+    }
+}
+
+// Parse a schedule from one of these forms:
+//   0.5                    constant
+//   linear:0.5:0.1:100     from 0.5 to 0.1 over 100 generations
+//   exp:1:0.01:100         from 1 to 0.01 geometrically over 100 generations
+//   step:0=0.5,100=0.1     0.5 from generation 0, then 0.1 from generation 100
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let num = |x: &str| x.parse::<f32>().map_err(|_| format!("bad number in schedule: {}", x));
+        let gens = |x: &str| x.parse::<usize>().map_err(|_| format!("bad generation in schedule: {}", x));
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts[..] {
+            [x] => Ok(Schedule::Constant(num(x)?)),
+            ["linear", from, to, over] => Ok(Schedule::Linear { from: num(from)?, to: num(to)?, over: gens(over)? }),
+            ["exp", from, to, over] => {
+                let (from, to) = (num(from)?, num(to)?);
+                if from <= 0.0 || to <= 0.0 {
+                    return Err(format!("exponential schedule needs positive values: {}", s));
+                }
+                Ok(Schedule::Exponential { from, to, over: gens(over)? })
+            }
+            ["step", steps] => {
+                let mut parsed = Vec::new();
+                for step in steps.split(',') {
+                    let mut kv = step.splitn(2, '=');
+                    let start = gens(kv.next().unwrap_or(""))?;
+                    let x = num(kv.next().ok_or_else(|| format!("bad step in schedule: {}", step))?)?;
+                    parsed.push((start, x));
+                }
+                parsed.sort_by_key(|&(start, _)| start);
+                Ok(Schedule::Step(parsed))
+            }
+            _ => Err(format!("unknown schedule: {}", s)),
+        }
+    }
+}
+
+// Display a schedule in the same form it is parsed from
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Schedule::Constant(x) => write!(f, "{}", x),
+            Schedule::Linear { from, to, over } => write!(f, "linear:{}:{}:{}", from, to, over),
+            Schedule::Exponential { from, to, over } => write!(f, "exp:{}:{}:{}", from, to, over),
+            Schedule::Step(ref steps) => {
+                write!(f, "step:")?;
+                for (i, &(start, x)) in steps.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}={}", start, x)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Schedules are stored in configs as the text they are parsed from
+impl Serialize for Schedule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Read a schedule from its text, or from a bare number for a constant
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ScheduleVisitor;
+
+        impl<'de> Visitor<'de> for ScheduleVisitor {
+            type Value = Schedule;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number or a schedule like linear:0.1:0.01:500")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Schedule, E> {
+                s.parse().map_err(E::custom)
+            }
+
+            fn visit_f64<E: de::Error>(self, x: f64) -> Result<Schedule, E> {
+                Ok(Schedule::Constant(x as f32))
+            }
+
+            fn visit_i64<E: de::Error>(self, x: i64) -> Result<Schedule, E> {
+                Ok(Schedule::Constant(x as f32))
+            }
+
+            fn visit_u64<E: de::Error>(self, x: u64) -> Result<Schedule, E> {
+                Ok(Schedule::Constant(x as f32))
+            }
+        }
+
+        deserializer.deserialize_any(ScheduleVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.00001
+    }
+
+    #[test]
+    fn schedules() {
+        let s: Schedule = "0.5".parse().unwrap();
+        assert_eq!(s.value_at(1000), 0.5);
+
+        let s: Schedule = "linear:0.5:0.1:100".parse().unwrap();
+        assert!(close(s.value_at(0), 0.5));
+        assert!(close(s.value_at(50), 0.3));
+        assert!(close(s.value_at(500), 0.1));
+
+        let s: Schedule = "exp:1:0.01:100".parse().unwrap();
+        assert!(close(s.value_at(0), 1.0));
+        assert!(close(s.value_at(50), 0.1));
+        assert!(close(s.value_at(100), 0.01));
+
+        let s: Schedule = "step:100=0.1,0=0.5".parse().unwrap();
+        assert_eq!(s.value_at(0), 0.5);
+        assert_eq!(s.value_at(99), 0.5);
+        assert_eq!(s.value_at(100), 0.1);
+
+        // Schedules display the way they are parsed
+        for text in &["0.5", "linear:0.5:0.1:100", "exp:1:0.01:100", "step:0=0.5,100=0.1"] {
+            assert_eq!(text.parse::<Schedule>().unwrap().to_string(), *text);
+        }
+
+        assert!("exp:0:1:10".parse::<Schedule>().is_err());
+        assert!("linear:1:2".parse::<Schedule>().is_err());
+        assert!("step:a=1".parse::<Schedule>().is_err());
+    }
+}
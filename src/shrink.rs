@@ -0,0 +1,73 @@
+//
+// Shrink a program that shows a bug, e.g. one the interpreter panics on, down to a few tokens that
+// still show it. This is Zeller's delta debugging: remove ever smaller chunks of the program for
+// as long as the bug remains, until no single token can go.
+//
+
+use std::panic::{self, AssertUnwindSafe};
+
+// The shortest program found by removing tokens from `program` for which `interesting` still
+// holds. `interesting` must hold for `program` itself. The result is 1-minimal: removing any one
+// more token makes `interesting` fail.
+pub fn shrink<T: Clone, P: FnMut(&[T]) -> bool>(program: &[T], mut interesting: P) -> Vec<T> {
+    let mut program = program.to_vec();
+    // The number of chunks to split the program into
+    let mut chunks = 2;
+    while program.len() >= 2 {
+        let size = program.len().div_ceil(chunks);
+        let removed = (0 .. program.len()).step_by(size).find_map(|start| {
+            let mut rest = program[.. start].to_vec();
+            rest.extend_from_slice(&program[(start + size).min(program.len()) ..]);
+            if interesting(&rest) { Some(rest) } else { None }
+        });
+        match removed {
+            Some(rest) => {
+                program = rest;
+                chunks = (chunks - 1).max(2);
+            }
+            None if size == 1 => break,
+            None => chunks = (chunks * 2).min(program.len()),
+        }
+    }
+    // A single token may be removable too
+    if program.len() == 1 && interesting(&[]) {
+        program.clear();
+    }
+    program
+}
+
+// Whether `f` panics. The panic is still reported on stderr as usual.
+pub fn panics<R, F: FnOnce() -> R>(f: F) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(f)).is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::Prog;
+    use prog_gene::{ProgramGene, Runnable};
+
+    #[test]
+    fn shrink_programs() {
+        // Needs a 7 somewhere before a 3
+        let both = |p: &[u32]| p.iter().position(|&x| x == 7).is_some_and(|i| p[i ..].contains(&3));
+        let shrunk = shrink(&[1, 7, 2, 2, 8, 0, 3, 5, 9, 1, 4], both);
+        assert_eq!(shrunk, vec![7, 3]);
+        assert_eq!(shrink(&[1, 2, 3], |_: &[u32]| true), Vec::<u32>::new());
+
+        // A program that fails the case (3, 7), where it should give their sum
+        let program: ProgramGene = "1 + dup 2 * swap - *".parse().unwrap();
+        let fails = |p: &[Prog]| ProgramGene(p.to_vec()).run(3, 7) != 10;
+        assert!(fails(&program.0));
+        let shrunk = shrink(&program.0, fails);
+        assert!(fails(&shrunk));
+        for i in 0 .. shrunk.len() {
+            let mut less = shrunk.clone();
+            less.remove(i);
+            assert!(!fails(&less));
+        }
+
+        let touchy = |p: &[u32]| panics(|| if p.contains(&7) { panic!("found a 7") });
+        assert_eq!(shrink(&[1, 7, 2, 7], touchy), vec![7]);
+    }
+}
@@ -0,0 +1,494 @@
+//
+// Ready-made test suites for evolving programs, each with a config that suits it. Unlike the
+// binary's single target function, a suite can expect more than one output per case.
+//
+// The stack language has no comparisons or loops, so only some of these can be solved exactly. GCD
+// in particular needs loops; its suite measures how close evolution can get without them.
+//
+// The boolean benchmarks from the GP literature (the 6-multiplexer and even parity) are here too.
+// There are no boolean programs yet, so their input bits are packed into a, lowest bit first, and
+// the programs work on them with integer arithmetic. Their results aren't directly comparable with
+// published boolean GP results until there is a boolean instruction set.
+//
+
+use super::gene::Gene;
+use super::lang::Underflow;
+use super::prog_gene::{Runnable, MAX_STEPS, PARSIMONY};
+
+use rand::{Rng, SeedableRng, StdRng};
+use std::ops::Range;
+
+// One test case: the inputs, and the expected outputs in order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    pub a: i32,
+    pub b: i32,
+    pub outputs: Vec<i32>,
+}
+
+// How to evolve programs against a suite
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub pool_size: usize,
+    pub generations: usize,
+    // The weight of shortness in the fitness. See `prog_gene::score_cases_with`.
+    pub parsimony: f32,
+}
+
+// One of the suites a multitask suite combines. Its cases are the next `cases` of the multitask
+// suite's cases, after those of the tasks before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Task {
+    pub name: &'static str,
+    pub weight: f32,
+    pub cases: usize,
+}
+
+// A named set of test cases
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestSuite {
+    pub name: &'static str,
+    // The cases programs are scored on
+    pub cases: Vec<Case>,
+    // Cases held out of scoring, to check programs on cases they weren't evolved against
+    pub holdout: Vec<Case>,
+    pub config: Config,
+    // What popping an empty stack gives while programs are scored
+    pub underflow: Underflow,
+    // The most steps programs may run for while they're scored. See `ProgramConfig::max_steps`.
+    pub steps: usize,
+    // The suites this one combines, if it's a multitask suite. See `TestSuite::multitask`.
+    pub tasks: Vec<Task>,
+}
+
+impl TestSuite {
+    // Make a suite from a reference function, run on the given inputs
+    pub fn from_fn<I, F>(name: &'static str, config: Config, inputs: I, f: F) -> TestSuite
+        where I: IntoIterator<Item = (i32, i32)>,
+              F: Fn(i32, i32) -> Vec<i32>,
+        {
+        let cases = inputs.into_iter().map(|(a, b)| Case { a, b, outputs: f(a, b) }).collect();
+        TestSuite { name, cases, holdout: Vec::new(), config, underflow: Underflow::Zero, steps: MAX_STEPS,
+                    tasks: Vec::new() }
+    }
+
+    // Start building a suite from a reference function. See `SuiteBuilder`.
+    pub fn builder<F: Fn(i32, i32) -> Vec<i32>>(f: F) -> SuiteBuilder<F> {
+        SuiteBuilder {
+            f,
+            name: "custom",
+            config: Config { pool_size: 100, generations: 300, parsimony: PARSIMONY },
+            cases: Vec::new(),
+            holdout: Vec::new(),
+        }
+    }
+
+    // Output the smaller input, then the larger
+    pub fn sort_two() -> TestSuite {
+        let config = Config { pool_size: 100, generations: 300, parsimony: 0.01 };
+        TestSuite::from_fn("sort-two", config, grid(-5 .. 5, -5 .. 5), |a, b| vec![a.min(b), a.max(b)])
+    }
+
+    // The integer square root of a, rounded down
+    pub fn isqrt() -> TestSuite {
+        let config = Config { pool_size: 200, generations: 500, parsimony: 0.01 };
+        TestSuite::from_fn("isqrt", config, grid(0 .. 50, 0 .. 1), |a, _| vec![(a as f64).sqrt() as i32])
+    }
+
+    // The greatest common divisor of a and b
+    pub fn gcd() -> TestSuite {
+        let config = Config { pool_size: 200, generations: 500, parsimony: 0.01 };
+        TestSuite::from_fn("gcd", config, grid(1 .. 13, 1 .. 13), |a, b| vec![gcd(a, b)])
+    }
+
+    // -1, 0, or 1 depending on the sign of a
+    pub fn sign() -> TestSuite {
+        let config = Config { pool_size: 100, generations: 300, parsimony: 0.01 };
+        TestSuite::from_fn("sign", config, grid(-10 .. 11, 0 .. 1), |a, _| vec![a.signum()])
+    }
+
+    // The 6-multiplexer: bits 0 and 1 of a are an address, and the output is the data bit it
+    // selects from bits 2 to 5
+    pub fn multiplexer6() -> TestSuite {
+        let config = Config { pool_size: 200, generations: 500, parsimony: 0.01 };
+        TestSuite::from_fn("multiplexer6", config, grid(0 .. 64, 0 .. 1), |a, _| {
+            let address = a & 0b11;
+            vec![(a >> (2 + address)) & 1]
+        })
+    }
+
+    // Even parity of the lowest `bits` bits of a: 1 if an even number of them are set, else 0
+    pub fn even_parity(bits: u32) -> TestSuite {
+        let config = Config { pool_size: 200, generations: 500, parsimony: 0.01 };
+        let name = match bits {
+            3 => "even-parity3",
+            4 => "even-parity4",
+            5 => "even-parity5",
+            _ => "even-parity",
+        };
+        TestSuite::from_fn(name, config, grid(0 .. 1 << bits, 0 .. 1), |a, _| vec![1 - (a.count_ones() as i32 & 1)])
+    }
+
+    // Every ready-made suite
+    pub fn all() -> Vec<TestSuite> {
+        vec![TestSuite::sort_two(), TestSuite::isqrt(), TestSuite::gcd(), TestSuite::sign(),
+             TestSuite::multiplexer6(), TestSuite::even_parity(3)]
+    }
+
+    // Combine suites, e.g. different targets that share structure, into one that scores programs
+    // on all of them. A program's correctness is the weighted mean of its correctness on each
+    // suite, so a suite with many cases doesn't drown out one with few. The combined config is the
+    // largest pool and generation count, and the weighted mean parsimony. Holdout cases are combined
+    // too, but aren't broken down by suite.
+    pub fn multitask(suites: Vec<(TestSuite, f32)>) -> TestSuite {
+        let total: f32 = suites.iter().map(|s| s.1).sum();
+        let mut combined = TestSuite::from_fn("multitask", Config { pool_size: 0, generations: 0, parsimony: 0.0 },
+                                              None, |_, _| vec![]);
+        for (suite, weight) in suites {
+            combined.config.pool_size = combined.config.pool_size.max(suite.config.pool_size);
+            combined.config.generations = combined.config.generations.max(suite.config.generations);
+            combined.config.parsimony += suite.config.parsimony * weight / total;
+            combined.tasks.push(Task { name: suite.name, weight, cases: suite.cases.len() });
+            combined.cases.extend(suite.cases);
+            combined.holdout.extend(suite.holdout);
+        }
+        combined
+    }
+
+    // Find a ready-made suite by name
+    pub fn by_name(name: &str) -> Option<TestSuite> {
+        TestSuite::all().into_iter().find(|s| s.name == name)
+    }
+
+    // The number of outputs the cases expect
+    pub fn num_outputs(&self) -> usize {
+        self.cases.iter().map(|c| c.outputs.len()).max().unwrap_or(0)
+    }
+
+    // Whether a program gets every output of every case right
+    pub fn solves<P: Runnable>(&self, p: &P) -> bool {
+        let num_outputs = self.num_outputs();
+        self.cases.iter().all(|c| {
+            p.run_limited(c.a, c.b, num_outputs, self.underflow, self.steps).is_some_and(|o| o[.. c.outputs.len()] == c.outputs[..])
+        })
+    }
+
+    // Score a program by the fraction of expected outputs it gets right, with the suite's weight for
+    // shortness. This matches `prog_gene::score_cases_with` for single-output suites.
+    pub fn score<P: Runnable + Gene>(&self, p: &P) -> f32 {
+        self.score_with(p, self.config.parsimony)
+    }
+
+    // Like `score`, but with the given weight for shortness instead of the suite's own
+    pub fn score_with<P: Runnable + Gene>(&self, p: &P, parsimony: f32) -> f32 {
+        if self.tasks.is_empty() {
+            self.score_cases(&self.cases, p, parsimony)
+        } else {
+            let correctness = || {
+                let total: f32 = self.tasks.iter().map(|t| t.weight).sum();
+                self.breakdown(p).iter().zip(&self.tasks).map(|(s, t)| s.1 * t.weight).sum::<f32>() / total
+            };
+            with_shortness(p.size(), &self.cases, correctness, parsimony)
+        }
+    }
+
+    // The fraction of expected outputs a program gets right on each task of a multitask suite, with
+    // no weight for shortness. Empty for other suites.
+    pub fn breakdown<P: Runnable + ?Sized>(&self, p: &P) -> Vec<(&'static str, f32)> {
+        let mut start = 0;
+        self.tasks.iter().map(|t| {
+            let cases = &self.cases[start .. start + t.cases];
+            start += t.cases;
+            (t.name, self.accuracy(cases, p))
+        }).collect()
+    }
+
+    // Score a program the same way on the holdout cases instead, to see how well it does on cases
+    // it wasn't evolved against. None if there are no holdout cases.
+    pub fn validate_with<P: Runnable + Gene>(&self, p: &P, parsimony: f32) -> Option<f32> {
+        if self.holdout.is_empty() {
+            None
+        } else {
+            Some(self.score_cases(&self.holdout, p, parsimony))
+        }
+    }
+
+    // Move the given fraction of the scored cases into the holdout set. See
+    // `SuiteBuilder::holdout_split`.
+    pub fn with_holdout(mut self, fraction: f32) -> TestSuite {
+        let (cases, holdout) = split_evenly(::std::mem::take(&mut self.cases), fraction);
+        self.cases = cases;
+        self.holdout.extend(holdout);
+        self
+    }
+
+    // Score programs with the given policy for popping an empty stack
+    pub fn with_underflow(mut self, underflow: Underflow) -> TestSuite {
+        self.underflow = underflow;
+        self
+    }
+
+    // Score programs with the given step budget
+    pub fn with_steps(mut self, steps: usize) -> TestSuite {
+        self.steps = steps;
+        self
+    }
+
+    // The fraction of expected outputs on the holdout cases that a program gets right, with no
+    // weight for shortness. This works for anything that runs, like an ensemble. None if there are
+    // no holdout cases.
+    pub fn holdout_accuracy<P: Runnable>(&self, p: &P) -> Option<f32> {
+        if self.holdout.is_empty() {
+            None
+        } else {
+            Some(self.accuracy(&self.holdout, p))
+        }
+    }
+
+    fn accuracy<P: Runnable + ?Sized>(&self, cases: &[Case], p: &P) -> f32 {
+        let num_outputs = self.num_outputs();
+        let mut total = 0;
+        let mut successful = 0;
+        for case in cases {
+            // A run that's an error gets every output wrong
+            if let Some(outputs) = p.run_limited(case.a, case.b, num_outputs, self.underflow, self.steps) {
+                successful += case.outputs.iter().zip(&outputs).filter(|&(x, y)| x == y).count();
+            }
+            total += case.outputs.len();
+        }
+        successful as f32 / total as f32
+    }
+
+    fn score_cases<P: Runnable + Gene>(&self, cases: &[Case], p: &P, parsimony: f32) -> f32 {
+        with_shortness(p.size(), cases, || self.accuracy(cases, p), parsimony)
+    }
+}
+
+// Weigh correctness on the cases against shortness. Empty programs and empty sets of cases score 0,
+// without working out the correctness.
+fn with_shortness<C: FnOnce() -> f32>(len: usize, cases: &[Case], correctness: C, parsimony: f32) -> f32 {
+    if len == 0 || cases.is_empty() {
+        return 0.0;
+    }
+    let shortness = 1.0 - (len as f32 / 100.0);
+    (1.0 - parsimony) * correctness() + parsimony * shortness
+}
+
+// Builds a suite out of cases of different kinds, e.g.
+//
+//     TestSuite::builder(|a, b| vec![a + b])
+//         .grid(0 .. 10, 0 .. 10)
+//         .random_cases(50, (-100 .. 100, -100 .. 100), 7)
+//         .case(&[3, -4], vec![-1])
+//         .holdout_split(0.2)
+//         .build()
+//
+// Cases keep the order they were added in.
+pub struct SuiteBuilder<F> {
+    f: F,
+    name: &'static str,
+    config: Config,
+    cases: Vec<Case>,
+    holdout: Vec<Case>,
+}
+
+impl<F: Fn(i32, i32) -> Vec<i32>> SuiteBuilder<F> {
+    // Name the suite. It's "custom" otherwise.
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    // Evolve against the suite with this config instead of the default: a pool of 100 for 300
+    // generations, with the default weight for shortness
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Add a case for every pair of inputs from the two ranges, with the reference outputs
+    pub fn grid(mut self, a: Range<i32>, b: Range<i32>) -> Self {
+        for (a, b) in grid(a, b) {
+            let outputs = (self.f)(a, b);
+            self.cases.push(Case { a, b, outputs });
+        }
+        self
+    }
+
+    // Add `n` cases with random inputs from the two ranges, with the reference outputs. The same
+    // seed always gives the same cases.
+    pub fn random_cases(mut self, n: usize, ranges: (Range<i32>, Range<i32>), seed: u64) -> Self {
+        let rng = &mut StdRng::from_seed(&[seed as usize]);
+        for _ in 0 .. n {
+            let a = rng.gen_range(ranges.0.start, ranges.0.end);
+            let b = rng.gen_range(ranges.1.start, ranges.1.end);
+            let outputs = (self.f)(a, b);
+            self.cases.push(Case { a, b, outputs });
+        }
+        self
+    }
+
+    // Add a case with the given inputs, a and then b, and expected outputs, which don't have to
+    // match the reference function. A missing b is 0.
+    pub fn case(mut self, inputs: &[i32], outputs: Vec<i32>) -> Self {
+        assert!(inputs.len() <= 2, "cases have at most two inputs: {:?}", inputs);
+        let a = inputs.first().cloned().unwrap_or(0);
+        let b = inputs.get(1).cloned().unwrap_or(0);
+        self.cases.push(Case { a, b, outputs });
+        self
+    }
+
+    // Move the given fraction of the cases so far, from 0 to 1, into the holdout set. The held
+    // out cases are spread evenly through the others, so a grid stays covered on both sides.
+    pub fn holdout_split(mut self, fraction: f32) -> Self {
+        let (cases, holdout) = split_evenly(::std::mem::take(&mut self.cases), fraction);
+        self.cases = cases;
+        self.holdout.extend(holdout);
+        self
+    }
+
+    pub fn build(self) -> TestSuite {
+        TestSuite { name: self.name, cases: self.cases, holdout: self.holdout, config: self.config,
+                    underflow: Underflow::Zero, steps: MAX_STEPS, tasks: Vec::new() }
+    }
+}
+
+// Split off the given fraction of the cases, spread evenly through them. Returns the cases kept and
+// the cases split off.
+fn split_evenly(cases: Vec<Case>, fraction: f32) -> (Vec<Case>, Vec<Case>) {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let (mut kept, mut split) = (Vec::new(), Vec::new());
+    for (i, case) in cases.into_iter().enumerate() {
+        // Whether the running count of split off cases goes up at this case
+        if ((i + 1) as f32 * fraction).floor() > (i as f32 * fraction).floor() {
+            split.push(case);
+        } else {
+            kept.push(case);
+        }
+    }
+    (kept, split)
+}
+
+// Every pair of inputs from the two ranges
+fn grid(a: Range<i32>, b: Range<i32>) -> Vec<(i32, i32)> {
+    a.flat_map(|a| b.clone().map(move |b| (a, b))).collect()
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::Command::*;
+    use lang::Prog::{C, D};
+    use prog_gene::ProgramGene;
+
+    #[test]
+    fn suites() {
+        let sort_two = TestSuite::sort_two();
+        assert_eq!(sort_two.num_outputs(), 2);
+        assert!(sort_two.cases.contains(&Case { a: 3, b: -2, outputs: vec![-2, 3] }));
+        assert!(TestSuite::gcd().cases.contains(&Case { a: 12, b: 8, outputs: vec![4] }));
+        assert!(TestSuite::isqrt().cases.contains(&Case { a: 24, b: 0, outputs: vec![4] }));
+        assert!(TestSuite::sign().cases.contains(&Case { a: -7, b: 0, outputs: vec![-1] }));
+        assert_eq!(TestSuite::by_name("gcd").map(|s| s.name), Some("gcd"));
+        assert_eq!(TestSuite::by_name("fib"), None);
+
+        // Swapping the inputs puts a on top, so the outputs are (a, b). That's right for the 55
+        // cases with a <= b, and wrong for both outputs otherwise.
+        let swap = ProgramGene(vec![C(Swap)]);
+        let exact = TestSuite { config: Config { parsimony: 0.0, ..sort_two.config }, ..sort_two };
+        assert_eq!(exact.score(&swap), 0.55);
+        assert!(!exact.solves(&swap));
+        let sort_positive = TestSuite::from_fn("sort-positive", exact.config, vec![(1, 2), (3, 4)], |a, b| vec![a, b]);
+        assert!(sort_positive.solves(&swap));
+
+        // The boolean benchmarks cover every combination of input bits
+        let mux = TestSuite::multiplexer6();
+        assert_eq!(mux.cases.len(), 64);
+        // Address 2 selects data bit 2, which is bit 4 of a
+        assert!(mux.cases.contains(&Case { a: 0b010010, b: 0, outputs: vec![1] }));
+        assert!(mux.cases.contains(&Case { a: 0b101110, b: 0, outputs: vec![0] }));
+        let parity = TestSuite::even_parity(3);
+        assert_eq!(parity.cases.len(), 8);
+        assert!(parity.cases.contains(&Case { a: 0b101, b: 0, outputs: vec![1] }));
+        assert!(parity.cases.contains(&Case { a: 0b111, b: 0, outputs: vec![0] }));
+        let parity = TestSuite { config: Config { parsimony: 0.0, ..parity.config }, ..parity };
+        assert_eq!(parity.score(&ProgramGene(vec![D(1)])), 0.5);
+
+        // Pushing 0 is only right for a sign of 0, and gets the usual shortness bonus
+        let zero = ProgramGene(vec![D(0)]);
+        assert!((TestSuite::sign().score(&zero) - (0.99 / 21.0 + 0.01 * 0.99)).abs() < 0.00001);
+    }
+
+    #[test]
+    fn builder() {
+        let add = TestSuite::builder(|a, b| vec![a + b])
+            .name("add")
+            .grid(0 .. 4, 0 .. 5)
+            .random_cases(10, (-100 .. 100, 50 .. 60), 7)
+            .case(&[3, -4], vec![-1])
+            .case(&[2], vec![7])
+            .build();
+        assert_eq!(add.name, "add");
+        assert_eq!(add.cases.len(), 20 + 10 + 2);
+        assert_eq!(add.cases[0], Case { a: 0, b: 0, outputs: vec![0] });
+        assert!(add.cases[20 .. 30].iter().all(|c| c.b >= 50 && c.b < 60 && c.outputs == vec![c.a + c.b]));
+        assert_eq!(add.cases[31], Case { a: 2, b: 0, outputs: vec![7] });
+        assert!(add.holdout.is_empty());
+
+        // Random cases come from the seed
+        let random = |seed| TestSuite::builder(|a, b| vec![a * b]).random_cases(5, (0 .. 1000, 0 .. 1000), seed).build();
+        assert_eq!(random(1), random(1));
+        assert!(random(1) != random(2));
+
+        // A fifth of the grid is held out, spread evenly
+        let split = TestSuite::builder(|a, b| vec![a - b]).grid(0 .. 10, 0 .. 10).holdout_split(0.2).build();
+        assert_eq!((split.cases.len(), split.holdout.len()), (80, 20));
+        assert_eq!(split.holdout[0], Case { a: 0, b: 4, outputs: vec![-4] });
+        assert!(split.holdout.iter().all(|c| !split.cases.contains(c)));
+        assert!(split.solves(&ProgramGene(vec![C(Sub)])));
+
+        // Validation scores the holdout cases the same way
+        let add = TestSuite::builder(|a, b| vec![a + b]).grid(0 .. 5, 0 .. 5).build();
+        let plus = ProgramGene(vec![C(Add)]);
+        assert_eq!(add.validate_with(&plus, 0.0), None);
+        let add = add.with_holdout(0.2);
+        assert_eq!((add.cases.len(), add.holdout.len()), (20, 5));
+        assert_eq!(add.validate_with(&plus, 0.0), Some(1.0));
+        // a - b is only right when b is 0, and the held out cases are the last of each row
+        let sub = ProgramGene(vec![C(Sub)]);
+        assert_eq!(add.validate_with(&sub, 0.0), Some(0.0));
+        assert_eq!(add.score_with(&sub, 0.0), 0.25);
+
+        // Adding the empty stack's 0 is only free when that's what popping it gives
+        let add_twice = ProgramGene(vec![C(Add), C(Add)]);
+        assert!(add.solves(&add_twice));
+        let add = add.with_underflow(Underflow::Error);
+        assert!(!add.solves(&add_twice));
+        assert_eq!(add.score_with(&add_twice, 0.0), 0.0);
+        assert!(add.solves(&plus));
+        // Repeating the sum doubles it, which is only right for 0 + 0
+        let add = add.with_underflow(Underflow::Repeat);
+        assert_eq!(add.score_with(&add_twice, 0.0), 0.05);
+    }
+
+    #[test]
+    fn multitask() {
+        let add = TestSuite::builder(|a, b| vec![a + b]).name("add").grid(0 .. 4, 0 .. 4).build();
+        let sub = TestSuite::builder(|a, b| vec![a - b]).name("sub").grid(0 .. 2, 0 .. 2).build();
+        let both = TestSuite::multitask(vec![(add, 1.0), (sub, 3.0)]);
+        assert_eq!(both.cases.len(), 20);
+        assert_eq!(both.tasks.iter().map(|t| t.cases).collect::<Vec<_>>(), vec![16, 4]);
+
+        // Adding is right on every add case, and on the sub cases where b is 0. The sub suite is
+        // small but counts three times as much.
+        let plus = ProgramGene(vec![C(Add)]);
+        assert_eq!(both.breakdown(&plus), vec![("add", 1.0), ("sub", 0.5)]);
+        assert_eq!(both.score_with(&plus, 0.0), 0.625);
+        assert!(!both.solves(&plus));
+        assert_eq!(TestSuite::sign().breakdown(&plus), vec![]);
+    }
+}
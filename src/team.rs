@@ -0,0 +1,156 @@
+//
+// Teams of programs evolved together as a single gene, with their outputs combined into one answer
+//
+
+use super::gene::Gene;
+use super::prog_gene::{self, Runnable};
+
+use std::fmt;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// The number of members in every team
+pub const TEAM_SIZE: usize = 3;
+
+// How a team combines its members' outputs into one answer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    // The median output
+    Median,
+    // The most common output. Ties go to the earliest member.
+    Vote,
+}
+
+impl Aggregate {
+    // Combine the given outputs. Panics if there are none.
+    pub fn combine(self, outputs: &mut [i32]) -> i32 {
+        match self {
+            Aggregate::Median => {
+                outputs.sort();
+                outputs[outputs.len() / 2]
+            }
+            Aggregate::Vote => {
+                let count = |x: i32| outputs.iter().filter(|&&y| y == x).count();
+                let mut best = outputs[0];
+                for &x in outputs.iter() {
+                    if count(x) > count(best) {
+                        best = x;
+                    }
+                }
+                best
+            }
+        }
+    }
+}
+
+// A fixed-size team of genes, which is itself a gene
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Team<T>(pub Vec<T>);
+
+impl<T: Runnable> Team<T> {
+    // Run every member and combine their outputs
+    pub fn run(&self, agg: Aggregate, a: i32, b: i32) -> i32 {
+        let mut outputs: Vec<i32> = self.0.iter().map(|m| m.run(a, b)).collect();
+        agg.combine(&mut outputs)
+    }
+}
+
+// Every member is made with the same config
+impl<T: Gene + Clone> Gene for Team<T> {
+    type Config = T::Config;
+
+    fn generate<R: Rng>(cfg: &T::Config, rng: &mut R) -> Self {
+        Team((0 .. TEAM_SIZE).map(|_| T::generate(cfg, rng)).collect())
+    }
+
+    fn mutate<R: Rng>(&self, cfg: &T::Config, rng: &mut R) -> Self {
+        // Mutate one member and keep the rest
+        let mut result = self.0.clone();
+        let i = rng.gen_range(0, result.len());
+        result[i] = result[i].mutate(cfg, rng);
+        Team(result)
+    }
+
+    fn cross<R: Rng>(&self, _cfg: &T::Config, other: &Self, rng: &mut R) -> Self {
+        // Take each member from either parent
+        Team(self.0.iter().zip(&other.0)
+            .map(|(mine, theirs)| if rng.gen() { mine.clone() } else { theirs.clone() })
+            .collect())
+    }
+
+    // The total size of all members
+    fn size(&self) -> usize {
+        self.0.iter().map(Gene::size).sum()
+    }
+}
+
+// Display the members separated by bars, e.g. "[1 + | dup * | -]"
+impl<T: fmt::Display> fmt::Display for Team<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, m) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", m)?;
+        }
+        write!(f, "]")
+    }
+}
+
+// Use to create a fitness function that runs the whole team and compares its combined output to
+// the given reference function. The shortness bonus counts every member.
+pub fn fitness<F, T>(f: F, agg: Aggregate, team: &Team<T>) -> f32
+    where F: Fn(i32, i32) -> i32,
+          T: Runnable + Gene + Clone,
+    {
+    prog_gene::score_cases(f, team.size(), |a, b| team.run(agg, a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::Command::*;
+    use lang::Prog::{C, D};
+    use prog_gene::ProgramGene;
+    use ::rand::SeedableRng;
+
+    #[test]
+    fn combine_outputs() {
+        assert_eq!(Aggregate::Median.combine(&mut [5, -1, 3]), 3);
+        assert_eq!(Aggregate::Vote.combine(&mut [5, 3, 3]), 3);
+        // Without a majority, the first member wins
+        assert_eq!(Aggregate::Vote.combine(&mut [5, -1, 3]), 5);
+    }
+
+    #[test]
+    fn team_fitness() {
+        // Two of three members compute a + b, so the team does too
+        let team = Team(vec![
+            ProgramGene(vec![C(Add)]),
+            ProgramGene(vec![D(-1)]),
+            ProgramGene(vec![C(Add)]),
+        ]);
+        assert_eq!(team.run(Aggregate::Vote, 3, 4), 7);
+        assert_eq!(team.run(Aggregate::Median, 3, 4), 7);
+        assert_eq!(format!("{}", team), "[+ | -1 | +]");
+        assert!((fitness(|a, b| a + b, Aggregate::Vote, &team) - 0.9997).abs() < 0.000001);
+    }
+
+    #[test]
+    fn generate_mutate_and_cross() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &Default::default();
+        let a: Team<ProgramGene> = Team::generate(cfg, rng);
+        let b: Team<ProgramGene> = Team::generate(cfg, rng);
+        assert_eq!(a.0.len(), TEAM_SIZE);
+        assert_eq!(a.mutate(cfg, rng).0.len(), TEAM_SIZE);
+
+        // Every member of a child comes from one of its parents
+        let child = a.cross(cfg, &b, rng);
+        for (i, m) in child.0.iter().enumerate() {
+            assert!(*m == a.0[i] || *m == b.0[i]);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//
+// Step-by-step traces of stack programs, and a static HTML page to show them. Each row of the page
+// is one step: the program with the prog just run highlighted, and the stack after it, bottom first.
+//
+
+use super::lang::{Prog, Stack};
+use super::prog_gene::MAX_STEPS;
+
+use std::fmt::Write;
+
+// The layout of the page, in pixels
+const LABEL_WIDTH: usize = 70;
+const CELL_WIDTH: usize = 56;
+const ROW_HEIGHT: usize = 28;
+
+// One step of a program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    // The position in the program of the prog run in this step
+    pub position: usize,
+    pub prog: Prog,
+    // The stack after this step, bottom first
+    pub stack: Vec<i32>,
+}
+
+// A program run from start to end
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub program: Vec<Prog>,
+    // The stack before the first step, bottom first
+    pub start: Vec<i32>,
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    // Run a program on the inputs (a, b) the way `ProgramGene::run` does, recording every step
+    pub fn run(program: &[Prog], a: i32, b: i32) -> Trace {
+        Trace::run_limited(program, a, b, MAX_STEPS)
+    }
+
+    // Like `run`, but for at most `steps` steps instead of `MAX_STEPS`
+    pub fn run_limited(program: &[Prog], a: i32, b: i32, steps: usize) -> Trace {
+        let mut s = Stack::new();
+        s.push(a);
+        s.push(b);
+        let start = s.data().to_vec();
+        s.queue_program(program);
+        s.set_fuel(Some(steps));
+        let mut ran = Vec::new();
+        while s.run_next() {
+            // Branches and loops mean steps don't follow the program in order
+            let position = s.position().expect("a step was run");
+            ran.push(Step { position, prog: program[position], stack: s.data().to_vec() });
+        }
+        Trace { program: program.to_vec(), start, steps: ran }
+    }
+
+    // The program's output: the top of the final stack, or 0 if it's empty
+    pub fn output(&self) -> i32 {
+        self.outputs(1)[0]
+    }
+
+    // The program's first `count` outputs, top of the final stack first, with 0 for each one the
+    // stack is too short for, as `Runnable::run_outputs` gives them
+    pub fn outputs(&self, count: usize) -> Vec<i32> {
+        let last = self.steps.last().map_or(&self.start, |s| &s.stack);
+        (0 .. count).map(|i| last.iter().rev().nth(i).cloned().unwrap_or(0)).collect()
+    }
+
+    // Draw the trace as a standalone HTML page with an SVG timeline
+    pub fn to_html(&self) -> String {
+        let depth = self.steps.iter().map(|s| s.stack.len()).chain(Some(self.start.len())).max().unwrap_or(0);
+        let stack_x = LABEL_WIDTH + (self.program.len() + 1) * CELL_WIDTH;
+        let width = stack_x + depth.max(1) * CELL_WIDTH;
+        let height = (self.steps.len() + 2) * ROW_HEIGHT;
+
+        let mut svg = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                               font-family=\"monospace\" font-size=\"14\">", width, height);
+        text(&mut svg, LABEL_WIDTH, 0, "program");
+        text(&mut svg, stack_x, 0, "stack, bottom first");
+        let row = |svg: &mut String, y: usize, label: &str, current: Option<usize>, stack: &[i32]| {
+            text(svg, 0, y, label);
+            for (i, p) in self.program.iter().enumerate() {
+                let fill = if Some(i) == current { "#ffd54f" } else { "#eeeeee" };
+                cell(svg, LABEL_WIDTH + i * CELL_WIDTH, y, fill, &p.to_string());
+            }
+            for (i, d) in stack.iter().enumerate() {
+                cell(svg, stack_x + i * CELL_WIDTH, y, "#bbdefb", &d.to_string());
+            }
+        };
+        row(&mut svg, ROW_HEIGHT, "start", None, &self.start);
+        for (i, step) in self.steps.iter().enumerate() {
+            row(&mut svg, (i + 2) * ROW_HEIGHT, &format!("step {}", i + 1), Some(step.position), &step.stack);
+        }
+        svg.push_str("</svg>\n");
+
+        let program: Vec<String> = self.program.iter().map(|p| p.to_string()).collect();
+        let title = escape(&program.join(" "));
+        format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n\
+                 <h1>{}</h1>\n<p>Output: {}</p>\n{}</body>\n</html>\n",
+                title, title, self.output(), svg)
+    }
+}
+
+// Draw text with its top left at (x, y)
+fn text(svg: &mut String, x: usize, y: usize, s: &str) {
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\">{}</text>", x + 4, y + ROW_HEIGHT * 2 / 3, escape(s));
+}
+
+// Draw a box with text in it, with its top left at (x, y)
+fn cell(svg: &mut String, x: usize, y: usize, fill: &str, s: &str) {
+    let _ = writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"#9e9e9e\"/>",
+                     x + 1, y + 1, CELL_WIDTH - 2, ROW_HEIGHT - 2, fill);
+    text(svg, x, y, s);
+}
+
+// Escape text for HTML
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prog_gene::{ProgramGene, Runnable};
+
+    #[test]
+    fn trace_program() {
+        let gene: ProgramGene = "swap dup * -".parse().unwrap();
+        let trace = Trace::run(&gene.0, 3, 4);
+        assert_eq!(trace.start, vec![3, 4]);
+        assert_eq!(trace.steps.len(), 4);
+        assert_eq!(trace.steps[1], Step { position: 1, prog: gene.0[1], stack: vec![4, 3, 3] });
+        assert_eq!(trace.output(), gene.run(3, 4));
+
+        let html = trace.to_html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>swap dup * -</title>"));
+        // One highlighted prog per step
+        assert_eq!(html.matches("#ffd54f").count(), 4);
+
+        // Programs stop after the most steps a program may take
+        let long: ProgramGene = "dup dup dup dup dup dup dup dup dup dup dup dup".parse().unwrap();
+        assert_eq!(Trace::run(&long.0, 1, 2).steps.len(), MAX_STEPS);
+
+        // Steps follow branches and loops
+        let looping: ProgramGene = "0 1 if 7 3 1 times dup".parse().unwrap();
+        let trace = Trace::run(&looping.0, 1, 2);
+        let positions: Vec<usize> = trace.steps.iter().map(|s| s.position).collect();
+        assert_eq!(positions, vec![0, 1, 2, 4, 5, 6, 7, 7, 7]);
+        assert_eq!(trace.outputs(3), vec![2, 2, 2]);
+        assert_eq!(trace.outputs(6), looping.run_outputs(1, 2, 6));
+    }
+}
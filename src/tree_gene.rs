@@ -0,0 +1,232 @@
+//
+// Expression trees as genes, an alternative to the stack-based program representation
+//
+
+use super::lang::Command;
+use super::gene;
+use super::prog_gene;
+use super::memory::GeneSize;
+
+use std::fmt;
+use rand::Rng;
+
+// The deepest tree that generation and crossover will produce by default. This keeps trees from
+// bloating.
+pub const MAX_DEPTH: usize = 8;
+
+// The parameters of expression trees as genes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeConfig {
+    // The deepest tree mutation and crossover will produce
+    pub max_depth: usize,
+    // The deepest tree to generate. Mutation grows subtrees one level shallower than this.
+    pub generate_depth: usize,
+}
+
+impl Default for TreeConfig {
+    fn default() -> TreeConfig {
+        TreeConfig { max_depth: MAX_DEPTH, generate_depth: 4 }
+    }
+}
+
+// A node of an arithmetic expression over the inputs a and b
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Node {
+    Const(i32),
+    A,
+    B,
+    // An arithmetic command applied to two subtrees
+    Op(Command, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    // Evaluate the expression for the given inputs
+    pub fn eval(&self, a: i32, b: i32) -> i32 {
+        match *self {
+            Node::Const(c) => c,
+            Node::A => a,
+            Node::B => b,
+            Node::Op(c, ref l, ref r) => c.apply(l.eval(a, b), r.eval(a, b)),
+        }
+    }
+
+    // The number of nodes in this tree
+    pub fn size(&self) -> usize {
+        match *self {
+            Node::Op(_, ref l, ref r) => 1 + l.size() + r.size(),
+            _ => 1,
+        }
+    }
+
+    // The number of levels in this tree. A single leaf has depth 1.
+    pub fn depth(&self) -> usize {
+        match *self {
+            Node::Op(_, ref l, ref r) => 1 + l.depth().max(r.depth()),
+            _ => 1,
+        }
+    }
+
+    // Get the `i`th node in pre-order
+    fn nth(&self, i: usize) -> &Node {
+        match *self {
+            Node::Op(_, ref l, ref r) if i > 0 => {
+                if i <= l.size() { l.nth(i - 1) } else { r.nth(i - 1 - l.size()) }
+            }
+            _ => self,
+        }
+    }
+
+    // Get the `i`th node in pre-order, mutably
+    fn nth_mut(&mut self, i: usize) -> &mut Node {
+        if i == 0 {
+            return self;
+        }
+        match *self {
+            Node::Op(_, ref mut l, ref mut r) => {
+                let l_size = l.size();
+                if i <= l_size { l.nth_mut(i - 1) } else { r.nth_mut(i - 1 - l_size) }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Generate a random tree no deeper than `depth`
+fn rand_node<R: Rng>(depth: usize, rng: &mut R) -> Node {
+    if depth <= 1 || rng.gen_weighted_bool(3) {
+        // Leaf: input or constant
+        match rng.gen_range(0, 3) {
+            0 => Node::A,
+            1 => Node::B,
+            _ => Node::Const(rng.gen_range(-10, 11)),
+        }
+    } else {
+        let c = *rng.choose(&Command::ARITHMETIC).unwrap();
+        Node::Op(c, Box::new(rand_node(depth - 1, rng)), Box::new(rand_node(depth - 1, rng)))
+    }
+}
+
+// An expression tree as a gene
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TreeGene(pub Node);
+
+impl gene::Gene for TreeGene {
+    type Config = TreeConfig;
+
+    fn generate<R: Rng>(cfg: &TreeConfig, rng: &mut R) -> Self {
+        TreeGene(rand_node(cfg.generate_depth, rng))
+    }
+
+    fn mutate<R: Rng>(&self, cfg: &TreeConfig, rng: &mut R) -> Self {
+        // Replace a random subtree with a new random subtree
+        let mut result = self.0.clone();
+        let i = rng.gen_range(0, result.size());
+        *result.nth_mut(i) = rand_node(cfg.generate_depth.saturating_sub(1), rng);
+        if result.depth() > cfg.max_depth {
+            return self.clone();
+        }
+        TreeGene(result)
+    }
+
+    fn cross<R: Rng>(&self, cfg: &TreeConfig, other: &Self, rng: &mut R) -> Self {
+        // Replace a random subtree of this tree with a random subtree of the other
+        let mut result = self.0.clone();
+        let i = rng.gen_range(0, result.size());
+        let j = rng.gen_range(0, other.0.size());
+        *result.nth_mut(i) = other.0.nth(j).clone();
+        if result.depth() > cfg.max_depth {
+            return self.clone();
+        }
+        TreeGene(result)
+    }
+
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+}
+
+// Display the tree in fully-parenthesized infix notation
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Node::Const(c) => write!(f, "{}", c),
+            Node::A => write!(f, "a"),
+            Node::B => write!(f, "b"),
+            Node::Op(c, ref l, ref r) => write!(f, "({} {} {})", l, c.symbol(), r),
+        }
+    }
+}
+
+impl fmt::Display for TreeGene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Use to create a fitness function that evaluates the tree and compares output to the given
+// reference function. See `prog_gene::fitness`.
+pub fn fitness<F: Fn(i32, i32) -> i32>(f: F, g: &TreeGene) -> f32 {
+    prog_gene::score(f, g)
+}
+
+// Every node but the root is boxed
+impl GeneSize for TreeGene {
+    fn heap_bytes(&self) -> usize {
+        (self.0.size() - 1) * ::std::mem::size_of::<Node>()
+    }
+}
+
+impl prog_gene::Runnable for TreeGene {
+    fn run(&self, a: i32, b: i32) -> i32 {
+        self.0.eval(a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene::Gene;
+    use ::rand::SeedableRng;
+
+    fn op(c: Command, l: Node, r: Node) -> Node {
+        Node::Op(c, Box::new(l), Box::new(r))
+    }
+
+    #[test]
+    fn eval_and_display() {
+        // 3 + a - b*b
+        let tree = op(Command::Sub, op(Command::Add, Node::Const(3), Node::A), op(Command::Mult, Node::B, Node::B));
+        assert_eq!(tree.eval(5, 2), 4);
+        assert_eq!(tree.size(), 7);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(format!("{}", TreeGene(tree.clone())), "((3 + a) - (b * b))");
+        assert!((fitness(|a, b| 3 + a - b*b, &TreeGene(tree)) - 0.9993).abs() < 0.000001);
+
+        // Nodes are numbered in pre-order
+        let tree = op(Command::Add, Node::A, Node::B);
+        assert_eq!(*tree.nth(0), tree);
+        assert_eq!(*tree.nth(1), Node::A);
+        assert_eq!(*tree.nth(2), Node::B);
+    }
+
+    #[test]
+    fn generate_mutate_and_cross() {
+        let rng = &mut ::rand::StdRng::from_seed(&[123]);
+        let cfg = &TreeConfig::default();
+        let genes: Vec<TreeGene> = (0 .. 1000).map(|_| TreeGene::generate(cfg, rng)).collect();
+        for pair in genes.windows(2) {
+            let other = pair[1].mutate(cfg, rng);
+            let child = pair[0].mutate(cfg, rng).cross(cfg, &other, rng);
+            assert!(child.0.depth() <= MAX_DEPTH);
+        }
+
+        // Shallower limits hold too
+        let shallow = &TreeConfig { max_depth: 3, generate_depth: 2 };
+        for pair in genes.windows(2) {
+            let (a, b) = (TreeGene::generate(shallow, rng), TreeGene::generate(shallow, rng));
+            assert!(a.0.depth() <= 2);
+            assert!(a.mutate(shallow, rng).cross(shallow, &b, rng).0.depth() <= 3);
+            assert!(pair[0].cross(shallow, &pair[1], rng).0.depth() <= pair[0].0.depth().max(3));
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//
+// Triage a config with a batch of short runs from distinct random seeds, to see whether it ever
+// reaches the target fitness before committing to a full-length run. The seeds and how each run
+// went make a corpus, saved as text, so a seed that hit can be run again in full with `--seed`.
+//
+
+use rand::Rng;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+// How one short run went
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outcome {
+    pub seed: u64,
+    // The number of generations it took to reach the target fitness, if it did
+    pub solved_at: Option<usize>,
+    pub best_fitness: f32,
+}
+
+// `count` distinct seeds
+pub fn seeds<R: Rng>(count: usize, rng: &mut R) -> Vec<u64> {
+    let mut seen = HashSet::new();
+    let mut seeds = Vec::with_capacity(count);
+    while seeds.len() < count {
+        let seed = rng.gen();
+        if seen.insert(seed) {
+            seeds.push(seed);
+        }
+    }
+    seeds
+}
+
+// The fraction of runs that reached the target fitness
+pub fn hit_rate(outcomes: &[Outcome]) -> f32 {
+    if outcomes.is_empty() {
+        return 0.0;
+    }
+    outcomes.iter().filter(|o| o.solved_at.is_some()).count() as f32 / outcomes.len() as f32
+}
+
+// Write the corpus, one run per line: its seed, the generations it took to reach the target or "-"
+// if it didn't, and its best fitness, separated by tabs
+pub fn write_corpus<W: Write>(outcomes: &[Outcome], w: &mut W) -> io::Result<()> {
+    writeln!(w, "seed\tsolved_at\tbest_fitness")?;
+    for o in outcomes {
+        let solved_at = o.solved_at.map_or("-".to_string(), |g| g.to_string());
+        writeln!(w, "{}\t{}\t{}", o.seed, solved_at, o.best_fitness)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn triage_seeds() {
+        let batch = seeds(50, &mut StdRng::from_seed(&[7]));
+        assert_eq!(batch.iter().collect::<HashSet<_>>().len(), 50);
+        // A seeded batch is the same every time
+        assert_eq!(seeds(50, &mut StdRng::from_seed(&[7])), batch);
+
+        let outcomes = [
+            Outcome { seed: 12, solved_at: Some(31), best_fitness: 1.0 },
+            Outcome { seed: 5, solved_at: None, best_fitness: 0.25 },
+        ];
+        assert_eq!(hit_rate(&outcomes), 0.5);
+        assert_eq!(hit_rate(&[]), 0.0);
+        let mut text = Vec::new();
+        write_corpus(&outcomes, &mut text).unwrap();
+        assert_eq!(String::from_utf8(text).unwrap(), "seed\tsolved_at\tbest_fitness\n12\t31\t1\n5\t-\t0.25\n");
+    }
+}
@@ -0,0 +1,95 @@
+//
+// A warm-up phase at the start of a run: evolve a large pool with heavy mutation for a while to
+// explore widely, then shrink it to the pool size of the rest of the run, keeping only the most fit
+// genes, and mutate normally from then on to exploit what was found.
+//
+
+use super::gene::DynPool;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WarmUp {
+    // The number of generations to explore for
+    pub generations: usize,
+    // The pool size while exploring
+    pub size: usize,
+    // The number of mutations in a row that make each mutated gene while exploring
+    pub mutations: usize,
+}
+
+impl WarmUp {
+    // Whether the given generation of the run, counting from 0, is part of the warm-up
+    pub fn is_exploring(&self, generation: usize) -> bool {
+        generation < self.generations
+    }
+
+    // Set the pool up to evolve the given generation of the run: heavy mutation while exploring,
+    // and then, on the first generation after, shrunk to `size` with normal mutation. Call this
+    // before evolving each generation. The pool should start at the exploring size.
+    pub fn prepare(&self, pool: &mut dyn DynPool, generation: usize, size: usize, rng: &mut dyn Rng) {
+        if self.is_exploring(generation) {
+            pool.set_mutations_dyn(self.mutations);
+        } else if generation == self.generations {
+            pool.resize_dyn(size, rng);
+            pool.set_mutations_dyn(1);
+        }
+    }
+}
+
+// Parse a warm-up from "GENERATIONS:SIZE:MUTATIONS", e.g. "20:1000:3"
+impl FromStr for WarmUp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<WarmUp, String> {
+        let parts: Vec<usize> = s.split(':').map(|x| x.parse().ok()).collect::<Option<_>>()
+            .ok_or_else(|| format!("bad number in warm-up: {}", s))?;
+        match parts[..] {
+            [generations, size, mutations] => Ok(WarmUp { generations, size, mutations }),
+            _ => Err(format!("warm-up needs GENERATIONS:SIZE:MUTATIONS: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for WarmUp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.generations, self.size, self.mutations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gene;
+    use prog_gene::{self, ProgramGene};
+    use ::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn explore_then_exploit() {
+        let warm_up: WarmUp = "3:80:4".parse().unwrap();
+        assert_eq!(warm_up, WarmUp { generations: 3, size: 80, mutations: 4 });
+        assert_eq!(warm_up.to_string(), "3:80:4");
+        assert!("3:80".parse::<WarmUp>().is_err());
+        assert!("3:80:x".parse::<WarmUp>().is_err());
+
+        let rng = &mut StdRng::from_seed(&[123]);
+        let fitness: gene::BoxedFitness<ProgramGene> = Box::new(|g: &ProgramGene| prog_gene::fitness(|a, b| a + b, g));
+        let mut pool = gene::new_dyn_pool(warm_up.size, fitness, rng);
+        let mut best_while_exploring = 0.0;
+        for i in 0 .. 6 {
+            warm_up.prepare(&mut *pool, i, 20, rng);
+            pool.evolve_dyn(rng);
+            let expected = if warm_up.is_exploring(i) { 80 } else { 20 };
+            assert_eq!(pool.genes_dyn().len(), expected);
+            if i == 2 {
+                best_while_exploring = pool.best_dyn().1;
+            }
+        }
+        // Shrinking keeps the best genes
+        assert!(pool.best_dyn().1 >= best_while_exploring);
+    }
+}
@@ -0,0 +1,148 @@
+//
+// Fitness functions made of several weighted terms on different scales. A plain weighted sum only
+// works when every term has the same range, like the correctness and shortness terms of
+// `prog_gene::score_cases_with`, which are both between 0 and 1. Here each term is first normalized
+// by what has been seen of it so far, so e.g. an error in the thousands and a length in the tens
+// count for their weights and no more.
+//
+// Normalization changes as more genes are scored, so the same gene can score differently over a
+// run. These fitness functions report themselves as not deterministic, so pools score survivors
+// again by default.
+//
+
+use super::gene::Fitness;
+
+use std::sync::Mutex;
+
+// How each term is scaled before weighting
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalize {
+    // Scale the lowest value seen so far to 0 and the highest to 1
+    MinMax,
+    // Subtract the mean of the values seen so far and divide by their standard deviation
+    ZScore,
+}
+
+// What has been seen of one term: its range, and its running mean and variance by Welford's method
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stats {
+    count: usize,
+    min: f32,
+    max: f32,
+    mean: f64,
+    // The sum of squared differences from the mean
+    m2: f64,
+}
+
+impl Stats {
+    fn new() -> Stats {
+        Stats { count: 0, min: f32::INFINITY, max: f32::NEG_INFINITY, mean: 0.0, m2: 0.0 }
+    }
+
+    fn observe(&mut self, x: f32) {
+        self.count += 1;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        let delta = x as f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x as f64 - self.mean);
+    }
+
+    // Scale a value. A term that hasn't varied yet scales to the middle: 0.5 for min-max, 0 for
+    // z-scores.
+    fn normalize(&self, how: Normalize, x: f32) -> f32 {
+        match how {
+            Normalize::MinMax => {
+                let range = self.max - self.min;
+                if range > 0.0 { (x - self.min) / range } else { 0.5 }
+            }
+            Normalize::ZScore => {
+                let sd = (self.m2 / self.count as f64).sqrt();
+                if sd > 0.0 { ((x as f64 - self.mean) / sd) as f32 } else { 0.0 }
+            }
+        }
+    }
+}
+
+// One term of a weighted sum. Higher is better, so negate errors and lengths.
+type Term<T> = Box<dyn Fn(&T) -> f32 + Send + Sync>;
+
+// A fitness function that adds up weighted terms, each normalized by the values it has had so far
+pub struct WeightedSum<T> {
+    normalize: Normalize,
+    terms: Vec<(f32, Term<T>)>,
+    stats: Mutex<Vec<Stats>>,
+}
+
+impl<T> WeightedSum<T> {
+    pub fn new(normalize: Normalize) -> WeightedSum<T> {
+        WeightedSum { normalize, terms: Vec::new(), stats: Mutex::new(Vec::new()) }
+    }
+
+    // Add a term with the given weight
+    pub fn term<F: Fn(&T) -> f32 + Send + Sync + 'static>(mut self, weight: f32, term: F) -> WeightedSum<T> {
+        self.terms.push((weight, Box::new(term)));
+        self.stats.get_mut().unwrap().push(Stats::new());
+        self
+    }
+
+    // Score each term of a gene, record the raw scores, and normalize them. A NaN score isn't
+    // recorded, and stays NaN.
+    fn normalized(&self, gene: &T) -> Vec<f32> {
+        let raw: Vec<f32> = self.terms.iter().map(|t| (t.1)(gene)).collect();
+        let mut stats = self.stats.lock().unwrap();
+        raw.iter().zip(stats.iter_mut())
+            .map(|(&x, s)| {
+                if x.is_nan() {
+                    return x;
+                }
+                s.observe(x);
+                s.normalize(self.normalize, x)
+            })
+            .collect()
+    }
+}
+
+impl<T> Fitness<T> for WeightedSum<T> {
+    fn evaluate(&self, gene: &T) -> f32 {
+        self.normalized(gene).iter().zip(&self.terms).map(|(x, t)| x * t.0).sum()
+    }
+
+    // The normalized terms, unweighted, e.g. as separate objectives for Pareto selection
+    fn evaluate_detailed(&self, gene: &T) -> Option<Vec<f32>> {
+        Some(self.normalized(gene))
+    }
+
+    fn is_deterministic(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_terms() {
+        // Two terms on very different scales, each worth half
+        let fitness = WeightedSum::new(Normalize::MinMax)
+            .term(0.5, |x: &(f32, f32)| x.0)
+            .term(0.5, |x: &(f32, f32)| x.1);
+        // Nothing varies yet, so everything is in the middle
+        assert_eq!(fitness.evaluate(&(1000.0, 1.0)), 0.5);
+        // The best of one term and the worst of the other are worth the same
+        assert_eq!(fitness.evaluate(&(5000.0, 0.0)), 0.5);
+        assert_eq!(fitness.evaluate(&(5000.0, 1.0)), 1.0);
+        assert_eq!(fitness.evaluate(&(1000.0, 0.0)), 0.0);
+        assert_eq!(fitness.evaluate_detailed(&(3000.0, 0.0)), Some(vec![0.5, 0.0]));
+        assert!(fitness.evaluate(&(f32::NAN, 1.0)).is_nan());
+        assert!(!fitness.is_deterministic());
+
+        let fitness = WeightedSum::new(Normalize::ZScore).term(2.0, |x: &f32| *x);
+        for &x in &[10.0, 20.0, 30.0] {
+            fitness.evaluate(&x);
+        }
+        // With this last value the mean is 22.5 and the variance 68.75
+        assert!((fitness.evaluate(&30.0) - 2.0 * 7.5 / 68.75f32.sqrt()).abs() < 0.0001);
+    }
+}
@@ -0,0 +1,100 @@
+//
+// Golden-file regression tests. Each test runs a small evolution from a fixed seed and compares
+// the best fitness of every generation, and the best program at the end, against a file in
+// tests/golden. A change to selection or the operators that changes what a run does will fail
+// here. If the change is intended, bless the new behavior with
+//
+//     GOLDEN_BLESS=1 cargo test --test golden
+//
+// and check the diff of the golden files in with it.
+//
+
+extern crate gene_code;
+extern crate rand;
+
+use gene_code::gene::{Fitness, Gene, Pool};
+use gene_code::prog_gene::{self, ProgramGene, Runnable};
+use gene_code::reg_gene::RegisterGene;
+use gene_code::tree_gene::TreeGene;
+use rand::{SeedableRng, StdRng};
+use std::env;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
+
+// The number of genes in each pool, and the generations to run it
+const POOL_SIZE: usize = 20;
+const GENERATIONS: usize = 15;
+
+// The function every test evolves toward, the same one the binary targets
+fn target(a: i32, b: i32) -> i32 {
+    3 + a - b * b
+}
+
+fn fitness<T: Runnable + Gene>(g: &T) -> f32 {
+    prog_gene::score(target, g)
+}
+
+// Describe a run: the best fitness of each generation, then the best gene
+fn describe<T, F>(pool: &mut Pool<T, F>, mut evolve: impl FnMut(&mut Pool<T, F>, usize)) -> String
+    where T: Gene + Hash + Eq + Clone + Display,
+          F: Fitness<T>,
+    {
+    let mut out = String::new();
+    for generation in 0 .. GENERATIONS {
+        evolve(pool, generation);
+        writeln!(out, "{} {:?}", generation, pool.get_best().1).unwrap();
+    }
+    writeln!(out, "best: {}", pool.get_best().0).unwrap();
+    out
+}
+
+// Compare the description of a run with its golden file, or overwrite the file when blessing
+fn check(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name));
+    if env::var_os("GOLDEN_BLESS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Couldn't read {}: {} (bless it with GOLDEN_BLESS=1)", path.display(), e));
+    assert!(actual == expected, "{} changed.\n--- expected\n{}--- actual\n{}", name, expected, actual);
+}
+
+// Run a pool of the given gene type with plain `evolve`
+fn golden_run<T>(name: &str)
+    where T: Gene + Hash + Eq + Clone + Display + Runnable,
+          T::Config: Default,
+    {
+    let rng = &mut StdRng::from_seed(&[123]);
+    let mut pool = Pool::new(POOL_SIZE, fitness::<T>, rng);
+    let actual = describe(&mut pool, |pool, _| pool.evolve(rng));
+    check(name, &actual);
+}
+
+#[test]
+fn stack() {
+    golden_run::<ProgramGene>("stack");
+}
+
+#[test]
+fn tree() {
+    golden_run::<TreeGene>("tree");
+}
+
+#[test]
+fn register() {
+    golden_run::<RegisterGene>("register");
+}
+
+#[test]
+fn deterministic() {
+    // The result doesn't depend on the number of workers, so both share one golden file
+    for &workers in &[1, 3] {
+        let rng = &mut StdRng::from_seed(&[123]);
+        let mut pool = Pool::new(POOL_SIZE, fitness::<ProgramGene>, rng);
+        let actual = describe(&mut pool, |pool, generation| pool.evolve_deterministic(123, generation, workers));
+        check("deterministic", &actual);
+    }
+}